@@ -1,17 +1,41 @@
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
+import { appWindow } from "@tauri-apps/api/window";
 import {
+  EnokiField,
   EnokiObject,
+  EnokiQuery,
   EnokiValue,
   TimestampedEnokiValue,
   enokiTypeFromTsType,
 } from "./EnokiTypes";
+import { EncodedObject, IpcEncoding, decodeEnokiObject } from "./Encoding";
 
 export async function readDatalogFile(path: string): Promise<EnokiObject> {
   return invoke("plugin:datalog|read_datalog_file", { path: path });
 }
 
-export async function getDaemonData(entry: string): Promise<EnokiObject> {
-  return invoke("plugin:datalog|retrieve_dl_daemon_data");
+export async function getDaemonData(
+  entry: string,
+  encoding?: IpcEncoding,
+): Promise<EnokiObject> {
+  return decodeEnokiObject(
+    await invoke<EncodedObject>("plugin:datalog|retrieve_dl_daemon_data", {
+      encoding: encoding,
+    }),
+  );
+}
+
+export async function queryDaemonData(
+  query: EnokiQuery,
+  encoding?: IpcEncoding,
+): Promise<EnokiObject> {
+  return decodeEnokiObject(
+    await invoke<EncodedObject>("plugin:datalog|query_dl_daemon_data", {
+      query: query,
+      encoding: encoding,
+    }),
+  );
 }
 
 export async function sendDatalogMark<T extends EnokiValue>(
@@ -29,3 +53,172 @@ export async function sendDatalogMark<T extends EnokiValue>(
     value: timestampedVal,
   });
 }
+
+export type DatalogFollowFinishReason =
+  | "AllEntriesFinished"
+  | "Idle"
+  | "Removed"
+  | "Unreadable"
+  | "Stopped";
+
+export class DatalogFollow {
+  public id: number;
+  private unlisteners: UnlistenFn[];
+
+  constructor(id: number, unlisteners: UnlistenFn[]) {
+    this.id = id;
+    this.unlisteners = unlisteners;
+  }
+
+  public async stop(): Promise<void> {
+    await invoke("plugin:datalog|stop_follow_datalog", { id: this.id });
+    this.unlisteners.forEach((unlisten) => unlisten());
+  }
+}
+
+/**
+ * The kind of a raw `tauri-plugin-fs-watch` event, only removals matter to a follow
+ */
+type RawEventKind = string | { remove?: unknown };
+
+/**
+ * Watches the file with `tauri-plugin-fs-watch` and passes every change on to the follow
+ */
+async function watchFollowedFile(id: number, path: string): Promise<UnlistenFn> {
+  let watchId = window.crypto.getRandomValues(new Uint32Array(1))[0];
+  await invoke("plugin:fs-watch|watch", {
+    id: watchId,
+    paths: [path],
+    options: { recursive: false },
+  });
+  let unlisten = await appWindow.listen<{ type: RawEventKind }>(
+    `watcher://raw-event/${watchId}`,
+    (event) => {
+      let kind = event.payload.type;
+      invoke("plugin:datalog|datalog_follow_changed", {
+        id: id,
+        removed: typeof kind === "object" && "remove" in kind,
+      });
+    },
+  );
+  let watching = true;
+  return () => {
+    if (watching) {
+      watching = false;
+      unlisten();
+      invoke("plugin:fs-watch|unwatch", { id: watchId });
+    }
+  };
+}
+
+/**
+ * Streams records appended to a wpilog that another process is still writing.
+ * @param onFinished Called once the follow ends, with the read error if the file became unreadable.
+ * @param idleTimeoutMs How long the file may go without growing before the writer is considered finished.
+ */
+export async function followDatalog(
+  path: string,
+  onFields: (fields: EnokiField<EnokiValue>[]) => void,
+  onFinished?: (reason: DatalogFollowFinishReason, error?: string) => void,
+  idleTimeoutMs?: number,
+): Promise<DatalogFollow> {
+  let id = await invoke<number>("plugin:datalog|follow_datalog", {
+    path: path,
+    idleTimeoutMs: idleTimeoutMs,
+  });
+  let unwatch = await watchFollowedFile(id, path);
+  let unlistenFields = await listen<{ id: number; fields: EnokiField<EnokiValue>[] }>(
+    "datalog_follow_fields",
+    (event) => {
+      if (event.payload.id === id) {
+        onFields(event.payload.fields);
+      }
+    },
+  );
+  let unlistenFinished = await listen<{
+    id: number;
+    reason: DatalogFollowFinishReason;
+    error: string | null;
+  }>("datalog_follow_finished", (event) => {
+    if (event.payload.id === id) {
+      onFinished?.(event.payload.reason, event.payload.error ?? undefined);
+      unwatch();
+      unlistenFields();
+      unlistenFinished();
+    }
+  });
+  return new DatalogFollow(id, [unwatch, unlistenFields, unlistenFinished]);
+}
+
+export type MatchType = "Practice" | "Qualification" | "Elimination";
+export type Alliance = "Red" | "Blue";
+
+export interface SessionMetadata {
+  event: string;
+  match_type: MatchType | null;
+  match_number: number | null;
+  alliance: Alliance | null;
+  robot: string;
+  code_version: string;
+  notes: string;
+}
+
+/**
+ * Finishes the running datalog and continues in a new file, the retention policy is applied to the older ones.
+ * Rejects if the new file can't be created, logging then continues in the running one.
+ */
+export async function rotateDatalog(): Promise<void> {
+  return invoke("plugin:datalog|rotate_datalog");
+}
+
+export async function getSessionMetadata(): Promise<SessionMetadata> {
+  return invoke("plugin:datalog|get_session_metadata");
+}
+
+/**
+ * Sets the metadata new datalogs are created with and records it in the running datalog.
+ */
+export async function setSessionMetadata(
+  metadata: SessionMetadata,
+): Promise<void> {
+  return invoke("plugin:datalog|set_session_metadata", { metadata: metadata });
+}
+
+export async function readDatalogSession(
+  path: string,
+): Promise<SessionMetadata | null> {
+  return invoke("plugin:datalog|read_datalog_session", { path: path });
+}
+
+export interface FieldSummary {
+  key: string;
+  value_type: string;
+  samples: number;
+  first_timestamp: number | null;
+  last_timestamp: number | null;
+  average_rate: number;
+  max_rate: number;
+  numeric: { min: number; max: number; mean: number } | null;
+  gaps: { start: number; end: number }[];
+}
+
+/**
+ * @param gapThreshold Gaps between samples longer than this, in microseconds, are reported.
+ */
+export async function datalogSummary(
+  path: string,
+  gapThreshold?: number,
+): Promise<FieldSummary[]> {
+  return invoke("plugin:datalog|datalog_summary", {
+    path: path,
+    gapThreshold: gapThreshold,
+  });
+}
+
+export async function daemonSummary(
+  gapThreshold?: number,
+): Promise<FieldSummary[]> {
+  return invoke("plugin:datalog|dl_daemon_summary", {
+    gapThreshold: gapThreshold,
+  });
+}
@@ -0,0 +1,51 @@
+import { invoke } from "@tauri-apps/api/tauri";
+import { EnokiObject } from "./EnokiTypes";
+import { EncodedObject, IpcEncoding, decodeEnokiObject } from "./Encoding";
+import { HistorySource } from "./Resample";
+
+export interface DerivedField {
+  key: string;
+  expression: string;
+}
+
+/**
+ * Defines a field computed from other fields, keys are wrapped in braces,
+ * e.g. `defineDerivedField("/Drive/skew", "{/Drive/Left/speed} - {/Drive/Right/speed}")`.
+ * It shows up in every network table subscription that has all of its inputs.
+ * Rejects if the expression doesn't parse.
+ */
+export async function defineDerivedField(
+  key: string,
+  expression: string,
+): Promise<void> {
+  return invoke("plugin:native|define_derived_field", {
+    key: key,
+    expression: expression,
+  });
+}
+
+export async function removeDerivedField(key: string): Promise<boolean> {
+  return invoke("plugin:native|remove_derived_field", { key: key });
+}
+
+export async function listDerivedFields(): Promise<DerivedField[]> {
+  return invoke("plugin:native|list_derived_fields");
+}
+
+/**
+ * Evaluates the derived fields over the histories of a datalog, every defined field if no keys are given
+ */
+export async function deriveDatalogHistories(
+  source: HistorySource,
+  options?: {
+    keys?: string[];
+    encoding?: IpcEncoding;
+  },
+): Promise<EnokiObject> {
+  return decodeEnokiObject(
+    await invoke<EncodedObject>("plugin:native|derive_datalog_histories", {
+      source: source,
+      ...options,
+    }),
+  );
+}
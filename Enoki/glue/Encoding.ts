@@ -0,0 +1,142 @@
+import { decode } from "@msgpack/msgpack";
+import {
+  EnokiField,
+  EnokiObject,
+  EnokiValue,
+  TimestampedEnokiValue,
+} from "./EnokiTypes";
+
+/**
+ * How the backend should encode an EnokiObject, json is the default.
+ * MessagePack is much cheaper for long histories.
+ */
+export type IpcEncoding = "Json" | "MessagePack";
+
+export interface BinaryPayload {
+  encoding: IpcEncoding;
+  data: string;
+}
+
+export type EncodedObject = EnokiObject | BinaryPayload;
+
+interface PackedField {
+  key: string;
+  type: string;
+  value: EnokiValue;
+  timestamp: number;
+  unit?: string;
+}
+
+interface PackedHistory {
+  type: string;
+  timestamps: Uint8Array;
+  values: Uint8Array | EnokiValue[];
+  types?: string[];
+}
+
+interface PackedObject {
+  timestamp: number;
+  fields: PackedField[];
+  history: Array<PackedHistory | null>;
+}
+
+function isBinaryPayload(payload: EncodedObject): payload is BinaryPayload {
+  return (payload as BinaryPayload).encoding !== undefined;
+}
+
+function base64ToBytes(data: string): Uint8Array {
+  let binary: string = atob(data);
+  let bytes: Uint8Array = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes;
+}
+
+/**
+ * Views little endian f64s, copied first as the msgpack slice may not be 8 byte aligned
+ */
+function toFloat64Array(bytes: Uint8Array): Float64Array {
+  return new Float64Array(bytes.slice().buffer);
+}
+
+function unpackHistory(
+  packed: PackedHistory,
+): Array<TimestampedEnokiValue<EnokiValue>> {
+  let timestamps: Float64Array = toFloat64Array(packed.timestamps);
+  let values: ArrayLike<EnokiValue> =
+    packed.values instanceof Uint8Array
+      ? toFloat64Array(packed.values)
+      : packed.values;
+  let history: Array<TimestampedEnokiValue<EnokiValue>> = [];
+  for (let i = 0; i < timestamps.length; i++) {
+    let type: string = packed.types ? packed.types[i] : packed.type;
+    history.push(new TimestampedEnokiValue(values[i], timestamps[i], type));
+  }
+  return history;
+}
+
+function unpackObject(packed: PackedObject): EnokiObject {
+  let obj: EnokiObject = new EnokiObject();
+  obj.timestamp = packed.timestamp;
+  packed.fields.forEach((field: PackedField, index: number): void => {
+    obj.fields.push(
+      new EnokiField(
+        new TimestampedEnokiValue(field.value, field.timestamp, field.type),
+        field.key,
+        field.unit,
+      ),
+    );
+    obj.paths.set(field.key, index);
+    let history: PackedHistory | null = packed.history[index];
+    obj.history.push(history ? unpackHistory(history) : undefined);
+  });
+  return obj;
+}
+
+function toWireValue<T extends EnokiValue>(
+  value: TimestampedEnokiValue<T>,
+): TimestampedEnokiValue<EnokiValue> {
+  return value.value instanceof Uint8Array
+    ? new TimestampedEnokiValue(
+        Array.from(value.value),
+        value.timestamp,
+        value.type,
+      )
+    : value;
+}
+
+/**
+ * Prepares an EnokiObject to be sent to the backend as json.
+ * Byte arrays are sent as plain arrays as json has no bytes, the type tag keeps them byte arrays.
+ */
+export function encodeEnokiObject(obj: EnokiObject): EnokiObject {
+  let wire: EnokiObject = new EnokiObject();
+  wire.timestamp = obj.timestamp;
+  obj.fields.forEach((field, index: number): void => {
+    if (!field) {
+      return;
+    }
+    wire.paths.set(field.key, wire.fields.length);
+    wire.fields.push(
+      new EnokiField(toWireValue(field.value), field.key, field.unit),
+    );
+    wire.history.push(obj.history[index]?.map(toWireValue));
+  });
+  return wire;
+}
+
+/**
+ * Turns whatever a command returned for the requested encoding into an EnokiObject
+ */
+export function decodeEnokiObject(payload: EncodedObject): EnokiObject {
+  if (!isBinaryPayload(payload)) {
+    return payload;
+  }
+  switch (payload.encoding) {
+    case "MessagePack":
+      return unpackObject(decode(base64ToBytes(payload.data)) as PackedObject);
+    default:
+      throw new Error(`Unsupported encoding ${payload.encoding}`);
+  }
+}
@@ -58,10 +58,12 @@ export class TimestampedEnokiValue<T extends EnokiValue> {
 export class EnokiField<T extends EnokiValue> {
   value: TimestampedEnokiValue<T>;
   key: string;
+  unit?: string;
 
-  constructor(value: TimestampedEnokiValue<T>, key: string) {
+  constructor(value: TimestampedEnokiValue<T>, key: string, unit?: string) {
     this.value = value;
     this.key = key;
+    this.unit = unit;
   }
 
   public getValue(): T {
@@ -78,6 +80,39 @@ export class EnokiField<T extends EnokiValue> {
   public getKey(): string {
     return this.key;
   }
+
+  /**
+   * @returns The unit symbol of the value, e.g. "m" or "rpm", if the source declared one.
+   */
+  public getUnit(): string | undefined {
+    return this.unit;
+  }
+}
+
+export type EnokiValueKind =
+  | "Numeric"
+  | "String"
+  | "Boolean"
+  | "Binary"
+  | "Array"
+  | "Single";
+
+/**
+ * Fields with a unit are converted to the units this system prefers, e.g. inches for "Imperial"
+ */
+export type UnitSystem = "Si" | "Metric" | "Imperial";
+
+/**
+ * Selects part of an EnokiObject, every filter that is set has to match.
+ * `pattern` is a glob where `*` matches one key segment and `**` any number of them.
+ */
+export interface EnokiQuery {
+  pattern?: string;
+  regex?: string;
+  kinds?: EnokiValueKind[];
+  after?: EnokiTimestamp;
+  before?: EnokiTimestamp;
+  unit_system?: UnitSystem;
 }
 
 export class EnokiObject {
@@ -1,5 +1,16 @@
 import { invoke } from "@tauri-apps/api/tauri";
-import { EnokiObject, EnokiValue, TimestampedEnokiValue } from "./EnokiTypes";
+import {
+  EnokiObject,
+  EnokiQuery,
+  EnokiValue,
+  TimestampedEnokiValue,
+} from "./EnokiTypes";
+import {
+  EncodedObject,
+  IpcEncoding,
+  decodeEnokiObject,
+  encodeEnokiObject,
+} from "./Encoding";
 
 export class NetworkTableClientId {
   ip: number[];
@@ -77,11 +88,28 @@ export class NetworkTableSubscription {
     this.cached_history = new EnokiObject();
   }
 
-  public async getSubbedData(): Promise<EnokiObject> {
-    return invoke("plugin:nt|get_subbed_data", {
-      clientId: this.clientId,
-      topic: this.topic,
-    });
+  public async getSubbedData(encoding?: IpcEncoding): Promise<EnokiObject> {
+    return decodeEnokiObject(
+      await invoke<EncodedObject>("plugin:nt|get_subbed_data", {
+        clientId: this.clientId,
+        topic: this.topic,
+        encoding: encoding,
+      }),
+    );
+  }
+
+  public async querySubbedData(
+    query: EnokiQuery,
+    encoding?: IpcEncoding,
+  ): Promise<EnokiObject> {
+    return decodeEnokiObject(
+      await invoke<EncodedObject>("plugin:nt|query_subbed_data", {
+        clientId: this.clientId,
+        topic: this.topic,
+        query: query,
+        encoding: encoding,
+      }),
+    );
   }
 
   public async getSubbedDataWithHistory(): Promise<EnokiObject> {
@@ -322,6 +350,17 @@ export class NetworkTableClient {
     });
   }
 
+  /**
+   * Publishes every field of an object, e.g. a table or snapshot read back from the backend
+   * @param obj the object to publish
+   */
+  public publishObject(obj: EnokiObject): Promise<void> {
+    return invoke("plugin:nt|publish_object", {
+      clientId: this.clientId,
+      obj: encodeEnokiObject(obj),
+    });
+  }
+
   public stop(): void {
     invoke("plugin:nt|stop_network_table_client", { clientId: this.clientId });
   }
@@ -0,0 +1,76 @@
+import { invoke } from "@tauri-apps/api/tauri";
+
+export interface ReplStatus {
+  /**
+   * False once the session was stopped or called `exit()`
+   */
+  running: boolean;
+  /**
+   * Running input or has input waiting to run
+   */
+  busy: boolean;
+  /**
+   * The last line started a block that isn't finished, the prompt should be `...`
+   */
+  more: boolean;
+}
+
+/**
+ * An interactive python session with `enoki_core`, `enoki` and the running
+ * network table clients bound as `clients` and `client`.
+ */
+export class Repl {
+  public id: number;
+
+  constructor(id: number) {
+    this.id = id;
+  }
+
+  /**
+   * Queues input, every line is run like at a python prompt so a block ends with an empty line
+   */
+  public async write(input: string): Promise<void> {
+    await invoke("plugin:scripting|write_repl", {
+      id: this.id,
+      input: input,
+    });
+  }
+
+  public async readOutRepl(): Promise<string> {
+    return invoke("plugin:scripting|read_out_repl", { id: this.id });
+  }
+
+  public async readErrRepl(): Promise<string> {
+    return invoke("plugin:scripting|read_err_repl", { id: this.id });
+  }
+
+  public async status(): Promise<ReplStatus> {
+    return invoke("plugin:scripting|repl_status", { id: this.id });
+  }
+
+  /**
+   * `line` completed with every name its last word could be
+   */
+  public async complete(line: string): Promise<string[]> {
+    return invoke("plugin:scripting|complete_repl", {
+      id: this.id,
+      line: line,
+    });
+  }
+
+  /**
+   * Like ctrl+c, interrupts what's running and drops queued input
+   */
+  public async interrupt(): Promise<void> {
+    await invoke("plugin:scripting|interrupt_repl", { id: this.id });
+  }
+
+  public async stop(): Promise<void> {
+    await invoke("plugin:scripting|stop_repl", { id: this.id });
+  }
+}
+
+export async function startRepl(): Promise<Repl> {
+  let id = await invoke<number>("plugin:scripting|start_repl");
+  return new Repl(id);
+}
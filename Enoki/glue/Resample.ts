@@ -0,0 +1,61 @@
+import { invoke } from "@tauri-apps/api/tauri";
+import {
+  EnokiObject,
+  EnokiTimestamp,
+  EnokiValue,
+  TimestampedEnokiValue,
+} from "./EnokiTypes";
+import { EncodedObject, IpcEncoding, decodeEnokiObject } from "./Encoding";
+
+export type Interpolation = "ZeroOrderHold" | "Linear" | "Nearest";
+
+/**
+ * "Daemon" is the datalog currently being recorded, `{ Datalog: path }` a wpilog on disk
+ */
+export type HistorySource = "Daemon" | { Datalog: string };
+
+export interface AlignedPair {
+  left: TimestampedEnokiValue<EnokiValue>;
+  right: TimestampedEnokiValue<EnokiValue>;
+}
+
+/**
+ * Resamples histories onto an evenly spaced grid, every field if no keys are given.
+ * Without a start or end the grid covers the selected histories, grids of more than a million points are rejected.
+ */
+export async function resampleHistories(
+  source: HistorySource,
+  period: EnokiTimestamp,
+  options?: {
+    keys?: string[];
+    start?: EnokiTimestamp;
+    end?: EnokiTimestamp;
+    interpolation?: Interpolation;
+    encoding?: IpcEncoding;
+  },
+): Promise<EnokiObject> {
+  return decodeEnokiObject(
+    await invoke<EncodedObject>("plugin:native|resample_histories", {
+      source: source,
+      period: period,
+      ...options,
+    }),
+  );
+}
+
+/**
+ * Pairs every sample of `left` with the closest sample of `right` at most `tolerance` away
+ */
+export async function alignHistories(
+  source: HistorySource,
+  left: string,
+  right: string,
+  tolerance: EnokiTimestamp,
+): Promise<AlignedPair[]> {
+  return invoke("plugin:native|align_histories", {
+    source: source,
+    left: left,
+    right: right,
+    tolerance: tolerance,
+  });
+}
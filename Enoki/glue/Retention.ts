@@ -0,0 +1,37 @@
+import { invoke } from "@tauri-apps/api/tauri";
+
+export interface RetentionPolicy {
+  max_total_bytes: number | null;
+  max_age_days: number | null;
+  keep_last: number | null;
+  compress: boolean;
+}
+
+export interface RetentionConfig {
+  datalogs: RetentionPolicy;
+  debug_logs: RetentionPolicy;
+  /** Paths relative to the documents folder, e.g. "Enoki/Datalogs/2023-09-01_10-00-00.wpilog" */
+  favorites: string[];
+}
+
+export async function getRetentionConfig(): Promise<RetentionConfig> {
+  return invoke("plugin:native|get_retention_config");
+}
+
+/**
+ * Saves the config and immediately enforces it on both log directories,
+ * the files Enoki is currently writing are left alone.
+ */
+export async function setRetentionConfig(config: RetentionConfig): Promise<void> {
+  return invoke("plugin:native|set_retention_config", { config: config });
+}
+
+export async function setLogFavorite(
+  file: string,
+  favorite: boolean,
+): Promise<void> {
+  return invoke("plugin:native|set_log_favorite", {
+    file: file,
+    favorite: favorite,
+  });
+}
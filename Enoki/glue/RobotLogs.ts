@@ -0,0 +1,78 @@
+import { invoke } from "@tauri-apps/api/tauri";
+
+export interface RemoteTarget {
+  host: string;
+  port: number;
+  username: string;
+  password: string;
+  log_directories: string[];
+  extensions: string[];
+}
+
+export interface RemoteLogFile {
+  path: string;
+  name: string;
+  size: number;
+  /** Seconds since the unix epoch */
+  modified: number;
+}
+
+export interface PulledLog {
+  remote: RemoteLogFile;
+  local_path: string;
+  deleted_remote: boolean;
+}
+
+export interface FailedPull {
+  remote: RemoteLogFile;
+  error: string;
+}
+
+/**
+ * A file that failed, e.g. the log the robot is still writing to, doesn't stop the others
+ */
+export interface PullResult {
+  pulled: PulledLog[];
+  failed: FailedPull[];
+}
+
+export async function roborioLogTarget(team: number): Promise<RemoteTarget> {
+  return invoke("plugin:native|roborio_log_target", { team: team });
+}
+
+export async function coprocessorLogTarget(
+  host: string,
+  username: string,
+  password: string,
+): Promise<RemoteTarget> {
+  return invoke("plugin:native|coprocessor_log_target", {
+    host: host,
+    username: username,
+    password: password,
+  });
+}
+
+export async function listRobotLogs(
+  target: RemoteTarget,
+): Promise<RemoteLogFile[]> {
+  return invoke("plugin:native|list_robot_logs", { target: target });
+}
+
+/**
+ * Downloads logs into Documents/Enoki/Datalogs/<robot>, under their remote paths.
+ * @param selected Remote paths to pull, every log not pulled since the last sync if omitted.
+ * @param deleteRemote Delete the remote copy after the download is verified.
+ */
+export async function pullRobotLogs(
+  target: RemoteTarget,
+  robot: string,
+  selected?: string[],
+  deleteRemote: boolean = false,
+): Promise<PullResult> {
+  return invoke("plugin:native|pull_robot_logs", {
+    target: target,
+    robot: robot,
+    selected: selected,
+    deleteRemote: deleteRemote,
+  });
+}
@@ -0,0 +1,184 @@
+import { invoke } from "@tauri-apps/api/tauri";
+import { EnokiObject, EnokiTimestamp } from "./EnokiTypes";
+import { EncodedObject, IpcEncoding, decodeEnokiObject } from "./Encoding";
+
+/**
+ * `error` is the python traceback, `restarts` counts the restarts in a row
+ */
+export type ScriptState =
+  | { state: "Stopped" }
+  | { state: "Running" }
+  | { state: "Restarting"; error: string; restarts: number }
+  | { state: "Failed"; error: string };
+
+/**
+ * `OnFailure` waits `delay_ms` before the first restart and twice as long before each one after it,
+ * a run that lasts a minute resets the count
+ */
+export type RestartPolicy =
+  | { policy: "Never" }
+  | { policy: "OnFailure"; max_restarts: number; delay_ms: number };
+
+export interface ScriptOptions {
+  /** How long one hook may run before a KeyboardInterrupt is raised in it, 2000 by default */
+  timeout_ms?: number;
+  /** Up to 3 restarts starting at 1s by default */
+  restart?: RestartPolicy;
+}
+
+export type ScriptStatus = ScriptState & {
+  name: string;
+  path: string;
+  /** When the script was last started, null if it never ran */
+  started: EnokiTimestamp | null;
+  options: Required<ScriptOptions> | null;
+};
+
+/**
+ * Every script in `Documents/Enoki/Scripts` and every script that has run this session.
+ * Files starting with `_` are helpers for scripts to import and aren't listed.
+ */
+export async function listScripts(): Promise<ScriptStatus[]> {
+  return invoke("plugin:scripting|list_scripts");
+}
+
+/**
+ * Loads the script and calls its `on_start`, then `on_tick` every 20ms until it is stopped.
+ */
+export async function startScript(
+  name: string,
+  options?: ScriptOptions,
+): Promise<void> {
+  return invoke("plugin:scripting|start_script", {
+    name: name,
+    options: options,
+  });
+}
+
+/**
+ * Resolves once the script was asked to stop, it may still be running its `on_stop` hook.
+ * A hook that doesn't return within half a second gets interrupted.
+ */
+export async function stopScript(name: string): Promise<void> {
+  return invoke("plugin:scripting|stop_script", { name: name });
+}
+
+export async function scriptStatus(name: string): Promise<ScriptStatus> {
+  return invoke("plugin:scripting|script_status", { name: name });
+}
+
+export async function scriptsDirectory(): Promise<string> {
+  return invoke("plugin:scripting|scripts_directory");
+}
+
+export type PluginPermission = "nt_publish" | "filesystem" | "terminal";
+
+/**
+ * The `plugin.json` in a plugin's folder, `entry` defaults to `main.py`
+ */
+export interface PluginManifest {
+  name: string;
+  version: string;
+  entry: string;
+  api_version: number;
+  permissions: PluginPermission[];
+}
+
+/**
+ * A folder that can't be loaded as a plugin is `Failed` with the reason and no manifest
+ */
+export type PluginStatus = ScriptState & {
+  name: string;
+  dir: string;
+  manifest: PluginManifest | null;
+  started: EnokiTimestamp | null;
+};
+
+/**
+ * Every folder in `Documents/Enoki/Plugins`, plugins are started with the app
+ * and reloaded when their files change.
+ */
+export async function listPlugins(): Promise<PluginStatus[]> {
+  return invoke("plugin:scripting|list_plugins");
+}
+
+export async function startPlugin(name: string): Promise<void> {
+  return invoke("plugin:scripting|start_plugin", { name: name });
+}
+
+/**
+ * The plugin stays stopped until it's started again, even if its files change.
+ */
+export async function stopPlugin(name: string): Promise<void> {
+  return invoke("plugin:scripting|stop_plugin", { name: name });
+}
+
+export async function reloadPlugin(name: string): Promise<void> {
+  return invoke("plugin:scripting|reload_plugin", { name: name });
+}
+
+export async function pluginsDirectory(): Promise<string> {
+  return invoke("plugin:scripting|plugins_directory");
+}
+
+/**
+ * A command a script registered, `schema` is a JSON schema for its arguments
+ */
+export interface ScriptCommand {
+  name: string;
+  script: string;
+  description: string;
+  schema: Record<string, unknown>;
+}
+
+/**
+ * A data provider a script registered, every field it returns is under `prefix`
+ */
+export interface ScriptProvider {
+  name: string;
+  script: string;
+  description: string;
+  prefix: string;
+}
+
+export interface ScriptRegistry {
+  commands: ScriptCommand[];
+  providers: ScriptProvider[];
+}
+
+/**
+ * The commands and data providers of running scripts, they're removed when their script stops.
+ */
+export async function listScriptRegistry(): Promise<ScriptRegistry> {
+  return invoke("plugin:scripting|list_script_registry");
+}
+
+/**
+ * Calls `callback(**args)` on the script's worker and resolves to what it returned.
+ * Rejects if the arguments don't match the command's schema, the command raises
+ * or it doesn't return within 10 seconds.
+ */
+export async function callScriptCommand(
+  name: string,
+  args?: Record<string, unknown>,
+): Promise<unknown> {
+  return invoke("plugin:scripting|call_script_command", {
+    name: name,
+    args: args,
+  });
+}
+
+/**
+ * Calls the provider on its script's worker, its fields are under the provider's prefix.
+ */
+export async function readDataProvider(
+  name: string,
+  encoding?: IpcEncoding,
+): Promise<EnokiObject> {
+  return decodeEnokiObject(
+    await invoke<EncodedObject>("plugin:scripting|read_data_provider", {
+      name: name,
+      encoding: encoding,
+    }),
+  );
+}
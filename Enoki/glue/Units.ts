@@ -0,0 +1,19 @@
+import { invoke } from "@tauri-apps/api/tauri";
+
+export type { UnitSystem } from "./EnokiTypes";
+
+/**
+ * Converts a value between two units, e.g. `convertUnit(1, "m", "in")`.
+ * Rejects if the units measure different things.
+ */
+export async function convertUnit(
+  value: number,
+  from: string,
+  to: string,
+): Promise<number> {
+  return invoke("plugin:native|convert_unit", {
+    value: value,
+    from: from,
+    to: to,
+  });
+}
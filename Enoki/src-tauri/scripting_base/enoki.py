@@ -1,19 +1,109 @@
-from typing import Generic, TypeVar
-from typing_extensions import override
-from enoki_core import NetworkTable as __NetworkTable
+import collections.abc
+import inspect
+from typing import Any, Callable, Generic, List, Optional, Sequence, TypeVar, get_args, get_origin
+
+import enoki_core
+from enoki_core import NetworkTable as _NetworkTable
 
 T = TypeVar('T')
+F = TypeVar('F', bound=Callable[..., Any])
+
+_VALUE_TYPES = {bool: "Boolean", int: "Int", float: "Double", str: "String", bytes: "ByteArray"}
+_ARRAY_TYPES = {bool: "BooleanArray", int: "IntArray", float: "DoubleArray", str: "StringArray"}
+_SEQUENCES = (list, List, Sequence, collections.abc.Sequence)
+
+
+def _value_type(value_type: Any) -> Optional[str]:
+    """The enoki_core type name of a python type like `float` or `List[int]`, None if there's none"""
+    if value_type in _VALUE_TYPES:
+        return _VALUE_TYPES[value_type]
+    args = get_args(value_type)
+    if get_origin(value_type) in _SEQUENCES and len(args) == 1:
+        return _ARRAY_TYPES.get(args[0])
+    return None
+
+
+def _is_instance(value: Any, value_type: Any) -> bool:
+    """isinstance that understands `List[float]` and doesn't take bools for numbers"""
+    if isinstance(value, enoki_core.TypedValue):
+        return True
+    if value_type is float:
+        return isinstance(value, (int, float)) and not isinstance(value, bool)
+    if value_type is int:
+        return isinstance(value, int) and not isinstance(value, bool)
+    if value_type is bytes:
+        return isinstance(value, (bytes, bytearray))
+    args = get_args(value_type)
+    if get_origin(value_type) in _SEQUENCES and len(args) == 1:
+        # ndarrays are checked by enoki_core when they're converted
+        if hasattr(value, "dtype"):
+            return True
+        return isinstance(value, (list, tuple)) and all(_is_instance(v, args[0]) for v in value)
+    if isinstance(value_type, type):
+        return isinstance(value, value_type)
+    return True
+
 
 class NetworkTable:
-    class Topic(__NetworkTable.NetworkTablePubbedTopic, Generic[T]):
-        def __init__(self, topic: str, type_name: str, client_id: __NetworkTable.NetworkTableClientId) -> None:
-            super().__init__(topic, type_name, client_id)
+    class Topic(Generic[T]):
+        """
+        A published topic that only takes values of type T, e.g. `NetworkTable.Topic[float]`.
+        The topic's type is worked out from T unless `type_name` is given.
+        """
+
+        def __init__(self, topic: str, client_id: _NetworkTable.NetworkTableClientId,
+                     type_name: Optional[str] = None) -> None:
+            self._topic = topic
+            self._client_id = client_id
+            self._type_name = type_name
+            self._inner: Optional[_NetworkTable.NetworkTablePubbedTopic] = None
+
+        @property
+        def value_type(self) -> Any:
+            """T, only known after the topic was created as `Topic[T](...)`"""
+            args = get_args(getattr(self, "__orig_class__", None))
+            return args[0] if args else Any
+
+        def _published(self) -> _NetworkTable.NetworkTablePubbedTopic:
+            # T is set on the instance after __init__ returns, so the topic is made on first use
+            if self._inner is None:
+                type_name = self._type_name or _value_type(self.value_type)
+                if type_name is None:
+                    raise TypeError(f"Can't tell the type of {self._topic} from {self.value_type}, pass type_name")
+                self._inner = _NetworkTable.NetworkTablePubbedTopic(self._topic, type_name, self._client_id)
+            return self._inner
+
+        def topic_name(self) -> str:
+            return self._topic
+
+        def topic_type(self) -> str:
+            return self._published().topic_type()
+
+        def set_value(self, value: T, timestamp: Optional[int] = None) -> None:
+            if not _is_instance(value, self.value_type):
+                raise TypeError(f"{self._topic} takes {self.value_type}, got {type(value).__name__}")
+            self._published().set_value(value, timestamp)
+
+        def unpublish(self) -> None:
+            self._published().unpublish()
+
+    Subscription = _NetworkTable.NetworkTableSubscription
+
 
-        @override()
-        def set_value(self, value: T) -> None:
-            super().set_value(value)
+def command(name: Optional[str] = None, schema: Optional[dict] = None) -> Callable[[F], F]:
+    """
+    Registers the decorated function as a command the frontend can call,
+    named after the function and described by its docstring unless given
+    """
+    def register(callback: F) -> F:
+        enoki_core.Scripting.register_command(name or callback.__name__, callback, schema, inspect.getdoc(callback))
+        return callback
+    return register
 
-    class Subscription(__NetworkTable.NetworkTableSubscription):
-        def __init__(self) -> None:
-            super().__init__()
 
+def provider(name: Optional[str] = None) -> Callable[[F], F]:
+    """Registers the decorated function as a data provider, see `command`"""
+    def register(callback: F) -> F:
+        enoki_core.Scripting.register_provider(name or callback.__name__, callback, inspect.getdoc(callback))
+        return callback
+    return register
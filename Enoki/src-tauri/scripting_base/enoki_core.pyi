@@ -1,8 +1,43 @@
 
-from typing import List, Tuple, TypeAlias, Union
+from typing import Any, AsyncIterator, Awaitable, Callable, Dict, List, Literal, Optional, Tuple, TypeAlias, Union
 
+import numpy
+import pandas
 
-EnokiValue: TypeAlias = Union[bool, int, float, str, List[bool], List[int], List[float], List[str], bytes]
+
+EnokiValue: TypeAlias = Union[bool, int, float, str, List[bool], List[int], List[float], List[str], bytes, numpy.ndarray, TypedValue]
+"""
+Numeric arrays are ndarrays after `use_numpy()`, ndarrays are always accepted as values.
+Plain python values get the narrowest type that fits, `1` is an Int and `[1.0]` a DoubleArray,
+wrap them in a constructor like `Double(1)` to pick the type yourself.
+"""
+
+ValueType: TypeAlias = Literal[
+    "ByteArray", "Protobuf", "Float", "FloatArray", "Double", "DoubleArray",
+    "Int", "IntArray", "String", "StringArray", "Boolean", "BooleanArray",
+]
+
+class TypedValue:
+    """A value with its type spelled out, made with one of the constructors below"""
+    value: EnokiValue
+    type: ValueType
+
+# Each raises TypeError if the value can't be converted without losing anything,
+# ints widen to doubles and int lists to byte arrays but `Int(1.5)` fails
+def Boolean(value: bool) -> TypedValue: ...
+def Int(value: int) -> TypedValue: ...
+def Float(value: float) -> TypedValue: ...
+def Double(value: float) -> TypedValue: ...
+def String(value: str) -> TypedValue: ...
+def BooleanArray(value: List[bool]) -> TypedValue: ...
+def IntArray(value: List[int]) -> TypedValue: ...
+def FloatArray(value: List[float]) -> TypedValue: ...
+def DoubleArray(value: List[float]) -> TypedValue: ...
+def StringArray(value: List[str]) -> TypedValue: ...
+def ByteArray(value: bytes) -> TypedValue: ...
+def Raw(value: bytes) -> TypedValue:
+    """The same as ByteArray, what NetworkTables calls it"""
+def Protobuf(value: bytes) -> TypedValue: ...
 
 """ A timestamp in microseconds since the epoch. """
 EnokiTimestamp = int
@@ -19,8 +54,15 @@ class EnokiField:
     value: EnokiValue
     timestamp: int
     key: str
+    unit: Optional[str]
 
-    def __init__(self, value: EnokiValue, timestamp: int, key: str) -> None: ...
+    def __init__(self, value: EnokiValue, timestamp: int, key: str, unit: Optional[str] = None) -> None: ...
+
+    def convert_to(self, unit: str) -> EnokiField:
+        """The field converted to another unit, raises ValueError if the units measure different things"""
+        ...
+
+UnitSystem: TypeAlias = Literal["Si", "Metric", "Imperial"]
 
 class EnokiObject:
     timestamp: int
@@ -33,23 +75,73 @@ class EnokiObject:
 
     def field_keys(self) -> List[str]: ...
 
+    def select(self, pattern: str) -> EnokiObject: ...
+
+    def select_regex(self, regex: str) -> EnokiObject: ...
+
+    def numeric_fields(self) -> EnokiObject: ...
+
+    def array_fields(self) -> EnokiObject: ...
+
+    def changed_since(self, after: int) -> EnokiObject: ...
+
+    def changed_between(self, after: Optional[int], before: Optional[int]) -> EnokiObject: ...
+
+    def query(
+        self,
+        pattern: Optional[str] = None,
+        regex: Optional[str] = None,
+        kinds: Optional[List[str]] = None,
+        after: Optional[int] = None,
+        before: Optional[int] = None,
+        unit_system: Optional[UnitSystem] = None,
+    ) -> EnokiObject: ...
+
+    def convert_units(self, system: UnitSystem) -> EnokiObject: ...
+
+    def history_arrays(self, key: str) -> Tuple[numpy.ndarray, numpy.ndarray]:
+        """The timestamps and values of a field's history as arrays, raises ImportError without numpy"""
+
+    def to_pandas(self) -> pandas.DataFrame:
+        """A DataFrame indexed by timestamp with a column per field, raises ImportError without pandas"""
+
+API_VERSION: int
+"""The version plugins ask for with `api_version` in their manifest"""
+
 def now() -> int: ...
 
+def use_numpy(enabled: bool = True) -> None:
+    """
+    Makes numeric array values ndarrays instead of lists for the calling script until it stops,
+    other scripts and plugins keep getting lists. Raises ImportError if numpy isn't installed.
+    ndarrays are accepted anywhere a value is, whether or not this was called.
+    """
+
 class NetworkTable:
     class NetworkTableClientId:
         ip: Tuple(int, int, int, int)
         port: int
         identity: str
 
+        def is_connected(self) -> bool: ...
+
+        def wait_connected(self) -> Awaitable[None]:
+            """Resolves once the client is connected, raises RuntimeError if it's stopped first"""
+
 
     class NetworkTablePubbedTopic:
-        def __init__(self, topic: str, type_name: str, client_id: NetworkTable.NetworkTableClientId) -> None: ...
+        def __init__(self, topic: str, type_name: ValueType, client_id: NetworkTable.NetworkTableClientId) -> None:
+            """Raises ValueError for an unknown type"""
 
         def topic_name(self) -> str: ...
 
         def topic_type(self) -> str: ...
 
-        def set_value(self, value: EnokiValue) -> None: ...
+        def set_value(self, value: EnokiValue, timestamp: Optional[int] = None) -> None:
+            """
+            Converts the value to the topic's type like the constructors do, TypeError if it can't.
+            Plugins need the `nt_publish` permission, PermissionError otherwise.
+            """
 
         def unpublish(self) -> None: ...
 
@@ -61,7 +153,208 @@ class NetworkTable:
 
         def get_subbed_data_with_history(self, after: int) -> EnokiObject: ...
 
+        def on_update(self, callback: Callable[[EnokiObject], None]) -> None:
+            """
+            Calls `callback` with the fields that changed, each with every value received since the last call in its history.
+            Callbacks run on the script's loop between ticks, under its timeout, and keep the script running until it's stopped.
+            A slow callback gets bigger batches and only the latest values once it falls far behind.
+            """
+
+        def on_field(self, key: str, callback: Callable[[EnokiField], None]) -> None:
+            """Calls `callback` for every value received for the topic `key`, in order"""
+
+        def on_announce(self, callback: Callable[[str, str], None]) -> None:
+            """Calls `callback(topic, type)` for every topic announced under the subscription, including earlier ones"""
+
+        def next_update(self) -> Awaitable[EnokiObject]:
+            """
+            Resolves to the fields that changed the next time values are received, like `on_update`.
+            Raises RuntimeError once the subscription is closed, must be awaited on the script's loop.
+            """
+
+        def __aiter__(self) -> AsyncIterator[EnokiObject]:
+            """`async for update in subscription` gets every update until the subscription is closed"""
+
+        async def __anext__(self) -> EnokiObject: ...
+
+        def close(self) -> None:
+            """Stops the callbacks and ends what awaits updates, they also stop when the script that registered them stops"""
+
     def start_network_table_client(
         ip: Tuple(int, int, int, int),
         port: int,
-        identity: str): "NetworkTableClientId"
\ No newline at end of file
+        identity: str): "NetworkTableClientId"
+
+class Datalog:
+    class SessionMetadata:
+        """Describes what a datalog was recording, e.g. qual 42 at a district event"""
+        event: str
+        match_type: Optional[Literal["Practice", "Qualification", "Elimination"]]
+        match_number: Optional[int]
+        alliance: Optional[Literal["Red", "Blue"]]
+        robot: str
+        code_version: str
+        notes: str
+
+        def __init__(
+            self,
+            event: str = "",
+            match_type: Optional[str] = None,
+            match_number: Optional[int] = None,
+            alliance: Optional[str] = None,
+            robot: str = "",
+            code_version: str = "",
+            notes: str = "") -> None: ...
+
+    def get_session_metadata() -> Datalog.SessionMetadata: ...
+
+    def set_session_metadata(metadata: Datalog.SessionMetadata) -> None:
+        """Sets the metadata for the current session and records it in the running datalog,
+        plugins need the `filesystem` permission, PermissionError otherwise"""
+
+    def read_session_metadata(path: str) -> Optional[Datalog.SessionMetadata]:
+        """Plugins need the `filesystem` permission, PermissionError otherwise"""
+
+    class DatalogEntry:
+        name: str
+        type: str
+        """The entry's metadata as written, usually json"""
+        metadata: str
+        unit: Optional[str]
+        """How many values were logged"""
+        count: int
+        first: Optional[EnokiTimestamp]
+        last: Optional[EnokiTimestamp]
+
+    class DatalogReader:
+        """A wpilog read from disk, everything is loaded up front so reading it again is free"""
+        path: str
+
+        def entries(self) -> List[Datalog.DatalogEntry]:
+            """Every entry in the order they were started"""
+
+        def entry_names(self) -> List[str]: ...
+
+        def history(
+            self,
+            key: str,
+            start: Optional[EnokiTimestamp] = None,
+            end: Optional[EnokiTimestamp] = None) -> List[TimestampedEnokiValue]:
+            """The values of an entry logged within [start, end], raises KeyError for an unknown entry"""
+
+        def object(self) -> EnokiObject:
+            """Every entry as a field with its full history, use `EnokiObject.query` to narrow it down"""
+
+        def session_metadata(self) -> Optional[Datalog.SessionMetadata]: ...
+
+    class DatalogWriter:
+        """Writes a new wpilog, usable as a context manager that closes it"""
+        path: str
+
+        def start_entry(self, name: str, entry_type: str, metadata: Optional[str] = None) -> None: ...
+
+        def append(self, name: str, value: EnokiValue, timestamp: Optional[EnokiTimestamp] = None) -> None:
+            """Appends a value at `timestamp` or now, an entry that wasn't started yet is started with the value's type"""
+
+        def finish_entry(self, name: str) -> None: ...
+
+        def close(self) -> None:
+            """Finishes writing the file, the writer can't be used after"""
+
+        def __enter__(self) -> Datalog.DatalogWriter: ...
+
+        def __exit__(self, exc_type, exc_value, traceback) -> None: ...
+
+    def open(path: str) -> Datalog.DatalogReader:
+        """Reads a wpilog and the units its entries declare, plugins need the `filesystem` permission"""
+
+    def open_async(path: str) -> Awaitable[Datalog.DatalogReader]:
+        """Like `open` but reads the wpilog on its own thread, so the script's other tasks keep running"""
+
+    def create(path: str, metadata: Optional[Datalog.SessionMetadata] = None) -> Datalog.DatalogWriter:
+        """Creates a new wpilog, with the current session in its header unless metadata is given, plugins need the `filesystem` permission"""
+
+class Resample:
+    Interpolation: TypeAlias = Literal["ZeroOrderHold", "Linear", "Nearest"]
+
+    def resample(
+        history: List[TimestampedEnokiValue],
+        period: EnokiTimestamp,
+        start: Optional[EnokiTimestamp] = None,
+        end: Optional[EnokiTimestamp] = None,
+        interpolation: Optional[Resample.Interpolation] = None) -> List[TimestampedEnokiValue]:
+        """Resamples a history onto an evenly spaced grid, without a start or end the grid covers the history"""
+
+    def resample_object(
+        obj: EnokiObject,
+        period: EnokiTimestamp,
+        keys: Optional[List[str]] = None,
+        start: Optional[EnokiTimestamp] = None,
+        end: Optional[EnokiTimestamp] = None,
+        interpolation: Optional[Resample.Interpolation] = None) -> EnokiObject:
+        """Resamples the histories of an object onto one grid, every field if no keys are given"""
+
+    def align(
+        left: List[TimestampedEnokiValue],
+        right: List[TimestampedEnokiValue],
+        tolerance: EnokiTimestamp) -> List[Tuple[TimestampedEnokiValue, TimestampedEnokiValue]]:
+        """Pairs every left sample with the closest right sample at most `tolerance` microseconds away"""
+
+class Derived:
+    """
+    Fields computed from other fields, keys are wrapped in braces e.g. `abs({/Drive/Left/speed} - {/Drive/Right/speed})`.
+    Supports + - * / %, comparisons, && || !, abs, min, max, derivative, integral, average(x, n) and array indexing.
+    """
+
+    def define_derived_field(key: str, expression: str) -> None:
+        """Defines a field that is evaluated live over network table subscriptions, raises ValueError on a bad expression"""
+
+    def remove_derived_field(key: str) -> bool: ...
+
+    def evaluate(obj: EnokiObject, expression: str) -> List[TimestampedEnokiValue]:
+        """Evaluates an expression at every timestamp its inputs changed at in the object's histories"""
+
+class Scripting:
+    """
+    Commands and data providers the frontend can call, e.g. from a dashboard button.
+    They're called on the script's worker between ticks and are removed when the script stops,
+    a script that registered any keeps running until it's stopped. Callbacks can be `async def`.
+    Names are shared by all scripts, registering a name another script has raises ValueError.
+    """
+
+    def register_command(
+        name: str,
+        callback: Callable[..., Any],
+        schema: Optional[Dict[str, Any]] = None,
+        description: Optional[str] = None,
+    ) -> None:
+        """
+        The frontend calls `callback(**args)` and gets back what it returns, which has to work with `json.dumps`.
+        `schema` is a JSON schema for `args`, its `type`, `enum`, `required`, `properties` and `items` are checked.
+        """
+
+    def register_provider(
+        name: str,
+        callback: Callable[[], Union[EnokiObject, Dict[str, EnokiValue]]],
+        description: Optional[str] = None,
+    ) -> None:
+        """
+        The frontend reads what `callback()` returns, its keys are put under `/Scripts/<script>/<name>`.
+        Values in a dict are timestamped with the time they were returned.
+        """
+
+    def unregister(name: str) -> bool:
+        """Removes a command or provider this script registered, False if it had none by that name"""
+
+class logging:
+    """Logs to the app's log with the calling file and line"""
+
+    def trace(msg: str) -> None: ...
+
+    def debug(msg: str) -> None: ...
+
+    def info(msg: str) -> None: ...
+
+    def warn(msg: str) -> None: ...
+
+    def error(msg: str) -> None: ...
@@ -0,0 +1,116 @@
+"""The console behind Enoki's python REPL, each session runs one on its own thread"""
+import ast
+import asyncio
+import code
+import re
+import rlcompleter
+import sys
+import threading
+import types
+from typing import Any, Dict, List, Optional
+
+import enoki_core
+
+_TOKEN = re.compile(r"[\w.]*$")
+
+
+class _ThreadStream:
+    """
+    Stands in for sys.stdout or sys.stderr, a thread that set a target writes to it
+    and every other thread to the stream that was there before
+    """
+
+    def __init__(self, fallback: Any) -> None:
+        self.fallback = fallback
+        self.local = threading.local()
+
+    def _target(self) -> Any:
+        return getattr(self.local, "target", None) or self.fallback
+
+    def write(self, text: str) -> int:
+        target = self._target()
+        # there's no console to write to when enoki isn't started from a terminal
+        if target is None:
+            return len(text)
+        return target.write(text)
+
+    def flush(self) -> None:
+        target = self._target()
+        if target is not None:
+            target.flush()
+
+    def __getattr__(self, name: str) -> Any:
+        return getattr(self.fallback, name)
+
+
+stdout = _ThreadStream(sys.stdout)
+stderr = _ThreadStream(sys.stderr)
+sys.stdout = stdout
+sys.stderr = stderr
+
+
+def _namespace(clients: Dict[str, Any]) -> Dict[str, Any]:
+    namespace = {
+        "__name__": "__console__",
+        "__doc__": None,
+        "enoki_core": enoki_core,
+        "NetworkTable": enoki_core.NetworkTable,
+        "clients": clients,
+        "client": next(iter(clients.values()), None),
+    }
+    try:
+        import enoki
+        namespace["enoki"] = enoki
+    except ImportError:
+        pass
+    return namespace
+
+
+class Console(code.InteractiveConsole):
+    """An interactive console with top level `await`, run on the session's own loop"""
+
+    def __init__(self, clients: Dict[str, Any], filename: str) -> None:
+        super().__init__(_namespace(clients), filename)
+        self.compile.compiler.flags |= ast.PyCF_ALLOW_TOP_LEVEL_AWAIT
+        self.loop = asyncio.new_event_loop()
+        self.completer = rlcompleter.Completer(self.locals)
+
+    def attach(self, out: Any, err: Any) -> None:
+        """Sends what the calling thread prints to `out` and `err`, called on the session's thread"""
+        stdout.local.target = out
+        stderr.local.target = err
+        asyncio.set_event_loop(self.loop)
+        bound = ", ".join(name for name in self.locals if not name.startswith("__"))
+        print(f"Python {sys.version} on Enoki\nBound: {bound}")
+
+    def runcode(self, code_object: types.CodeType) -> None:
+        try:
+            result = types.FunctionType(code_object, self.locals)()
+            if asyncio.iscoroutine(result):
+                self.loop.run_until_complete(result)
+        except SystemExit:
+            raise
+        except BaseException:
+            self.showtraceback()
+
+    def complete(self, line: str) -> List[str]:
+        """`line` with the name it ends with completed, once for every name that fits"""
+        token = _TOKEN.search(line).group()
+        if not token:
+            return []
+        start = line[:len(line) - len(token)]
+        completions: List[str] = []
+        while True:
+            completion: Optional[str] = self.completer.complete(token, len(completions))
+            if completion is None:
+                return [start + completion for completion in completions]
+            completions.append(completion)
+
+    def close(self) -> None:
+        tasks = asyncio.all_tasks(self.loop)
+        for task in tasks:
+            task.cancel()
+        self.loop.run_until_complete(asyncio.gather(*tasks, return_exceptions=True))
+        self.loop.close()
+        stdout.local.target = None
+        stderr.local.target = None
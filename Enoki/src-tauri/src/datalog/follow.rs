@@ -0,0 +1,564 @@
+//! Follow mode for wpilogs that are still being written, e.g. by a robot sim or a script.
+//!
+//! Records are decoded here rather than by the `wpilog` crate. The crate keeps its record parser
+//! private and only offers `DataLog::open`, which reads and parses a whole file in one go.
+//! It can't resume from an offset or hold on to a record that is only partly flushed yet.
+//!
+//! Changes to the file come from `tauri-plugin-fs-watch`. The plugin only reports them to the
+//! webview, so the frontend's `followDatalog` watches the file and forwards every change with
+//! `datalog_follow_changed`. The file is polled as well, in case no change is reported.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::{
+    enoki_types::{EnokiField, EnokiKey, EnokiTimeStamp, EnokiValue, TimestampedEnokiValue},
+    error::EnokiError,
+    units::Unit,
+};
+
+pub type FollowId = usize;
+
+/// How long to wait for a change to be reported before checking the file anyway,
+/// some platforms coalesce or drop modify events on files held open by another process
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// If the file hasn't grown in this long the writer is considered finished
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static FOLLOWERS: Lazy<Mutex<HashMap<FollowId, DatalogFollowHandle>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_FOLLOW_ID: Lazy<Mutex<FollowId>> = Lazy::new(|| Mutex::new(0));
+
+#[derive(Debug, Clone)]
+struct FollowedEntry {
+    name: String,
+    entry_type: String,
+    unit: Option<Unit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FollowFinishReason {
+    /// Every entry that was started has since been finished by the writer
+    AllEntriesFinished,
+    /// The file has not grown within the idle timeout
+    Idle,
+    /// The file was removed
+    Removed,
+    /// The file could not be read anymore, e.g. it was truncated or isn't a wpilog
+    Unreadable,
+    /// The follow was stopped from our side
+    Stopped,
+}
+
+/// Incrementally reads records from a wpilog that is still being written.
+///
+/// Bytes are only consumed once a whole record is available,
+/// a partially flushed record at the end of the file is kept around until the rest arrives.
+#[derive(Debug)]
+pub struct WpilogFollower {
+    file: File,
+    offset: u64,
+    buffer: Vec<u8>,
+    header_read: bool,
+    metadata: String,
+    entries: HashMap<u32, FollowedEntry>,
+    started_any: bool,
+}
+
+impl WpilogFollower {
+    pub fn open(path: &PathBuf) -> Result<Self, EnokiError> {
+        let file = File::open(path)?;
+        Ok(Self {
+            file,
+            offset: 0,
+            buffer: Vec::new(),
+            header_read: false,
+            metadata: String::new(),
+            entries: HashMap::new(),
+            started_any: false,
+        })
+    }
+
+    /// The extra header string of the log, empty until the header has been read
+    pub fn metadata(&self) -> &str {
+        &self.metadata
+    }
+
+    /// How many bytes of the file have been read so far, including a record that's only partly there
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// True once the writer has started entries and finished every one of them
+    pub fn all_entries_finished(&self) -> bool {
+        self.started_any && self.entries.is_empty()
+    }
+
+    /// Reads whatever was appended since the last call and decodes every complete record in it
+    pub fn poll(&mut self) -> Result<Vec<EnokiField>, EnokiError> {
+        let len = self.file.metadata()?.len();
+        if len < self.offset {
+            return Err(EnokiError::DatalogIo(
+                "Datalog was truncated while being followed".to_string(),
+            ));
+        }
+        if len > self.offset {
+            self.file.seek(SeekFrom::Start(self.offset))?;
+            let mut new_bytes = Vec::with_capacity((len - self.offset) as usize);
+            (&mut self.file)
+                .take(len - self.offset)
+                .read_to_end(&mut new_bytes)?;
+            self.offset += new_bytes.len() as u64;
+            self.buffer.extend_from_slice(&new_bytes);
+        }
+
+        let mut fields = Vec::new();
+        let mut consumed = 0;
+
+        if !self.header_read {
+            match parse_header(&self.buffer)? {
+                Some((metadata, header_len)) => {
+                    self.metadata = metadata;
+                    self.header_read = true;
+                    consumed = header_len;
+                }
+                None => return Ok(fields),
+            }
+        }
+
+        // records borrow from the buffer while handling them needs the entry table
+        let mut buffer = std::mem::take(&mut self.buffer);
+        while let Some(record) = parse_record(&buffer[consumed..]) {
+            consumed += record.len;
+            if let Some(field) = self.handle_record(record) {
+                fields.push(field);
+            }
+        }
+
+        buffer.drain(..consumed);
+        self.buffer = buffer;
+        Ok(fields)
+    }
+
+    fn handle_record(&mut self, record: RawRecord) -> Option<EnokiField> {
+        if record.entry == 0 {
+            self.handle_control(record.payload);
+            return None;
+        }
+        let entry = self.entries.get(&record.entry)?;
+        match decode_payload(&entry.entry_type, record.payload) {
+            Some(value) => Some(
+                EnokiField::new(
+                    EnokiKey::from_topic_name(&entry.name),
+                    TimestampedEnokiValue::new(record.timestamp, value),
+                )
+                .with_unit(entry.unit.clone()),
+            ),
+            None => {
+                tracing::warn!(
+                    "Skipping malformed {} record for {}",
+                    entry.entry_type,
+                    entry.name
+                );
+                None
+            }
+        }
+    }
+
+    fn handle_control(&mut self, payload: &[u8]) {
+        let mut cursor = ByteCursor::new(payload);
+        match cursor.u8() {
+            Some(0) => {
+                let start = (|| {
+                    let entry = cursor.u32()?;
+                    let name = cursor.string()?;
+                    let entry_type = cursor.string()?;
+                    let unit = cursor.string().as_deref().and_then(Unit::from_metadata);
+                    Some((
+                        entry,
+                        FollowedEntry {
+                            name,
+                            entry_type,
+                            unit,
+                        },
+                    ))
+                })();
+                if let Some((entry, followed)) = start {
+                    self.started_any = true;
+                    self.entries.insert(entry, followed);
+                }
+            }
+            Some(1) => {
+                if let Some(entry) = cursor.u32() {
+                    self.entries.remove(&entry);
+                }
+            }
+            // set metadata doesn't change how we decode values, only the unit it may carry
+            Some(2) => {
+                if let Some(entry) = cursor.u32() {
+                    let unit = cursor.string().as_deref().and_then(Unit::from_metadata);
+                    if let Some(followed) = self.entries.get_mut(&entry) {
+                        followed.unit = unit;
+                    }
+                }
+            }
+            _ => tracing::warn!("Unknown datalog control record"),
+        }
+    }
+}
+
+struct RawRecord<'a> {
+    entry: u32,
+    timestamp: EnokiTimeStamp,
+    payload: &'a [u8],
+    len: usize,
+}
+
+/// Returns the extra header string and the header length, or none if the header isn't complete yet
+pub(crate) fn parse_header(bytes: &[u8]) -> Result<Option<(String, usize)>, EnokiError> {
+    if bytes.len() < 12 {
+        return Ok(None);
+    }
+    if &bytes[0..6] != b"WPILOG" {
+        return Err(EnokiError::DatalogIo("File is not a wpilog".to_string()));
+    }
+    let extra_len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    if bytes.len() < 12 + extra_len {
+        return Ok(None);
+    }
+    let metadata = String::from_utf8_lossy(&bytes[12..12 + extra_len]).to_string();
+    Ok(Some((metadata, 12 + extra_len)))
+}
+
+/// What a wpilog says about one of its entries
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryInfo {
+    pub name: String,
+    pub entry_type: String,
+    /// The latest metadata of the entry, usually json
+    pub metadata: String,
+}
+
+/// Every entry of a wpilog on disk in the order they were first started.
+/// An entry started again under the same name is only listed once, with its latest metadata.
+pub(crate) fn entry_infos(path: &PathBuf) -> Result<Vec<EntryInfo>, EnokiError> {
+    let bytes = std::fs::read(path)?;
+    let mut consumed = match parse_header(&bytes)? {
+        Some((_, header_len)) => header_len,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut infos: Vec<EntryInfo> = Vec::new();
+    // entry ids to indices into infos
+    let mut started = HashMap::new();
+    while let Some(record) = parse_record(&bytes[consumed..]) {
+        consumed += record.len;
+        if record.entry != 0 {
+            continue;
+        }
+        let mut cursor = ByteCursor::new(record.payload);
+        match cursor.u8() {
+            Some(0) => {
+                let start = (|| Some((cursor.u32()?, cursor.string()?, cursor.string()?)))();
+                let (entry, name, entry_type) = match start {
+                    Some(start) => start,
+                    None => continue,
+                };
+                let info = EntryInfo {
+                    name,
+                    entry_type,
+                    metadata: cursor.string().unwrap_or_default(),
+                };
+                let index = match infos.iter().position(|known| known.name == info.name) {
+                    Some(index) => {
+                        infos[index] = info;
+                        index
+                    }
+                    None => {
+                        infos.push(info);
+                        infos.len() - 1
+                    }
+                };
+                started.insert(entry, index);
+            }
+            Some(2) => {
+                let index = cursor.u32().and_then(|entry| started.get(&entry));
+                if let (Some(index), Some(metadata)) = (index, cursor.string()) {
+                    infos[*index].metadata = metadata;
+                }
+            }
+            _ => continue,
+        }
+    }
+    Ok(infos)
+}
+
+/// The units declared in the metadata of every entry of a wpilog on disk, keyed by entry name.
+/// The latest metadata of an entry wins.
+pub(crate) fn entry_units(path: &PathBuf) -> Result<HashMap<String, Unit>, EnokiError> {
+    Ok(entry_infos(path)?
+        .into_iter()
+        .filter_map(|info| Some((info.name, Unit::from_metadata(&info.metadata)?)))
+        .collect())
+}
+
+/// Parses a single record from the front of the buffer, none if it is incomplete
+fn parse_record(bytes: &[u8]) -> Option<RawRecord> {
+    let header = *bytes.first()?;
+    let entry_len = (header & 0b11) as usize + 1;
+    let size_len = ((header >> 2) & 0b11) as usize + 1;
+    let timestamp_len = ((header >> 4) & 0b111) as usize + 1;
+
+    let mut cursor = ByteCursor::new(&bytes[1..]);
+    let entry = cursor.uint(entry_len)? as u32;
+    let size = cursor.uint(size_len)? as usize;
+    let timestamp = cursor.uint(timestamp_len)?;
+    let payload = cursor.bytes(size)?;
+
+    Some(RawRecord {
+        entry,
+        timestamp,
+        payload,
+        len: 1 + entry_len + size_len + timestamp_len + size,
+    })
+}
+
+fn decode_payload(entry_type: &str, payload: &[u8]) -> Option<EnokiValue> {
+    let mut cursor = ByteCursor::new(payload);
+    let value = match entry_type {
+        "boolean" => EnokiValue::Boolean(cursor.u8()? != 0),
+        "int64" => EnokiValue::Int(cursor.uint(8)? as i64),
+        "float" => EnokiValue::Float(f32::from_bits(cursor.u32()?) as f64),
+        "double" => EnokiValue::Double(f64::from_bits(cursor.uint(8)?)),
+        "string" | "json" => EnokiValue::String(String::from_utf8_lossy(payload).to_string()),
+        "boolean[]" => EnokiValue::BooleanArray(payload.iter().map(|b| *b != 0).collect()),
+        "int64[]" => EnokiValue::IntArray(
+            payload
+                .chunks_exact(8)
+                .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+        ),
+        "float[]" => EnokiValue::FloatArray(
+            payload
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+        ),
+        "double[]" => EnokiValue::DoubleArray(
+            payload
+                .chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+        ),
+        "string[]" => {
+            let count = cursor.u32()?;
+            let mut strings = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                strings.push(cursor.string()?);
+            }
+            EnokiValue::StringArray(strings)
+        }
+        t if t.starts_with("proto:") => EnokiValue::Protobuf(payload.to_vec()),
+        _ => EnokiValue::ByteArray(payload.to_vec()),
+    };
+    Some(value)
+}
+
+struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteCursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    /// Little endian unsigned int of `len` bytes
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let slice = self.bytes(len)?;
+        let mut val = 0u64;
+        for (i, b) in slice.iter().enumerate() {
+            val |= (*b as u64) << (8 * i);
+        }
+        Some(val)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.uint(1).map(|v| v as u8)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.uint(4).map(|v| v as u32)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        Some(String::from_utf8_lossy(bytes).to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FollowFieldsEvent {
+    pub id: FollowId,
+    pub fields: Vec<EnokiField>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FollowFinishedEvent {
+    pub id: FollowId,
+    pub reason: FollowFinishReason,
+    /// Why the file couldn't be read if the reason is `Unreadable`
+    pub error: Option<String>,
+}
+
+/// A change to a followed file as reported by the file watcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowChange {
+    Modified,
+    Removed,
+}
+
+#[derive(Debug)]
+struct DatalogFollowHandle {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    changes: mpsc::Sender<FollowChange>,
+}
+
+/// Starts following a wpilog, new records are emitted to the frontend as
+/// `datalog_follow_fields` events and `datalog_follow_finished` once the writer is done.
+/// Changes to the file are passed in with [follow_changed].
+pub fn start_follow<R: Runtime>(
+    app_handle: AppHandle<R>,
+    path: PathBuf,
+    idle_timeout: Option<Duration>,
+) -> Result<FollowId, EnokiError> {
+    let follower = WpilogFollower::open(&path)?;
+    let idle_timeout = idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT);
+
+    let id = {
+        let mut next = NEXT_FOLLOW_ID.lock();
+        *next += 1;
+        *next
+    };
+
+    let (changes, change_receiver) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    // held until the handle is inserted so a follow that ends instantly can still remove itself
+    let mut followers = FOLLOWERS.lock();
+    std::thread::Builder::new()
+        .name(format!("DatalogFollow-{}", id))
+        .spawn(move || {
+            let finished = follow_loop(
+                &app_handle,
+                id,
+                follower,
+                change_receiver,
+                &thread_stop,
+                idle_timeout,
+            );
+            let (reason, error) = match finished {
+                Ok(reason) => (reason, None),
+                Err(err) => {
+                    tracing::error!("Failed to read followed datalog: {}", err);
+                    (FollowFinishReason::Unreadable, Some(err.to_string()))
+                }
+            };
+            tracing::info!("Stopped following datalog {} because {:?}", id, reason);
+            let _ = app_handle.emit_all(
+                "datalog_follow_finished",
+                FollowFinishedEvent { id, reason, error },
+            );
+            FOLLOWERS.lock().remove(&id);
+        })?;
+
+    tracing::info!("Following datalog {} as {}", path.display(), id);
+    followers.insert(
+        id,
+        DatalogFollowHandle {
+            path,
+            stop,
+            changes,
+        },
+    );
+    Ok(id)
+}
+
+fn follow_loop<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    id: FollowId,
+    mut follower: WpilogFollower,
+    changes: mpsc::Receiver<FollowChange>,
+    stop: &AtomicBool,
+    idle_timeout: Duration,
+) -> Result<FollowFinishReason, EnokiError> {
+    let mut last_growth = Instant::now();
+    let mut last_offset = follower.offset();
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(FollowFinishReason::Stopped);
+        }
+
+        // the sender lives in the handle, which is only removed once this loop returns
+        if let Ok(FollowChange::Removed) = changes.recv_timeout(POLL_INTERVAL) {
+            return Ok(FollowFinishReason::Removed);
+        }
+
+        let fields = follower.poll()?;
+        // records without values or only part of one still mean the writer is busy
+        if follower.offset() > last_offset {
+            last_offset = follower.offset();
+            last_growth = Instant::now();
+        }
+        if !fields.is_empty() {
+            let _ = app_handle.emit_all("datalog_follow_fields", FollowFieldsEvent { id, fields });
+        }
+
+        if follower.all_entries_finished() {
+            return Ok(FollowFinishReason::AllEntriesFinished);
+        }
+        if last_growth.elapsed() > idle_timeout {
+            return Ok(FollowFinishReason::Idle);
+        }
+    }
+}
+
+/// Wakes the follow up to read the file right away, unknown ids are ignored
+/// since changes can still be reported just after a follow finished
+pub fn follow_changed(id: FollowId, change: FollowChange) {
+    if let Some(handle) = FOLLOWERS.lock().get(&id) {
+        let _ = handle.changes.send(change);
+    }
+}
+
+pub fn stop_follow(id: FollowId) {
+    if let Some(handle) = FOLLOWERS.lock().get(&id) {
+        tracing::info!("Stopping datalog follow of {}", handle.path.display());
+        handle.stop.store(true, Ordering::Relaxed);
+    } else {
+        tracing::warn!("No datalog follow found for {}", id);
+    }
+}
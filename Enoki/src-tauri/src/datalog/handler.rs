@@ -1,13 +1,23 @@
 use std::path::PathBuf;
 
 use tauri::api::path::document_dir;
-use wpilog::log::{CreateDataLogConfig, DataLog, DataLogDaemon, OpenDataLogConfig};
+use wpilog::log::{
+    CreateDataLogConfig, DataLog, DataLogDaemon, DatalogEntryResponse, OpenDataLogConfig,
+};
 
-use crate::{error::EnokiError, enoki_types::EnokiValue};
+use crate::{
+    enoki_types::{now, EnokiHistory, EnokiKey, EnokiObject, EnokiValue, TimestampedEnokiValue},
+    error::EnokiError,
+    retention::{log_rotated, new_log_path, DATALOG_DIRECTORY},
+};
 
-use super::DATALOG;
+use super::{
+    follow::{entry_infos, entry_units, EntryInfo},
+    session::{current_session, SessionMetadata},
+    DATALOG,
+};
 
-static RELATIVE_DIRECTORY: &str = "Enoki/Datalogs";
+static RELATIVE_DIRECTORY: &str = DATALOG_DIRECTORY;
 
 pub fn setup_directory() -> Result<(), ()> {
     if let Some(docu_path) = document_dir() {
@@ -25,32 +35,44 @@ pub fn setup_directory() -> Result<(), ()> {
     }
 }
 
-pub fn create_datalog_daemon() -> DataLogDaemon {
-    if let Err(_) = setup_directory() {
-        tracing::error!("Failed to setup datalog directory");
-        panic!();
+/// A daemon writing to a new file in the datalog directory, and the path of that file
+fn create_datalog_daemon() -> Result<(DataLogDaemon, PathBuf), EnokiError> {
+    if setup_directory().is_err() {
+        return Err(EnokiError::DatalogIo(
+            "Failed to setup datalog directory".to_string(),
+        ));
     }
+    let path = new_log_path(RELATIVE_DIRECTORY, "wpilog")
+        .ok_or(EnokiError::DatalogIo("No documents directory".to_string()))?;
+    let datalog = create_datalog(path.clone(), &current_session())?;
+    Ok((datalog.as_daemon(), path))
+}
 
-    let current_time_string =
-        chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string() + ".wpilog";
-
-    let relative_path = format!("{}/{}", RELATIVE_DIRECTORY, current_time_string);
+/// The daemon everything is logged to until the datalog is rotated
+pub fn start_datalog_daemon() -> DataLogDaemon {
+    //if can't create datalog crash
+    let (daemon, path) = create_datalog_daemon().expect("Failed to create datalog");
+    log_rotated(RELATIVE_DIRECTORY, &path);
+    daemon
+}
 
-    let abs_path = document_dir()
-        .unwrap()
-        .join(relative_path)
-        .to_str()
-        .unwrap()
-        .to_string();
+/// Finishes the running datalog and continues logging in a new file,
+/// logging continues in the running one if the new one can't be created
+pub fn rotate_datalog() -> Result<(), EnokiError> {
+    let mut datalog = DATALOG.lock();
+    let (daemon, path) = create_datalog_daemon()?;
+    std::mem::replace(&mut *datalog, daemon).kill();
+    log_rotated(RELATIVE_DIRECTORY, &path);
+    Ok(())
+}
 
+/// A new wpilog with the session in its header
+pub fn create_datalog(path: PathBuf, session: &SessionMetadata) -> Result<DataLog, EnokiError> {
     let config = CreateDataLogConfig {
-        file_path: abs_path.into(),
-        metadata: "".into(),
+        file_path: path,
+        metadata: session.to_json().into(),
     };
-
-    //if can't create datalog crash
-    let datalog = DataLog::create(config).expect("Failed to create datalog");
-    datalog.as_daemon()
+    Ok(DataLog::create(config)?)
 }
 
 pub fn start_datalog_entry(
@@ -78,7 +100,7 @@ pub fn log_datalog_value(name: &str, value: EnokiValue) -> Result<(), EnokiError
     DATALOG
         .lock()
         .borrow_sender()
-        .append_to_entry(String::from(name), value.into())?;
+        .append_to_entry(String::from(name), value.try_into()?)?;
     Ok(())
 }
 
@@ -90,3 +112,37 @@ pub fn open_datalog(path: PathBuf) -> Result<DataLog, EnokiError> {
     let datalog = DataLog::open(config)?;
     Ok(datalog)
 }
+
+fn entries_to_object(entries: Vec<DatalogEntryResponse>) -> EnokiObject {
+    let mut obj = EnokiObject::new(now());
+    for entry in entries {
+        let history = entry
+            .marks
+            .iter()
+            .map(|mark| {
+                TimestampedEnokiValue::new(mark.timestamp, EnokiValue::from(mark.value.clone()))
+            })
+            .collect::<EnokiHistory>();
+        obj.set_history(&EnokiKey::from_topic_name(&entry.name), history)
+    }
+    obj
+}
+
+/// Every entry of a wpilog on disk with its full history
+pub fn datalog_object(path: PathBuf) -> Result<EnokiObject, EnokiError> {
+    let mut obj = entries_to_object(open_datalog(path.clone())?.get_all_entries());
+    for (name, unit) in entry_units(&path)? {
+        obj.set_unit(&EnokiKey::from_topic_name(&name), Some(unit));
+    }
+    Ok(obj)
+}
+
+/// The name, type and metadata of every entry of a wpilog on disk
+pub fn datalog_entries(path: PathBuf) -> Result<Vec<EntryInfo>, EnokiError> {
+    entry_infos(&path)
+}
+
+/// Everything logged by the running datalog daemon with its full history
+pub fn daemon_object() -> EnokiObject {
+    entries_to_object(DATALOG.lock().get_all_entries())
+}
@@ -5,10 +5,14 @@ use wpilog::log::DataLogDaemon;
 
 pub mod tauri_cmds;
 pub mod handler;
+pub mod follow;
+pub mod session;
+pub mod summary;
+pub mod python;
 
 use tauri_cmds::*;
 
-pub static DATALOG: Lazy<Mutex<DataLogDaemon>> = Lazy::new(|| Mutex::new(handler::create_datalog_daemon()));
+pub static DATALOG: Lazy<Mutex<DataLogDaemon>> = Lazy::new(|| Mutex::new(handler::start_datalog_daemon()));
 
 pub fn datalog_plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R> {
     tauri::plugin::Builder::new("datalog")
@@ -16,7 +20,17 @@ pub fn datalog_plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R> {
         .invoke_handler(tauri::generate_handler![
             read_datalog,
             retrieve_dl_daemon_data,
-            send_mark
+            query_dl_daemon_data,
+            send_mark,
+            follow_datalog,
+            datalog_follow_changed,
+            stop_follow_datalog,
+            get_session_metadata,
+            set_session_metadata,
+            read_datalog_session,
+            datalog_summary,
+            dl_daemon_summary,
+            rotate_datalog
         ])
         .build()
 }
\ No newline at end of file
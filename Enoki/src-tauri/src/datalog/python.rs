@@ -0,0 +1,362 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+use wpilog::log::{DataLog, DataLogValue};
+
+use crate::enoki_types::{now, EnokiKey, EnokiObject, EnokiTimeStamp, EnokiValue};
+use crate::error::EnokiError;
+use crate::python_helpers::awaitable::run_blocking;
+use crate::python_helpers::py_enoki_types::{PyEnokiObject, PyTimestampedEnokiValue};
+use crate::scripting::plugins::{require_permission, Permission};
+
+use super::follow::EntryInfo;
+use super::handler;
+use super::session::{self, SessionMetadata};
+
+fn runtime_err(context: &str, err: impl Display) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}: {}", context, err))
+}
+
+#[derive(Debug, Clone)]
+#[pyclass(name = "SessionMetadata")]
+pub struct PySessionMetadata {
+    #[pyo3(get, set)]
+    event: String,
+    #[pyo3(get, set)]
+    match_type: Option<String>,
+    #[pyo3(get, set)]
+    match_number: Option<u16>,
+    #[pyo3(get, set)]
+    alliance: Option<String>,
+    #[pyo3(get, set)]
+    robot: String,
+    #[pyo3(get, set)]
+    code_version: String,
+    #[pyo3(get, set)]
+    notes: String,
+}
+
+#[pymethods]
+impl PySessionMetadata {
+    #[new]
+    #[pyo3(signature = (event = String::new(), match_type = None, match_number = None, alliance = None, robot = String::new(), code_version = String::new(), notes = String::new()))]
+    fn new(
+        event: String,
+        match_type: Option<String>,
+        match_number: Option<u16>,
+        alliance: Option<String>,
+        robot: String,
+        code_version: String,
+        notes: String,
+    ) -> Self {
+        Self {
+            event,
+            match_type,
+            match_number,
+            alliance,
+            robot,
+            code_version,
+            notes,
+        }
+    }
+}
+
+impl From<SessionMetadata> for PySessionMetadata {
+    fn from(metadata: SessionMetadata) -> Self {
+        Self {
+            event: metadata.event,
+            match_type: metadata.match_type.map(|t| format!("{:?}", t)),
+            match_number: metadata.match_number,
+            alliance: metadata.alliance.map(|a| format!("{:?}", a)),
+            robot: metadata.robot,
+            code_version: metadata.code_version,
+            notes: metadata.notes,
+        }
+    }
+}
+
+impl TryFrom<PySessionMetadata> for SessionMetadata {
+    type Error = PyErr;
+
+    fn try_from(metadata: PySessionMetadata) -> Result<Self, Self::Error> {
+        let to_py_err = |err: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(err);
+        Ok(Self {
+            event: metadata.event,
+            match_type: metadata
+                .match_type
+                .map(|t| t.parse())
+                .transpose()
+                .map_err(to_py_err)?,
+            match_number: metadata.match_number,
+            alliance: metadata
+                .alliance
+                .map(|a| a.parse())
+                .transpose()
+                .map_err(to_py_err)?,
+            robot: metadata.robot,
+            code_version: metadata.code_version,
+            notes: metadata.notes,
+        })
+    }
+}
+
+#[pyfunction]
+pub fn get_session_metadata() -> PySessionMetadata {
+    session::current_session().into()
+}
+
+#[pyfunction]
+pub fn set_session_metadata(metadata: PySessionMetadata) -> PyResult<()> {
+    // it's recorded in the running datalog
+    require_permission(Permission::Filesystem)?;
+    session::update_session(metadata.try_into()?).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+            "Error setting session metadata: {}",
+            e
+        ))
+    })
+}
+
+#[pyfunction]
+pub fn read_session_metadata(path: String) -> PyResult<Option<PySessionMetadata>> {
+    require_permission(Permission::Filesystem)?;
+    match session::read_session_metadata(path.into()) {
+        Ok(metadata) => Ok(metadata.map(Into::into)),
+        Err(e) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+            "Error reading session metadata: {}",
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Clone)]
+#[pyclass(name = "DatalogEntry")]
+pub struct PyDatalogEntry {
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    r#type: String,
+    #[pyo3(get)]
+    metadata: String,
+    #[pyo3(get)]
+    unit: Option<String>,
+    /// How many values were logged
+    #[pyo3(get)]
+    count: usize,
+    #[pyo3(get)]
+    first: Option<EnokiTimeStamp>,
+    #[pyo3(get)]
+    last: Option<EnokiTimeStamp>,
+}
+
+impl PyDatalogEntry {
+    fn new(info: EntryInfo, object: &EnokiObject) -> Self {
+        let key = EnokiKey::from_topic_name(&info.name);
+        let history = object.get_history(&key);
+        Self {
+            unit: object.get_unit(&key).map(|unit| unit.to_string()),
+            count: history.map_or(0, |history| history.len()),
+            first: history
+                .and_then(|history| history.first())
+                .map(|v| v.timestamp),
+            last: history
+                .and_then(|history| history.last())
+                .map(|v| v.timestamp),
+            name: info.name,
+            r#type: info.entry_type,
+            metadata: info.metadata,
+        }
+    }
+}
+
+/// A wpilog read from disk, everything is loaded up front so reading it again is free
+#[pyclass(name = "DatalogReader")]
+pub struct PyDatalogReader {
+    #[pyo3(get)]
+    path: String,
+    object: EnokiObject,
+    entries: Vec<PyDatalogEntry>,
+}
+
+#[pymethods]
+impl PyDatalogReader {
+    /// The name, type, metadata and logged span of every entry in the order they were started
+    fn entries(&self) -> Vec<PyDatalogEntry> {
+        self.entries.clone()
+    }
+
+    fn entry_names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    /// The values of an entry logged within `[start, end]`, raises KeyError for an unknown entry
+    #[pyo3(signature = (key, start = None, end = None))]
+    fn history(
+        &self,
+        key: String,
+        start: Option<EnokiTimeStamp>,
+        end: Option<EnokiTimeStamp>,
+    ) -> PyResult<Vec<PyTimestampedEnokiValue>> {
+        match self.object.get_history(&EnokiKey::from_topic_name(&key)) {
+            Some(history) => Ok(history.range(start, end).iter().map(Into::into).collect()),
+            None if self.entries.iter().any(|entry| entry.name == key) => Ok(Vec::new()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyKeyError, _>(key)),
+        }
+    }
+
+    /// Every entry as a field with its full history, use `EnokiObject.query` to narrow it down
+    fn object(&self) -> PyEnokiObject {
+        self.object.clone().into()
+    }
+
+    fn session_metadata(&self) -> PyResult<Option<PySessionMetadata>> {
+        read_session_metadata(self.path.clone())
+    }
+}
+
+/// Writes a new wpilog, entries are started the first time a value is appended to them
+/// unless they were started with `start_entry`.
+/// Writes go straight to the log rather than through a daemon, so everything is on disk once it's closed.
+#[pyclass(name = "DatalogWriter")]
+pub struct PyDatalogWriter {
+    #[pyo3(get)]
+    path: String,
+    datalog: Option<DataLog>,
+}
+
+impl PyDatalogWriter {
+    fn datalog(&mut self) -> PyResult<&mut DataLog> {
+        let path = &self.path;
+        self.datalog
+            .as_mut()
+            .ok_or_else(|| runtime_err("Datalog writer is closed", path))
+    }
+}
+
+/// Maps the errors of the writer's log the way the app's datalog does
+fn write_result<T, E>(result: Result<T, E>) -> PyResult<T>
+where
+    EnokiError: From<E>,
+{
+    result.map_err(|err| runtime_err("Error writing datalog", EnokiError::from(err)))
+}
+
+#[pymethods]
+impl PyDatalogWriter {
+    #[pyo3(signature = (name, entry_type, metadata = None))]
+    fn start_entry(
+        &mut self,
+        name: String,
+        entry_type: String,
+        metadata: Option<String>,
+    ) -> PyResult<()> {
+        write_result(
+            self.datalog()?
+                .create_entry(name, entry_type, metadata.unwrap_or_default()),
+        )
+    }
+
+    /// Appends a value at `timestamp` or now, an entry that wasn't started yet is started with the value's type
+    #[pyo3(signature = (name, value, timestamp = None))]
+    fn append(
+        &mut self,
+        name: String,
+        value: EnokiValue,
+        timestamp: Option<EnokiTimeStamp>,
+    ) -> PyResult<()> {
+        let value = DataLogValue::try_from(value)
+            .map_err(|err| PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string()))?;
+        let datalog = self.datalog()?;
+        if datalog.get_entry_id(&name).is_none() {
+            write_result(datalog.create_entry(
+                name.clone(),
+                value.get_data_type(),
+                "{ source: \"script\"}".to_string(),
+            ))?;
+        }
+        write_result(datalog.append_to_entry_timestamp(
+            name,
+            value,
+            timestamp.unwrap_or_else(now),
+        ))
+    }
+
+    fn finish_entry(&mut self, name: String) -> PyResult<()> {
+        write_result(self.datalog()?.kill_entry(name))
+    }
+
+    /// Finishes writing the file, the writer can't be used after
+    fn close(&mut self) {
+        // the log finishes its open entries and flushes when dropped
+        self.datalog.take();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) {
+        self.close();
+    }
+}
+
+/// Reads a wpilog and the units its entries declare
+#[pyfunction]
+#[pyo3(name = "open")]
+pub fn open_datalog(path: String) -> PyResult<PyDatalogReader> {
+    require_permission(Permission::Filesystem)?;
+    read_datalog(path)
+}
+
+/// Like `open` but reads the wpilog on its own thread, for `await Datalog.open_async(path)`
+#[pyfunction]
+#[pyo3(name = "open_async")]
+pub fn open_datalog_async<'py>(py: Python<'py>, path: String) -> PyResult<&'py PyAny> {
+    // the reading thread doesn't have the caller's grant
+    require_permission(Permission::Filesystem)?;
+    run_blocking(py, move || read_datalog(path))
+}
+
+fn read_datalog(path: String) -> PyResult<PyDatalogReader> {
+    let path_buf = PathBuf::from(&path);
+    let read = || -> Result<_, EnokiError> {
+        Ok((
+            handler::datalog_object(path_buf.clone())?,
+            handler::datalog_entries(path_buf.clone())?,
+        ))
+    };
+    let (object, infos) = read().map_err(|e| runtime_err("Error reading datalog", e))?;
+    let entries = infos
+        .into_iter()
+        .map(|info| PyDatalogEntry::new(info, &object))
+        .collect();
+    Ok(PyDatalogReader {
+        path,
+        object,
+        entries,
+    })
+}
+
+/// Creates a new wpilog, with the current session in its header unless one is given
+#[pyfunction]
+#[pyo3(name = "create", signature = (path, metadata = None))]
+pub fn create_datalog(
+    path: String,
+    metadata: Option<PySessionMetadata>,
+) -> PyResult<PyDatalogWriter> {
+    require_permission(Permission::Filesystem)?;
+    let session = match metadata {
+        Some(metadata) => metadata.try_into()?,
+        None => session::current_session(),
+    };
+    let datalog = handler::create_datalog(PathBuf::from(&path), &session)
+        .map_err(|e| runtime_err("Error creating datalog", e))?;
+    Ok(PyDatalogWriter {
+        path,
+        datalog: Some(datalog),
+    })
+}
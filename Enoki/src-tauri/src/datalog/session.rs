@@ -0,0 +1,143 @@
+use std::{fs::File, io::Read, path::PathBuf, str::FromStr};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use wpilog::log::DataLogValue;
+
+use crate::error::EnokiError;
+
+use super::{follow::parse_header, handler::open_datalog, DATALOG};
+
+/// Entry every session metadata edit is appended to as json,
+/// the latest value wins over whatever was written into the header
+pub const SESSION_ENTRY: &str = "/Enoki/Session";
+
+static SESSION_METADATA: Lazy<Mutex<SessionMetadata>> =
+    Lazy::new(|| Mutex::new(SessionMetadata::default()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchType {
+    Practice,
+    Qualification,
+    Elimination,
+}
+
+impl FromStr for MatchType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "practice" | "p" => Ok(MatchType::Practice),
+            "qualification" | "qual" | "q" => Ok(MatchType::Qualification),
+            "elimination" | "elim" | "playoff" | "e" => Ok(MatchType::Elimination),
+            _ => Err(format!("Unknown match type {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alliance {
+    Red,
+    Blue,
+}
+
+impl FromStr for Alliance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "red" => Ok(Alliance::Red),
+            "blue" => Ok(Alliance::Blue),
+            _ => Err(format!("Unknown alliance {}", s)),
+        }
+    }
+}
+
+/// Describes what a datalog was recording, e.g. qual 42 at a district event
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionMetadata {
+    pub event: String,
+    pub match_type: Option<MatchType>,
+    pub match_number: Option<u16>,
+    pub alliance: Option<Alliance>,
+    pub robot: String,
+    pub code_version: String,
+    pub notes: String,
+}
+
+impl SessionMetadata {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+/// The metadata new datalogs are created with
+pub fn current_session() -> SessionMetadata {
+    SESSION_METADATA.lock().clone()
+}
+
+/// Replaces the current session metadata and records it in the running datalog
+pub fn update_session(metadata: SessionMetadata) -> Result<(), EnokiError> {
+    *SESSION_METADATA.lock() = metadata.clone();
+
+    let mut datalog = DATALOG.lock();
+    if !datalog.summary().contains_key(SESSION_ENTRY) {
+        datalog.borrow_sender().start_entry(
+            SESSION_ENTRY.to_string(),
+            "string".to_string(),
+            Some("{ source: \"session\"}".to_string()),
+        )?;
+    }
+    datalog
+        .borrow_sender()
+        .append_to_entry(SESSION_ENTRY.to_string(), DataLogValue::String(metadata.to_json()))?;
+    Ok(())
+}
+
+/// Headers are only ever a line of json, anything longer isn't one Enoki wrote
+pub const MAX_HEADER_METADATA: usize = 1024 * 1024;
+
+/// Reads only the header of a wpilog and parses the session metadata it was created with
+pub fn read_header_session(path: &PathBuf) -> Result<Option<SessionMetadata>, EnokiError> {
+    let mut file = File::open(path)?;
+    let mut header = vec![0u8; 12];
+    file.read_exact(&mut header)?;
+    // checks the magic before the length is trusted
+    parse_header(&header)?;
+    let extra_len = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+    if extra_len > MAX_HEADER_METADATA {
+        return Err(EnokiError::DatalogIo(format!(
+            "Header metadata of {} bytes is too long",
+            extra_len
+        )));
+    }
+    file.take(extra_len as u64).read_to_end(&mut header)?;
+
+    match parse_header(&header)? {
+        Some((metadata, _)) => Ok(SessionMetadata::from_json(&metadata)),
+        None => Err(EnokiError::DatalogIo("Header is cut off".to_string())),
+    }
+}
+
+/// The session metadata of a wpilog, edits recorded in the log take priority over the header
+pub fn read_session_metadata(path: PathBuf) -> Result<Option<SessionMetadata>, EnokiError> {
+    let datalog = open_datalog(path.clone())?;
+    let latest_edit = datalog
+        .get_all_entries()
+        .into_iter()
+        .find(|entry| entry.name == SESSION_ENTRY)
+        .and_then(|entry| entry.marks.last().map(|mark| mark.value.clone()));
+
+    if let Some(DataLogValue::String(json)) = latest_edit {
+        if let Some(metadata) = SessionMetadata::from_json(&json) {
+            return Ok(Some(metadata));
+        }
+    }
+    read_header_session(&path)
+}
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{
+    enoki_types::{EnokiHistory, EnokiObject, EnokiTimeStamp, EnokiValue, TimestampedEnokiValue},
+    error::EnokiError,
+};
+
+use super::handler::open_datalog;
+
+/// Gaps longer than this are reported if the caller doesn't pick a threshold, 100ms
+pub const DEFAULT_GAP_THRESHOLD: EnokiTimeStamp = 100_000;
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NumericSummary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gap {
+    pub start: EnokiTimeStamp,
+    pub end: EnokiTimeStamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldSummary {
+    pub key: String,
+    pub value_type: String,
+    pub samples: usize,
+    pub first_timestamp: Option<EnokiTimeStamp>,
+    pub last_timestamp: Option<EnokiTimeStamp>,
+    /// Samples per second over the whole span of the field
+    pub average_rate: f64,
+    /// Samples per second between the two closest samples
+    pub max_rate: f64,
+    /// Only for single numeric values
+    pub numeric: Option<NumericSummary>,
+    pub gaps: Vec<Gap>,
+}
+
+/// Summarizes a single field history
+pub fn summarize_history(
+    key: String,
+    history: &EnokiHistory,
+    gap_threshold: EnokiTimeStamp,
+) -> FieldSummary {
+    let value_type = history
+        .first()
+        .map(|v| v.value.get_type())
+        .unwrap_or_else(|| "Unknown".to_string());
+    let first_timestamp = history.timestamps().first().copied();
+    let last_timestamp = history.timestamps().last().copied();
+
+    let mut gaps = Vec::new();
+    let mut min_interval: Option<EnokiTimeStamp> = None;
+    for pair in history.timestamps().windows(2) {
+        let interval = pair[1].saturating_sub(pair[0]);
+        if interval > gap_threshold {
+            gaps.push(Gap {
+                start: pair[0],
+                end: pair[1],
+            });
+        }
+        if interval > 0 {
+            min_interval = Some(min_interval.map_or(interval, |min| min.min(interval)));
+        }
+    }
+
+    let span = match (first_timestamp, last_timestamp) {
+        (Some(first), Some(last)) => last.saturating_sub(first),
+        _ => 0,
+    };
+    let average_rate = if span > 0 {
+        (history.len() - 1) as f64 / (span as f64 / MICROS_PER_SECOND)
+    } else {
+        0.0
+    };
+    let max_rate = min_interval
+        .map(|interval| MICROS_PER_SECOND / interval as f64)
+        .unwrap_or(0.0);
+
+    FieldSummary {
+        key,
+        value_type,
+        samples: history.len(),
+        first_timestamp,
+        last_timestamp,
+        average_rate,
+        max_rate,
+        numeric: summarize_numeric(history),
+        gaps,
+    }
+}
+
+fn summarize_numeric(history: &EnokiHistory) -> Option<NumericSummary> {
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    let mut sum = 0.0;
+    let mut count = 0usize;
+    for sample in history.iter() {
+        let value = match sample.value {
+            EnokiValue::Float(v) | EnokiValue::Double(v) => v,
+            EnokiValue::Int(v) => v as f64,
+            _ => return None,
+        };
+        min = min.min(value);
+        max = max.max(value);
+        sum += value;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    Some(NumericSummary {
+        min,
+        max,
+        mean: sum / count as f64,
+    })
+}
+
+/// Summarizes every field of an object that has a history
+pub fn summarize_object(obj: &EnokiObject, gap_threshold: EnokiTimeStamp) -> Vec<FieldSummary> {
+    obj.get_fields()
+        .iter()
+        .filter_map(|field| {
+            obj.get_history(field.get_key()).map(|history| {
+                summarize_history(String::from(field.get_key()), history, gap_threshold)
+            })
+        })
+        .collect()
+}
+
+/// Summarizes every entry of a wpilog on disk
+pub fn summarize_datalog(
+    path: PathBuf,
+    gap_threshold: EnokiTimeStamp,
+) -> Result<Vec<FieldSummary>, EnokiError> {
+    let datalog = open_datalog(path)?;
+    Ok(datalog
+        .get_all_entries()
+        .into_iter()
+        .map(|entry| {
+            let history = entry
+                .marks
+                .iter()
+                .map(|mark| {
+                    TimestampedEnokiValue::new(mark.timestamp, EnokiValue::from(mark.value.clone()))
+                })
+                .collect::<EnokiHistory>();
+            summarize_history(entry.name, &history, gap_threshold)
+        })
+        .collect())
+}
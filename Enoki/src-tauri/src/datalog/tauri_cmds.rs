@@ -1,8 +1,17 @@
+use std::time::Duration;
+
+use tauri::{AppHandle, Runtime};
 use wpilog::log::{DatalogEntryResponse, DataLogValue};
 
-use crate::{error::{log_result, EnokiError, log_result_consume}, enoki_types::{TimestampedEnokiValue, EnokiObject, now, EnokiValue, EnokiKey}};
+use crate::{error::{log_result, EnokiError, log_result_consume}, frontend_helpers::encoding::{EncodedObject, IpcEncoding}, enoki_types::{TimestampedEnokiValue, EnokiTimeStamp, EnokiQuery}};
 
-use super::{handler::open_datalog, DATALOG};
+use super::{
+    handler::{self, daemon_object, open_datalog},
+    follow::{self, FollowChange, FollowId},
+    session::{self, SessionMetadata},
+    summary::{self, FieldSummary, DEFAULT_GAP_THRESHOLD},
+    DATALOG,
+};
 
 
 #[tauri::command]
@@ -13,39 +22,102 @@ pub fn read_datalog(path: String) -> Result<Vec<DatalogEntryResponse>, EnokiErro
 }
 
 #[tauri::command]
-pub fn retrieve_dl_daemon_data() -> EnokiObject {
-    let mut obj = EnokiObject::new(now());
-    let mut dl = DATALOG.lock();
-    let entries = dl.get_all_entries();
-    for entry in entries {
-        let key = EnokiKey::from(entry.name.clone());
-        let mut history = Vec::new();
-        entry.marks.iter().for_each(|mark| {
-            history.push(TimestampedEnokiValue::new(mark.timestamp, EnokiValue::from(mark.value.clone())));
-        });
-        obj.set_history(&key, history)
-    }
-    obj
+pub fn retrieve_dl_daemon_data(encoding: Option<IpcEncoding>) -> Result<EncodedObject, EnokiError> {
+    log_result(EncodedObject::encode(daemon_object(), encoding))
+}
+
+#[tauri::command]
+pub fn query_dl_daemon_data(
+    query: EnokiQuery,
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, EnokiError> {
+    let data = log_result(query.apply(&daemon_object()).map_err(EnokiError::from))?;
+    log_result(EncodedObject::encode(data, encoding))
 }
 
 #[tauri::command]
-pub fn send_mark(field: String, value: TimestampedEnokiValue) {
+pub fn send_mark(field: String, value: TimestampedEnokiValue) -> Result<(), EnokiError> {
+    let dl_val = log_result(DataLogValue::try_from(value.value).map_err(EnokiError::from))?;
     let mut datalog = DATALOG.lock();
     let already_exist = datalog.summary().contains_key(&field);
-    if already_exist {
-        log_result_consume(
-            datalog.borrow_sender()
-                .append_to_entry_with_timestamp(field, value.value.into(), value.timestamp));
-    } else {
-        let dl_val = DataLogValue::from(value.value);
+    if !already_exist {
         log_result_consume(
             datalog.borrow_sender().start_entry(
                 field.clone(),
                 dl_val.get_data_type(),
                 Some("{ source: \"frontend\"}".to_string()))
             );
-        log_result_consume(
-            datalog.borrow_sender()
-                .append_to_entry_with_timestamp(field, dl_val, value.timestamp));
     }
-}
\ No newline at end of file
+    log_result_consume(
+        datalog.borrow_sender()
+            .append_to_entry_with_timestamp(field, dl_val, value.timestamp));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn follow_datalog<R: Runtime>(
+    app_handle: AppHandle<R>,
+    path: String,
+    idle_timeout_ms: Option<u64>,
+) -> Result<FollowId, EnokiError> {
+    log_result(follow::start_follow(
+        app_handle,
+        path.into(),
+        idle_timeout_ms.map(Duration::from_millis),
+    ))
+}
+
+/// Called by the frontend for every change `tauri-plugin-fs-watch` reports on a followed file
+#[tauri::command]
+pub fn datalog_follow_changed(id: FollowId, removed: bool) {
+    let change = match removed {
+        true => FollowChange::Removed,
+        false => FollowChange::Modified,
+    };
+    follow::follow_changed(id, change)
+}
+
+#[tauri::command]
+pub fn stop_follow_datalog(id: FollowId) {
+    follow::stop_follow(id)
+}
+
+/// Starts a new datalog file, e.g. between matches, retention is applied to the old ones
+#[tauri::command]
+pub fn rotate_datalog() -> Result<(), EnokiError> {
+    handler::rotate_datalog()
+}
+
+#[tauri::command]
+pub fn get_session_metadata() -> SessionMetadata {
+    session::current_session()
+}
+
+#[tauri::command]
+pub fn set_session_metadata(metadata: SessionMetadata) -> Result<(), EnokiError> {
+    log_result(session::update_session(metadata))
+}
+
+#[tauri::command]
+pub fn read_datalog_session(path: String) -> Result<Option<SessionMetadata>, EnokiError> {
+    log_result(session::read_session_metadata(path.into()))
+}
+
+#[tauri::command]
+pub fn datalog_summary(
+    path: String,
+    gap_threshold: Option<EnokiTimeStamp>,
+) -> Result<Vec<FieldSummary>, EnokiError> {
+    log_result(summary::summarize_datalog(
+        path.into(),
+        gap_threshold.unwrap_or(DEFAULT_GAP_THRESHOLD),
+    ))
+}
+
+#[tauri::command]
+pub fn dl_daemon_summary(gap_threshold: Option<EnokiTimeStamp>) -> Vec<FieldSummary> {
+    summary::summarize_object(
+        &daemon_object(),
+        gap_threshold.unwrap_or(DEFAULT_GAP_THRESHOLD),
+    )
+}
@@ -0,0 +1,693 @@
+//! Parsing and evaluation of derived field expressions.
+//!
+//! ```text
+//! expr    := or
+//! or      := and ("||" and)*
+//! and     := compare ("&&" compare)*
+//! compare := sum (("<" | "<=" | ">" | ">=" | "==" | "!=") sum)?
+//! sum     := product (("+" | "-") product)*
+//! product := unary (("*" | "/" | "%") unary)*
+//! unary   := ("-" | "!") unary | postfix
+//! postfix := primary ("[" expr "]")*
+//! primary := number | "true" | "false" | "{" key "}" | name "(" args ")" | "(" expr ")"
+//! ```
+//! Keys are wrapped in braces so their `/` can't be mistaken for division,
+//! e.g. `abs({/Drive/Left/speed} - {/Drive/Right/speed})`.
+
+use std::collections::VecDeque;
+
+use crate::enoki_types::{EnokiKey, EnokiTimeStamp, EnokiValue};
+
+use super::DerivedError;
+
+/// How deep an expression may nest, deeper ones would overflow the stack when parsed or evaluated.
+/// Every operand of a chain like `a + b + c` counts as a level since the chain is a nested tree.
+pub const MAX_DEPTH: usize = 128;
+/// The most samples an average may span, as many as a resampled grid can have
+pub const MAX_AVERAGE_WINDOW: usize = crate::resample::MAX_GRID_POINTS as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+/// Functions that only look at the current sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Min,
+    Max,
+}
+
+/// Functions that remember earlier samples, every call site keeps its own state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatefulFunction {
+    /// Change per second
+    Derivative,
+    /// Trapezoidal sum over seconds
+    Integral,
+    /// Mean of the last `n` samples
+    Average(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(EnokiValue),
+    Key(EnokiKey),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Stateful {
+        function: StatefulFunction,
+        arg: Box<Expr>,
+        slot: usize,
+    },
+}
+
+impl Expr {
+    /// Every key the expression reads, in order of first use
+    pub fn keys(&self) -> Vec<EnokiKey> {
+        let mut keys = Vec::new();
+        self.collect_keys(&mut keys);
+        keys
+    }
+
+    fn collect_keys(&self, keys: &mut Vec<EnokiKey>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Key(key) => {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+            Expr::Unary(_, arg) | Expr::Stateful { arg, .. } => arg.collect_keys(keys),
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                left.collect_keys(keys);
+                right.collect_keys(keys);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.collect_keys(keys)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(EnokiValue),
+    Key(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 19] = [
+    "<=", ">=", "==", "!=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "[", "]",
+    ",",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, DerivedError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < source.len() {
+        let rest = &source[position..];
+        let c = rest.chars().next().unwrap_or_default();
+        if c.is_whitespace() {
+            position += c.len_utf8();
+        } else if c == '{' {
+            let end = rest.find('}').ok_or_else(|| DerivedError::parse(position, "unclosed key"))?;
+            tokens.push((position, Token::Key(rest[1..end].trim().to_string())));
+            position += end + 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            let text = rest[..len].replace('_', "");
+            let number = match text.parse::<i64>() {
+                Ok(int) => EnokiValue::Int(int),
+                Err(_) => EnokiValue::Double(
+                    text.parse()
+                        .map_err(|_| DerivedError::parse(position, "invalid number"))?,
+                ),
+            };
+            tokens.push((position, Token::Number(number)));
+            position += len;
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((position, Token::Name(rest[..len].to_string())));
+            position += len;
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| DerivedError::parse(position, format!("unexpected '{}'", c)))?;
+            tokens.push((position, Token::Symbol(symbol)));
+            position += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+    slots: usize,
+    /// How many levels deep the expression being parsed is
+    depth: usize,
+}
+
+impl Parser {
+    /// Goes a level deeper, the caller goes back up once it's done with the level
+    fn descend(&mut self) -> Result<(), DerivedError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(DerivedError::parse(
+                self.position(),
+                format!("nested more than {} levels deep", MAX_DEPTH),
+            ));
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.next) {
+            Some((_, Token::Symbol(symbol))) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if self.peek_symbol() == Some(symbol) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), DerivedError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(DerivedError::parse(
+                self.position(),
+                format!("expected '{}'", symbol),
+            ))
+        }
+    }
+
+    /// Parses a left associative chain of the operators in `ops` over `operand`
+    fn chain(
+        &mut self,
+        ops: &[(&str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, DerivedError>,
+    ) -> Result<Expr, DerivedError> {
+        let mut left = operand(self)?;
+        let depth = self.depth;
+        'outer: loop {
+            for (symbol, op) in ops {
+                if self.eat(symbol) {
+                    self.descend()?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(operand(self)?));
+                    continue 'outer;
+                }
+            }
+            self.depth = depth;
+            return Ok(left);
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, DerivedError> {
+        self.descend()?;
+        let expr = self.chain(&[("||", BinaryOp::Or)], Self::and)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, DerivedError> {
+        self.chain(&[("&&", BinaryOp::And)], Self::compare)
+    }
+
+    fn compare(&mut self) -> Result<Expr, DerivedError> {
+        let left = self.sum()?;
+        let ops = [
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ];
+        for (symbol, op) in ops {
+            if self.eat(symbol) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, DerivedError> {
+        self.chain(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, DerivedError> {
+        self.chain(
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, DerivedError> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else {
+            return self.postfix();
+        };
+        self.descend()?;
+        let arg = self.unary()?;
+        self.depth -= 1;
+        Ok(Expr::Unary(op, Box::new(arg)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, DerivedError> {
+        let mut expr = self.primary()?;
+        let depth = self.depth;
+        while self.eat("[") {
+            self.descend()?;
+            let index = self.expr()?;
+            self.expect("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, DerivedError> {
+        let position = self.position();
+        let token = match self.tokens.get(self.next) {
+            Some((_, token)) => token.clone(),
+            None => return Err(DerivedError::parse(position, "unexpected end")),
+        };
+        self.next += 1;
+        match token {
+            Token::Number(number) => Ok(Expr::Literal(number)),
+            Token::Key(key) => Ok(Expr::Key(EnokiKey::from(key.as_str()))),
+            Token::Symbol("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Name(name) => match name.as_str() {
+                "true" => Ok(Expr::Literal(EnokiValue::Boolean(true))),
+                "false" => Ok(Expr::Literal(EnokiValue::Boolean(false))),
+                _ => self.call(name, position),
+            },
+            Token::Symbol(symbol) => Err(DerivedError::parse(
+                position,
+                format!("unexpected '{}'", symbol),
+            )),
+        }
+    }
+
+    fn call(&mut self, name: String, position: usize) -> Result<Expr, DerivedError> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let arity = |expected: &str, ok: bool| {
+            if ok {
+                Ok(())
+            } else {
+                Err(DerivedError::Arity {
+                    name: name.clone(),
+                    expected: expected.to_string(),
+                    got: args.len(),
+                })
+            }
+        };
+        let function = match name.as_str() {
+            "abs" => {
+                arity("1", args.len() == 1)?;
+                return Ok(Expr::Call(Function::Abs, args));
+            }
+            "min" | "max" => {
+                arity("at least 1", !args.is_empty())?;
+                let function = if name == "min" { Function::Min } else { Function::Max };
+                return Ok(Expr::Call(function, args));
+            }
+            "derivative" | "ddt" => {
+                arity("1", args.len() == 1)?;
+                StatefulFunction::Derivative
+            }
+            "integral" => {
+                arity("1", args.len() == 1)?;
+                StatefulFunction::Integral
+            }
+            "average" | "avg" | "moving_average" => {
+                arity("2", args.len() == 2)?;
+                match args.pop() {
+                    Some(Expr::Literal(EnokiValue::Int(window)))
+                        if window > 0 && window as u64 <= MAX_AVERAGE_WINDOW as u64 =>
+                    {
+                        StatefulFunction::Average(window as usize)
+                    }
+                    Some(Expr::Literal(EnokiValue::Int(window))) if window > 0 => {
+                        return Err(DerivedError::parse(
+                            position,
+                            format!(
+                                "the window of an average can be at most {} samples",
+                                MAX_AVERAGE_WINDOW
+                            ),
+                        ))
+                    }
+                    _ => {
+                        return Err(DerivedError::parse(
+                            position,
+                            "the window of an average must be a positive whole number",
+                        ))
+                    }
+                }
+            }
+            _ => return Err(DerivedError::UnknownFunction(name)),
+        };
+        let slot = self.slots;
+        self.slots += 1;
+        Ok(Expr::Stateful {
+            function,
+            arg: Box::new(args.remove(0)),
+            slot,
+        })
+    }
+}
+
+/// Parses an expression, returns it with the number of stateful call sites in it
+pub fn parse(source: &str) -> Result<(Expr, usize), DerivedError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        next: 0,
+        end: source.len(),
+        slots: 0,
+        depth: 0,
+    };
+    let expr = parser.expr()?;
+    if parser.next < parser.tokens.len() {
+        return Err(DerivedError::parse(parser.position(), "unexpected trailing input"));
+    }
+    Ok((expr, parser.slots))
+}
+
+#[derive(Debug, Clone)]
+enum CallState {
+    Derivative {
+        previous: Option<(EnokiTimeStamp, f64)>,
+        rate: Option<f64>,
+    },
+    Integral {
+        previous: Option<(EnokiTimeStamp, f64)>,
+        sum: f64,
+    },
+    Average {
+        window: usize,
+        samples: VecDeque<f64>,
+    },
+}
+
+impl CallState {
+    fn new(function: StatefulFunction) -> Self {
+        match function {
+            StatefulFunction::Derivative => CallState::Derivative {
+                previous: None,
+                rate: None,
+            },
+            StatefulFunction::Integral => CallState::Integral {
+                previous: None,
+                sum: 0.0,
+            },
+            StatefulFunction::Average(window) => CallState::Average {
+                window,
+                // grows with the samples, a window is often far longer than the data
+                samples: VecDeque::new(),
+            },
+        }
+    }
+
+    fn step(&mut self, timestamp: EnokiTimeStamp, value: f64) -> Option<f64> {
+        let seconds = |from: EnokiTimeStamp| (timestamp - from) as f64 / 1_000_000.0;
+        match self {
+            CallState::Derivative { previous, rate } => {
+                if let Some((last, last_value)) = *previous {
+                    if timestamp > last {
+                        *rate = Some((value - last_value) / seconds(last));
+                    }
+                }
+                *previous = Some((timestamp, value));
+                *rate
+            }
+            CallState::Integral { previous, sum } => {
+                if let Some((last, last_value)) = *previous {
+                    *sum += (value + last_value) / 2.0 * seconds(last);
+                }
+                *previous = Some((timestamp, value));
+                Some(*sum)
+            }
+            CallState::Average { window, samples } => {
+                if samples.len() == *window {
+                    samples.pop_front();
+                }
+                samples.push_back(value);
+                Some(samples.iter().sum::<f64>() / samples.len() as f64)
+            }
+        }
+    }
+}
+
+fn as_f64(value: &EnokiValue) -> Option<f64> {
+    match value {
+        EnokiValue::Int(v) => Some(*v as f64),
+        EnokiValue::Float(v) => Some(*v),
+        EnokiValue::Double(v) => Some(*v),
+        EnokiValue::Boolean(v) => Some(if *v { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+fn truthy(value: &EnokiValue) -> Option<bool> {
+    match value {
+        EnokiValue::Boolean(v) => Some(*v),
+        other => as_f64(other).map(|v| v != 0.0),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: &EnokiValue, right: &EnokiValue) -> Option<EnokiValue> {
+    if let (EnokiValue::Int(a), EnokiValue::Int(b)) = (left, right) {
+        let int = match op {
+            BinaryOp::Add => a.checked_add(*b),
+            BinaryOp::Sub => a.checked_sub(*b),
+            BinaryOp::Mul => a.checked_mul(*b),
+            BinaryOp::Rem => a.checked_rem(*b),
+            _ => None,
+        };
+        if let Some(int) = int {
+            return Some(EnokiValue::Int(int));
+        }
+    }
+    let (a, b) = (as_f64(left)?, as_f64(right)?);
+    let double = match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Sub => a - b,
+        BinaryOp::Mul => a * b,
+        BinaryOp::Div => a / b,
+        BinaryOp::Rem => a % b,
+        _ => return None,
+    };
+    Some(EnokiValue::Double(double))
+}
+
+fn compare(op: BinaryOp, left: &EnokiValue, right: &EnokiValue) -> Option<EnokiValue> {
+    let result = match (as_f64(left), as_f64(right)) {
+        (Some(a), Some(b)) => match op {
+            BinaryOp::Lt => a < b,
+            BinaryOp::Le => a <= b,
+            BinaryOp::Gt => a > b,
+            BinaryOp::Ge => a >= b,
+            BinaryOp::Eq => a == b,
+            BinaryOp::Ne => a != b,
+            _ => return None,
+        },
+        // anything else can only be checked for equality
+        _ => match op {
+            BinaryOp::Eq => left == right,
+            BinaryOp::Ne => left != right,
+            _ => return None,
+        },
+    };
+    Some(EnokiValue::Boolean(result))
+}
+
+/// Evaluates an expression one timestamp at a time, keeping the state of its stateful calls
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    expr: std::sync::Arc<Expr>,
+    states: Vec<Option<CallState>>,
+    last_timestamp: Option<EnokiTimeStamp>,
+}
+
+impl Evaluator {
+    pub fn new(expr: std::sync::Arc<Expr>, slots: usize) -> Self {
+        Self {
+            expr,
+            states: vec![None; slots],
+            last_timestamp: None,
+        }
+    }
+
+    pub fn last_timestamp(&self) -> Option<EnokiTimeStamp> {
+        self.last_timestamp
+    }
+
+    /// Evaluates the expression at `timestamp` with `lookup` providing the value of every key.
+    /// Timestamps must increase between calls, older ones are ignored.
+    /// None if an input is missing or the values don't fit the operation.
+    pub fn step(
+        &mut self,
+        timestamp: EnokiTimeStamp,
+        lookup: impl Fn(&EnokiKey) -> Option<EnokiValue>,
+    ) -> Option<EnokiValue> {
+        if self.last_timestamp.map_or(false, |last| timestamp <= last) {
+            return None;
+        }
+        self.last_timestamp = Some(timestamp);
+        let expr = self.expr.clone();
+        self.eval(&expr, timestamp, &lookup)
+    }
+
+    fn eval(
+        &mut self,
+        expr: &Expr,
+        timestamp: EnokiTimeStamp,
+        lookup: &dyn Fn(&EnokiKey) -> Option<EnokiValue>,
+    ) -> Option<EnokiValue> {
+        match expr {
+            Expr::Literal(value) => Some(value.clone()),
+            Expr::Key(key) => lookup(key),
+            Expr::Unary(op, arg) => {
+                let value = self.eval(arg, timestamp, lookup)?;
+                match (op, value) {
+                    (UnaryOp::Neg, EnokiValue::Int(v)) => v.checked_neg().map(EnokiValue::Int),
+                    (UnaryOp::Neg, value) => as_f64(&value).map(|v| EnokiValue::Double(-v)),
+                    (UnaryOp::Not, value) => truthy(&value).map(|v| EnokiValue::Boolean(!v)),
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                // both sides are always evaluated so stateful calls see every sample
+                let left = self.eval(left, timestamp, lookup);
+                let right = self.eval(right, timestamp, lookup);
+                let (left, right) = (left?, right?);
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                        arithmetic(*op, &left, &right)
+                    }
+                    BinaryOp::And => Some(EnokiValue::Boolean(truthy(&left)? && truthy(&right)?)),
+                    BinaryOp::Or => Some(EnokiValue::Boolean(truthy(&left)? || truthy(&right)?)),
+                    _ => compare(*op, &left, &right),
+                }
+            }
+            Expr::Index(array, index) => {
+                let array = self.eval(array, timestamp, lookup);
+                let index = self.eval(index, timestamp, lookup);
+                match index? {
+                    EnokiValue::Int(index) if index >= 0 => array?.get_index(index as usize),
+                    _ => None,
+                }
+            }
+            Expr::Call(function, args) => {
+                // collected before checking for missing values so every argument is evaluated
+                let values = args
+                    .iter()
+                    .map(|arg| self.eval(arg, timestamp, lookup))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .collect::<Option<Vec<_>>>()?;
+                match function {
+                    Function::Abs => match &values[0] {
+                        EnokiValue::Int(v) => v.checked_abs().map(EnokiValue::Int),
+                        value => as_f64(value).map(|v| EnokiValue::Double(v.abs())),
+                    },
+                    Function::Min | Function::Max => {
+                        let min = *function == Function::Min;
+                        let ints = values
+                            .iter()
+                            .map(|value| match value {
+                                EnokiValue::Int(v) => Some(*v),
+                                _ => None,
+                            })
+                            .collect::<Option<Vec<_>>>();
+                        match ints {
+                            Some(ints) => ints
+                                .into_iter()
+                                .reduce(|a, b| if min { a.min(b) } else { a.max(b) })
+                                .map(EnokiValue::Int),
+                            None => values
+                                .iter()
+                                .map(as_f64)
+                                .collect::<Option<Vec<_>>>()?
+                                .into_iter()
+                                .reduce(|a, b| if min { a.min(b) } else { a.max(b) })
+                                .map(EnokiValue::Double),
+                        }
+                    }
+                }
+            }
+            Expr::Stateful {
+                function,
+                arg,
+                slot,
+            } => {
+                let value = as_f64(&self.eval(arg, timestamp, lookup)?)?;
+                self.states[*slot]
+                    .get_or_insert_with(|| CallState::new(*function))
+                    .step(timestamp, value)
+                    .map(EnokiValue::Double)
+            }
+        }
+    }
+}
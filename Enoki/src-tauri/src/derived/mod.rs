@@ -0,0 +1,223 @@
+pub mod expr;
+pub mod python;
+pub mod tauri_cmds;
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    enoki_types::{EnokiField, EnokiHistory, EnokiKey, EnokiObject, TimestampedEnokiValue},
+    resample::{sample_at, Interpolation},
+};
+
+use self::expr::{Evaluator, Expr};
+
+/// Derived fields evaluated live over every network table subscription, in definition order
+/// so a derived field can use the ones defined before it
+static DERIVED_FIELDS: Lazy<Mutex<Vec<DerivedField>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DerivedError {
+    #[error("{message} at {position}")]
+    Parse { position: usize, message: String },
+    #[error("Unknown function {0}")]
+    UnknownFunction(String),
+    #[error("{name} takes {expected} arguments, got {got}")]
+    Arity {
+        name: String,
+        expected: String,
+        got: usize,
+    },
+    #[error("Derived field {0} does not read any keys")]
+    NoInputs(String),
+    #[error("Derived field {0} reads itself")]
+    SelfReference(String),
+}
+
+impl DerivedError {
+    fn parse(position: usize, message: impl Into<String>) -> Self {
+        DerivedError::Parse {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+/// A field computed from other fields, e.g. `{/Drive/Left/speed} - {/Drive/Right/speed}`.
+/// See [expr] for the syntax.
+#[derive(Debug, Clone, Serialize)]
+pub struct DerivedField {
+    key: EnokiKey,
+    expression: String,
+    #[serde(skip)]
+    expr: Arc<Expr>,
+    #[serde(skip)]
+    slots: usize,
+    #[serde(skip)]
+    inputs: Vec<EnokiKey>,
+}
+
+impl DerivedField {
+    pub fn new(key: EnokiKey, expression: &str) -> Result<Self, DerivedError> {
+        let (expr, slots) = expr::parse(expression)?;
+        let inputs = expr.keys();
+        if inputs.is_empty() {
+            return Err(DerivedError::NoInputs(key.to_string()));
+        }
+        if inputs.contains(&key) {
+            return Err(DerivedError::SelfReference(key.to_string()));
+        }
+        Ok(Self {
+            key,
+            expression: expression.to_string(),
+            expr: Arc::new(expr),
+            slots,
+            inputs,
+        })
+    }
+
+    pub fn key(&self) -> &EnokiKey {
+        &self.key
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// The keys the expression reads
+    pub fn inputs(&self) -> &[EnokiKey] {
+        &self.inputs
+    }
+
+    /// A fresh evaluator, stateful functions start over
+    pub fn evaluator(&self) -> Evaluator {
+        Evaluator::new(self.expr.clone(), self.slots)
+    }
+
+    /// Evaluates the field at every timestamp any of its inputs changed at,
+    /// inputs are held at their latest value in between.
+    /// Fields without a history count as a single sample.
+    pub fn evaluate_history(&self, obj: &EnokiObject) -> EnokiHistory {
+        let histories = self
+            .inputs
+            .iter()
+            .map(|key| match obj.get_field_with_history(key)? {
+                (_, Some(history)) => Some(Cow::Borrowed(history)),
+                (field, None) => Some(Cow::Owned(EnokiHistory::from(vec![
+                    field.get_value_owned(),
+                ]))),
+            })
+            .collect::<Option<Vec<_>>>();
+        let histories = match histories {
+            Some(histories) => histories,
+            None => return EnokiHistory::new(),
+        };
+
+        let mut timestamps = histories
+            .iter()
+            .flat_map(|history| history.timestamps().iter().copied())
+            .collect::<Vec<_>>();
+        timestamps.sort_unstable();
+        timestamps.dedup();
+
+        let mut evaluator = self.evaluator();
+        timestamps
+            .into_iter()
+            .filter_map(|timestamp| {
+                let value = evaluator.step(timestamp, |key| {
+                    let index = self.inputs.iter().position(|input| input == key)?;
+                    sample_at(&histories[index], timestamp, Interpolation::ZeroOrderHold)
+                })?;
+                Some(TimestampedEnokiValue::new(timestamp, value))
+            })
+            .collect()
+    }
+}
+
+/// Adds or replaces a derived field, a replaced field keeps its place in the evaluation order
+pub fn define_derived_field(field: DerivedField) {
+    let mut fields = DERIVED_FIELDS.lock();
+    match fields.iter_mut().find(|existing| existing.key == field.key) {
+        Some(existing) => *existing = field,
+        None => fields.push(field),
+    }
+}
+
+pub fn remove_derived_field(key: &EnokiKey) -> bool {
+    let mut fields = DERIVED_FIELDS.lock();
+    let len = fields.len();
+    fields.retain(|field| &field.key != key);
+    fields.len() != len
+}
+
+pub fn derived_fields() -> Vec<DerivedField> {
+    DERIVED_FIELDS.lock().clone()
+}
+
+/// Evaluates `fields` over the histories in `obj`, in order.
+/// Their histories are added to `obj` so later fields can read earlier ones,
+/// the returned object only has the derived fields.
+pub fn derive_histories(obj: &mut EnokiObject, fields: &[DerivedField]) -> EnokiObject {
+    let mut derived = EnokiObject::new(obj.get_timestamp());
+    for field in fields {
+        let history = field.evaluate_history(obj);
+        if history.is_empty() {
+            continue;
+        }
+        obj.set_history(field.key(), history.clone());
+        derived.set_history(field.key(), history);
+    }
+    derived
+}
+
+/// Evaluates the registered derived fields over one stream of live objects,
+/// keeping the state of their stateful functions between updates
+#[derive(Debug, Default)]
+pub struct LiveDerived {
+    evaluators: HashMap<EnokiKey, (String, Evaluator)>,
+}
+
+impl LiveDerived {
+    /// Evaluates every derived field whose inputs are all in `obj` and adds the results to it,
+    /// a field is only evaluated again once one of its inputs has a newer timestamp
+    pub fn apply(&mut self, obj: &mut EnokiObject) {
+        let fields = derived_fields();
+        // redefined or removed fields start over
+        self.evaluators.retain(|key, (expression, _)| {
+            fields
+                .iter()
+                .any(|field| &field.key == key && &field.expression == expression)
+        });
+
+        for field in fields {
+            let timestamp = field
+                .inputs
+                .iter()
+                .map(|key| obj.get_field_with_history(key).map(|(f, _)| f.get_timestamp()))
+                .collect::<Option<Vec<_>>>()
+                .and_then(|timestamps| timestamps.into_iter().max());
+            let timestamp = match timestamp {
+                Some(timestamp) => timestamp,
+                None => continue,
+            };
+            let (_, evaluator) = self
+                .evaluators
+                .entry(field.key.clone())
+                .or_insert_with(|| (field.expression.clone(), field.evaluator()));
+            let value = evaluator.step(timestamp, |key| {
+                obj.get_field_with_history(key)
+                    .map(|(f, _)| f.get_value().value.clone())
+            });
+            if let Some(value) = value {
+                obj.add_field(EnokiField::new(
+                    field.key.clone(),
+                    TimestampedEnokiValue::new(timestamp, value),
+                ));
+            }
+        }
+    }
+}
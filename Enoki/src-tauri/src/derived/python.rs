@@ -0,0 +1,36 @@
+use pyo3::prelude::*;
+
+use crate::{
+    enoki_types::{EnokiKey, EnokiObject},
+    python_helpers::py_enoki_types::{PyEnokiObject, PyTimestampedEnokiValue},
+};
+
+use super::DerivedField;
+
+fn derived_field(key: String, expression: &str) -> PyResult<DerivedField> {
+    DerivedField::new(EnokiKey::from(key), expression)
+        .map_err(|err| PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string()))
+}
+
+/// Defines a derived field that is evaluated live over network table subscriptions
+#[pyfunction]
+pub fn define_derived_field(key: String, expression: String) -> PyResult<()> {
+    super::define_derived_field(derived_field(key, &expression)?);
+    Ok(())
+}
+
+#[pyfunction]
+pub fn remove_derived_field(key: String) -> bool {
+    super::remove_derived_field(&EnokiKey::from(key))
+}
+
+/// Evaluates an expression over the histories of an object without defining a field
+#[pyfunction]
+pub fn evaluate(obj: PyEnokiObject, expression: String) -> PyResult<Vec<PyTimestampedEnokiValue>> {
+    let field = derived_field("/derived".to_string(), &expression)?;
+    Ok(field
+        .evaluate_history(&EnokiObject::from(obj))
+        .iter()
+        .map(Into::into)
+        .collect())
+}
@@ -0,0 +1,44 @@
+use crate::{
+    enoki_types::EnokiKey,
+    error::{log_result, EnokiError},
+    frontend_helpers::encoding::{EncodedObject, IpcEncoding},
+    resample::tauri_cmds::HistorySource,
+};
+
+use super::{derive_histories, derived_fields, DerivedField};
+
+/// Defines a derived field, it is evaluated live over every network table subscription
+/// that has all of its inputs
+#[tauri::command]
+pub fn define_derived_field(key: String, expression: String) -> Result<(), EnokiError> {
+    let field = log_result(DerivedField::new(EnokiKey::from(key), &expression))?;
+    tracing::info!("Defined derived field {} = {}", field.key(), field.expression());
+    super::define_derived_field(field);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_derived_field(key: String) -> bool {
+    super::remove_derived_field(&EnokiKey::from(key))
+}
+
+#[tauri::command]
+pub fn list_derived_fields() -> Vec<DerivedField> {
+    derived_fields()
+}
+
+/// Evaluates the derived fields over the histories of a datalog, every defined field if none are given
+#[tauri::command]
+pub fn derive_datalog_histories(
+    source: HistorySource,
+    keys: Option<Vec<String>>,
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, EnokiError> {
+    let mut obj = log_result(source.load())?;
+    let keys = keys.map(|keys| keys.into_iter().map(EnokiKey::from).collect::<Vec<_>>());
+    let fields = derived_fields()
+        .into_iter()
+        .filter(|field| keys.as_ref().map_or(true, |keys| keys.contains(field.key())))
+        .collect::<Vec<_>>();
+    log_result(EncodedObject::encode(derive_histories(&mut obj, &fields), encoding))
+}
@@ -1,12 +1,24 @@
-use std::{collections::HashMap, fmt::{Display, self}, hash::Hash};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{Arc, Weak},
+};
 
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use regex::Regex;
 use serde::{
     de::Visitor,
     ser::{SerializeMap, SerializeSeq},
     Deserialize, Serialize,
 };
+use thiserror::Error;
 use wpilog::log::DataLogValue;
 
+use crate::units::{Unit, UnitError, UnitSystem};
+
 /// Microseconds
 pub type EnokiTimeStamp = u64;
 
@@ -119,21 +131,26 @@ impl<'de> Deserialize<'de> for EnokiValue {
                 Ok(EnokiValue::Double(value))
             }
 
-            #[cfg(any(feature = "std", feature = "alloc"))]
             #[inline]
-            fn visit_str<E>(self, value: &str) -> Result<Value, E>
-            where
-                E: serde::de::Error,
-            {
-                self.visit_string(String::from(value))
+            fn visit_str<E>(self, value: &str) -> Result<EnokiValue, E> {
+                Ok(EnokiValue::String(value.to_string()))
             }
 
-            #[cfg(any(feature = "std", feature = "alloc"))]
             #[inline]
-            fn visit_string<E>(self, value: String) -> Result<Value, E> {
+            fn visit_string<E>(self, value: String) -> Result<EnokiValue, E> {
                 Ok(EnokiValue::String(value))
             }
 
+            #[inline]
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<EnokiValue, E> {
+                Ok(EnokiValue::ByteArray(value.to_vec()))
+            }
+
+            #[inline]
+            fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<EnokiValue, E> {
+                Ok(EnokiValue::ByteArray(value))
+            }
+
             #[inline]
             fn visit_none<E>(self) -> Result<EnokiValue, E> {
                 tracing::warn!("enoki value visit none");
@@ -154,6 +171,7 @@ impl<'de> Deserialize<'de> for EnokiValue {
                 Ok(EnokiValue::DoubleArray(vec![]))
             }
 
+            /// Ints stay ints unless mixed with floats, then every element widens to a double
             #[inline]
             fn visit_seq<V>(self, mut visitor: V) -> Result<EnokiValue, V::Error>
             where
@@ -165,38 +183,31 @@ impl<'de> Deserialize<'de> for EnokiValue {
                     vec.push(elem);
                 }
 
+                let all = |f: fn(&EnokiValue) -> bool| vec.iter().all(f);
                 if vec.is_empty() {
                     Ok(EnokiValue::DoubleArray(vec![]))
+                } else if all(|v| matches!(v, EnokiValue::Int(_))) {
+                    Ok(EnokiValue::IntArray(vec.iter().map(i64::from).collect()))
+                } else if all(|v| v.is_numeric() && v.is_single()) {
+                    Ok(EnokiValue::DoubleArray(vec.iter().map(f64::from).collect()))
+                } else if all(|v| matches!(v, EnokiValue::Boolean(_))) {
+                    Ok(EnokiValue::BooleanArray(vec.iter().map(bool::from).collect()))
+                } else if all(|v| matches!(v, EnokiValue::String(_))) {
+                    Ok(EnokiValue::StringArray(vec.iter().map(String::from).collect()))
                 } else {
-                    match vec.first().unwrap().clone() {
-                        EnokiValue::Double(_) => Ok(EnokiValue::DoubleArray(
-                            vec.iter().map(|v| f64::from(v)).collect(),
-                        )),
-                        EnokiValue::Int(_) => Ok(EnokiValue::IntArray(
-                            vec.iter().map(|v| i64::from(v)).collect(),
-                        )),
-                        EnokiValue::Boolean(_) => Ok(EnokiValue::BooleanArray(
-                            vec.iter().map(|v| bool::from(v)).collect(),
-                        )),
-                        EnokiValue::String(_) => Ok(EnokiValue::StringArray(
-                            vec.iter().map(|v| String::from(v)).collect(),
-                        )),
-                        _ => {
-                            tracing::warn!("enoki value visit seq");
-                            Ok(EnokiValue::DoubleArray(vec![]))
-                        },
-                    }
+                    Err(serde::de::Error::custom(EnokiValueError::HeterogeneousArray(
+                        format!("starts with {}", vec[0].get_type()),
+                    )))
                 }
             }
 
-            #[cfg(any(feature = "std", feature = "alloc"))]
-            fn visit_map<V>(self, mut visitor: V) -> Result<Value, V::Error>
+            fn visit_map<V>(self, _visitor: V) -> Result<EnokiValue, V::Error>
             where
-                V: MapAccess<'de>,
+                V: serde::de::MapAccess<'de>,
             {
-                Err(serde::de::Error::custom(
-                    "enoki value visit map not implemented",
-                ))
+                Err(serde::de::Error::custom(EnokiValueError::Unsupported(
+                    "a map".to_string(),
+                )))
             }
         }
 
@@ -224,6 +235,22 @@ impl Display for EnokiValue {
 }
 
 impl EnokiValue {
+    /// Every name [EnokiValue::get_type] returns
+    pub const TYPE_NAMES: [&'static str; 12] = [
+        "ByteArray",
+        "Protobuf",
+        "Float",
+        "FloatArray",
+        "Double",
+        "DoubleArray",
+        "Int",
+        "IntArray",
+        "String",
+        "StringArray",
+        "Boolean",
+        "BooleanArray",
+    ];
+
     pub fn get_type(&self) -> String {
         match self {
             EnokiValue::ByteArray(_) => "ByteArray".to_string(),
@@ -354,6 +381,59 @@ impl EnokiValue {
     {
         self.get().unwrap()
     }
+
+    /// Reinterprets a value that was decoded without its type as `value_type`, a name
+    /// [EnokiValue::get_type] returns. This is how the wire formats resolve their ambiguities:
+    /// - floats and doubles look the same, so any number can become either and ints widen to both
+    /// - byte arrays and protobufs look the same and json sends both as int arrays,
+    ///   so any of those become either as long as every int fits in a byte
+    /// - an empty array has no element type, so it becomes any array type
+    pub fn coerce(self, value_type: &str) -> Result<EnokiValue, EnokiValueError> {
+        let mismatch = |found: &EnokiValue| EnokiValueError::TypeMismatch {
+            expected: value_type.to_string(),
+            found: found.get_type(),
+        };
+        let bytes = |value: EnokiValue| match value {
+            EnokiValue::ByteArray(v) | EnokiValue::Protobuf(v) => Ok(v),
+            EnokiValue::IntArray(v) => v
+                .iter()
+                .map(|i| u8::try_from(*i).map_err(|_| EnokiValueError::IntegerOutOfRange(i.to_string())))
+                .collect(),
+            other => Err(mismatch(&other)),
+        };
+        let doubles = |value: EnokiValue| match value {
+            EnokiValue::DoubleArray(v) | EnokiValue::FloatArray(v) => Ok(v),
+            EnokiValue::IntArray(v) => Ok(v.into_iter().map(|i| i as f64).collect()),
+            other => Err(mismatch(&other)),
+        };
+
+        if self.get_type() == value_type {
+            return Ok(self);
+        }
+        if self.get_len() == Some(0) {
+            return match value_type {
+                "ByteArray" => Ok(EnokiValue::ByteArray(vec![])),
+                "Protobuf" => Ok(EnokiValue::Protobuf(vec![])),
+                "FloatArray" => Ok(EnokiValue::FloatArray(vec![])),
+                "DoubleArray" => Ok(EnokiValue::DoubleArray(vec![])),
+                "IntArray" => Ok(EnokiValue::IntArray(vec![])),
+                "StringArray" => Ok(EnokiValue::StringArray(vec![])),
+                "BooleanArray" => Ok(EnokiValue::BooleanArray(vec![])),
+                _ => Err(mismatch(&self)),
+            };
+        }
+        match value_type {
+            "Float" if self.is_numeric() && self.is_single() => Ok(EnokiValue::Float(f64::from(self))),
+            "Double" if self.is_numeric() && self.is_single() => Ok(EnokiValue::Double(f64::from(self))),
+            "FloatArray" => Ok(EnokiValue::FloatArray(doubles(self)?)),
+            "DoubleArray" => Ok(EnokiValue::DoubleArray(doubles(self)?)),
+            "ByteArray" => Ok(EnokiValue::ByteArray(bytes(self)?)),
+            "Protobuf" => Ok(EnokiValue::Protobuf(bytes(self)?)),
+            "Float" | "Double" | "Int" | "IntArray" | "String" | "StringArray" | "Boolean"
+            | "BooleanArray" => Err(mismatch(&self)),
+            _ => Err(EnokiValueError::Unsupported(value_type.to_string())),
+        }
+    }
 }
 
 impl From<EnokiValue> for f32 {
@@ -592,53 +672,203 @@ impl From<&EnokiValue> for rmpv::Value {
     }
 }
 
-impl From<rmpv::Value> for EnokiValue {
-    fn from(v: rmpv::Value) -> Self {
+/// Why a value couldn't be turned into or out of an [`EnokiValue`]
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EnokiValueError {
+    #[error("Cannot convert {0} to an EnokiValue")]
+    Unsupported(String),
+    #[error("Array mixes element types: {0}")]
+    HeterogeneousArray(String),
+    #[error("Empty array has no element type to infer from")]
+    AmbiguousEmptyArray,
+    #[error("Integer {0} is out of range for an i64")]
+    IntegerOutOfRange(String),
+    #[error("Expected {expected} but got {found}")]
+    TypeMismatch { expected: String, found: String },
+    #[error("{0} has no datalog equivalent")]
+    NoDatalogEquivalent(String),
+}
+
+fn rmpv_type_name(v: &rmpv::Value) -> &'static str {
+    match v {
+        rmpv::Value::Nil => "Nil",
+        rmpv::Value::Boolean(_) => "Boolean",
+        rmpv::Value::Integer(_) => "Integer",
+        rmpv::Value::F32(_) => "F32",
+        rmpv::Value::F64(_) => "F64",
+        rmpv::Value::String(_) => "String",
+        rmpv::Value::Binary(_) => "Binary",
+        rmpv::Value::Array(_) => "Array",
+        rmpv::Value::Map(_) => "Map",
+        rmpv::Value::Ext(_, _) => "Ext",
+    }
+}
+
+fn rmpv_mismatch(expected: &str, found: &rmpv::Value) -> EnokiValueError {
+    EnokiValueError::TypeMismatch {
+        expected: expected.to_string(),
+        found: rmpv_type_name(found).to_string(),
+    }
+}
+
+fn rmpv_to_f64(v: &rmpv::Value, expected: &str) -> Result<f64, EnokiValueError> {
+    match v {
+        rmpv::Value::F32(f) => Ok(*f as f64),
+        rmpv::Value::F64(f) => Ok(*f),
+        rmpv::Value::Integer(i) => i.as_f64().ok_or_else(|| rmpv_mismatch(expected, v)),
+        _ => Err(rmpv_mismatch(expected, v)),
+    }
+}
+
+fn rmpv_to_i64(v: &rmpv::Value) -> Result<i64, EnokiValueError> {
+    match v {
+        rmpv::Value::Integer(i) => i
+            .as_i64()
+            .ok_or_else(|| EnokiValueError::IntegerOutOfRange(i.to_string())),
+        _ => Err(rmpv_mismatch("Int", v)),
+    }
+}
+
+fn rmpv_to_string(v: rmpv::Value) -> Result<String, EnokiValueError> {
+    match v {
+        // msgpack strings aren't guaranteed to be utf8, keep what we can rather than dropping the value
+        rmpv::Value::String(s) => Ok(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        other => Err(rmpv_mismatch("String", &other)),
+    }
+}
+
+fn rmpv_to_bool(v: &rmpv::Value) -> Result<bool, EnokiValueError> {
+    match v {
+        rmpv::Value::Boolean(b) => Ok(*b),
+        _ => Err(rmpv_mismatch("Boolean", v)),
+    }
+}
+
+fn rmpv_to_array(v: rmpv::Value, expected: &str) -> Result<Vec<rmpv::Value>, EnokiValueError> {
+    match v {
+        rmpv::Value::Array(arr) => Ok(arr),
+        other => Err(rmpv_mismatch(expected, &other)),
+    }
+}
+
+impl EnokiValue {
+    /// Converts a msgpack value using the type the topic was announced with,
+    /// without a hint the type is inferred from the value itself
+    pub fn from_rmpv(
+        v: rmpv::Value,
+        hint: Option<&network_tables::v4::message_type::Type>,
+    ) -> Result<EnokiValue, EnokiValueError> {
+        use network_tables::v4::message_type::Type;
+        let hint = match hint {
+            Some(hint) => hint,
+            None => return Self::infer_from_rmpv(v),
+        };
+        match hint {
+            Type::Boolean => Ok(EnokiValue::Boolean(rmpv_to_bool(&v)?)),
+            Type::Double => Ok(EnokiValue::Double(rmpv_to_f64(&v, "Double")?)),
+            Type::Float => Ok(EnokiValue::Float(rmpv_to_f64(&v, "Float")?)),
+            Type::Int => Ok(EnokiValue::Int(rmpv_to_i64(&v)?)),
+            Type::String | Type::Json => Ok(EnokiValue::String(rmpv_to_string(v)?)),
+            Type::ProtoBuf => match v {
+                rmpv::Value::Binary(b) => Ok(EnokiValue::Protobuf(b)),
+                other => Err(rmpv_mismatch("Protobuf", &other)),
+            },
+            Type::BooleanArray => Ok(EnokiValue::BooleanArray(
+                rmpv_to_array(v, "BooleanArray")?
+                    .iter()
+                    .map(rmpv_to_bool)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Type::DoubleArray => Ok(EnokiValue::DoubleArray(
+                rmpv_to_array(v, "DoubleArray")?
+                    .iter()
+                    .map(|e| rmpv_to_f64(e, "Double"))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Type::FloatArray => Ok(EnokiValue::FloatArray(
+                rmpv_to_array(v, "FloatArray")?
+                    .iter()
+                    .map(|e| rmpv_to_f64(e, "Float"))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Type::IntArray => Ok(EnokiValue::IntArray(
+                rmpv_to_array(v, "IntArray")?
+                    .iter()
+                    .map(rmpv_to_i64)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Type::StringArray => Ok(EnokiValue::StringArray(
+                rmpv_to_array(v, "StringArray")?
+                    .into_iter()
+                    .map(rmpv_to_string)
+                    .collect::<Result<_, _>>()?,
+            )),
+            // raw, rpc and msgpack topics are all opaque bytes to us
+            _ => match v {
+                rmpv::Value::Binary(b) => Ok(EnokiValue::ByteArray(b)),
+                other => Err(rmpv_mismatch("ByteArray", &other)),
+            },
+        }
+    }
+
+    fn infer_from_rmpv(v: rmpv::Value) -> Result<EnokiValue, EnokiValueError> {
         match v {
-            rmpv::Value::F32(v) => EnokiValue::Float(v as f64),
-            rmpv::Value::F64(v) => EnokiValue::Double(v),
-            rmpv::Value::Integer(v) => EnokiValue::Int(v.as_i64().unwrap_or_default()),
-            rmpv::Value::String(v) => EnokiValue::String(v.to_string().replace("\"", "")),
-            rmpv::Value::Boolean(v) => EnokiValue::Boolean(v),
-            rmpv::Value::Binary(v) => EnokiValue::ByteArray(v),
-            rmpv::Value::Array(v) => {
-                if v.len() == 0 {
-                    return EnokiValue::FloatArray(Vec::new());
-                }
-                match v[0] {
-                    rmpv::Value::F32(_) => EnokiValue::FloatArray(
-                        v.into_iter()
-                            .map(|v| v.as_f64().unwrap_or_default())
-                            .collect(),
-                    ),
-                    rmpv::Value::F64(_) => EnokiValue::DoubleArray(
-                        v.into_iter()
-                            .map(|v| v.as_f64().unwrap_or_default())
-                            .collect(),
-                    ),
-                    rmpv::Value::Integer(_) => EnokiValue::IntArray(
-                        v.into_iter()
-                            .map(|v| v.as_i64().unwrap_or_default())
-                            .collect(),
-                    ),
-                    rmpv::Value::String(_) => EnokiValue::StringArray(
-                        v.into_iter()
-                            .map(|v| v.as_str().unwrap_or("").to_owned())
-                            .collect(),
-                    ),
-                    rmpv::Value::Boolean(_) => EnokiValue::BooleanArray(
-                        v.into_iter()
-                            .map(|v| v.as_bool().unwrap_or_default())
-                            .collect(),
-                    ),
-                    _ => panic!("Cannot convert {:?} to MushroomTypes", v),
+            rmpv::Value::F32(v) => Ok(EnokiValue::Float(v as f64)),
+            rmpv::Value::F64(v) => Ok(EnokiValue::Double(v)),
+            rmpv::Value::Integer(_) => Ok(EnokiValue::Int(rmpv_to_i64(&v)?)),
+            rmpv::Value::String(_) => Ok(EnokiValue::String(rmpv_to_string(v)?)),
+            rmpv::Value::Boolean(v) => Ok(EnokiValue::Boolean(v)),
+            rmpv::Value::Binary(v) => Ok(EnokiValue::ByteArray(v)),
+            rmpv::Value::Array(arr) => {
+                let first = arr.first().ok_or(EnokiValueError::AmbiguousEmptyArray)?;
+                let all = |f: fn(&rmpv::Value) -> bool| arr.iter().all(f);
+                if all(rmpv::Value::is_bool) {
+                    Ok(EnokiValue::BooleanArray(
+                        arr.iter().map(rmpv_to_bool).collect::<Result<_, _>>()?,
+                    ))
+                } else if all(rmpv::Value::is_str) {
+                    Ok(EnokiValue::StringArray(
+                        arr.into_iter().map(rmpv_to_string).collect::<Result<_, _>>()?,
+                    ))
+                } else if all(|e| e.is_i64() || e.is_u64()) {
+                    Ok(EnokiValue::IntArray(
+                        arr.iter().map(rmpv_to_i64).collect::<Result<_, _>>()?,
+                    ))
+                } else if all(|e| matches!(e, rmpv::Value::F32(_))) {
+                    Ok(EnokiValue::FloatArray(
+                        arr.iter()
+                            .map(|e| rmpv_to_f64(e, "Float"))
+                            .collect::<Result<_, _>>()?,
+                    ))
+                } else if all(rmpv::Value::is_number) {
+                    // mixed ints and floats widen to doubles
+                    Ok(EnokiValue::DoubleArray(
+                        arr.iter()
+                            .map(|e| rmpv_to_f64(e, "Double"))
+                            .collect::<Result<_, _>>()?,
+                    ))
+                } else {
+                    Err(EnokiValueError::HeterogeneousArray(format!(
+                        "starts with {}",
+                        rmpv_type_name(first)
+                    )))
                 }
             }
-            _ => panic!("Cannot convert {:?} to MushroomTypes", v),
+            other => Err(EnokiValueError::Unsupported(
+                rmpv_type_name(&other).to_string(),
+            )),
         }
     }
 }
 
+impl TryFrom<rmpv::Value> for EnokiValue {
+    type Error = EnokiValueError;
+
+    fn try_from(v: rmpv::Value) -> Result<Self, Self::Error> {
+        EnokiValue::from_rmpv(v, None)
+    }
+}
+
 impl From<EnokiValue> for network_tables::v4::message_type::Type {
     fn from(m: EnokiValue) -> Self {
         match m {
@@ -697,23 +927,25 @@ impl From<DataLogValue> for EnokiValue {
     }
 }
 
-impl From<EnokiValue> for DataLogValue {
-    fn from(m: EnokiValue) -> Self {
+impl TryFrom<EnokiValue> for DataLogValue {
+    type Error = EnokiValueError;
+
+    fn try_from(m: EnokiValue) -> Result<Self, Self::Error> {
         match m {
-            EnokiValue::Boolean(v) => DataLogValue::Boolean(v),
-            EnokiValue::Double(v) => DataLogValue::Double(v),
-            EnokiValue::Float(v) => DataLogValue::Float(v as f32),
-            EnokiValue::Int(v) => DataLogValue::Integer(v),
-            EnokiValue::String(v) => DataLogValue::String(v),
-            EnokiValue::BooleanArray(v) => DataLogValue::BooleanArray(v),
-            EnokiValue::DoubleArray(v) => DataLogValue::DoubleArray(v),
-            EnokiValue::FloatArray(v) => {
-                DataLogValue::FloatArray(v.into_iter().map(|v| v as f32).collect())
-            }
-            EnokiValue::IntArray(v) => DataLogValue::IntegerArray(v),
-            EnokiValue::StringArray(v) => DataLogValue::StringArray(v),
-            EnokiValue::ByteArray(v) => DataLogValue::Raw(v),
-            _ => panic!("Cannot convert {:?} to DataLogValue", m),
+            EnokiValue::Boolean(v) => Ok(DataLogValue::Boolean(v)),
+            EnokiValue::Double(v) => Ok(DataLogValue::Double(v)),
+            EnokiValue::Float(v) => Ok(DataLogValue::Float(v as f32)),
+            EnokiValue::Int(v) => Ok(DataLogValue::Integer(v)),
+            EnokiValue::String(v) => Ok(DataLogValue::String(v)),
+            EnokiValue::BooleanArray(v) => Ok(DataLogValue::BooleanArray(v)),
+            EnokiValue::DoubleArray(v) => Ok(DataLogValue::DoubleArray(v)),
+            EnokiValue::FloatArray(v) => Ok(DataLogValue::FloatArray(
+                v.into_iter().map(|v| v as f32).collect(),
+            )),
+            EnokiValue::IntArray(v) => Ok(DataLogValue::IntegerArray(v)),
+            EnokiValue::StringArray(v) => Ok(DataLogValue::StringArray(v)),
+            EnokiValue::ByteArray(v) => Ok(DataLogValue::Raw(v)),
+            EnokiValue::Protobuf(_) => Err(EnokiValueError::NoDatalogEquivalent(m.get_type())),
         }
     }
 }
@@ -803,65 +1035,30 @@ impl<'a> Visitor<'a> for TimestampedEnokiValueVisitor {
     where
         V: serde::de::MapAccess<'a>,
     {
-        let mut value = None;
-        let timestamp;
-
-        let val_type = map.next_value::<String>()?;
-        match val_type.as_str() {
-            "ByteArray" => {
-                let v = map.next_value::<Vec<u8>>()?;
-                value = Some(EnokiValue::ByteArray(v));
-            }
-            "Protobuf" => {
-                let v = map.next_value::<Vec<u8>>()?;
-                value = Some(EnokiValue::Protobuf(v));
-            }
-            "Float" => {
-                let v = map.next_value::<f64>()?;
-                value = Some(EnokiValue::Float(v));
-            }
-            "FloatArray" => {
-                let v = map.next_value::<Vec<f64>>()?;
-                value = Some(EnokiValue::FloatArray(v));
-            }
-            "Double" => {
-                let v = map.next_value::<f64>()?;
-                value = Some(EnokiValue::Double(v));
-            }
-            "DoubleArray" => {
-                let v = map.next_value::<Vec<f64>>()?;
-                value = Some(EnokiValue::DoubleArray(v));
-            }
-            "Int" => {
-                let v = map.next_value::<i64>()?;
-                value = Some(EnokiValue::Int(v));
-            }
-            "IntArray" => {
-                let v = map.next_value::<Vec<i64>>()?;
-                value = Some(EnokiValue::IntArray(v));
-            }
-            "String" => {
-                let v = map.next_value::<String>()?;
-                value = Some(EnokiValue::String(v));
-            }
-            "StringArray" => {
-                let v = map.next_value::<Vec<String>>()?;
-                value = Some(EnokiValue::StringArray(v));
-            }
-            "Boolean" => {
-                let v = map.next_value::<bool>()?;
-                value = Some(EnokiValue::Boolean(v));
-            }
-            "BooleanArray" => {
-                let v = map.next_value::<Vec<bool>>()?;
-                value = Some(EnokiValue::BooleanArray(v));
+        let mut value_type: Option<String> = None;
+        let mut value: Option<EnokiValue> = None;
+        let mut timestamp: Option<EnokiTimeStamp> = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => value_type = Some(map.next_value()?),
+                "value" => value = Some(map.next_value()?),
+                "timestamp" => timestamp = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
             }
-            _ => {}
-        };
-        timestamp = Some(map.next_value::<EnokiTimeStamp>()?);
+        }
 
         let value = value.ok_or_else(|| serde::de::Error::missing_field("value"))?;
         let timestamp = timestamp.ok_or_else(|| serde::de::Error::missing_field("timestamp"))?;
+        // the value was inferred without knowing its type, the type tag decides what it is
+        let value = match value_type {
+            Some(value_type) => value
+                .coerce(&value_type)
+                .map_err(serde::de::Error::custom)?,
+            None => value,
+        };
 
         Ok(TimestampedEnokiValue { value, timestamp })
     }
@@ -882,59 +1079,410 @@ impl Display for TimestampedEnokiValue {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// A normalized path to a field, always absolute with no empty segments.
+///
+/// `/` separates segments, a literal `/`, `*`, `?` or `\` inside a segment is escaped with `\`
+///
+/// Keys are interned, every key with the same path shares one allocation,
+/// so cloning is a reference count, equality is usually a pointer compare
+/// and hashing uses the hash computed when the key was first made.
+#[derive(Clone)]
 pub struct EnokiKey {
+    interned: Arc<InternedKey>,
+}
+
+struct InternedKey {
     path: Vec<String>,
+    hash: u64,
+}
+
+/// Every live key by path, entries of dropped keys are purged once the table has doubled
+static KEY_INTERNER: Lazy<Mutex<KeyInterner>> = Lazy::new(|| {
+    Mutex::new(KeyInterner {
+        keys: HashMap::new(),
+        purge_at: 1024,
+    })
+});
+
+struct KeyInterner {
+    keys: HashMap<Vec<String>, Weak<InternedKey>>,
+    purge_at: usize,
+}
+
+impl KeyInterner {
+    fn intern(&mut self, path: Vec<String>) -> Arc<InternedKey> {
+        if let Some(interned) = self.keys.get(&path).and_then(Weak::upgrade) {
+            return interned;
+        }
+        if self.keys.len() >= self.purge_at {
+            self.keys.retain(|_, key| key.strong_count() > 0);
+            self.purge_at = (self.keys.len() * 2).max(1024);
+        }
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        let interned = Arc::new(InternedKey {
+            path: path.clone(),
+            hash: hasher.finish(),
+        });
+        self.keys.insert(path, Arc::downgrade(&interned));
+        interned
+    }
+}
+
+impl PartialEq for EnokiKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.interned, &other.interned) || self.path() == other.path()
+    }
+}
+
+impl Eq for EnokiKey {}
+
+impl Hash for EnokiKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.interned.hash);
+    }
+}
+
+impl PartialOrd for EnokiKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EnokiKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.path().cmp(other.path())
+    }
+}
+
+impl fmt::Debug for EnokiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnokiKey")
+            .field("path", &self.path())
+            .finish()
+    }
+}
+
+const KEY_SEPARATOR: char = '/';
+const KEY_ESCAPE: char = '\\';
+const KEY_RESERVED: [char; 4] = ['/', '*', '?', '\\'];
+
+fn escape_key_segment(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if KEY_RESERVED.contains(&c) {
+            escaped.push(KEY_ESCAPE);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Splits on unescaped separators, the bool per char is true if it was escaped
+fn split_key_segments(key: &str) -> Vec<Vec<(char, bool)>> {
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            KEY_ESCAPE => match chars.next() {
+                Some(escaped) => current.push((escaped, true)),
+                // a trailing escape has nothing to escape, keep it as is
+                None => current.push((KEY_ESCAPE, true)),
+            },
+            KEY_SEPARATOR => {
+                if !current.is_empty() {
+                    segments.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push((c, false)),
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
 }
 
 impl EnokiKey {
-    pub fn prefix(mut self, prefix: String) -> Self {
-        self.path.insert(0, prefix);
-        self
+    pub fn new(segments: Vec<String>) -> Self {
+        Self::from_path(segments.into_iter().filter(|s| !s.is_empty()).collect())
     }
 
-    pub fn suffix(mut self, suffix: String) -> Self {
-        self.path.push(suffix);
-        self
+    fn from_path(path: Vec<String>) -> Self {
+        Self {
+            interned: KEY_INTERNER.lock().intern(path),
+        }
+    }
+
+    fn path(&self) -> &[String] {
+        &self.interned.path
+    }
+
+    pub fn root() -> Self {
+        Self::from_path(Vec::new())
+    }
+
+    /// The key of a network table topic, its name is split on `/` and nothing in it is an escape
+    pub fn from_topic_name(name: &str) -> Self {
+        Self::new(name.split(KEY_SEPARATOR).map(str::to_string).collect())
+    }
+
+    /// The network table topic the key stands for, the unescaped segments joined by `/`.
+    /// Use it for network table io, `String::from` is escaped and only for showing and globbing keys.
+    pub fn topic_name(&self) -> String {
+        let mut name = String::new();
+        for segment in self.path() {
+            name.push(KEY_SEPARATOR);
+            name.push_str(segment);
+        }
+        if name.is_empty() {
+            name.push(KEY_SEPARATOR);
+        }
+        name
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.path().is_empty()
+    }
+
+    /// The unescaped segments of the key
+    pub fn segments(&self) -> &[String] {
+        self.path()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.path().len()
+    }
+
+    /// The last segment, none for the root
+    pub fn name(&self) -> Option<&str> {
+        self.path().last().map(|s| s.as_str())
+    }
+
+    pub fn prefix(self, prefix: String) -> Self {
+        if prefix.is_empty() {
+            return self;
+        }
+        let mut path = Vec::with_capacity(self.depth() + 1);
+        path.push(prefix);
+        path.extend(self.path().iter().cloned());
+        Self::from_path(path)
+    }
+
+    pub fn suffix(self, suffix: String) -> Self {
+        if suffix.is_empty() {
+            return self;
+        }
+        let mut path = self.path().to_vec();
+        path.push(suffix);
+        Self::from_path(path)
+    }
+
+    /// The key one level up, none for the root
+    pub fn parent(&self) -> Option<EnokiKey> {
+        if self.is_root() {
+            return None;
+        }
+        Some(Self::from_path(self.path()[..self.depth() - 1].to_vec()))
+    }
+
+    /// Appends a single literal segment, reserved characters in it are not separators
+    pub fn child(&self, segment: impl Into<String>) -> EnokiKey {
+        self.clone().suffix(segment.into())
+    }
+
+    pub fn join(&self, other: &EnokiKey) -> EnokiKey {
+        let mut path = self.path().to_vec();
+        path.extend(other.path().iter().cloned());
+        Self::from_path(path)
+    }
+
+    /// True if every segment of `prefix` matches the start of this key, whole segments only
+    pub fn starts_with(&self, prefix: &EnokiKey) -> bool {
+        self.path().starts_with(prefix.path())
+    }
+
+    /// The rest of the key after `prefix`, as a key rooted at `prefix`
+    pub fn strip_prefix(&self, prefix: &EnokiKey) -> Option<EnokiKey> {
+        if self.starts_with(prefix) {
+            Some(Self::from_path(self.path()[prefix.depth()..].to_vec()))
+        } else {
+            None
+        }
+    }
+
+    /// A relative path from `base` to this key using `..` to go up, empty if they are equal
+    pub fn relative_to(&self, base: &EnokiKey) -> String {
+        let common = self
+            .path()
+            .iter()
+            .zip(base.path().iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut parts: Vec<String> = std::iter::repeat("..".to_string())
+            .take(base.depth() - common)
+            .collect();
+        parts.extend(self.path()[common..].iter().map(|s| escape_key_segment(s)));
+        parts.join("/")
+    }
+
+    /// Resolves a path relative to this key, `..` goes up a level and `.` is ignored,
+    /// a path starting with `/` is absolute
+    pub fn resolve(&self, relative: &str) -> EnokiKey {
+        let mut path = if relative.starts_with(KEY_SEPARATOR) {
+            Vec::new()
+        } else {
+            self.path().to_vec()
+        };
+        for segment in split_key_segments(relative) {
+            let all_literal = segment.iter().all(|(_, escaped)| !escaped);
+            let text: String = segment.iter().map(|(c, _)| c).collect();
+            match text.as_str() {
+                ".." if all_literal => {
+                    path.pop();
+                }
+                "." if all_literal => {}
+                _ => path.push(text),
+            }
+        }
+        Self::from_path(path)
+    }
+
+    /// Matches the key against a glob, see [`EnokiKeyPattern`]
+    pub fn matches(&self, pattern: &str) -> bool {
+        EnokiKeyPattern::new(pattern).matches(self)
     }
 }
 
 impl From<EnokiKey> for String {
     fn from(m: EnokiKey) -> Self {
-        m.path.join("/")
+        String::from(&m)
     }
 }
 
 impl From<&EnokiKey> for String {
     fn from(m: &EnokiKey) -> Self {
-        m.path.join("/")
+        let mut s = String::new();
+        for segment in m.path() {
+            s.push(KEY_SEPARATOR);
+            s.push_str(&escape_key_segment(segment));
+        }
+        if s.is_empty() {
+            s.push(KEY_SEPARATOR);
+        }
+        s
     }
 }
 
 impl From<String> for EnokiKey {
     fn from(m: String) -> Self {
-        Self {
-            path: m
-                .replace(":", "/")
-                .split("/")
-                .map(|s| s.to_string())
-                .collect(),
-        }
+        EnokiKey::from(m.as_str())
     }
 }
 
 impl From<&str> for EnokiKey {
     fn from(m: &str) -> Self {
-        Self {
-            path: m
-                .replace(":", "/")
-                .split("/")
-                .map(|s| s.to_string())
+        Self::from_path(
+            split_key_segments(m)
+                .into_iter()
+                .map(|segment| segment.into_iter().map(|(c, _)| c).collect())
                 .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum KeyPatternChar {
+    Literal(char),
+    /// `?`, any single character
+    AnyChar,
+    /// `*` inside a segment, any run of characters
+    AnyChars,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum KeyPatternSegment {
+    Segment(Vec<KeyPatternChar>),
+    /// `**`, zero or more whole segments
+    AnySegments,
+}
+
+/// A glob over [`EnokiKey`]s.
+///
+/// `*` matches within a single segment, `?` matches one character and a segment that is
+/// exactly `**` matches any number of segments, so `/Drive/*/velocity` and `/**/temp` work as expected
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnokiKeyPattern {
+    segments: Vec<KeyPatternSegment>,
+}
+
+impl EnokiKeyPattern {
+    pub fn new(pattern: &str) -> Self {
+        let segments = split_key_segments(pattern)
+            .into_iter()
+            .map(|segment| {
+                if segment == [('*', false), ('*', false)] {
+                    return KeyPatternSegment::AnySegments;
+                }
+                KeyPatternSegment::Segment(
+                    segment
+                        .into_iter()
+                        .map(|(c, escaped)| match (c, escaped) {
+                            ('*', false) => KeyPatternChar::AnyChars,
+                            ('?', false) => KeyPatternChar::AnyChar,
+                            (c, _) => KeyPatternChar::Literal(c),
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        Self { segments }
+    }
+
+    pub fn matches(&self, key: &EnokiKey) -> bool {
+        Self::match_segments(&self.segments, key.path())
+    }
+
+    fn match_segments(pattern: &[KeyPatternSegment], path: &[String]) -> bool {
+        match pattern.first() {
+            None => path.is_empty(),
+            Some(KeyPatternSegment::AnySegments) => {
+                (0..=path.len()).any(|skip| Self::match_segments(&pattern[1..], &path[skip..]))
+            }
+            Some(KeyPatternSegment::Segment(chars)) => match path.first() {
+                Some(segment) => {
+                    let segment: Vec<char> = segment.chars().collect();
+                    Self::match_chars(chars, &segment)
+                        && Self::match_segments(&pattern[1..], &path[1..])
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn match_chars(pattern: &[KeyPatternChar], text: &[char]) -> bool {
+        match pattern.first() {
+            None => text.is_empty(),
+            Some(KeyPatternChar::AnyChars) => {
+                (0..=text.len()).any(|skip| Self::match_chars(&pattern[1..], &text[skip..]))
+            }
+            Some(KeyPatternChar::AnyChar) => {
+                !text.is_empty() && Self::match_chars(&pattern[1..], &text[1..])
+            }
+            Some(KeyPatternChar::Literal(c)) => {
+                text.first() == Some(c) && Self::match_chars(&pattern[1..], &text[1..])
+            }
         }
     }
 }
 
+impl From<&str> for EnokiKeyPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
 impl Serialize for EnokiKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -967,21 +1515,55 @@ impl Display for EnokiKey {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct EnokiField {
     value: TimestampedEnokiValue,
     key: EnokiKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unit: Option<Unit>,
 }
 
 impl Display for EnokiField {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {}", self.key, self.value)
+        match &self.unit {
+            Some(unit) => write!(f, "{}: {} {}", self.key, self.value, unit),
+            None => write!(f, "{}: {}", self.key, self.value),
+        }
     }
 }
 
 impl EnokiField {
     pub fn new(key: EnokiKey, value: TimestampedEnokiValue) -> Self {
-        Self { key, value }
+        Self {
+            key,
+            value,
+            unit: None,
+        }
+    }
+
+    pub fn with_unit(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn get_unit(&self) -> Option<&Unit> {
+        self.unit.as_ref()
+    }
+
+    /// The field with its value converted, fields without a unit can't be converted
+    pub fn convert_to(&self, to: &Unit) -> Result<EnokiField, UnitError> {
+        let from = self.unit.as_ref().ok_or_else(|| UnitError::Incompatible {
+            from: "no unit".to_string(),
+            to: to.to_string(),
+        })?;
+        Ok(Self {
+            key: self.key.clone(),
+            value: TimestampedEnokiValue::new(
+                self.value.timestamp,
+                from.convert_value(&self.value.value, to)?,
+            ),
+            unit: Some(to.clone()),
+        })
     }
 
     pub fn get_key(&self) -> &EnokiKey {
@@ -1001,14 +1583,345 @@ impl EnokiField {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+macro_rules! enoki_columns {
+    ($($variant:ident($ty:ty)),* $(,)?) => {
+        /// The values of a history stored as one typed array,
+        /// falls back to `Mixed` if a field ever changes type
+        #[derive(Clone, Debug, PartialEq)]
+        enum EnokiColumn {
+            $($variant(Vec<$ty>),)*
+            Mixed(Vec<EnokiValue>),
+        }
+
+        impl EnokiColumn {
+            fn empty_for(value: &EnokiValue) -> Self {
+                match value {
+                    $(EnokiValue::$variant(_) => EnokiColumn::$variant(Vec::new()),)*
+                }
+            }
+
+            fn get(&self, index: usize) -> Option<EnokiValue> {
+                match self {
+                    $(EnokiColumn::$variant(v) => v.get(index).cloned().map(EnokiValue::$variant),)*
+                    EnokiColumn::Mixed(v) => v.get(index).cloned(),
+                }
+            }
+
+            /// Gives the value back if the column holds another type
+            fn insert(&mut self, index: usize, value: EnokiValue) -> Result<(), EnokiValue> {
+                match (self, value) {
+                    $((EnokiColumn::$variant(v), EnokiValue::$variant(x)) => v.insert(index, x),)*
+                    (EnokiColumn::Mixed(v), x) => v.insert(index, x),
+                    (_, x) => return Err(x),
+                }
+                Ok(())
+            }
+
+            fn slice(&self, range: Range<usize>) -> Self {
+                match self {
+                    $(EnokiColumn::$variant(v) => EnokiColumn::$variant(v[range].to_vec()),)*
+                    EnokiColumn::Mixed(v) => EnokiColumn::Mixed(v[range].to_vec()),
+                }
+            }
+
+            fn into_values(self) -> Vec<EnokiValue> {
+                match self {
+                    $(EnokiColumn::$variant(v) => v.into_iter().map(EnokiValue::$variant).collect(),)*
+                    EnokiColumn::Mixed(v) => v,
+                }
+            }
+        }
+    };
+}
+
+enoki_columns!(
+    ByteArray(Vec<u8>),
+    Protobuf(Vec<u8>),
+    Float(f64),
+    FloatArray(Vec<f64>),
+    Double(f64),
+    DoubleArray(Vec<f64>),
+    Int(i64),
+    IntArray(Vec<i64>),
+    String(String),
+    StringArray(Vec<String>),
+    Boolean(bool),
+    BooleanArray(Vec<bool>),
+);
+
+/// The values a field had over time, kept sorted by timestamp.
+///
+/// Timestamps and values are stored as separate arrays so a double field costs
+/// 16 bytes a sample and range lookups are a binary search over the timestamps.
+/// Serializes as a list of [TimestampedEnokiValue].
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnokiHistory {
+    timestamps: Vec<EnokiTimeStamp>,
+    values: EnokiColumn,
+}
+
+impl Default for EnokiHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnokiHistory {
+    pub fn new() -> Self {
+        Self {
+            timestamps: Vec::new(),
+            values: EnokiColumn::Mixed(Vec::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    pub fn timestamps(&self) -> &[EnokiTimeStamp] {
+        &self.timestamps
+    }
+
+    /// Appends a sample, samples older than the newest one are inserted in order
+    pub fn push(&mut self, sample: TimestampedEnokiValue) {
+        if self.is_empty() {
+            self.values = EnokiColumn::empty_for(&sample.value);
+        }
+        let index = match self.timestamps.last() {
+            Some(last) if *last > sample.timestamp => self
+                .timestamps
+                .partition_point(|timestamp| *timestamp <= sample.timestamp),
+            _ => self.len(),
+        };
+        if let Err(value) = self.values.insert(index, sample.value) {
+            let values = std::mem::replace(&mut self.values, EnokiColumn::Mixed(Vec::new()));
+            let mut values = values.into_values();
+            values.insert(index, value);
+            self.values = EnokiColumn::Mixed(values);
+        }
+        self.timestamps.insert(index, sample.timestamp);
+    }
+
+    pub fn get(&self, index: usize) -> Option<TimestampedEnokiValue> {
+        let timestamp = *self.timestamps.get(index)?;
+        let value = self.values.get(index)?;
+        Some(TimestampedEnokiValue::new(timestamp, value))
+    }
+
+    pub fn first(&self) -> Option<TimestampedEnokiValue> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<TimestampedEnokiValue> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// The latest sample at or before `timestamp`
+    pub fn value_at(&self, timestamp: EnokiTimeStamp) -> Option<TimestampedEnokiValue> {
+        let index = self.timestamps.partition_point(|t| *t <= timestamp);
+        self.get(index.checked_sub(1)?)
+    }
+
+    /// The samples within `[after, before]` without copying them
+    pub fn range(
+        &self,
+        after: Option<EnokiTimeStamp>,
+        before: Option<EnokiTimeStamp>,
+    ) -> EnokiHistoryView<'_> {
+        let start = after.map_or(0, |after| self.timestamps.partition_point(|t| *t < after));
+        let end = before.map_or(self.len(), |before| {
+            self.timestamps.partition_point(|t| *t <= before)
+        });
+        EnokiHistoryView {
+            history: self,
+            indices: start..end.max(start),
+        }
+    }
+
+    pub fn view(&self) -> EnokiHistoryView<'_> {
+        EnokiHistoryView {
+            history: self,
+            indices: 0..self.len(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TimestampedEnokiValue> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+
+    pub fn to_vec(&self) -> Vec<TimestampedEnokiValue> {
+        self.iter().collect()
+    }
+
+    /// True if the field changed type at some point
+    pub fn is_mixed(&self) -> bool {
+        !self.is_empty() && matches!(self.values, EnokiColumn::Mixed(_))
+    }
+
+    /// The type name shared by every sample, `Mixed` if they differ
+    pub fn value_type(&self) -> String {
+        if self.is_mixed() {
+            return "Mixed".to_string();
+        }
+        self.values
+            .get(0)
+            .map(|value| value.get_type())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    /// The values as one slice if the history only holds floats or only doubles
+    pub fn doubles(&self) -> Option<&[f64]> {
+        match &self.values {
+            EnokiColumn::Float(values) | EnokiColumn::Double(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// A borrowed, contiguous part of an [EnokiHistory]
+#[derive(Clone, Debug)]
+pub struct EnokiHistoryView<'a> {
+    history: &'a EnokiHistory,
+    indices: Range<usize>,
+}
+
+impl<'a> EnokiHistoryView<'a> {
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn timestamps(&self) -> &'a [EnokiTimeStamp] {
+        &self.history.timestamps[self.indices.clone()]
+    }
+
+    pub fn get(&self, index: usize) -> Option<TimestampedEnokiValue> {
+        if index >= self.len() {
+            return None;
+        }
+        self.history.get(self.indices.start + index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TimestampedEnokiValue> + 'a {
+        let history = self.history;
+        self.indices
+            .clone()
+            .filter_map(move |index| history.get(index))
+    }
+
+    /// Copies the viewed samples into their own history
+    pub fn to_history(&self) -> EnokiHistory {
+        if self.is_empty() {
+            return EnokiHistory::new();
+        }
+        EnokiHistory {
+            timestamps: self.timestamps().to_vec(),
+            values: self.history.values.slice(self.indices.clone()),
+        }
+    }
+}
+
+impl FromIterator<TimestampedEnokiValue> for EnokiHistory {
+    fn from_iter<T: IntoIterator<Item = TimestampedEnokiValue>>(iter: T) -> Self {
+        let mut history = EnokiHistory::new();
+        for sample in iter {
+            history.push(sample);
+        }
+        history
+    }
+}
+
+impl From<Vec<TimestampedEnokiValue>> for EnokiHistory {
+    fn from(samples: Vec<TimestampedEnokiValue>) -> Self {
+        samples.into_iter().collect()
+    }
+}
+
+impl From<EnokiHistory> for Vec<TimestampedEnokiValue> {
+    fn from(history: EnokiHistory) -> Self {
+        history
+            .timestamps
+            .into_iter()
+            .zip(history.values.into_values())
+            .map(|(timestamp, value)| TimestampedEnokiValue::new(timestamp, value))
+            .collect()
+    }
+}
+
+impl Serialize for EnokiHistory {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for sample in self.iter() {
+            seq.serialize_element(&sample)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'a> Deserialize<'a> for EnokiHistory {
+    fn deserialize<D>(deserializer: D) -> Result<EnokiHistory, D::Error>
+    where
+        D: serde::Deserializer<'a>,
+    {
+        Ok(Vec::<TimestampedEnokiValue>::deserialize(deserializer)?.into())
+    }
+}
+
+/// Deserializes from the same shape it serializes to, `paths` is rebuilt from `fields`
+/// so it may be left out and `history` may be missing or shorter than `fields`
+///
+/// Fields, histories and paths are shared between clones, cloning copies a pointer per field
+/// and writing to a clone copies only the field or history that is written.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "EnokiObjectRepr")]
 pub struct EnokiObject {
+    fields: Vec<Arc<EnokiField>>,
+    history: Vec<Option<Arc<EnokiHistory>>>,
+    paths: Arc<HashMap<EnokiKey, usize>>,
+    timestamp: EnokiTimeStamp,
+}
+
+#[derive(Deserialize)]
+struct EnokiObjectRepr {
     fields: Vec<EnokiField>,
-    history: Vec<Option<Vec<TimestampedEnokiValue>>>,
-    paths: HashMap<EnokiKey, usize>,
+    #[serde(default)]
+    history: Vec<Option<EnokiHistory>>,
     timestamp: EnokiTimeStamp,
 }
 
+impl TryFrom<EnokiObjectRepr> for EnokiObject {
+    type Error = String;
+
+    fn try_from(repr: EnokiObjectRepr) -> Result<Self, Self::Error> {
+        if repr.history.len() > repr.fields.len() {
+            return Err(format!(
+                "{} histories for {} fields",
+                repr.history.len(),
+                repr.fields.len()
+            ));
+        }
+        let mut obj = EnokiObject::new(repr.timestamp);
+        let mut history = repr.history.into_iter();
+        for field in repr.fields {
+            match history.next().flatten() {
+                Some(history) => obj.add_field_with_history(field, history),
+                None => obj.add_field(field),
+            }
+        }
+        Ok(obj)
+    }
+}
+
 impl Display for EnokiObject {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Table at {}, contains:", self.timestamp)?;
@@ -1025,7 +1938,7 @@ impl EnokiObject {
             timestamp,
             fields: Vec::new(),
             history: Vec::new(),
-            paths: HashMap::new(),
+            paths: Arc::new(HashMap::new()),
         }
     }
 
@@ -1036,75 +1949,153 @@ impl EnokiObject {
         obj
     }
 
+    /// Adds or replaces a field, a replacement without a unit keeps the unit the field had
     pub fn add_field(&mut self, entry: EnokiField) {
-        if self.has_field(&entry.get_key()) {
-            let index = self.paths.get(&entry.get_key()).unwrap();
-            self.fields[*index] = entry;
-        } else {
-            let path = entry.get_key();
-            self.paths.insert(path.to_owned(), self.fields.len());
-            self.fields.push(entry);
-            self.history.push(None);
-        }
-    }
-
-    pub fn add_field_with_history(
-        &mut self,
-        entry: EnokiField,
-        history: Vec<TimestampedEnokiValue>,
-    ) {
-        if self.has_field(&entry.get_key()) {
-            let index = self.paths.get(&entry.get_key()).unwrap();
-            self.fields[*index] = entry;
-            self.history[*index] = Some(history);
-        } else {
-            let path = entry.get_key();
-            self.paths.insert(path.to_owned(), self.fields.len());
-            self.fields.push(entry);
-            self.history.push(Some(history));
+        self.add_shared_field(Arc::new(entry));
+    }
+
+    fn add_shared_field(&mut self, entry: Arc<EnokiField>) {
+        match self.paths.get(entry.get_key()) {
+            Some(&index) => {
+                let previous_unit = &self.fields[index].unit;
+                self.fields[index] = if entry.unit.is_none() && previous_unit.is_some() {
+                    let mut entry = EnokiField::clone(&entry);
+                    entry.unit = previous_unit.clone();
+                    Arc::new(entry)
+                } else {
+                    entry
+                };
+            }
+            None => self.push_field(entry, None),
         }
     }
 
-    pub fn set_history(&mut self, path: &EnokiKey, history: Vec<TimestampedEnokiValue>) {
-        if self.has_field(path) {
-            let index = self.paths.get(path).unwrap();
-            self.history[*index] = Some(history);
-        } else {
-            let entry = EnokiField::new(path.to_owned(), history.last().unwrap().to_owned());
+    fn push_field(&mut self, entry: Arc<EnokiField>, history: Option<Arc<EnokiHistory>>) {
+        Arc::make_mut(&mut self.paths).insert(entry.get_key().to_owned(), self.fields.len());
+        self.fields.push(entry);
+        self.history.push(history);
+    }
+
+    pub fn add_field_with_history(&mut self, entry: EnokiField, history: impl Into<EnokiHistory>) {
+        let history = Arc::new(history.into());
+        match self.paths.get(entry.get_key()) {
+            Some(&index) => {
+                self.fields[index] = Arc::new(entry);
+                self.history[index] = Some(history);
+            }
+            None => self.push_field(Arc::new(entry), Some(history)),
+        }
+    }
+
+    /// Replaces the history of a field, a missing field is added with the latest sample as its value.
+    /// An empty history for a missing field is ignored as there is no value to give the field.
+    pub fn set_history(&mut self, path: &EnokiKey, history: impl Into<EnokiHistory>) {
+        let history = history.into();
+        if let Some(index) = self.paths.get(path) {
+            self.history[*index] = Some(Arc::new(history));
+        } else if let Some(latest) = history.last() {
+            let entry = EnokiField::new(path.to_owned(), latest);
             self.add_field_with_history(entry, history);
         }
     }
 
+    /// Sets the field and appends its value to the field's history
+    pub fn record_field(&mut self, entry: EnokiField) {
+        let sample = entry.get_value_owned();
+        let key = entry.get_key().to_owned();
+        self.add_field(entry);
+        let index = self.paths[&key];
+        // only copied if a clone still holds the history
+        Arc::make_mut(self.history[index].get_or_insert_with(Default::default)).push(sample);
+    }
+
     pub fn get_field(&self, path: &EnokiKey) -> Option<EnokiField> {
         if self.has_field(path) {
             let index = self.paths.get(path).unwrap();
-            Some(self.fields[*index].clone())
+            Some(EnokiField::clone(&self.fields[*index]))
         } else {
             None
         }
     }
 
+    /// The field and its history if it has one, nothing is copied
     pub fn get_field_with_history(
         &self,
         path: &EnokiKey,
-    ) -> Option<(EnokiField, Vec<TimestampedEnokiValue>)> {
-        if self.has_field(path) {
-            let index = self.paths.get(path).unwrap();
-            let entry = self.fields[*index].clone();
-            let history = self.history[*index].clone().unwrap();
-            Some((entry, history))
-        } else {
-            None
+    ) -> Option<(&EnokiField, Option<&EnokiHistory>)> {
+        let index = *self.paths.get(path)?;
+        let history = self.history.get(index).and_then(|h| h.as_deref());
+        Some((&self.fields[index], history))
+    }
+
+    pub fn get_history(&self, path: &EnokiKey) -> Option<&EnokiHistory> {
+        let index = self.paths.get(path)?;
+        self.history.get(*index)?.as_deref()
+    }
+
+    pub fn get_unit(&self, path: &EnokiKey) -> Option<&Unit> {
+        let index = self.paths.get(path)?;
+        self.fields[*index].get_unit()
+    }
+
+    /// Sets the unit of an existing field, returns false if there is no such field
+    pub fn set_unit(&mut self, path: &EnokiKey, unit: Option<Unit>) -> bool {
+        match self.paths.get(path) {
+            Some(index) => {
+                Arc::make_mut(&mut self.fields[*index]).unit = unit;
+                true
+            }
+            None => false,
         }
     }
 
+    /// A copy with every field that has a unit converted to what the unit system prefers,
+    /// fields that can't be converted are left as they are
+    pub fn convert_units(&self, system: UnitSystem) -> EnokiObject {
+        let mut converted = self.clone();
+        for (index, field) in self.fields.iter().enumerate() {
+            let unit = match field.get_unit() {
+                Some(unit) => unit,
+                None => continue,
+            };
+            let preferred = system.preferred(unit);
+            if &preferred == unit {
+                continue;
+            }
+            let history = match self.history.get(index).and_then(|h| h.as_ref()) {
+                Some(history) => history
+                    .iter()
+                    .map(|sample| {
+                        unit.convert_value(&sample.value, &preferred)
+                            .map(|value| TimestampedEnokiValue::new(sample.timestamp, value))
+                    })
+                    .collect::<Result<EnokiHistory, _>>()
+                    .map(Some),
+                None => Ok(None),
+            };
+            match (field.convert_to(&preferred), history) {
+                (Ok(field), Ok(history)) => {
+                    converted.fields[index] = Arc::new(field);
+                    converted.history[index] = history.map(Arc::new);
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    tracing::debug!("Not converting {}: {}", field.get_key(), err);
+                }
+            }
+        }
+        converted
+    }
+
     pub fn clone_without_history(&self) -> Self {
-        let mut new = self.clone();
-        new.history = Vec::new();
-        new
+        Self {
+            fields: self.fields.clone(),
+            history: vec![None; self.fields.len()],
+            paths: self.paths.clone(),
+            timestamp: self.timestamp,
+        }
     }
 
-    pub fn get_fields(&self) -> &Vec<EnokiField> {
+    pub fn get_fields(&self) -> &[Arc<EnokiField>] {
         &self.fields
     }
 
@@ -1126,7 +2117,7 @@ impl EnokiObject {
 
     pub fn update_fields(&mut self, other: &EnokiObject) {
         for entry in other.get_fields() {
-            self.add_field(entry.to_owned());
+            self.add_shared_field(entry.clone());
         }
     }
 
@@ -1138,4 +2129,137 @@ impl EnokiObject {
         self.update_fields(other);
         self.update_timestamp(other);
     }
+
+    /// A new object with only the fields the predicate accepts, histories are kept
+    pub fn filter<F: Fn(&EnokiField) -> bool>(&self, predicate: F) -> EnokiObject {
+        let mut obj = EnokiObject::new(self.timestamp);
+        for (index, field) in self.fields.iter().enumerate() {
+            if predicate(field) {
+                obj.push_field(field.clone(), self.history.get(index).cloned().flatten());
+            }
+        }
+        obj
+    }
+
+    /// Fields whose key matches a glob like `/Drive/*/temp` or `/Vision/**`
+    pub fn select(&self, pattern: &str) -> EnokiObject {
+        let pattern = EnokiKeyPattern::new(pattern);
+        self.filter(|field| pattern.matches(field.get_key()))
+    }
+
+    /// Fields whose full key string matches the regex
+    pub fn select_regex(&self, regex: &Regex) -> EnokiObject {
+        self.filter(|field| regex.is_match(&String::from(field.get_key())))
+    }
+
+    /// Fields whose current value the predicate accepts
+    pub fn filter_values<F: Fn(&EnokiValue) -> bool>(&self, predicate: F) -> EnokiObject {
+        self.filter(|field| predicate(&field.get_value().value))
+    }
+
+    pub fn numeric_fields(&self) -> EnokiObject {
+        self.filter_values(EnokiValue::is_numeric)
+    }
+
+    pub fn array_fields(&self) -> EnokiObject {
+        self.filter_values(EnokiValue::is_array)
+    }
+
+    /// Fields that changed within `[after, before]`, histories are trimmed to the same window.
+    /// A field with a history is kept if any sample lands in the window,
+    /// otherwise its current value has to.
+    pub fn changed_between(
+        &self,
+        after: Option<EnokiTimeStamp>,
+        before: Option<EnokiTimeStamp>,
+    ) -> EnokiObject {
+        let in_window = |timestamp: EnokiTimeStamp| {
+            after.map_or(true, |after| timestamp >= after)
+                && before.map_or(true, |before| timestamp <= before)
+        };
+        let mut obj = EnokiObject::new(self.timestamp);
+        for (index, field) in self.fields.iter().enumerate() {
+            match self.history.get(index).and_then(|h| h.as_ref()) {
+                Some(history) => {
+                    let trimmed = history.range(after, before);
+                    if !trimmed.is_empty() {
+                        let trimmed = Arc::new(trimmed.to_history());
+                        obj.push_field(field.clone(), Some(trimmed));
+                    }
+                }
+                None => {
+                    if in_window(field.get_timestamp()) {
+                        obj.push_field(field.clone(), None);
+                    }
+                }
+            }
+        }
+        obj
+    }
+
+    pub fn changed_since(&self, after: EnokiTimeStamp) -> EnokiObject {
+        self.changed_between(Some(after), None)
+    }
+}
+
+/// A kind of value a query can be narrowed down to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnokiValueKind {
+    Numeric,
+    String,
+    Boolean,
+    Binary,
+    Array,
+    Single,
+}
+
+impl EnokiValueKind {
+    pub fn matches(&self, value: &EnokiValue) -> bool {
+        match self {
+            EnokiValueKind::Numeric => value.is_numeric(),
+            EnokiValueKind::String => value.is_string(),
+            EnokiValueKind::Boolean => value.is_boolean(),
+            EnokiValueKind::Binary => value.is_binary(),
+            EnokiValueKind::Array => value.is_array(),
+            EnokiValueKind::Single => value.is_single(),
+        }
+    }
+}
+
+/// Selects part of an [EnokiObject], every filter that is set has to match.
+/// `kinds` matches if the value is any of the listed kinds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnokiQuery {
+    pub pattern: Option<String>,
+    pub regex: Option<String>,
+    pub kinds: Vec<EnokiValueKind>,
+    pub after: Option<EnokiTimeStamp>,
+    pub before: Option<EnokiTimeStamp>,
+    /// Converts the selected fields to the units the system prefers
+    pub unit_system: Option<UnitSystem>,
+}
+
+impl EnokiQuery {
+    pub fn apply(&self, obj: &EnokiObject) -> Result<EnokiObject, regex::Error> {
+        let regex = self.regex.as_deref().map(Regex::new).transpose()?;
+        let pattern = self.pattern.as_deref().map(EnokiKeyPattern::new);
+        let selected = obj.filter(|field| {
+            pattern.as_ref().map_or(true, |p| p.matches(field.get_key()))
+                && regex
+                    .as_ref()
+                    .map_or(true, |r| r.is_match(&String::from(field.get_key())))
+                && (self.kinds.is_empty()
+                    || self.kinds.iter().any(|kind| kind.matches(&field.get_value().value)))
+        });
+        let selected = if self.after.is_some() || self.before.is_some() {
+            selected.changed_between(self.after, self.before)
+        } else {
+            selected
+        };
+        match self.unit_system {
+            Some(system) => Ok(selected.convert_units(system)),
+            None => Ok(selected),
+        }
+    }
 }
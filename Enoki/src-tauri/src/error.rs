@@ -1,7 +1,10 @@
+use std::{io::Write, path::PathBuf};
+
 use serde::Serialize;
-use tauri::api::path::document_dir;
 use thiserror::Error;
 
+use crate::retention::{log_rotated, new_log_path, DEBUGLOG_DIRECTORY};
+
 #[derive(Error, Debug)]
 pub enum EnokiError {
     #[error("DataLog io error: {0:?}")]
@@ -15,6 +18,8 @@ pub enum EnokiError {
     #[error("No valid address found")]
     //used specifically for pinging ssh clients
     NoValidAddress,
+    #[error("Team {0} is too high to have a roboRIO address")]
+    InvalidTeamNumber(u16),
     #[error("NT Topic not found")]
     NTTopicNotFound(String),
     #[error("DL Entry not found")]
@@ -23,6 +28,22 @@ pub enum EnokiError {
     Ping(#[from] surge_ping::SurgeError),
     #[error("Io error: {0:?}")]
     Io(#[from] std::io::Error),
+    #[error("Value error: {0}")]
+    Value(#[from] crate::enoki_types::EnokiValueError),
+    #[error("SSH error: {0:?}")]
+    Ssh(#[from] ssh2::Error),
+    #[error("Invalid query: {0}")]
+    Query(#[from] regex::Error),
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+    #[error("Unit error: {0}")]
+    Unit(#[from] crate::units::UnitError),
+    #[error("Invalid derived field: {0}")]
+    Derived(#[from] crate::derived::DerivedError),
+    #[error("Resample error: {0}")]
+    Resample(#[from] crate::resample::ResampleError),
+    #[error("Script error: {0}")]
+    Script(#[from] crate::scripting::ScriptError),
 }
 
 impl Serialize for EnokiError {
@@ -55,38 +76,59 @@ pub fn log_result_consume<T, E: std::error::Error>(result: Result<T, E>) {
     }
 }
 
+/// Debug logs continue in a new file once they grow past this
+const MAX_DEBUGLOG_BYTES: u64 = 64 * 1024 * 1024;
+
 pub struct TraceWriter {
     buffer: Vec<u8>,
     file: std::fs::File,
+    /// Bytes written to the current file
+    written: u64,
 }
 
 impl TraceWriter {
     pub fn new() -> Self {
-        let currunt_time_string =
-            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string() + ".debuglog.txt";
-
-        let file_path = document_dir()
-            .unwrap()
-            .join("Enoki/DebugLogs")
-            .join(currunt_time_string);
+        let file_path = Self::new_file_path();
 
         if !file_path.exists() {
             std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
         };
 
-        let file = std::fs::File::create(file_path).unwrap();
+        let file = std::fs::File::create(&file_path).unwrap();
+
+        log_rotated(DEBUGLOG_DIRECTORY, &file_path);
 
         Self {
             buffer: Vec::new(),
             file,
+            written: 0,
         }
     }
+
+    fn new_file_path() -> PathBuf {
+        new_log_path(DEBUGLOG_DIRECTORY, "debuglog.txt").unwrap()
+    }
+
+    /// Continues in a new file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let file_path = Self::new_file_path();
+        self.file.flush()?;
+        self.file = std::fs::File::create(&file_path)?;
+        self.written = 0;
+        log_rotated(DEBUGLOG_DIRECTORY, &file_path);
+        Ok(())
+    }
 }
 
 impl std::io::Write for TraceWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written >= MAX_DEBUGLOG_BYTES {
+            self.rotate()?;
+        }
         self.buffer.extend_from_slice(buf);
-        self.file.write(buf)
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
@@ -0,0 +1,299 @@
+//! Binary encodings for sending [EnokiObject]s to the frontend.
+//!
+//! The MessagePack layout mirrors the json one, the decoder lives in `glue/Encoding.ts`:
+//! ```text
+//! { timestamp: uint,
+//!   fields: [{ key: str, type: str, value: any, timestamp: uint, unit?: str }],
+//!   history: [nil | { type: str, timestamps: bin, values: bin | [any], types?: [str] }] }
+//! ```
+//! `history` is index aligned with `fields`. Timestamps are little endian f64s so they can be
+//! viewed as a `Float64Array`, as are the values of float and double histories.
+//! Other histories carry their values as an array, `types` is only present if the field changed type.
+//! Floats are sent as f32s so a float only survives a round trip if it fits in one.
+
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enoki_types::{
+        EnokiField, EnokiHistory, EnokiKey, EnokiObject, EnokiValue, TimestampedEnokiValue,
+    },
+    error::EnokiError,
+    units::Unit,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IpcEncoding {
+    Json,
+    MessagePack,
+}
+
+impl Default for IpcEncoding {
+    fn default() -> Self {
+        IpcEncoding::Json
+    }
+}
+
+/// An [EnokiObject] in the encoding the frontend asked for.
+/// Json is sent as is so callers that don't pick an encoding see no difference,
+/// binary encodings are base64 encoded as tauri only passes json.
+/// The frontend can send objects back in either encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EncodedObject {
+    Json(Arc<EnokiObject>),
+    Binary { encoding: IpcEncoding, data: String },
+}
+
+impl EncodedObject {
+    /// Takes anything that can become an `Arc<EnokiObject>` so shared snapshots aren't copied
+    pub fn encode(
+        obj: impl Into<Arc<EnokiObject>>,
+        encoding: Option<IpcEncoding>,
+    ) -> Result<Self, EnokiError> {
+        let obj = obj.into();
+        match encoding.unwrap_or_default() {
+            IpcEncoding::Json => Ok(EncodedObject::Json(obj)),
+            IpcEncoding::MessagePack => Ok(EncodedObject::Binary {
+                encoding: IpcEncoding::MessagePack,
+                data: STANDARD.encode(encode_msgpack(&obj)?),
+            }),
+        }
+    }
+
+    pub fn decode(self) -> Result<EnokiObject, EnokiError> {
+        match self {
+            EncodedObject::Json(obj) => {
+                Ok(Arc::try_unwrap(obj).unwrap_or_else(|obj| (*obj).clone()))
+            }
+            EncodedObject::Binary {
+                encoding: IpcEncoding::Json,
+                data,
+            } => {
+                let bytes = decode_base64(&data)?;
+                serde_json::from_slice(&bytes).map_err(|err| EnokiError::Encoding(err.to_string()))
+            }
+            EncodedObject::Binary {
+                encoding: IpcEncoding::MessagePack,
+                data,
+            } => decode_msgpack(&decode_base64(&data)?),
+        }
+    }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, EnokiError> {
+    STANDARD
+        .decode(data)
+        .map_err(|err| EnokiError::Encoding(err.to_string()))
+}
+
+pub fn encode_msgpack(obj: &EnokiObject) -> Result<Vec<u8>, EnokiError> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &object_to_rmpv(obj))
+        .map_err(|err| EnokiError::Encoding(err.to_string()))?;
+    Ok(bytes)
+}
+
+/// Reads what [encode_msgpack] wrote
+pub fn decode_msgpack(bytes: &[u8]) -> Result<EnokiObject, EnokiError> {
+    let value = rmpv::decode::read_value(&mut &bytes[..])
+        .map_err(|err| EnokiError::Encoding(err.to_string()))?;
+    object_from_rmpv(&value).map_err(EnokiError::Encoding)
+}
+
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    Value::Map(
+        entries
+            .into_iter()
+            .map(|(key, value)| (Value::from(key), value))
+            .collect(),
+    )
+}
+
+fn f64_bytes(values: impl Iterator<Item = f64>) -> Value {
+    Value::Binary(values.flat_map(f64::to_le_bytes).collect())
+}
+
+fn object_to_rmpv(obj: &EnokiObject) -> Value {
+    let fields = obj
+        .get_fields()
+        .iter()
+        .map(|field| {
+            let value = field.get_value();
+            let mut entries = vec![
+                ("key", Value::from(String::from(field.get_key()))),
+                ("type", Value::from(value.value.get_type())),
+                ("value", Value::from(&value.value)),
+                ("timestamp", Value::from(value.timestamp)),
+            ];
+            if let Some(unit) = field.get_unit() {
+                entries.push(("unit", Value::from(unit.symbol())));
+            }
+            map(entries)
+        })
+        .collect();
+    let history = obj
+        .get_fields()
+        .iter()
+        .map(|field| match obj.get_history(field.get_key()) {
+            Some(history) => history_to_rmpv(history),
+            None => Value::Nil,
+        })
+        .collect();
+    map(vec![
+        ("timestamp", Value::from(obj.get_timestamp())),
+        ("fields", Value::Array(fields)),
+        ("history", Value::Array(history)),
+    ])
+}
+
+fn history_to_rmpv(history: &EnokiHistory) -> Value {
+    let timestamps = f64_bytes(history.timestamps().iter().map(|t| *t as f64));
+    if let Some(doubles) = history.doubles() {
+        return map(vec![
+            ("type", Value::from(history.value_type())),
+            ("timestamps", timestamps),
+            ("values", f64_bytes(doubles.iter().copied())),
+        ]);
+    }
+
+    let samples = history.iter().collect::<Vec<_>>();
+    let mut entries = vec![
+        ("type", Value::from(history.value_type())),
+        ("timestamps", timestamps),
+        (
+            "values",
+            Value::Array(samples.iter().map(|s| Value::from(&s.value)).collect()),
+        ),
+    ];
+    if history.is_mixed() {
+        entries.push((
+            "types",
+            Value::Array(
+                samples
+                    .iter()
+                    .map(|s| Value::from(s.value.get_type()))
+                    .collect(),
+            ),
+        ));
+    }
+    map(entries)
+}
+
+fn entry<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn required<'a>(map: &'a Value, key: &str) -> Result<&'a Value, String> {
+    entry(map, key).ok_or_else(|| format!("Missing {}", key))
+}
+
+fn str_entry<'a>(map: &'a Value, key: &str) -> Result<&'a str, String> {
+    required(map, key)?
+        .as_str()
+        .ok_or_else(|| format!("{} is not a string", key))
+}
+
+fn u64_entry(map: &Value, key: &str) -> Result<u64, String> {
+    required(map, key)?
+        .as_u64()
+        .ok_or_else(|| format!("{} is not a timestamp", key))
+}
+
+fn f64s_from_bytes(value: &Value) -> Result<Vec<f64>, String> {
+    let bytes = value.as_slice().ok_or("Expected binary f64s")?;
+    if bytes.len() % 8 != 0 {
+        return Err(format!(
+            "{} bytes is not a whole number of f64s",
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|chunk| {
+            let mut le = [0u8; 8];
+            le.copy_from_slice(chunk);
+            f64::from_le_bytes(le)
+        })
+        .collect())
+}
+
+fn value_from_rmpv(value: &Value, value_type: &str) -> Result<EnokiValue, String> {
+    let inferred = match value {
+        Value::Array(array) if array.is_empty() => EnokiValue::DoubleArray(vec![]),
+        other => EnokiValue::try_from(other.clone()).map_err(|err| err.to_string())?,
+    };
+    inferred.coerce(value_type).map_err(|err| err.to_string())
+}
+
+fn object_from_rmpv(value: &Value) -> Result<EnokiObject, String> {
+    let fields = required(value, "fields")?
+        .as_array()
+        .ok_or("fields is not an array")?;
+    let histories = match entry(value, "history") {
+        Some(Value::Array(histories)) => histories.as_slice(),
+        _ => &[],
+    };
+
+    let mut obj = EnokiObject::new(u64_entry(value, "timestamp")?);
+    for (index, packed) in fields.iter().enumerate() {
+        let value = value_from_rmpv(required(packed, "value")?, str_entry(packed, "type")?)?;
+        let unit = entry(packed, "unit")
+            .and_then(Value::as_str)
+            .map(Unit::from);
+        let field = EnokiField::new(
+            EnokiKey::from(str_entry(packed, "key")?),
+            TimestampedEnokiValue::new(u64_entry(packed, "timestamp")?, value),
+        )
+        .with_unit(unit);
+        match histories.get(index) {
+            Some(Value::Nil) | None => obj.add_field(field),
+            Some(history) => obj.add_field_with_history(field, history_from_rmpv(history)?),
+        }
+    }
+    Ok(obj)
+}
+
+fn history_from_rmpv(history: &Value) -> Result<EnokiHistory, String> {
+    let value_type = str_entry(history, "type")?;
+    let timestamps = f64s_from_bytes(required(history, "timestamps")?)?;
+    let values = match required(history, "values")? {
+        Value::Array(values) => {
+            let types = entry(history, "types").and_then(Value::as_array);
+            values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    let value_type = types
+                        .and_then(|types| types.get(index))
+                        .and_then(Value::as_str)
+                        .unwrap_or(value_type);
+                    value_from_rmpv(value, value_type)
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        binary => f64s_from_bytes(binary)?
+            .into_iter()
+            .map(|v| EnokiValue::Double(v).coerce(value_type))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?,
+    };
+    if values.len() != timestamps.len() {
+        return Err(format!(
+            "{} values for {} timestamps",
+            values.len(),
+            timestamps.len()
+        ));
+    }
+    Ok(timestamps
+        .into_iter()
+        .zip(values)
+        .map(|(timestamp, value)| TimestampedEnokiValue::new(timestamp as u64, value))
+        .collect())
+}
@@ -1,4 +1,5 @@
 
 pub mod logging;
 pub mod keybinds;
-pub mod windows;
\ No newline at end of file
+pub mod windows;
+pub mod encoding;
\ No newline at end of file
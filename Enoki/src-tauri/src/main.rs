@@ -23,13 +23,18 @@ pub mod enoki_types;
 #[cfg(test)]
 mod test;
 
-// pub mod datalog;
+pub mod datalog;
 pub mod frontend_helpers;
 pub mod networktable;
 pub mod python_helpers;
 // pub mod terminal;
 pub mod robot_interface;
 pub mod logging;
+pub mod retention;
+pub mod resample;
+pub mod derived;
+pub mod units;
+pub mod scripting;
 
 fn main() {
     // guard lock needs to live till end of program
@@ -54,7 +59,8 @@ fn main() {
     tauri::Builder::default()
         .plugin(backend_plugin())
         .plugin(networktable::networktable_plugin())
-        // .plugin(datalog::datalog_plugin())
+        .plugin(datalog::datalog_plugin())
+        .plugin(scripting::scripting_plugin())
         // .plugin(terminal::terminal_plugin())
         .plugin(tauri_plugin_positioner::init())
         .plugin(tauri_plugin_fs_extra::init())
@@ -86,7 +92,23 @@ pub fn backend_plugin<R: Runtime>() -> TauriPlugin<R> {
             }
             _ => {}
         })
-        .invoke_handler(tauri::generate_handler![tracing_frontend])
+        .invoke_handler(tauri::generate_handler![
+            tracing_frontend,
+            retention::get_retention_config,
+            retention::set_retention_config,
+            retention::set_log_favorite,
+            robot_interface::log_retrieval::roborio_log_target,
+            robot_interface::log_retrieval::coprocessor_log_target,
+            robot_interface::log_retrieval::list_robot_logs,
+            robot_interface::log_retrieval::pull_robot_logs,
+            resample::tauri_cmds::resample_histories,
+            resample::tauri_cmds::align_histories,
+            units::convert_unit,
+            derived::tauri_cmds::define_derived_field,
+            derived::tauri_cmds::remove_derived_field,
+            derived::tauri_cmds::list_derived_fields,
+            derived::tauri_cmds::derive_datalog_histories
+        ])
         .build()
 }
 
@@ -119,5 +141,6 @@ fn per_frame() {
 fn exit() {
     tracing::info!("Closing");
     // DATALOG.lock().kill();
+    scripting::stop_all_scripts();
     NETWORK_CLIENT_MAP.lock().clear();
 }
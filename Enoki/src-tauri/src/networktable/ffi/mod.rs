@@ -2,16 +2,17 @@
 pub mod tauri;
 pub mod python;
 
-use std::net::SocketAddrV4;
+use std::{net::SocketAddrV4, sync::Arc};
 
 use network_tables::v4::SubscriptionOptions;
 
 use crate::{
     networktable::handler::{start_nt4_client, SubscriptionPackage},
-    NETWORK_CLIENT_MAP, enoki_types::{EnokiObject, EnokiTimeStamp, now, TimestampedEnokiValue, EnokiField}, error::EnokiError,
+    NETWORK_CLIENT_MAP, enoki_types::{EnokiObject, EnokiTimeStamp, now, TimestampedEnokiValue, EnokiField, EnokiKey, EnokiQuery}, error::EnokiError,
 };
 
 use super::handler::NetworkTableClientId;
+use super::listeners::NetworkTableListener;
 
 pub fn start_network_table_client(
     ip: SocketAddrV4,
@@ -37,6 +38,19 @@ pub fn is_network_table_client_stopped(client_id: NetworkTableClientId) -> bool
     NETWORK_CLIENT_MAP.lock().contains_key(&client_id)
 }
 
+/// Every running client
+pub fn network_table_clients() -> Vec<NetworkTableClientId> {
+    NETWORK_CLIENT_MAP.lock().keys().cloned().collect()
+}
+
+/// Whether the client is connected to its server, none if there's no such client
+pub fn is_network_table_client_connected(client_id: NetworkTableClientId) -> Option<bool> {
+    NETWORK_CLIENT_MAP
+        .lock()
+        .get(&client_id)
+        .map(|client| client.is_connected())
+}
+
 pub fn stop_network_table_client(client_id: NetworkTableClientId) {
     if let Some(client) = NETWORK_CLIENT_MAP.lock().remove(&client_id) {
         tracing::info!("Stopping network table cleint for {}", client_id);
@@ -82,10 +96,11 @@ pub fn unsubscribe_from_topic(
     }
 }
 
+/// The latest object of a subscription, shared with the client so this doesn't copy it
 pub fn get_subbed_data(
     client_id: NetworkTableClientId,
     topic: String,
-) -> Result<EnokiObject, EnokiError> {
+) -> Result<Arc<EnokiObject>, EnokiError> {
     if let Some(client) = NETWORK_CLIENT_MAP.lock().get_mut(&client_id) {
         let data = client.poll(topic.clone());
         if data.is_err() {
@@ -100,6 +115,29 @@ pub fn get_subbed_data(
     }
 }
 
+/// A listener for the values and announcements of a subscription, it stays open until closed
+pub fn listen_to_topic(
+    client_id: NetworkTableClientId,
+    topic: String,
+) -> Result<Arc<NetworkTableListener>, EnokiError> {
+    if let Some(client) = NETWORK_CLIENT_MAP.lock().get_mut(&client_id) {
+        tracing::info!("Listening to topic {}", topic);
+        Ok(client.listen(topic))
+    } else {
+        tracing::warn!("No network table client found for {}", client_id);
+        Err(EnokiError::NTLostConnection)
+    }
+}
+
+pub fn query_subbed_data(
+    client_id: NetworkTableClientId,
+    topic: String,
+    query: &EnokiQuery,
+) -> Result<EnokiObject, EnokiError> {
+    let data = get_subbed_data(client_id, topic)?;
+    Ok(query.apply(&data)?)
+}
+
 pub fn get_subbed_data_with_history(
     client_id: NetworkTableClientId,
     topic: String,
@@ -128,7 +166,20 @@ pub fn set_topic_value(
 ) {
     if let Some(client) = NETWORK_CLIENT_MAP.lock().get_mut(&client_id) {
         tracing::info!("Set topic {} to {}", &topic, &value);
-        client.publish_field(EnokiField::new(topic.into(), value));
+        client.publish_field(EnokiField::new(EnokiKey::from_topic_name(&topic), value));
+    } else {
+        tracing::warn!("No network table client found for {}", client_id);
+    }
+}
+
+/// Publishes every field of an object, topics that aren't published yet are published with the type of their value
+pub fn publish_object(
+    client_id: NetworkTableClientId,
+    obj: EnokiObject,
+) {
+    if let Some(client) = NETWORK_CLIENT_MAP.lock().get_mut(&client_id) {
+        tracing::info!("Publishing {} fields", obj.len());
+        client.publish_obj(obj);
     } else {
         tracing::warn!("No network table client found for {}", client_id);
     }
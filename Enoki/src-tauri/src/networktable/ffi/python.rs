@@ -1,9 +1,16 @@
 
 use std::net::SocketAddrV4;
+use std::sync::{mpsc, Arc};
 
-use pyo3::prelude::*;
+use parking_lot::Mutex;
+use pyo3::{exceptions::PyException, prelude::*};
 
 use crate::{networktable::handler::NetworkTableClientId, enoki_types::*, python_helpers::py_enoki_types::PyEnokiObject};
+use crate::networktable::listeners::{ListenerBatch, NetworkTableListener};
+use crate::python_helpers::awaitable::{call_soon, wait_until};
+use crate::python_helpers::logging::{format_py_err, tracing_log_exception};
+use crate::python_helpers::py_enoki_types::{coerce_value, PyEnokiField};
+use crate::scripting::plugins::{self, require_permission, Permission};
 
 #[derive(Debug, Clone)]
 #[pyclass(name = "NetworkTableClientId")]
@@ -34,6 +41,38 @@ impl From<PyNetworkTableClientId> for NetworkTableClientId {
     }
 }
 
+#[pymethods]
+impl PyNetworkTableClientId {
+    /// Whether the client is connected to its server
+    fn is_connected(&self) -> bool {
+        super::is_network_table_client_connected(self.clone().into()) == Some(true)
+    }
+
+    /// Resolves once the client is connected, raises if the client is stopped first
+    fn wait_connected<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        let waiting: NetworkTableClientId = self.clone().into();
+        let taking = waiting.clone();
+        wait_until(
+            py,
+            move |timeout| match super::is_network_table_client_connected(waiting.clone()) {
+                Some(false) => {
+                    std::thread::sleep(timeout);
+                    false
+                }
+                _ => true,
+            },
+            move |py| match super::is_network_table_client_connected(taking.clone()) {
+                Some(true) => Ok(Some(py.None())),
+                Some(false) => Ok(None),
+                None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Network table client {} was stopped",
+                    taking
+                ))),
+            },
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 #[pyclass(name = "NetworkTablePubbedTopic")]
 pub struct PyNetworkTablePubbedTopic {
@@ -44,13 +83,21 @@ pub struct PyNetworkTablePubbedTopic {
 
 #[pymethods]
 impl PyNetworkTablePubbedTopic {
+    /// `type_name` is what [EnokiValue::get_type] calls it, e.g. "Double"
     #[new]
-    fn new(topic: String, type_name: String, client_id: PyNetworkTableClientId) -> Self {
-        Self {
+    fn new(topic: String, type_name: String, client_id: PyNetworkTableClientId) -> PyResult<Self> {
+        if !EnokiValue::TYPE_NAMES.contains(&type_name.as_str()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown type {}, expected one of {}",
+                type_name,
+                EnokiValue::TYPE_NAMES.join(", ")
+            )));
+        }
+        Ok(Self {
             topic,
             r#type: type_name,
             client_id,
-        }
+        })
     }
 
 
@@ -63,14 +110,15 @@ impl PyNetworkTablePubbedTopic {
     }
 
     fn set_value(&self, value: EnokiValue, timestamp: Option<u64>) -> PyResult<()> {
-        if value.get_type() != self.r#type {
-            tracing::error!("Value type mismatch");
-            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
-                "Value type mismatch: expected {}, got {}",
-                self.r#type,
-                value.get_type()
-            )));
-        }
+        require_permission(Permission::NtPublish)?;
+        // so `1` can be set on a double topic
+        let value = match coerce_value(value, &self.r#type) {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::error!("Value type mismatch for {}", self.topic);
+                return Err(err);
+            }
+        };
         let timestamp = timestamp.unwrap_or_else(now);
         super::set_topic_value(
             self.client_id.clone().into(),
@@ -86,23 +134,262 @@ impl PyNetworkTablePubbedTopic {
     }
 }
 
+#[derive(Default)]
+struct Callbacks {
+    on_update: Vec<PyObject>,
+    on_field: Vec<(EnokiKey, PyObject)>,
+    on_announce: Vec<PyObject>,
+}
+
+/// The callbacks of a subscription and the listener feeding them, shared with the thread dispatching batches
+struct SubscriptionCallbacks {
+    listener: Arc<NetworkTableListener>,
+    callbacks: Mutex<Callbacks>,
+}
+
+impl SubscriptionCallbacks {
+    /// Calls the callbacks on this thread until the listener is closed
+    fn dispatch(&self) {
+        while let Some(batch) = self.listener.next_batch() {
+            self.warn_dropped(&batch);
+            Python::with_gil(|py| {
+                if let Err(err) = self.call_all(py, batch) {
+                    tracing::error!("Failed to dispatch {}: {}", self.listener.topic(), format_py_err(py, &err));
+                }
+            });
+        }
+        tracing::info!("Stopped callbacks of {}", self.listener.topic());
+    }
+
+    /// Has the script's loop call the callbacks, one batch at a time so values coalesce
+    /// while they run, until the listener or the loop is closed
+    fn dispatch_on(self: Arc<Self>, event_loop: PyObject) {
+        while let Some(batch) = self.listener.next_batch() {
+            self.warn_dropped(&batch);
+            let (called, was_called) = mpsc::channel();
+            let callbacks = self.clone();
+            Python::with_gil(|py| {
+                call_soon(py, &event_loop, move |py| {
+                    let result = callbacks.call_all(py, batch);
+                    let _ = called.send(());
+                    match result {
+                        // an interrupt from the script's watchdog or stop ends the loop's run
+                        Err(err) if !err.is_instance_of::<PyException>(py) => Err(err),
+                        Err(err) => {
+                            tracing::error!("Failed to dispatch {}: {}", callbacks.listener.topic(), format_py_err(py, &err));
+                            Ok(())
+                        }
+                        Ok(()) => Ok(()),
+                    }
+                })
+            });
+            // dropped without being called, the loop was closed
+            if was_called.recv().is_err() {
+                break;
+            }
+        }
+        tracing::info!("Stopped callbacks of {}", self.listener.topic());
+    }
+
+    fn warn_dropped(&self, batch: &ListenerBatch) {
+        if batch.dropped > 0 {
+            tracing::warn!(
+                "Callbacks of {} fell behind, {} values were skipped",
+                self.listener.topic(),
+                batch.dropped
+            );
+        }
+    }
+
+    fn call_all(&self, py: Python, batch: ListenerBatch) -> PyResult<()> {
+        // copied so callbacks can register more callbacks
+        let callbacks = {
+            let callbacks = self.callbacks.lock();
+            Callbacks {
+                on_update: callbacks.on_update.clone(),
+                on_field: callbacks.on_field.clone(),
+                on_announce: callbacks.on_announce.clone(),
+            }
+        };
+        for (topic, type_name) in batch.announced {
+            for callback in &callbacks.on_announce {
+                call_logged(py, callback, (topic.clone(), type_name.clone()))?;
+            }
+        }
+        let values = match batch.values {
+            Some(values) => values,
+            None => return Ok(()),
+        };
+        for (key, callback) in &callbacks.on_field {
+            let (field, history) = match values.get_field_with_history(key) {
+                Some(field) => field,
+                None => continue,
+            };
+            let fields = match history {
+                Some(history) => history
+                    .iter()
+                    .map(|sample| {
+                        EnokiField::new(key.clone(), sample).with_unit(field.get_unit().cloned())
+                    })
+                    .collect(),
+                None => vec![field.clone()],
+            };
+            for field in fields {
+                call_logged(py, callback, (PyEnokiField::from(field),))?;
+            }
+        }
+        if !callbacks.on_update.is_empty() {
+            let object = Py::new(py, PyEnokiObject::from(values))?;
+            for callback in &callbacks.on_update {
+                call_logged(py, callback, (object.clone_ref(py),))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn callable(callback: &PyAny) -> PyResult<PyObject> {
+    if callback.is_callable() {
+        Ok(callback.into())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "{} is not callable",
+            callback.get_type().name()?
+        )))
+    }
+}
+
+/// An exception in a callback is logged and doesn't stop the others, an interrupt stops them all
+fn call_logged(
+    py: Python,
+    callback: &PyObject,
+    args: impl IntoPy<Py<pyo3::types::PyTuple>>,
+) -> PyResult<()> {
+    match callback.call1(py, args) {
+        Err(err) if err.is_instance_of::<PyException>(py) => {
+            if let Err(log_err) = tracing_log_exception(py, &err) {
+                tracing::error!("Callback failed: {}", format_py_err(py, &log_err));
+            }
+            Ok(())
+        }
+        result => result.map(drop),
+    }
+}
+
 #[pyclass(name = "NetworkTableSubscription")]
 pub struct PyNetworkTableSubscription {
     topic: String,
     client_id: PyNetworkTableClientId,
+    callbacks: Option<Arc<SubscriptionCallbacks>>,
+    /// Feeds the awaitables, separate from the callbacks so neither takes the other's values
+    updates: Option<Arc<NetworkTableListener>>,
+}
+
+impl PyNetworkTableSubscription {
+    fn listen(&self) -> PyResult<Arc<NetworkTableListener>> {
+        let listener = super::listen_to_topic(self.client_id.clone().into(), self.topic.clone())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+        let closing = listener.clone();
+        // a script's listeners stop with it instead of calling into a module that's gone
+        crate::scripting::on_script_stop(move || closing.close());
+        Ok(listener)
+    }
+
+    /// Starts listening the first time a callback is registered
+    fn callbacks(&mut self) -> PyResult<&SubscriptionCallbacks> {
+        if self.callbacks.is_none() {
+            let listener = self.listen()?;
+            let callbacks = Arc::new(SubscriptionCallbacks {
+                listener,
+                callbacks: Mutex::new(Callbacks::default()),
+            });
+            let dispatcher = callbacks.clone();
+            // a script's callbacks run on its loop, where its timeout and stop apply to them
+            let event_loop = crate::scripting::serving_loop();
+            // elsewhere they may do what the plugin registering them may do
+            let grant = plugins::current_grant();
+            std::thread::Builder::new()
+                .name(format!("NT-Callbacks-{}", self.topic))
+                .spawn(move || match event_loop {
+                    Some(event_loop) => dispatcher.dispatch_on(event_loop),
+                    None => {
+                        plugins::set_thread_grant(grant);
+                        dispatcher.dispatch()
+                    }
+                })?;
+            self.callbacks = Some(callbacks);
+        }
+        Ok(self.callbacks.as_ref().unwrap())
+    }
+
+    /// A future for the next values received, raising `closed` once the subscription is closed
+    fn next_update_or<'py>(
+        &mut self,
+        py: Python<'py>,
+        closed: fn(String) -> PyErr,
+    ) -> PyResult<&'py PyAny> {
+        if self.updates.is_none() {
+            self.updates = Some(self.listen()?);
+        }
+        let listener = self.updates.clone().unwrap();
+        let waiting = listener.clone();
+        wait_until(
+            py,
+            move |timeout| waiting.wait_ready(timeout),
+            move |py| {
+                if listener.is_closed() {
+                    return Err(closed(format!(
+                        "Subscription to {} was closed",
+                        listener.topic()
+                    )));
+                }
+                let batch = match listener.take_batch() {
+                    Some(batch) => batch,
+                    None => return Ok(None),
+                };
+                if batch.dropped > 0 {
+                    tracing::warn!(
+                        "Awaiting {} fell behind, {} values were skipped",
+                        listener.topic(),
+                        batch.dropped
+                    );
+                }
+                // announcements alone aren't an update
+                Ok(batch
+                    .values
+                    .map(|values| PyEnokiObject::from(values).into_py(py)))
+            },
+        )
+    }
+}
+
+impl Drop for PyNetworkTableSubscription {
+    fn drop(&mut self) {
+        if let Some(callbacks) = &self.callbacks {
+            callbacks.listener.close();
+        }
+        if let Some(updates) = &self.updates {
+            updates.close();
+        }
+    }
 }
 
 #[pymethods]
 impl PyNetworkTableSubscription {
     #[new]
     fn new(topic: String, client_id: PyNetworkTableClientId) -> Self {
-        Self { topic, client_id }
+        Self {
+            topic,
+            client_id,
+            callbacks: None,
+            updates: None,
+        }
     }
 
 
     fn get_subbed_data(&self) -> PyResult<PyEnokiObject> {
         match super::get_subbed_data(self.client_id.clone().into(), self.topic.clone()) {
-            Ok(data) => Ok(data.into()),
+            Ok(data) => Ok(EnokiObject::clone(&data).into()),
             Err(e) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
                 "Error getting subbed data: {}",
                 e
@@ -119,6 +406,59 @@ impl PyNetworkTableSubscription {
             ))),
         }
     }
+
+    /// Calls `callback(object)` with the fields that changed, their histories hold every value received since the last call
+    fn on_update(&mut self, callback: &PyAny) -> PyResult<()> {
+        let callback = callable(callback)?;
+        self.callbacks()?.callbacks.lock().on_update.push(callback);
+        Ok(())
+    }
+
+    /// Calls `callback(field)` for every value received for one field, in order
+    fn on_field(&mut self, key: String, callback: &PyAny) -> PyResult<()> {
+        let callback = callable(callback)?;
+        self.callbacks()?
+            .callbacks
+            .lock()
+            .on_field
+            .push((key.into(), callback));
+        Ok(())
+    }
+
+    /// Calls `callback(topic, type)` for every topic announced under the subscription, including ones announced before
+    fn on_announce(&mut self, callback: &PyAny) -> PyResult<()> {
+        let callback = callable(callback)?;
+        self.callbacks()?.callbacks.lock().on_announce.push(callback);
+        Ok(())
+    }
+
+    /// Resolves to the fields that changed the next time values are received, like `on_update`
+    fn next_update<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        self.next_update_or(py, PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)
+    }
+
+    /// `async for update in subscription` runs until the subscription is closed
+    fn __aiter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<&'py PyAny>> {
+        if matches!(&self.updates, Some(updates) if updates.is_closed()) {
+            return Ok(None);
+        }
+        self.next_update_or(py, PyErr::new::<pyo3::exceptions::PyStopAsyncIteration, _>)
+            .map(Some)
+    }
+
+    /// Stops calling the callbacks and ends anything awaiting updates
+    fn close(&mut self) {
+        if let Some(callbacks) = self.callbacks.take() {
+            callbacks.listener.close();
+        }
+        if let Some(updates) = &self.updates {
+            updates.close();
+        }
+    }
 }
 
 #[pyfunction]
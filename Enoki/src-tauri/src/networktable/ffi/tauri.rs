@@ -1,6 +1,10 @@
 use std::net::SocketAddrV4;
 
-use crate::{networktable::handler::NetworkTableClientId, enoki_types::*};
+use crate::{
+    enoki_types::*,
+    frontend_helpers::encoding::{EncodedObject, IpcEncoding},
+    networktable::handler::NetworkTableClientId,
+};
 
 
 #[tauri::command]
@@ -44,8 +48,26 @@ pub fn unsubscribe_from_topic(
 pub fn get_subbed_data(
     client_id: NetworkTableClientId,
     topic: String,
-) -> Result<EnokiObject, String> {
-    match super::get_subbed_data(client_id, topic) {
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, String> {
+    match super::get_subbed_data(client_id, topic)
+        .and_then(|data| EncodedObject::encode(data, encoding))
+    {
+        Ok(data) => Ok(data),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn query_subbed_data(
+    client_id: NetworkTableClientId,
+    topic: String,
+    query: EnokiQuery,
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, String> {
+    match super::query_subbed_data(client_id, topic, &query)
+        .and_then(|data| EncodedObject::encode(data, encoding))
+    {
         Ok(data) => Ok(data),
         Err(e) => Err(e.to_string()),
     }
@@ -69,6 +91,16 @@ pub fn set_topic_value(
     super::set_topic_value(client_id, topic, value)
 }
 
+#[tauri::command]
+pub fn publish_object(
+    client_id: NetworkTableClientId,
+    obj: EncodedObject,
+) -> Result<(), String> {
+    let obj = obj.decode().map_err(|e| e.to_string())?;
+    super::publish_object(client_id, obj);
+    Ok(())
+}
+
 #[tauri::command]
 pub fn unpublish_topic(
     client_id: NetworkTableClientId,
@@ -2,6 +2,7 @@ use network_tables::v4::client::clear_identity;
 use network_tables::v4::client_config::default_should_reconnect;
 use network_tables::v4::subscription::SubscriptionOptions;
 use network_tables::v4::{Client, Config, PublishedTopic, Subscription, Type};
+use parking_lot::Mutex;
 use single_value_channel::{
     channel_starting_with as single_channel, Receiver as SingleReceiver, Updater as SingleUpdater,
 };
@@ -9,13 +10,18 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::hash::Hash;
 use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::task::JoinHandle as TokioJoinHandle;
 
 // use crate::datalog::DATALOG;
-use crate::enoki_types::{now, EnokiField, EnokiObject, TimestampedEnokiValue, EnokiKey, EnokiTimeStamp};
+use crate::enoki_types::{now, EnokiField, EnokiObject, TimestampedEnokiValue, EnokiKey, EnokiTimeStamp, EnokiValue};
 use crate::error::{EnokiError, log_result_consume};
+use crate::networktable::listeners::{NetworkTableListener, NetworkTableListeners};
+use crate::networktable::table::LiveTable;
+use crate::units::Unit;
 use crate::NETWORK_CLIENT_MAP;
 
 pub fn get_connect_client_names() -> Vec<String> {
@@ -57,12 +63,17 @@ impl Display for NetworkTableClientId {
     }
 }
 
+pub use super::table::NetworkTableSnapshot;
+
 #[derive(Debug)]
 pub struct NetworkTableClient {
     id: NetworkTableClientId,
     subscriptions: Sender<SubscriptionPackage>,
     input: Sender<EnokiObject>,
-    output: SingleReceiver<HashMap<String, EnokiObject>>,
+    output: SingleReceiver<NetworkTableSnapshot>,
+    listeners: Arc<Mutex<NetworkTableListeners>>,
+    /// Set by the nt4 loop while it's connected to the server
+    connected: Arc<AtomicBool>,
     thread: TokioJoinHandle<()>,
 }
 impl NetworkTableClient {
@@ -70,7 +81,9 @@ impl NetworkTableClient {
         id: NetworkTableClientId,
         subscriptions: Sender<SubscriptionPackage>,
         input: Sender<EnokiObject>,
-        output: SingleReceiver<HashMap<String, EnokiObject>>,
+        output: SingleReceiver<NetworkTableSnapshot>,
+        listeners: Arc<Mutex<NetworkTableListeners>>,
+        connected: Arc<AtomicBool>,
         thread: TokioJoinHandle<()>,
     ) -> Self {
         Self {
@@ -78,13 +91,20 @@ impl NetworkTableClient {
             subscriptions,
             input,
             output,
+            listeners,
+            connected,
             thread,
         }
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
     pub fn stop(&self) {
         clear_identity(self.id.identity.clone());
         self.thread.abort();
+        self.listeners.lock().close_all();
     }
 
     pub fn publish_obj(&mut self, obj: EnokiObject) {
@@ -131,16 +151,26 @@ impl NetworkTableClient {
             });
     }
 
-    pub fn poll(&mut self, topic: String) -> Result<EnokiObject, EnokiError> {
-        if let Some(sub_obj) = self.output.latest().get(&topic) {
+    pub fn poll(&mut self, topic: String) -> Result<Arc<EnokiObject>, EnokiError> {
+        if let Some(sub_obj) = self.output.latest().get(topic.as_str()) {
             Ok(sub_obj.clone())
         } else {
             Err(EnokiError::NTTopicNotFound(topic))
         }
     }
 
-    pub fn poll_all(&mut self) -> Vec<EnokiObject> {
-        self.output.latest().clone().values().cloned().collect()
+    pub fn poll_all(&mut self) -> Vec<Arc<EnokiObject>> {
+        self.output.latest().values().cloned().collect()
+    }
+
+    /// Every subscription at once, see [NetworkTableSnapshot]
+    pub fn snapshot(&mut self) -> NetworkTableSnapshot {
+        self.output.latest().clone()
+    }
+
+    /// Gets told about every value and announcement of a subscription, see [NetworkTableListener]
+    pub fn listen(&mut self, topic: String) -> Arc<NetworkTableListener> {
+        self.listeners.lock().listen(topic)
     }
 }
 
@@ -220,16 +250,28 @@ pub fn start_nt4_client(
     identity: String,
 ) -> Result<NetworkTableClient, EnokiError> {
     let (snd_pub, rec_pub) = channel::<EnokiObject>(255);
-    let (rec_sub, snd_sub) = single_channel(HashMap::new());
+    let (rec_sub, snd_sub) = single_channel(NetworkTableSnapshot::default());
     let (subscription_sender, subscription_receiver) = channel::<SubscriptionPackage>(255);
+    let listeners = Arc::new(Mutex::new(NetworkTableListeners::default()));
+    let connected = Arc::new(AtomicBool::new(false));
     let thread = nt4(
         id.clone(),
         identity,
         subscription_receiver,
         rec_pub,
         snd_sub,
+        listeners.clone(),
+        connected.clone(),
+    );
+    let client = NetworkTableClient::new(
+        id,
+        subscription_sender,
+        snd_pub,
+        rec_sub,
+        listeners,
+        connected,
+        thread,
     );
-    let client = NetworkTableClient::new(id, subscription_sender, snd_pub, rec_sub, thread);
 
     Ok(client)
 }
@@ -239,7 +281,9 @@ fn nt4(
     identity: String,
     mut subscriptions: Receiver<SubscriptionPackage>,
     mut input: Receiver<EnokiObject>,
-    output: SingleUpdater<HashMap<String, EnokiObject>>,
+    output: SingleUpdater<NetworkTableSnapshot>,
+    listeners: Arc<Mutex<NetworkTableListeners>>,
+    connected: Arc<AtomicBool>,
 ) -> TokioJoinHandle<()> {
     tokio::task::Builder::new()
         .name(format!("NT4-{}", identity).as_str())
@@ -249,6 +293,18 @@ fn nt4(
 
             let mut subs: HashMap<String, Subscription> = HashMap::new();
             let mut pubs: HashMap<String, PublishedTopic> = HashMap::new();
+            // announced types are used as hints when decoding values
+            let announced_types: Arc<Mutex<HashMap<String, Type>>> = Arc::new(Mutex::new(HashMap::new()));
+            let announce_types = announced_types.clone();
+            // units come from the `unit` topic property and are attached to every field of the topic
+            let announced_units: Arc<Mutex<HashMap<String, Unit>>> = Arc::new(Mutex::new(HashMap::new()));
+            let announce_units = announced_units.clone();
+            // the names topics were announced under, so a key read from a topic is published back to it
+            let topic_names: Arc<Mutex<HashMap<EnokiKey, String>>> = Arc::new(Mutex::new(HashMap::new()));
+            let announce_names = topic_names.clone();
+            let announce_listeners = listeners.clone();
+            let disconnected = connected.clone();
+            let reconnected = connected.clone();
 
             let client = Client::try_new_w_config(
                 SocketAddrV4::new(address, port),
@@ -256,7 +312,20 @@ fn nt4(
                     connect_timeout: 30000,
                     disconnect_retry_interval: 10000,
                     should_reconnect: Box::new(default_should_reconnect),
-                    on_announce: Box::new(|topic| {
+                    on_announce: Box::new(move |topic| {
+                        announce_types.lock().insert(topic.name.clone(), topic.r#type.clone());
+                        announce_names
+                            .lock()
+                            .insert(EnokiKey::from_topic_name(&topic.name), topic.name.clone());
+                        let unit = serde_json::to_value(&topic.properties)
+                            .ok()
+                            .as_ref()
+                            .and_then(Unit::from_properties);
+                        match unit {
+                            Some(unit) => announce_units.lock().insert(topic.name.clone(), unit),
+                            None => announce_units.lock().remove(&topic.name),
+                        };
+                        announce_listeners.lock().push_announce(&topic.name, datalog_type(&topic.r#type));
                         Box::pin(async {
                             // log_result_consume(DATALOG.lock().borrow_sender().start_entry(
                             //     topic.name.clone(),
@@ -280,12 +349,14 @@ fn nt4(
                             }
                         })
                     }),
-                    on_disconnect: Box::new(|| {
+                    on_disconnect: Box::new(move || {
+                        disconnected.store(false, Ordering::Relaxed);
                         Box::pin(async {
                             tracing::info!("Disconnected");
                         })
                     }),
-                    on_reconnect: Box::new(|| {
+                    on_reconnect: Box::new(move || {
+                        reconnected.store(true, Ordering::Relaxed);
                         Box::pin(async {
                             tracing::info!("Reconnected");
                         })
@@ -298,13 +369,15 @@ fn nt4(
                 tracing::error!("Failed to connect to {}:{} because {}", address, port, err);
                 panic!();
             });
+            connected.store(true, Ordering::Relaxed);
 
             // let datalog_sender = DATALOG.lock().get_sender();
 
-            let mut table = HashMap::new();
+            let mut table = LiveTable::default();
 
             loop {
                 let start_time = std::time::Instant::now();
+                let mut changed = false;
 
                 let new_sub_data = subscriptions.try_recv();
                 if let Ok(sub_data) = new_sub_data {
@@ -323,9 +396,11 @@ fn nt4(
                                 panic!();
                             });
                         tracing::info!("Subscribed to {}:{}:{}", address, port, &topic);
-                        table.insert(topic.clone(), EnokiObject::new(now()));
+                        table.subscribe(&topic, now());
+                        changed = true;
                         subs.insert(topic, sub);
                     } else {
+                        table.unsubscribe(&topic);
                         tracing::info!("Unsubscribed from {}:{}:{}", address, port, &topic);
                     }
                 }
@@ -333,7 +408,11 @@ fn nt4(
                 let new_pub_data = input.try_recv();
                 if let Ok(table) = new_pub_data {
                     for entry in table.get_fields() {
-                        let path = String::from(entry.get_key());
+                        let path = topic_names
+                            .lock()
+                            .get(entry.get_key())
+                            .cloned()
+                            .unwrap_or_else(|| entry.get_key().topic_name());
                         if !pubs.contains_key(&path) {
                             let topic = client
                                 .publish_topic(
@@ -357,31 +436,44 @@ fn nt4(
                 for (topic, sub) in subs.iter_mut() {
                     let mut new_obj_data: EnokiObject = EnokiObject::new(client.real_server_time());
                     while let Ok(msg) = sub.try_next().await {
+                        let hint = announced_types.lock().get(&msg.topic_name).cloned();
+                        let value = match EnokiValue::from_rmpv(msg.data, hint.as_ref()) {
+                            Ok(value) => value,
+                            Err(err) => {
+                                tracing::warn!("Dropping value for {}: {}", msg.topic_name, err);
+                                continue;
+                            }
+                        };
                         let field = EnokiField::new(
-                            msg.topic_name.clone().into(),
+                            EnokiKey::from_topic_name(&msg.topic_name),
                             TimestampedEnokiValue::new(
                                 client.to_real_time(msg.timestamp as u64),
-                                msg.data.into(),
+                                value,
                             ),
-                        );
+                        )
+                        .with_unit(announced_units.lock().get(&msg.topic_name).cloned());
                         new_obj_data.add_field(field.clone());
                         // log_result_consume(datalog_sender.append_to_entry_with_timestamp(
                         //     EnokiKey::from(msg.topic_name).prefix(identity.clone()).into(),
                         //     field.get_value_owned().value.into(),
                         //     field.get_value().timestamp));
                     }
-                    if let Some(object) = table.get_mut(topic) {
-                        object.update_all(&new_obj_data)
+                    if new_obj_data.is_empty() {
+                        continue;
                     }
+                    listeners.lock().push_values(topic, &new_obj_data);
+                    changed |= table.update(topic, &new_obj_data);
+                }
+                if changed {
+                    output.update(table.snapshot()).unwrap_or_else(|err| {
+                        tracing::error!(
+                            "Failed to send to network table client {}:{}",
+                            address,
+                            port
+                        );
+                        tracing::error!("Error: {}", err);
+                    });
                 }
-                output.update(table.clone()).unwrap_or_else(|err| {
-                    tracing::error!(
-                        "Failed to send to network table client {}:{}",
-                        address,
-                        port
-                    );
-                    tracing::error!("Error: {}", err);
-                });
 
                 let elapsed = start_time.elapsed();
                 tokio::time::sleep(Duration::from_secs_f64(
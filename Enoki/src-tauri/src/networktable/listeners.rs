@@ -0,0 +1,196 @@
+//! Lets code outside the nt4 loop react to a subscription instead of polling it.
+//!
+//! The loop only ever adds to a listener's pending batch, the listener takes the whole batch
+//! whenever it's ready for more. A slow listener gets bigger batches instead of holding up
+//! the client, and once it falls too far behind only the latest value of each field is kept.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+
+/// How many values a listener may have waiting before older ones are dropped
+pub const MAX_PENDING_VALUES: usize = 4096;
+
+/// Everything that happened to a subscription since the listener last took a batch
+#[derive(Debug, Default)]
+pub struct ListenerBatch {
+    /// Topics announced under the subscription with their datalog type, e.g. `double[]`
+    pub announced: Vec<(String, String)>,
+    /// The latest value of every field that changed,
+    /// with every value received for it in its history unless some were dropped
+    pub values: Option<EnokiObject>,
+    /// How many values were left out of the histories because the listener fell behind
+    pub dropped: usize,
+}
+
+impl ListenerBatch {
+    pub fn is_empty(&self) -> bool {
+        self.announced.is_empty() && self.values.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingBatch {
+    batch: ListenerBatch,
+    values: usize,
+    closed: bool,
+}
+
+#[derive(Debug)]
+pub struct NetworkTableListener {
+    topic: String,
+    pending: Mutex<PendingBatch>,
+    ready: Condvar,
+}
+
+impl NetworkTableListener {
+    fn new(topic: String) -> Self {
+        Self {
+            topic,
+            pending: Mutex::new(PendingBatch::default()),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// The subscription topic this listens to
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    fn push_values(&self, values: &EnokiObject) {
+        let mut guard = self.pending.lock();
+        let pending = &mut *guard;
+        if pending.closed {
+            return;
+        }
+        let object = pending
+            .batch
+            .values
+            .get_or_insert_with(|| EnokiObject::new(values.get_timestamp()));
+        for field in values.get_fields() {
+            object.record_field(EnokiField::clone(field));
+        }
+        object.update_timestamp(values);
+        pending.values += values.len();
+        if pending.values > MAX_PENDING_VALUES {
+            // the listener still hears about every field that changed, just not every value
+            let mut latest = EnokiObject::new(object.get_timestamp());
+            for field in object.get_fields() {
+                latest.record_field(EnokiField::clone(field));
+            }
+            pending.batch.dropped += pending.values - latest.len();
+            pending.values = latest.len();
+            *object = latest;
+        }
+        self.ready.notify_all();
+    }
+
+    fn push_announce(&self, topic: &str, type_name: &str) {
+        let mut pending = self.pending.lock();
+        if pending.closed {
+            return;
+        }
+        pending
+            .batch
+            .announced
+            .push((topic.to_string(), type_name.to_string()));
+        self.ready.notify_all();
+    }
+
+    /// Waits for something to happen and takes all of it, none once the listener is closed
+    pub fn next_batch(&self) -> Option<ListenerBatch> {
+        let mut pending = self.pending.lock();
+        while !pending.closed && pending.batch.is_empty() {
+            self.ready.wait(&mut pending);
+        }
+        if pending.closed {
+            return None;
+        }
+        pending.values = 0;
+        Some(std::mem::take(&mut pending.batch))
+    }
+
+    /// Waits up to `timeout` for something to happen without taking it,
+    /// true if there's a batch to take or the listener was closed
+    pub fn wait_ready(&self, timeout: Duration) -> bool {
+        let mut pending = self.pending.lock();
+        if !pending.closed && pending.batch.is_empty() {
+            self.ready.wait_for(&mut pending, timeout);
+        }
+        pending.closed || !pending.batch.is_empty()
+    }
+
+    /// Takes everything that happened so far without waiting, none if nothing did or it's closed
+    pub fn take_batch(&self) -> Option<ListenerBatch> {
+        let mut pending = self.pending.lock();
+        if pending.closed || pending.batch.is_empty() {
+            return None;
+        }
+        pending.values = 0;
+        Some(std::mem::take(&mut pending.batch))
+    }
+
+    /// Stops the listener, anything pending is thrown away
+    pub fn close(&self) {
+        let mut pending = self.pending.lock();
+        pending.closed = true;
+        pending.batch = ListenerBatch::default();
+        self.ready.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().closed
+    }
+}
+
+/// The listeners of one client, shared by its handle and its nt4 loop
+#[derive(Debug, Default)]
+pub struct NetworkTableListeners {
+    listeners: Vec<Arc<NetworkTableListener>>,
+    /// Every topic announced so far with its datalog type, replayed to new listeners
+    announced: HashMap<String, String>,
+}
+
+impl NetworkTableListeners {
+    /// A listener for a subscription topic, topics already announced under it are in its first batch
+    pub fn listen(&mut self, topic: String) -> Arc<NetworkTableListener> {
+        let listener = Arc::new(NetworkTableListener::new(topic));
+        let prefix = EnokiKey::from_topic_name(listener.topic());
+        for (announced, type_name) in self.announced.iter() {
+            if EnokiKey::from_topic_name(announced).starts_with(&prefix) {
+                listener.push_announce(announced, type_name);
+            }
+        }
+        self.listeners.push(listener.clone());
+        listener
+    }
+
+    /// Hands the values a subscription received to its listeners
+    pub fn push_values(&mut self, topic: &str, values: &EnokiObject) {
+        self.listeners.retain(|listener| !listener.is_closed());
+        for listener in self.listeners.iter().filter(|l| l.topic() == topic) {
+            listener.push_values(values);
+        }
+    }
+
+    /// Tells every listener whose subscription covers the topic that it was announced
+    pub fn push_announce(&mut self, topic: &str, type_name: String) {
+        let key = EnokiKey::from_topic_name(topic);
+        for listener in self.listeners.iter() {
+            if key.starts_with(&EnokiKey::from_topic_name(listener.topic())) {
+                listener.push_announce(topic, &type_name);
+            }
+        }
+        self.announced.insert(topic.to_string(), type_name);
+    }
+
+    pub fn close_all(&mut self) {
+        for listener in self.listeners.drain(..) {
+            listener.close();
+        }
+    }
+}
@@ -2,6 +2,8 @@
 #[macro_use]
 pub mod handler;
 pub mod ffi;
+pub mod listeners;
+pub mod table;
 // pub mod python_funcs;
 
 use std::collections::HashMap;
@@ -26,8 +28,10 @@ pub fn networktable_plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R>
             subscribe_to_topic,
             unsubscribe_from_topic,
             get_subbed_data,
+            query_subbed_data,
             get_subbed_data_with_history,
             set_topic_value,
+            publish_object,
             unpublish_topic
         ])
         .build()
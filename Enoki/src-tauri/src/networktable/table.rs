@@ -0,0 +1,54 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    derived::LiveDerived,
+    enoki_types::{EnokiObject, EnokiTimeStamp},
+};
+
+/// The latest objects of every subscription of a client, keyed by topic.
+/// Snapshots share every object that didn't change since the previous one,
+/// so taking or keeping one costs nothing until the client writes to a topic it holds.
+pub type NetworkTableSnapshot = Arc<HashMap<Arc<str>, Arc<EnokiObject>>>;
+
+/// The objects of a client's subscriptions as its nt4 loop keeps them.
+///
+/// An update to an object a snapshot still holds copies the object's field pointers,
+/// the fields themselves are shared and only the ones that were written are replaced.
+#[derive(Debug, Default)]
+pub struct LiveTable {
+    objects: HashMap<Arc<str>, Arc<EnokiObject>>,
+    derived: HashMap<String, LiveDerived>,
+}
+
+impl LiveTable {
+    /// Starts the subscription over with an empty object
+    pub fn subscribe(&mut self, topic: &str, timestamp: EnokiTimeStamp) {
+        self.derived.remove(topic);
+        self.objects
+            .insert(topic.into(), Arc::new(EnokiObject::new(timestamp)));
+    }
+
+    /// The object is kept as it was, derived fields stop being evaluated for it
+    pub fn unsubscribe(&mut self, topic: &str) {
+        self.derived.remove(topic);
+    }
+
+    /// Adds what a subscription received to its object and evaluates the derived fields over it,
+    /// false if the topic isn't subscribed
+    pub fn update(&mut self, topic: &str, values: &EnokiObject) -> bool {
+        let object = match self.objects.get_mut(topic) {
+            Some(object) => Arc::make_mut(object),
+            None => return false,
+        };
+        object.update_all(values);
+        self.derived
+            .entry(topic.to_string())
+            .or_default()
+            .apply(object);
+        true
+    }
+
+    pub fn snapshot(&self) -> NetworkTableSnapshot {
+        Arc::new(self.objects.clone())
+    }
+}
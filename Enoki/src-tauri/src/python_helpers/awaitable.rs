@@ -0,0 +1,250 @@
+//! Awaitables for the python bindings.
+//!
+//! Each one is an asyncio future on the loop that was running when it was made. One helper thread
+//! per loop waits for all of its futures without holding the gil and hands their results to the
+//! loop's own thread, so a future that was cancelled in the meantime, e.g. by `asyncio.wait_for`,
+//! never takes anything.
+
+use std::cell::RefCell;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+
+use super::logging::format_py_err;
+
+/// How long a waiter thread spends on its futures before it looks for new ones
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Ready = Box<dyn Fn(Duration) -> bool + Send + Sync>;
+type Take = Box<dyn Fn(Python) -> PyResult<Option<PyObject>> + Send + Sync>;
+
+thread_local! {
+    /// The loop last awaited on from this thread and where its waiter thread gets new futures
+    static WAITER: RefCell<Option<(PyObject, mpsc::Sender<Arc<Waiting>>)>> =
+        const { RefCell::new(None) };
+}
+
+struct Waiting {
+    future: PyObject,
+    /// Set by the future once it's done for any reason
+    done: Arc<AtomicBool>,
+    /// Set while a take is scheduled on the loop, it isn't waited for until it comes back empty
+    taking: AtomicBool,
+    ready: Ready,
+    take: Take,
+}
+
+impl Waiting {
+    /// Runs on the loop's thread
+    fn resolve(&self, py: Python) {
+        if self.done.load(Ordering::Relaxed) {
+            return;
+        }
+        let result = match (self.take)(py) {
+            Ok(Some(value)) => Ok(value),
+            // something else got to it first
+            Ok(None) => {
+                self.taking.store(false, Ordering::Relaxed);
+                return;
+            }
+            Err(err) => Err(err),
+        };
+        set_result(py, &self.future, result);
+    }
+}
+
+/// Hands `waiting` to the waiter thread of `event_loop`, starting one if the loop has none yet
+fn wait_on(event_loop: &PyAny, waiting: Arc<Waiting>) -> std::io::Result<()> {
+    WAITER.with(|waiter| {
+        let mut waiter = waiter.borrow_mut();
+        let waiting = match &*waiter {
+            Some((running, sender)) if running.as_ref(event_loop.py()).is(event_loop) => {
+                match sender.send(waiting) {
+                    Ok(()) => return Ok(()),
+                    Err(mpsc::SendError(waiting)) => waiting,
+                }
+            }
+            _ => waiting,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let thread_loop = event_loop.into();
+        std::thread::Builder::new()
+            .name("PyAwaitable".to_string())
+            .spawn(move || wait_loop(thread_loop, receiver))?;
+        let _ = sender.send(waiting);
+        // replacing the sender of a previous loop lets its thread stop once it's done
+        *waiter = Some((event_loop.into(), sender));
+        Ok(())
+    })
+}
+
+/// Waits for the futures of one loop, until the loop is closed
+/// or no more are coming and all of them are done
+fn wait_loop(event_loop: PyObject, new: mpsc::Receiver<Arc<Waiting>>) {
+    let mut pending: Vec<Arc<Waiting>> = Vec::new();
+    let mut connected = true;
+    loop {
+        if pending.is_empty() && connected {
+            match new.recv() {
+                Ok(waiting) => pending.push(waiting),
+                Err(_) => connected = false,
+            }
+        }
+        loop {
+            match new.try_recv() {
+                Ok(waiting) => pending.push(waiting),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    connected = false;
+                    break;
+                }
+            }
+        }
+        pending.retain(|waiting| !waiting.done.load(Ordering::Relaxed));
+        if !connected && (pending.is_empty() || loop_closed(&event_loop)) {
+            return;
+        }
+
+        let waiting = pending
+            .iter()
+            .filter(|waiting| !waiting.taking.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        if waiting.is_empty() {
+            // a take that was never run is stuck on a loop that's closed
+            std::thread::sleep(POLL_INTERVAL);
+            if loop_closed(&event_loop) {
+                return;
+            }
+            continue;
+        }
+        let timeout = POLL_INTERVAL / waiting.len() as u32;
+        for waiting in waiting {
+            if (waiting.ready)(timeout) {
+                waiting.taking.store(true, Ordering::Relaxed);
+                let resolving = waiting.clone();
+                Python::with_gil(|py| {
+                    call_soon(py, &event_loop, move |py| {
+                        resolving.resolve(py);
+                        Ok(())
+                    })
+                });
+            }
+        }
+    }
+}
+
+fn loop_closed(event_loop: &PyObject) -> bool {
+    Python::with_gil(|py| {
+        event_loop
+            .call_method0(py, "is_closed")
+            .and_then(|closed| closed.extract(py))
+            .unwrap_or(true)
+    })
+}
+
+fn set_result(py: Python, future: &PyObject, result: PyResult<PyObject>) {
+    let set = match result {
+        Ok(value) => future.call_method1(py, "set_result", (value,)),
+        Err(err) => future.call_method1(py, "set_exception", (err.into_value(py),)),
+    };
+    if let Err(err) = set {
+        tracing::warn!("Failed to resolve a future: {}", format_py_err(py, &err));
+    }
+}
+
+/// Runs `f` on the loop's thread, what it raises is raised in the loop.
+/// Nothing happens if the loop was closed, `f` is dropped without being called.
+pub fn call_soon(
+    py: Python,
+    event_loop: &PyObject,
+    f: impl FnOnce(Python) -> PyResult<()> + Send + 'static,
+) {
+    let f = Mutex::new(Some(f));
+    let callback = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+            let f = f.lock().take();
+            match f {
+                Some(f) => f(args.py()),
+                None => Ok(()),
+            }
+        },
+    );
+    let scheduled = callback
+        .and_then(|callback| event_loop.call_method1(py, "call_soon_threadsafe", (callback,)));
+    if let Err(err) = scheduled {
+        tracing::debug!(
+            "Dropped a result for a closed loop: {}",
+            format_py_err(py, &err)
+        );
+    }
+}
+
+/// A future on the running loop that gets its result from `take`, called on the loop's thread
+/// whenever `ready` says there may be something. `ready` is called on the loop's waiter thread,
+/// which it shares with the loop's other futures, and should wait at most the duration it's given.
+/// `take` returning none goes back to waiting.
+pub fn wait_until<'py>(
+    py: Python<'py>,
+    ready: impl Fn(Duration) -> bool + Send + Sync + 'static,
+    take: impl Fn(Python) -> PyResult<Option<PyObject>> + Send + Sync + 'static,
+) -> PyResult<&'py PyAny> {
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let done = Arc::new(AtomicBool::new(false));
+    let set_done = done.clone();
+    let on_done = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |_args: &PyTuple, _kwargs: Option<&PyDict>| set_done.store(true, Ordering::Relaxed),
+    )?;
+    future.call_method1("add_done_callback", (on_done,))?;
+
+    let waiting = Arc::new(Waiting {
+        future: future.into(),
+        done,
+        taking: AtomicBool::new(false),
+        ready: Box::new(ready),
+        take: Box::new(take),
+    });
+    wait_on(event_loop, waiting)?;
+    Ok(future)
+}
+
+/// A future on the running loop for what `f` returns, `f` runs on its own thread without the gil
+pub fn run_blocking<'py, T: IntoPy<PyObject> + Send + 'static>(
+    py: Python<'py>,
+    f: impl FnOnce() -> PyResult<T> + Send + 'static,
+) -> PyResult<&'py PyAny> {
+    let result = Arc::new(Mutex::new(None));
+    let finished = Arc::new(AtomicBool::new(false));
+    let (thread_result, thread_finished) = (result.clone(), finished.clone());
+    std::thread::Builder::new()
+        .name("PyBlocking".to_string())
+        .spawn(move || {
+            *thread_result.lock() = Some(f());
+            thread_finished.store(true, Ordering::Relaxed);
+        })?;
+    wait_until(
+        py,
+        move |timeout| {
+            if !finished.load(Ordering::Relaxed) {
+                std::thread::sleep(timeout.min(Duration::from_millis(5)));
+            }
+            finished.load(Ordering::Relaxed)
+        },
+        move |py| match result.lock().take() {
+            Some(result) => result.map(|value| Some(value.into_py(py))),
+            None => Ok(None),
+        },
+    )
+}
@@ -0,0 +1,89 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::datalog::python as datalog;
+use crate::derived::python as derived;
+use crate::enoki_types::EnokiTimeStamp;
+use crate::networktable::ffi::python as nt;
+use crate::resample::python as resample;
+use crate::scripting::plugins::ENOKI_API_VERSION;
+use crate::scripting::python as scripting;
+
+use super::logging;
+use super::numpy;
+use super::py_enoki_types::{
+    add_typed_constructors, PyEnokiField, PyEnokiObject, PyTimestampedEnokiValue, PyTypedValue,
+};
+
+/// The current time in microseconds since the epoch
+#[pyfunction]
+fn now() -> EnokiTimeStamp {
+    crate::enoki_types::now()
+}
+
+/// Adds a namespace to `enoki_core`, it's also put in `sys.modules`
+/// so `import enoki_core.NetworkTable` works as well as attribute access
+fn add_submodule<'py>(
+    py: Python<'py>,
+    parent: &PyModule,
+    name: &str,
+    fill: impl FnOnce(&'py PyModule) -> PyResult<()>,
+) -> PyResult<()> {
+    let module = PyModule::new(py, name)?;
+    fill(module)?;
+    parent.add_submodule(module)?;
+    py.import("sys")?
+        .getattr("modules")?
+        .downcast::<PyDict>()?
+        .set_item(format!("{}.{}", parent.name()?, name), module)
+}
+
+/// The module scripts import to talk to the app, described by `scripting_base/enoki_core.pyi`
+#[pymodule]
+pub fn enoki_core(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyTimestampedEnokiValue>()?;
+    m.add_class::<PyEnokiField>()?;
+    m.add_class::<PyEnokiObject>()?;
+    m.add_class::<PyTypedValue>()?;
+    add_typed_constructors(py, m)?;
+    m.add("API_VERSION", ENOKI_API_VERSION)?;
+    m.add_function(wrap_pyfunction!(now, m)?)?;
+    m.add_function(wrap_pyfunction!(numpy::use_numpy, m)?)?;
+
+    add_submodule(py, m, "NetworkTable", |sub| {
+        sub.add_class::<nt::PyNetworkTableClientId>()?;
+        sub.add_class::<nt::PyNetworkTablePubbedTopic>()?;
+        sub.add_class::<nt::PyNetworkTableSubscription>()?;
+        sub.add_function(wrap_pyfunction!(nt::start_network_table_client, sub)?)
+    })?;
+    add_submodule(py, m, "Datalog", |sub| {
+        sub.add_class::<datalog::PySessionMetadata>()?;
+        sub.add_class::<datalog::PyDatalogEntry>()?;
+        sub.add_class::<datalog::PyDatalogReader>()?;
+        sub.add_class::<datalog::PyDatalogWriter>()?;
+        sub.add_function(wrap_pyfunction!(datalog::open_datalog, sub)?)?;
+        sub.add_function(wrap_pyfunction!(datalog::open_datalog_async, sub)?)?;
+        sub.add_function(wrap_pyfunction!(datalog::create_datalog, sub)?)?;
+        sub.add_function(wrap_pyfunction!(datalog::get_session_metadata, sub)?)?;
+        sub.add_function(wrap_pyfunction!(datalog::set_session_metadata, sub)?)?;
+        sub.add_function(wrap_pyfunction!(datalog::read_session_metadata, sub)?)
+    })?;
+    add_submodule(py, m, "Resample", |sub| {
+        sub.add_function(wrap_pyfunction!(resample::resample, sub)?)?;
+        sub.add_function(wrap_pyfunction!(resample::resample_object, sub)?)?;
+        sub.add_function(wrap_pyfunction!(resample::align, sub)?)
+    })?;
+    add_submodule(py, m, "Derived", |sub| {
+        sub.add_function(wrap_pyfunction!(derived::define_derived_field, sub)?)?;
+        sub.add_function(wrap_pyfunction!(derived::remove_derived_field, sub)?)?;
+        sub.add_function(wrap_pyfunction!(derived::evaluate, sub)?)
+    })?;
+    add_submodule(py, m, "Scripting", |sub| {
+        sub.add_function(wrap_pyfunction!(scripting::register_command, sub)?)?;
+        sub.add_function(wrap_pyfunction!(scripting::register_provider, sub)?)?;
+        sub.add_function(wrap_pyfunction!(scripting::unregister, sub)?)
+    })?;
+    add_submodule(py, m, "logging", |sub| logging::logging(py, sub))?;
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 
 use pyo3::prelude::*;
+use pyo3::types::PyTraceback;
 use tracing::Level;
 
 // import traceback, threading
@@ -10,21 +11,56 @@ use tracing::Level;
 
 pub fn tracing_log(py: Python, level: Level, msg: String) -> PyResult<()> {
     let traceback = py.import("traceback")?;
-    let threading = py.import("threading")?;
     let call_frame = traceback
         .getattr("extract_stack")?
         .call0()?
         .get_item(-2)?;
     // let name = call_frame.getattr("name")?.extract::<String>()?;
-    let lineno = call_frame.getattr("lineno")?.extract::<String>()?;
+    let lineno = call_frame.getattr("lineno")?.to_string();
     let filename = call_frame.getattr("filename")?.extract::<String>()?;
-    let mut thread = threading
+    log_at(level, msg, lineno, filename, python_thread(py)?);
+    Ok(())
+}
+
+/// Logs an exception with its traceback as an error at the line that raised it
+pub fn tracing_log_exception(py: Python, err: &PyErr) -> PyResult<()> {
+    let mut lineno = String::from("?");
+    let mut filename = String::from("?");
+    if let Some(mut traceback) = err.traceback(py) {
+        while let Ok(next) = traceback.getattr("tb_next")?.downcast::<PyTraceback>() {
+            traceback = next;
+        }
+        lineno = traceback.getattr("tb_lineno")?.to_string();
+        filename = traceback
+            .getattr("tb_frame")?
+            .getattr("f_code")?
+            .getattr("co_filename")?
+            .extract()?;
+    }
+    log_at(Level::ERROR, format_py_err(py, err), lineno, filename, python_thread(py)?);
+    Ok(())
+}
+
+/// The exception with its traceback, the way python prints it
+pub fn format_py_err(py: Python, err: &PyErr) -> String {
+    let traceback = err
+        .traceback(py)
+        .and_then(|traceback| traceback.format().ok())
+        .unwrap_or_default();
+    format!("{}{}", traceback, err)
+}
+
+fn python_thread(py: Python) -> PyResult<String> {
+    let thread = py
+        .import("threading")?
         .getattr("current_thread")?
         .call0()?
         .getattr("name")?
         .extract::<String>()?;
-    thread = format!("py:{}", thread);
+    Ok(format!("py:{}", thread))
+}
 
+pub(crate) fn log_at(level: Level, msg: String, lineno: String, filename: String, thread: String) {
     match level {
         Level::TRACE => tracing::trace!(source = "scripting", message = msg, line = lineno, file = filename, thread = thread),
         Level::DEBUG => tracing::debug!(source = "scripting", message = msg, line = lineno, file = filename, thread = thread),
@@ -32,8 +68,6 @@ pub fn tracing_log(py: Python, level: Level, msg: String) -> PyResult<()> {
         Level::WARN => tracing::warn!(source = "scripting", message = msg, line = lineno, file = filename, thread = thread),
         Level::ERROR => tracing::error!(source = "scripting", message = msg, line = lineno, file = filename, thread = thread),
     }
-
-    Ok(())
 }
 
 #[pyfunction]
@@ -62,7 +96,7 @@ pub fn error(py: Python, msg: String) -> PyResult<()> {
 }
 
 #[pymodule]
-fn logging(_py: Python, m: &PyModule) -> PyResult<()> {
+pub(crate) fn logging(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(trace, m)?)?;
     m.add_function(wrap_pyfunction!(debug, m)?)?;
     m.add_function(wrap_pyfunction!(info, m)?)?;
@@ -1,3 +1,6 @@
 
+pub mod awaitable;
+pub mod enoki_core;
 pub mod logging;
+pub mod numpy;
 pub mod py_enoki_types;
@@ -0,0 +1,202 @@
+//! Optional NumPy and pandas interop.
+//!
+//! Neither is a dependency of the app, they are imported from the interpreter when first needed.
+//! Numeric arrays only turn into ndarrays for a script that called `enoki_core.use_numpy()`,
+//! ndarrays are accepted as values either way.
+
+use std::cell::Cell;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyDict};
+
+use crate::enoki_types::{EnokiHistory, EnokiValue};
+
+thread_local! {
+    /// Whether the script running python on this thread asked for ndarrays,
+    /// a script's hooks, tasks and callbacks all run on its worker
+    static USE_NUMPY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes numeric arrays ndarrays instead of lists for the calling script,
+/// raises ImportError if numpy isn't installed
+#[pyfunction]
+#[pyo3(signature = (enabled = true))]
+pub fn use_numpy(py: Python, enabled: bool) -> PyResult<()> {
+    if enabled {
+        py.import("numpy")?;
+    }
+    USE_NUMPY.with(|use_numpy| use_numpy.set(enabled));
+    // the worker is reused when the script restarts, which starts over with lists
+    crate::scripting::on_script_stop(|| USE_NUMPY.with(|use_numpy| use_numpy.set(false)));
+    Ok(())
+}
+
+/// A writable 1d array that owns a copy of the little endian `bytes`
+fn array_from_bytes<'py>(numpy: &'py PyModule, bytes: &[u8], dtype: &str) -> PyResult<&'py PyAny> {
+    let buffer = PyByteArray::new(numpy.py(), bytes);
+    numpy.call_method1("frombuffer", (buffer, dtype))
+}
+
+fn f64_array<'py>(numpy: &'py PyModule, values: &[f64]) -> PyResult<&'py PyAny> {
+    let bytes = values
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    array_from_bytes(numpy, &bytes, "<f8")
+}
+
+fn i64_array(numpy: &PyModule, values: impl Iterator<Item = i64>) -> PyResult<&PyAny> {
+    let bytes = values.flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+    array_from_bytes(numpy, &bytes, "<i8")
+}
+
+fn bool_array(numpy: &PyModule, values: impl Iterator<Item = bool>) -> PyResult<&PyAny> {
+    let bytes = values.map(u8::from).collect::<Vec<_>>();
+    array_from_bytes(numpy, &bytes, "?")
+}
+
+/// The value as an ndarray if it's a numeric array and the calling script asked for numpy
+pub(crate) fn value_to_ndarray(py: Python, value: &EnokiValue) -> Option<PyObject> {
+    if !USE_NUMPY.with(Cell::get) {
+        return None;
+    }
+    let numpy = py.import("numpy").ok()?;
+    let array = match value {
+        EnokiValue::FloatArray(values) | EnokiValue::DoubleArray(values) => {
+            f64_array(numpy, values)
+        }
+        EnokiValue::IntArray(values) => i64_array(numpy, values.iter().copied()),
+        EnokiValue::BooleanArray(values) => bool_array(numpy, values.iter().copied()),
+        _ => return None,
+    };
+    match array {
+        Ok(array) => Some(array.into()),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to convert {} to an ndarray: {}",
+                value.get_type(),
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Reads an ndarray as a value, none if `obj` isn't one
+pub(crate) fn ndarray_to_value(obj: &PyAny) -> Option<PyResult<EnokiValue>> {
+    let class = obj.get_type();
+    let module = class
+        .getattr("__module__")
+        .and_then(|module| module.extract::<&str>());
+    if class.name().ok() != Some("ndarray") || module.ok() != Some("numpy") {
+        return None;
+    }
+    Some(read_ndarray(obj))
+}
+
+fn read_ndarray(array: &PyAny) -> PyResult<EnokiValue> {
+    let ndim = array.getattr("ndim")?.extract::<usize>()?;
+    if ndim == 0 {
+        return array.call_method0("item")?.extract();
+    }
+    if ndim > 1 {
+        return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "Only 1d arrays can be values, got {} dimensions",
+            ndim
+        )));
+    }
+    let raw = |dtype: &str| -> PyResult<Vec<u8>> {
+        Ok(array
+            .call_method1("astype", (dtype,))?
+            .call_method0("tobytes")?
+            .downcast::<PyBytes>()?
+            .as_bytes()
+            .to_vec())
+    };
+    let kind = array
+        .getattr("dtype")?
+        .getattr("kind")?
+        .extract::<String>()?;
+    match kind.as_str() {
+        "b" => Ok(EnokiValue::BooleanArray(
+            raw("?")?.into_iter().map(|b| b != 0).collect(),
+        )),
+        "i" | "u" => Ok(EnokiValue::IntArray(
+            raw("<i8")?
+                .chunks_exact(8)
+                .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        )),
+        "f" => Ok(EnokiValue::DoubleArray(
+            raw("<f8")?
+                .chunks_exact(8)
+                .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        )),
+        // strings and objects go through python lists like any other sequence
+        _ => array.call_method0("tolist")?.extract(),
+    }
+}
+
+/// The timestamps and values of a history as two arrays,
+/// values that aren't all numbers or all booleans end up in an object array
+pub(crate) fn history_arrays<'py>(
+    py: Python<'py>,
+    history: &EnokiHistory,
+) -> PyResult<(&'py PyAny, &'py PyAny)> {
+    let numpy = py.import("numpy")?;
+    let timestamps = i64_array(numpy, history.timestamps().iter().map(|t| *t as i64))?;
+    if let Some(doubles) = history.doubles() {
+        return Ok((timestamps, f64_array(numpy, doubles)?));
+    }
+    let values = history
+        .iter()
+        .map(|sample| sample.value)
+        .collect::<Vec<_>>();
+    let ints = values
+        .iter()
+        .map(|value| match value {
+            EnokiValue::Int(i) => Some(*i),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    if let Some(ints) = ints {
+        return Ok((timestamps, i64_array(numpy, ints.into_iter())?));
+    }
+    let bools = values
+        .iter()
+        .map(|value| match value {
+            EnokiValue::Boolean(b) => Some(*b),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    if let Some(bools) = bools {
+        return Ok((timestamps, bool_array(numpy, bools.into_iter())?));
+    }
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", "object")?;
+    let objects = numpy.call_method("empty", (values.len(),), Some(kwargs))?;
+    // filled one by one so arrays stay single values instead of becoming a second dimension
+    for (index, value) in values.into_iter().enumerate() {
+        objects.set_item(index, value.into_py(py))?;
+    }
+    Ok((timestamps, objects))
+}
+
+/// A pandas Series indexed by timestamp, a later value at the same timestamp wins
+pub(crate) fn history_series<'py>(
+    py: Python<'py>,
+    pandas: &'py PyModule,
+    history: &EnokiHistory,
+) -> PyResult<&'py PyAny> {
+    let (timestamps, values) = history_arrays(py, history)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("index", timestamps)?;
+    let series = pandas.call_method("Series", (values,), Some(kwargs))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("keep", "last")?;
+    let duplicated = series
+        .getattr("index")?
+        .call_method("duplicated", (), Some(kwargs))?;
+    series.get_item(duplicated.call_method0("__invert__")?)
+}
@@ -1,12 +1,22 @@
 use std::collections::HashMap;
 
+use pyo3::basic::CompareOp;
 use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyCFunction, PyDict, PyTuple};
 
-use crate::enoki_types::{EnokiValue, TimestampedEnokiValue, EnokiField, EnokiObject};
+use crate::enoki_types::{
+    EnokiField, EnokiHistory, EnokiObject, EnokiQuery, EnokiValue, EnokiValueKind, TimestampedEnokiValue,
+};
+use crate::units::{Unit, UnitSystem};
+
+use super::numpy;
 
 
 impl IntoPy<PyObject> for EnokiValue {
     fn into_py(self, py: Python<'_>) -> PyObject {
+        if let Some(array) = numpy::value_to_ndarray(py, &self) {
+            return array;
+        }
         match self {
             EnokiValue::Boolean(b) => b.into_py(py),
             EnokiValue::Int(i) => i.into_py(py),
@@ -18,14 +28,25 @@ impl IntoPy<PyObject> for EnokiValue {
             EnokiValue::FloatArray(f) => f.into_py(py),
             EnokiValue::DoubleArray(d) => d.into_py(py),
             EnokiValue::StringArray(s) => s.into_py(py),
-            EnokiValue::ByteArray(b) => b.into_py(py),
-            EnokiValue::Protobuf(p) => p.into_py(py),
+            // bytes so they come back as a byte array instead of an int array
+            EnokiValue::ByteArray(b) => PyBytes::new(py, &b).into_py(py),
+            EnokiValue::Protobuf(p) => PyBytes::new(py, &p).into_py(py),
         }
     }
 }
 
 impl FromPyObject<'_> for EnokiValue {
     fn extract(obj: &PyAny) -> PyResult<Self> {
+        if let Ok(typed) = obj.extract::<PyRef<PyTypedValue>>() {
+            return Ok(typed.value.clone());
+        }
+        if let Some(value) = numpy::ndarray_to_value(obj) {
+            return value;
+        }
+        // bytes are sequences of ints too, they'd be taken for an int array below
+        if obj.is_instance_of::<PyBytes>() || obj.is_instance_of::<PyByteArray>() {
+            return Ok(EnokiValue::ByteArray(obj.extract()?));
+        }
         if let Ok(b) = obj.extract::<bool>() {
             return Ok(EnokiValue::Boolean(b));
         }
@@ -50,18 +71,84 @@ impl FromPyObject<'_> for EnokiValue {
         if let Ok(s) = obj.extract::<Vec<String>>() {
             return Ok(EnokiValue::StringArray(s));
         }
-        if let Ok(b) = obj.extract::<Vec<u8>>() {
-            return Ok(EnokiValue::ByteArray(b));
-        }
-        if let Ok(p) = obj.extract::<Vec<u8>>() {
-            return Ok(EnokiValue::Protobuf(p));
-        }
         Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
             "Could not convert to EnokiValue",
         ))
     }
 }
 
+/// Converts a value to `value_type` like [EnokiValue::coerce], raises TypeError when it can't
+pub fn coerce_value(value: EnokiValue, value_type: &str) -> PyResult<EnokiValue> {
+    value
+        .coerce(value_type)
+        .map_err(|err| PyErr::new::<pyo3::exceptions::PyTypeError, _>(err.to_string()))
+}
+
+/// A value with its type spelled out, made with one of the constructors like `Double(1)`
+#[derive(Debug, Clone)]
+#[pyclass(name = "TypedValue")]
+pub struct PyTypedValue {
+    value: EnokiValue,
+}
+
+#[pymethods]
+impl PyTypedValue {
+    #[getter]
+    fn value(&self) -> EnokiValue {
+        self.value.clone()
+    }
+
+    #[getter]
+    fn r#type(&self) -> String {
+        self.value.get_type()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let value = self.value.clone().into_py(py);
+        Ok(format!(
+            "{}({})",
+            self.value.get_type(),
+            value.as_ref(py).repr()?
+        ))
+    }
+
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp, py: Python) -> PyObject {
+        let other = match other.extract::<PyRef<PyTypedValue>>() {
+            Ok(other) => other,
+            Err(_) => return py.NotImplemented(),
+        };
+        match op {
+            CompareOp::Eq => (other.value == self.value).into_py(py),
+            CompareOp::Ne => (other.value != self.value).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+}
+
+/// Adds a constructor named after every value type, and `Raw` for byte arrays like NT calls them.
+/// They take anything that can be converted to the type, `Double(1)` works but `Int(1.5)` doesn't.
+pub fn add_typed_constructors(py: Python, m: &PyModule) -> PyResult<()> {
+    let constructors = EnokiValue::TYPE_NAMES
+        .iter()
+        .map(|name| (*name, *name))
+        .chain([("Raw", "ByteArray")]);
+    for (name, value_type) in constructors {
+        let constructor = PyCFunction::new_closure(
+            py,
+            Some(name),
+            None,
+            move |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<PyTypedValue> {
+                let (value,) = args.extract::<(EnokiValue,)>()?;
+                Ok(PyTypedValue {
+                    value: coerce_value(value, value_type)?,
+                })
+            },
+        )?;
+        m.add(name, constructor)?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 #[pyclass(name = "TimestampedEnokiValue")]
 pub struct PyTimestampedEnokiValue {
@@ -88,25 +175,38 @@ pub struct PyEnokiField {
     timestamp: u64,
     #[pyo3(get)]
     key: String,
+    #[pyo3(get)]
+    unit: Option<String>,
 }
 
 #[pymethods]
 impl PyEnokiField {
     #[new]
-    fn new(value: EnokiValue, timestamp: u64, key: String) -> Self {
+    #[pyo3(signature = (value, timestamp, key, unit = None))]
+    fn new(value: EnokiValue, timestamp: u64, key: String, unit: Option<String>) -> Self {
         PyEnokiField {
             value,
             timestamp,
             key,
+            unit,
         }
     }
+
+    /// The field with its value converted to another unit, e.g. `field.convert_to("in")`
+    fn convert_to(&self, unit: String) -> PyResult<PyEnokiField> {
+        EnokiField::from(self.clone())
+            .convert_to(&Unit::from(unit.as_str()))
+            .map(Into::into)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
 }
 
 #[derive(Debug, Clone)]
 #[pyclass(name = "EnokiObject")]
 pub struct PyEnokiObject {
     fields: Vec<PyEnokiField>,
-    history: Vec<Option<Vec<PyTimestampedEnokiValue>>>,
+    /// Kept columnar so large histories stay compact and convert to arrays cheaply
+    history: Vec<Option<EnokiHistory>>,
     paths: HashMap<String, usize>,
     #[pyo3(get)]
     timestamp: u64,
@@ -130,7 +230,7 @@ impl PyEnokiObject {
     fn field_history(&self, key: String) -> PyResult<Vec<PyTimestampedEnokiValue>> {
         if let Some(idx) = self.paths.get(&key) {
             if let Some(history) = &self.history[*idx] {
-                return Ok(history.clone());
+                return Ok(history.iter().map(Into::into).collect());
             }
         }
         Err(PyErr::new::<pyo3::exceptions::PyKeyError, _>(
@@ -138,9 +238,127 @@ impl PyEnokiObject {
         ))
     }
 
+    /// The history of a field as `(timestamps, values)` ndarrays, needs numpy
+    fn history_arrays<'py>(
+        &self,
+        py: Python<'py>,
+        key: String,
+    ) -> PyResult<(&'py PyAny, &'py PyAny)> {
+        let history = self
+            .paths
+            .get(&key)
+            .and_then(|idx| self.history[*idx].as_ref());
+        match history {
+            Some(history) => numpy::history_arrays(py, history),
+            None => Err(PyErr::new::<pyo3::exceptions::PyKeyError, _>(
+                "Could not find field history",
+            )),
+        }
+    }
+
+    /// A DataFrame indexed by timestamp with a column per field, needs pandas.
+    /// Fields without a history are a single row at their timestamp.
+    fn to_pandas<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        let pandas = py.import("pandas")?;
+        let columns = pyo3::types::PyDict::new(py);
+        for (field, history) in self.fields.iter().zip(&self.history) {
+            let series = match history {
+                Some(history) => numpy::history_series(py, pandas, history)?,
+                None => {
+                    let sample = TimestampedEnokiValue::new(field.timestamp, field.value.clone());
+                    numpy::history_series(py, pandas, &EnokiHistory::from(vec![sample]))?
+                }
+            };
+            columns.set_item(&field.key, series)?;
+        }
+        let frame = pandas.call_method1("DataFrame", (columns,))?;
+        frame.getattr("index")?.setattr("name", "timestamp")?;
+        Ok(frame)
+    }
+
     fn field_keys(&self) -> PyResult<Vec<String>> {
         Ok(self.paths.keys().cloned().collect())
     }
+
+    /// Fields whose key matches a glob like `/Drive/*/temp` or `/Vision/**`
+    fn select(&self, pattern: String) -> PyEnokiObject {
+        EnokiObject::from(self.clone()).select(&pattern).into()
+    }
+
+    fn select_regex(&self, regex: String) -> PyResult<PyEnokiObject> {
+        self.query(None, Some(regex), None, None, None, None)
+    }
+
+    fn numeric_fields(&self) -> PyEnokiObject {
+        EnokiObject::from(self.clone()).numeric_fields().into()
+    }
+
+    fn array_fields(&self) -> PyEnokiObject {
+        EnokiObject::from(self.clone()).array_fields().into()
+    }
+
+    fn changed_since(&self, after: u64) -> PyEnokiObject {
+        EnokiObject::from(self.clone()).changed_since(after).into()
+    }
+
+    /// Converts every field with a unit to what the system prefers, "Si", "Metric" or "Imperial"
+    fn convert_units(&self, system: String) -> PyResult<PyEnokiObject> {
+        let system = system
+            .parse::<UnitSystem>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e))?;
+        Ok(EnokiObject::from(self.clone()).convert_units(system).into())
+    }
+
+    fn changed_between(&self, after: Option<u64>, before: Option<u64>) -> PyEnokiObject {
+        EnokiObject::from(self.clone())
+            .changed_between(after, before)
+            .into()
+    }
+
+    /// Every given filter has to match, `kinds` are names like "Numeric" or "Array"
+    #[pyo3(signature = (pattern = None, regex = None, kinds = None, after = None, before = None, unit_system = None))]
+    fn query(
+        &self,
+        pattern: Option<String>,
+        regex: Option<String>,
+        kinds: Option<Vec<String>>,
+        after: Option<u64>,
+        before: Option<u64>,
+        unit_system: Option<String>,
+    ) -> PyResult<PyEnokiObject> {
+        let unit_system = unit_system
+            .map(|system| system.parse::<UnitSystem>())
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e))?;
+        let kinds = kinds
+            .unwrap_or_default()
+            .iter()
+            .map(|kind| {
+                serde_json::from_value::<EnokiValueKind>(serde_json::Value::String(kind.clone()))
+                    .map_err(|_| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Unknown value kind {}",
+                            kind
+                        ))
+                    })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let query = EnokiQuery {
+            pattern,
+            regex,
+            kinds,
+            after,
+            before,
+            unit_system,
+        };
+        match query.apply(&self.clone().into()) {
+            Ok(obj) => Ok(obj.into()),
+            Err(e) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid query: {}",
+                e
+            ))),
+        }
+    }
 }
 
 impl IntoPy<PyTimestampedEnokiValue> for TimestampedEnokiValue {
@@ -183,11 +401,7 @@ impl From<TimestampedEnokiValue> for PyTimestampedEnokiValue {
 
 impl IntoPy<PyEnokiField> for EnokiField {
     fn into_py(self, _py: Python<'_>) -> PyEnokiField {
-        PyEnokiField {
-            key: self.get_key().into(),
-            value: self.get_value_owned().value,
-            timestamp: self.get_value().timestamp,
-        }
+        self.into()
     }
 }
 
@@ -196,13 +410,18 @@ impl FromPyObject<'_> for EnokiField {
         let key = obj.getattr("key")?;
         let value = obj.getattr("value")?;
         let timestamp = obj.getattr("timestamp")?;
+        let unit = match obj.getattr("unit") {
+            Ok(unit) => unit.extract::<Option<String>>()?,
+            Err(_) => None,
+        };
         Ok(EnokiField::new(
             key.extract::<String>()?.into(),
             TimestampedEnokiValue {
                 value: value.extract()?,
                 timestamp: timestamp.extract()?,
             },
-        ))
+        )
+        .with_unit(unit.map(|unit| Unit::from(unit.as_str()))))
     }
 }
 
@@ -212,6 +431,7 @@ impl From<PyEnokiField> for EnokiField {
             value: py.value,
             timestamp: py.timestamp,
         })
+        .with_unit(py.unit.map(|unit| Unit::from(unit.as_str())))
     }
 }
 
@@ -221,6 +441,7 @@ impl From<EnokiField> for PyEnokiField {
             key: field.get_key().into(),
             value: field.get_value_owned().value,
             timestamp: field.get_value().timestamp,
+            unit: field.get_unit().map(|unit| unit.to_string()),
         }
     }
 }
@@ -230,7 +451,7 @@ impl IntoPy<PyEnokiObject> for EnokiObject {
         let fields = self
             .get_fields()
             .iter()
-            .map(|f| f.to_owned().into_py(py))
+            .map(|f| EnokiField::clone(f).into_py(py))
             .collect::<Vec<_>>();
         let paths = self
             .get_fields()
@@ -241,14 +462,7 @@ impl IntoPy<PyEnokiObject> for EnokiObject {
         let history = self
             .get_fields()
             .iter()
-            .map(|f| {
-                if let Some(field_and_history) = self.get_field_with_history(f.get_key()) {
-                    let history = field_and_history.1;
-                    Some(history.iter().map(|v| v.to_owned().into_py(py)).collect())
-                } else {
-                    None
-                }
-            })
+            .map(|f| self.get_history(f.get_key()).cloned())
             .collect::<Vec<_>>();
         PyEnokiObject {
             fields,
@@ -276,8 +490,12 @@ impl FromPyObject<'_> for EnokiObject {
 impl From<PyEnokiObject> for EnokiObject {
     fn from(py: PyEnokiObject) -> Self {
         let mut enoki_object = EnokiObject::new(py.timestamp);
+        let mut history = py.history.into_iter();
         for field in py.fields {
-            enoki_object.add_field(field.into());
+            match history.next().flatten() {
+                Some(history) => enoki_object.add_field_with_history(field.into(), history),
+                None => enoki_object.add_field(field.into()),
+            }
         }
         enoki_object
     }
@@ -288,7 +506,7 @@ impl From<EnokiObject> for PyEnokiObject {
         let fields = enoki_object
             .get_fields()
             .iter()
-            .map(|f| f.to_owned().into())
+            .map(|f| EnokiField::clone(f).into())
             .collect::<Vec<_>>();
         let paths = enoki_object
             .get_fields()
@@ -299,14 +517,7 @@ impl From<EnokiObject> for PyEnokiObject {
         let history = enoki_object
             .get_fields()
             .iter()
-            .map(|f| {
-                if let Some(field_and_history) = enoki_object.get_field_with_history(f.get_key()) {
-                    let history = field_and_history.1;
-                    Some(history.iter().map(|v| v.to_owned().into()).collect())
-                } else {
-                    None
-                }
-            })
+            .map(|f| enoki_object.get_history(f.get_key()).cloned())
             .collect::<Vec<_>>();
         PyEnokiObject {
             fields,
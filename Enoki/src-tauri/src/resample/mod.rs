@@ -0,0 +1,212 @@
+pub mod python;
+pub mod tauri_cmds;
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::enoki_types::{
+    EnokiHistory, EnokiKey, EnokiObject, EnokiTimeStamp, EnokiValue, TimestampedEnokiValue,
+};
+
+/// Grids are capped so a tiny period over a long log can't exhaust memory
+pub const MAX_GRID_POINTS: u64 = 1_000_000;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ResampleError {
+    #[error("Resampling with a period of {period} would take {points} points, at most {max} are allowed")]
+    GridTooLarge { period: u64, points: u64, max: u64 },
+}
+
+/// How a value between two samples is picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// The latest sample at or before the grid point
+    ZeroOrderHold,
+    /// Linear between the surrounding samples for single numerics, zero order hold otherwise
+    Linear,
+    /// The closest sample on either side
+    Nearest,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::ZeroOrderHold
+    }
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['_', '-', ' '], "").as_str() {
+            "zeroorderhold" | "zoh" | "hold" => Ok(Interpolation::ZeroOrderHold),
+            "linear" | "lerp" => Ok(Interpolation::Linear),
+            "nearest" => Ok(Interpolation::Nearest),
+            _ => Err(format!("Unknown interpolation {}", s)),
+        }
+    }
+}
+
+/// Evenly spaced timestamps from `start` to `end` inclusive, at most [`MAX_GRID_POINTS`] of them
+pub fn time_grid(
+    start: EnokiTimeStamp,
+    end: EnokiTimeStamp,
+    period: EnokiTimeStamp,
+) -> Result<Vec<EnokiTimeStamp>, ResampleError> {
+    if period == 0 || end < start {
+        return Ok(Vec::new());
+    }
+    let points = ((end - start) / period).saturating_add(1);
+    if points > MAX_GRID_POINTS {
+        return Err(ResampleError::GridTooLarge {
+            period,
+            points,
+            max: MAX_GRID_POINTS,
+        });
+    }
+    Ok((0..points).map(|step| start + step * period).collect())
+}
+
+/// The span covered by any of the histories
+pub fn common_span<'a>(
+    histories: impl IntoIterator<Item = &'a EnokiHistory>,
+) -> Option<(EnokiTimeStamp, EnokiTimeStamp)> {
+    histories
+        .into_iter()
+        .filter_map(|history| {
+            Some((
+                *history.timestamps().first()?,
+                *history.timestamps().last()?,
+            ))
+        })
+        .reduce(|(start, end), (first, last)| (start.min(first), end.max(last)))
+}
+
+fn lerp(before: &EnokiValue, after: &EnokiValue, fraction: f64) -> Option<EnokiValue> {
+    let between = |a: f64, b: f64| a + (b - a) * fraction;
+    match (before, after) {
+        (EnokiValue::Double(a), EnokiValue::Double(b)) => Some(EnokiValue::Double(between(*a, *b))),
+        (EnokiValue::Float(a), EnokiValue::Float(b)) => Some(EnokiValue::Float(between(*a, *b))),
+        (EnokiValue::Int(a), EnokiValue::Int(b)) => {
+            Some(EnokiValue::Double(between(*a as f64, *b as f64)))
+        }
+        _ => None,
+    }
+}
+
+/// The value of a history at `timestamp`, none before the first sample unless `Nearest` is used
+pub fn sample_at(
+    history: &EnokiHistory,
+    timestamp: EnokiTimeStamp,
+    interpolation: Interpolation,
+) -> Option<EnokiValue> {
+    let timestamps = history.timestamps();
+    // index of the first sample after the timestamp
+    let after = timestamps.partition_point(|t| *t <= timestamp);
+    let hold = after.checked_sub(1).and_then(|index| history.get(index));
+
+    match interpolation {
+        Interpolation::ZeroOrderHold => hold.map(|sample| sample.value),
+        Interpolation::Linear => {
+            let before = hold?;
+            match history.get(after) {
+                Some(next) if next.timestamp > before.timestamp => {
+                    let fraction = (timestamp - before.timestamp) as f64
+                        / (next.timestamp - before.timestamp) as f64;
+                    Some(lerp(&before.value, &next.value, fraction).unwrap_or(before.value))
+                }
+                _ => Some(before.value),
+            }
+        }
+        Interpolation::Nearest => match (hold, history.get(after)) {
+            (Some(before), Some(next)) => {
+                if timestamp - before.timestamp <= next.timestamp - timestamp {
+                    Some(before.value)
+                } else {
+                    Some(next.value)
+                }
+            }
+            (Some(only), None) | (None, Some(only)) => Some(only.value),
+            (None, None) => None,
+        },
+    }
+}
+
+/// Samples a history at every grid point, points without a value are skipped
+pub fn resample(
+    history: &EnokiHistory,
+    grid: &[EnokiTimeStamp],
+    interpolation: Interpolation,
+) -> EnokiHistory {
+    grid.iter()
+        .filter_map(|timestamp| {
+            sample_at(history, *timestamp, interpolation)
+                .map(|value| TimestampedEnokiValue::new(*timestamp, value))
+        })
+        .collect()
+}
+
+/// Resamples the histories of `keys`, or every field with a history, onto one grid.
+/// Without a start or end the grid covers the span of the selected histories.
+pub fn resample_object(
+    obj: &EnokiObject,
+    keys: Option<&[EnokiKey]>,
+    period: EnokiTimeStamp,
+    start: Option<EnokiTimeStamp>,
+    end: Option<EnokiTimeStamp>,
+    interpolation: Interpolation,
+) -> Result<EnokiObject, ResampleError> {
+    let selected = obj
+        .get_fields()
+        .iter()
+        .map(|field| field.get_key())
+        .filter(|key| keys.map_or(true, |keys| keys.contains(key)))
+        .filter_map(|key| obj.get_history(key).map(|history| (key, history)))
+        .collect::<Vec<_>>();
+
+    let mut resampled = EnokiObject::new(obj.get_timestamp());
+    let span = common_span(selected.iter().map(|(_, history)| *history));
+    let (start, end) = match (start, end, span) {
+        (Some(start), Some(end), _) => (start, end),
+        (start, end, Some((first, last))) => (start.unwrap_or(first), end.unwrap_or(last)),
+        _ => return Ok(resampled),
+    };
+    let grid = time_grid(start, end, period)?;
+    for (key, history) in selected {
+        resampled.set_history(key, resample(history, &grid, interpolation));
+    }
+    Ok(resampled)
+}
+
+/// Two samples from different series that were matched by timestamp
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlignedPair {
+    pub left: TimestampedEnokiValue,
+    pub right: TimestampedEnokiValue,
+}
+
+/// Pairs every left sample with the closest right sample at most `tolerance` away,
+/// left samples without a match are dropped
+pub fn align(
+    left: &EnokiHistory,
+    right: &EnokiHistory,
+    tolerance: EnokiTimeStamp,
+) -> Vec<AlignedPair> {
+    left.iter()
+        .filter_map(|sample| {
+            let matched = right
+                .range(
+                    Some(sample.timestamp.saturating_sub(tolerance)),
+                    Some(sample.timestamp.saturating_add(tolerance)),
+                )
+                .iter()
+                .min_by_key(|candidate| candidate.timestamp.abs_diff(sample.timestamp))?;
+            Some(AlignedPair {
+                left: sample,
+                right: matched,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,84 @@
+use pyo3::prelude::*;
+
+use crate::{
+    enoki_types::{EnokiHistory, EnokiKey, EnokiTimeStamp, TimestampedEnokiValue},
+    python_helpers::py_enoki_types::{PyEnokiObject, PyTimestampedEnokiValue},
+};
+
+use super::{Interpolation, ResampleError};
+
+fn parse_interpolation(interpolation: Option<String>) -> PyResult<Interpolation> {
+    match interpolation {
+        Some(interpolation) => interpolation
+            .parse()
+            .map_err(|err: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(err)),
+        None => Ok(Interpolation::default()),
+    }
+}
+
+fn grid_err(err: ResampleError) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string())
+}
+
+/// Resamples a single history onto an evenly spaced grid,
+/// without a start or end the grid covers the history
+#[pyfunction]
+#[pyo3(signature = (history, period, start = None, end = None, interpolation = None))]
+pub fn resample(
+    history: Vec<TimestampedEnokiValue>,
+    period: EnokiTimeStamp,
+    start: Option<EnokiTimeStamp>,
+    end: Option<EnokiTimeStamp>,
+    interpolation: Option<String>,
+) -> PyResult<Vec<PyTimestampedEnokiValue>> {
+    let interpolation = parse_interpolation(interpolation)?;
+    let history = EnokiHistory::from(history);
+    let (first, last) = match super::common_span([&history]) {
+        Some(span) => span,
+        None => return Ok(Vec::new()),
+    };
+    let grid =
+        super::time_grid(start.unwrap_or(first), end.unwrap_or(last), period).map_err(grid_err)?;
+    Ok(super::resample(&history, &grid, interpolation)
+        .iter()
+        .map(Into::into)
+        .collect())
+}
+
+/// Resamples the histories of an object onto one grid, see `resample`
+#[pyfunction]
+#[pyo3(signature = (obj, period, keys = None, start = None, end = None, interpolation = None))]
+pub fn resample_object(
+    obj: PyEnokiObject,
+    period: EnokiTimeStamp,
+    keys: Option<Vec<String>>,
+    start: Option<EnokiTimeStamp>,
+    end: Option<EnokiTimeStamp>,
+    interpolation: Option<String>,
+) -> PyResult<PyEnokiObject> {
+    let interpolation = parse_interpolation(interpolation)?;
+    let keys = keys.map(|keys| keys.into_iter().map(EnokiKey::from).collect::<Vec<_>>());
+    super::resample_object(
+        &obj.into(),
+        keys.as_deref(),
+        period,
+        start,
+        end,
+        interpolation,
+    )
+    .map(Into::into)
+    .map_err(grid_err)
+}
+
+/// Pairs every left sample with the closest right sample at most `tolerance` microseconds away
+#[pyfunction]
+pub fn align(
+    left: Vec<TimestampedEnokiValue>,
+    right: Vec<TimestampedEnokiValue>,
+    tolerance: EnokiTimeStamp,
+) -> Vec<(PyTimestampedEnokiValue, PyTimestampedEnokiValue)> {
+    super::align(&left.into(), &right.into(), tolerance)
+        .into_iter()
+        .map(|pair| (pair.left.into(), pair.right.into()))
+        .collect()
+}
@@ -0,0 +1,70 @@
+use serde::Deserialize;
+
+use crate::{
+    datalog::handler::{daemon_object, datalog_object},
+    enoki_types::{EnokiKey, EnokiObject, EnokiTimeStamp},
+    error::{log_result, EnokiError},
+    frontend_helpers::encoding::{EncodedObject, IpcEncoding},
+};
+
+use super::{align, resample_object, AlignedPair, Interpolation};
+
+/// Where the histories come from
+#[derive(Debug, Clone, Deserialize)]
+pub enum HistorySource {
+    /// The datalog currently being recorded
+    Daemon,
+    /// A wpilog on disk
+    Datalog(String),
+}
+
+impl HistorySource {
+    pub(crate) fn load(&self) -> Result<EnokiObject, EnokiError> {
+        match self {
+            HistorySource::Daemon => Ok(daemon_object()),
+            HistorySource::Datalog(path) => datalog_object(path.into()),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn resample_histories(
+    source: HistorySource,
+    keys: Option<Vec<String>>,
+    period: EnokiTimeStamp,
+    start: Option<EnokiTimeStamp>,
+    end: Option<EnokiTimeStamp>,
+    interpolation: Option<Interpolation>,
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, EnokiError> {
+    let obj = log_result(source.load())?;
+    let keys = keys.map(|keys| keys.into_iter().map(EnokiKey::from).collect::<Vec<_>>());
+    let resampled = log_result(resample_object(
+        &obj,
+        keys.as_deref(),
+        period,
+        start,
+        end,
+        interpolation.unwrap_or_default(),
+    ))?;
+    log_result(EncodedObject::encode(resampled, encoding))
+}
+
+#[tauri::command]
+pub fn align_histories(
+    source: HistorySource,
+    left: String,
+    right: String,
+    tolerance: EnokiTimeStamp,
+) -> Result<Vec<AlignedPair>, EnokiError> {
+    let obj = log_result(source.load())?;
+    let history = |key: String| {
+        obj.get_history(&EnokiKey::from(key.as_str()))
+            .ok_or(EnokiError::DLEntryNotFound(key))
+    };
+    Ok(align(
+        log_result(history(left))?,
+        log_result(history(right))?,
+        tolerance,
+    ))
+}
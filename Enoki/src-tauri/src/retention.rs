@@ -0,0 +1,333 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::api::path::document_dir;
+
+use crate::error::{log_result, log_result_consume, EnokiError};
+
+static POLICY_FILE: &str = "Enoki/retention.json";
+pub const DATALOG_DIRECTORY: &str = "Enoki/Datalogs";
+pub const DEBUGLOG_DIRECTORY: &str = "Enoki/DebugLogs";
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// The file each log directory is currently being written to, keyed by the relative directory
+static ACTIVE_FILES: Lazy<Mutex<HashMap<String, PathBuf>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Held while a policy is enforced so passes started close together don't touch the same files
+static ENFORCING: Mutex<()> = parking_lot::const_mutex(());
+
+/// Limits applied to the files in a log directory, any limit left as none is not enforced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Total size of the directory in bytes, oldest files are deleted first to get under it
+    pub max_total_bytes: Option<u64>,
+    /// Files created longer ago than this are removed
+    pub max_age_days: Option<u64>,
+    /// The newest N files are never removed for age
+    pub keep_last: Option<usize>,
+    /// Compress files that exceed the age/count limits instead of deleting them,
+    /// compressed files are then only deleted to satisfy `max_total_bytes`
+    pub compress: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: Some(2 * 1024 * 1024 * 1024),
+            max_age_days: Some(30),
+            keep_last: Some(20),
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub datalogs: RetentionPolicy,
+    pub debug_logs: RetentionPolicy,
+    /// File names relative to the documents directory that are never touched
+    pub favorites: HashSet<String>,
+}
+
+impl RetentionConfig {
+    fn path() -> Option<PathBuf> {
+        document_dir().map(|docs| docs.join(POLICY_FILE))
+    }
+
+    /// Loads the saved config, falling back to the defaults if there isn't one
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        match fs::File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|err| {
+                tracing::warn!("Invalid retention config {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), EnokiError> {
+        let path = Self::path().ok_or(EnokiError::DatalogIo(
+            "No documents directory".to_string(),
+        ))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .map_err(|err| EnokiError::DatalogIo(err.to_string()))
+    }
+
+    fn policy_for(&self, relative_dir: &str) -> &RetentionPolicy {
+        if relative_dir == DEBUGLOG_DIRECTORY {
+            &self.debug_logs
+        } else {
+            &self.datalogs
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LogFile {
+    path: PathBuf,
+    favorite_key: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl LogFile {
+    fn is_compressed(&self) -> bool {
+        self.path.extension().map_or(false, |ext| ext == "gz")
+    }
+}
+
+/// A new log file in a directory relative to the documents folder, named after the current time
+/// with a `-N` suffix if a log was already started this second
+pub fn new_log_path(relative_dir: &str, extension: &str) -> Option<PathBuf> {
+    let dir = document_dir()?.join(relative_dir);
+    let stamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let mut path = dir.join(format!("{}.{}", stamp, extension));
+    let mut suffix = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.{}", stamp, suffix, extension));
+        suffix += 1;
+    }
+    Some(path)
+}
+
+/// Records `active` as the file now being written in a directory relative to the documents folder
+/// and enforces the saved retention policy on it, call whenever a log starts or rotates.
+/// The policy is enforced on its own thread, compressing old logs can take a while.
+pub fn log_rotated(relative_dir: &str, active: &Path) {
+    ACTIVE_FILES
+        .lock()
+        .insert(relative_dir.to_string(), active.to_path_buf());
+    let relative_dir = relative_dir.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("Retention".to_string())
+        .spawn(move || enforce_retention(&relative_dir));
+    log_result_consume(spawned);
+}
+
+/// Enforces the saved retention policy on a directory relative to the documents folder,
+/// the file it's currently being written to is never touched
+pub fn enforce_retention(relative_dir: &str) {
+    let config = RetentionConfig::load();
+    let docs = match document_dir() {
+        Some(docs) => docs,
+        None => return,
+    };
+    let _enforcing = ENFORCING.lock();
+    let active = ACTIVE_FILES.lock().get(relative_dir).cloned();
+    log_result_consume(enforce_policy(
+        &docs,
+        relative_dir,
+        config.policy_for(relative_dir),
+        &config.favorites,
+        active.as_deref(),
+    ));
+}
+
+pub(crate) fn enforce_policy(
+    docs: &Path,
+    relative_dir: &str,
+    policy: &RetentionPolicy,
+    favorites: &HashSet<String>,
+    active: Option<&Path>,
+) -> Result<(), EnokiError> {
+    let dir = docs.join(relative_dir);
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        if active.map_or(false, |active| active == path) {
+            continue;
+        }
+        let favorite_key = format!("{}/{}", relative_dir, entry.file_name().to_string_lossy());
+        let modified = created_time(&path).unwrap_or(metadata.modified()?);
+        files.push(LogFile {
+            path,
+            favorite_key,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    // newest first
+    files.sort_by(|a, b| b.modified.cmp(&a.modified));
+
+    let now = SystemTime::now();
+    let max_age = policy
+        .max_age_days
+        .map(|days| Duration::from_secs(days * SECONDS_PER_DAY));
+    let keep_last = policy.keep_last.unwrap_or(0);
+
+    let mut kept = Vec::new();
+    for (index, file) in files.into_iter().enumerate() {
+        if favorites.contains(&file.favorite_key) {
+            kept.push(file);
+            continue;
+        }
+        let too_old = max_age.map_or(false, |max_age| {
+            now.duration_since(file.modified).unwrap_or_default() > max_age
+        });
+        let too_many = policy.keep_last.map_or(false, |keep| index >= keep);
+        if index < keep_last || !(too_old || too_many) {
+            kept.push(file);
+            continue;
+        }
+        if policy.compress {
+            if !file.is_compressed() {
+                tracing::info!("Compressing old log {}", file.path.display());
+                // one that can't be compressed, e.g. because it's open elsewhere, is left as it is
+                match compress_file(&file) {
+                    Ok(compressed) => kept.push(compressed),
+                    Err(err) => {
+                        tracing::error!("Failed to compress {}: {}", file.path.display(), err);
+                        kept.push(file);
+                    }
+                }
+            } else {
+                kept.push(file);
+            }
+        } else {
+            tracing::info!("Removing old log {}", file.path.display());
+            log_result_consume(remove_log(&file));
+        }
+    }
+
+    if let Some(max_total) = policy.max_total_bytes {
+        let mut total: u64 = kept.iter().map(|f| f.size).sum();
+        // kept is still newest first so pop from the back
+        while total > max_total {
+            let index = match kept
+                .iter()
+                .rposition(|f| !favorites.contains(&f.favorite_key))
+            {
+                Some(index) => index,
+                None => break,
+            };
+            let file = kept.remove(index);
+            tracing::info!("Removing log {} to stay under size limit", file.path.display());
+            if log_result(remove_log(&file)).is_ok() {
+                total -= file.size;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Logs are named after the time they were created, prefer that over the modified time
+/// so compressing a file doesn't reset its age
+fn created_time(path: &Path) -> Option<SystemTime> {
+    let name = path.file_name()?.to_str()?;
+    let stamp = name.get(0..19)?;
+    let naive = chrono::NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d_%H-%M-%S").ok()?;
+    let local = naive.and_local_timezone(chrono::Local).earliest()?;
+    Some(SystemTime::from(local))
+}
+
+fn remove_log(file: &LogFile) -> Result<(), EnokiError> {
+    fs::remove_file(&file.path).map_err(|err| {
+        EnokiError::DatalogIo(format!("Failed to remove {}: {}", file.path.display(), err))
+    })
+}
+
+fn compress_file(file: &LogFile) -> Result<LogFile, EnokiError> {
+    let mut compressed_name = file.path.file_name().unwrap_or_default().to_os_string();
+    compressed_name.push(".gz");
+    let compressed_path = file.path.with_file_name(compressed_name);
+
+    let compressed =
+        write_compressed(&file.path, &compressed_path).and_then(|()| fs::remove_file(&file.path));
+    if let Err(err) = compressed {
+        // a partial copy next to the log would be taken for it once the log is gone
+        let _ = fs::remove_file(&compressed_path);
+        return Err(err.into());
+    }
+
+    Ok(LogFile {
+        size: fs::metadata(&compressed_path)?.len(),
+        favorite_key: format!("{}.gz", file.favorite_key),
+        path: compressed_path,
+        modified: file.modified,
+    })
+}
+
+fn write_compressed(path: &Path, compressed_path: &Path) -> std::io::Result<()> {
+    let mut input = BufReader::new(fs::File::open(path)?);
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(fs::File::create(compressed_path)?),
+        Compression::default(),
+    );
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_retention_config() -> RetentionConfig {
+    RetentionConfig::load()
+}
+
+#[tauri::command]
+pub fn set_retention_config(config: RetentionConfig) -> Result<(), EnokiError> {
+    config.save()?;
+    enforce_retention(DATALOG_DIRECTORY);
+    enforce_retention(DEBUGLOG_DIRECTORY);
+    Ok(())
+}
+
+/// Marks a log, given relative to the documents folder, as a favorite so retention never removes it
+#[tauri::command]
+pub fn set_log_favorite(file: String, favorite: bool) -> Result<(), EnokiError> {
+    let mut config = RetentionConfig::load();
+    if favorite {
+        config.favorites.insert(file);
+    } else {
+        config.favorites.remove(&file);
+    }
+    config.save()
+}
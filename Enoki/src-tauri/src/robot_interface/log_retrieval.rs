@@ -0,0 +1,364 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufReader, BufWriter, Read},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use ssh2::{FileStat, Session, Sftp};
+use tauri::api::path::document_dir;
+
+use crate::{error::EnokiError, retention::DATALOG_DIRECTORY};
+
+use super::team_number::ip_from_team_number;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+static SYNC_STATE_FILE: &str = ".enoki_sync.json";
+
+/// A robot side device logs can be pulled from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteTarget {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// Directories searched for logs, missing ones are skipped
+    pub log_directories: Vec<String>,
+    /// File extensions considered logs, without the dot
+    pub extensions: Vec<String>,
+}
+
+fn default_port() -> u16 {
+    22
+}
+
+impl RemoteTarget {
+    /// The roboRIO of a team, WPILib's DataLogManager writes to a usb stick if present
+    /// otherwise the lvuser home directory
+    pub fn roborio(team: u16) -> Result<Self, EnokiError> {
+        let ip = ip_from_team_number(team).ok_or(EnokiError::InvalidTeamNumber(team))?;
+        Ok(Self {
+            host: ip.to_string(),
+            port: default_port(),
+            username: "lvuser".to_string(),
+            password: String::new(),
+            log_directories: vec!["/u/logs".to_string(), "/home/lvuser/logs".to_string()],
+            extensions: vec![
+                "wpilog".to_string(),
+                "dslog".to_string(),
+                "dsevents".to_string(),
+            ],
+        })
+    }
+
+    /// A coprocessor reachable at `host`, logs are expected in the user's home `logs` directory
+    pub fn coprocessor(host: String, username: String, password: String) -> Self {
+        Self {
+            log_directories: vec![format!("/home/{}/logs", username)],
+            extensions: vec!["wpilog".to_string()],
+            host,
+            port: default_port(),
+            username,
+            password,
+        }
+    }
+
+    fn connect(&self) -> Result<Sftp, EnokiError> {
+        let addr: SocketAddr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or(EnokiError::NoValidAddress)?;
+        let tcp = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+        session.handshake()?;
+        session.userauth_password(&self.username, &self.password)?;
+        Ok(session.sftp()?)
+    }
+
+    fn is_log(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| self.extensions.iter().any(|e| e == ext))
+    }
+}
+
+/// The file operations pulling needs from a target, over SFTP or in tests a local directory
+pub(crate) trait LogSource {
+    fn readdir(&self, dir: &Path) -> io::Result<Vec<(PathBuf, FileStat)>>;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>>;
+    fn unlink(&self, path: &Path) -> io::Result<()>;
+}
+
+impl LogSource for Sftp {
+    fn readdir(&self, dir: &Path) -> io::Result<Vec<(PathBuf, FileStat)>> {
+        Ok(Sftp::readdir(self, dir)?)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(Sftp::open(self, path)?))
+    }
+
+    fn unlink(&self, path: &Path) -> io::Result<()> {
+        Ok(Sftp::unlink(self, path)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteLogFile {
+    /// Absolute path on the target
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    /// Seconds since the unix epoch
+    pub modified: u64,
+}
+
+/// Remembers what was already pulled from a robot so "new since last sync" survives restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncState {
+    /// Remote path to the size it had when it was downloaded
+    downloaded: HashMap<String, u64>,
+}
+
+impl SyncState {
+    fn load(dir: &Path) -> Self {
+        fs::File::open(dir.join(SYNC_STATE_FILE))
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), EnokiError> {
+        let file = fs::File::create(dir.join(SYNC_STATE_FILE))?;
+        serde_json::to_writer(BufWriter::new(file), self)
+            .map_err(|err| EnokiError::DatalogIo(err.to_string()))
+    }
+
+    fn is_synced(&self, file: &RemoteLogFile) -> bool {
+        self.downloaded.get(&file.path) == Some(&file.size)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PulledLog {
+    pub remote: RemoteLogFile,
+    pub local_path: PathBuf,
+    pub deleted_remote: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedPull {
+    pub remote: RemoteLogFile,
+    pub error: String,
+}
+
+/// What a pull downloaded, a file that failed doesn't stop the others
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PullResult {
+    pub pulled: Vec<PulledLog>,
+    pub failed: Vec<FailedPull>,
+}
+
+/// Local directory logs from a robot are downloaded into, `Enoki/Datalogs/<robot>`
+pub fn robot_log_directory(robot: &str) -> Result<PathBuf, EnokiError> {
+    let dir = document_dir()
+        .ok_or(EnokiError::DatalogIo("No documents directory".to_string()))?
+        .join(DATALOG_DIRECTORY)
+        .join(sanitize_file_name(robot));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+pub fn list_remote_logs(target: &RemoteTarget) -> Result<Vec<RemoteLogFile>, EnokiError> {
+    let sftp = target.connect()?;
+    list_with(&sftp, target)
+}
+
+/// Where a remote log is kept locally, its remote directories are kept so same named logs
+/// from different directories don't overwrite each other
+pub(crate) fn local_log_path(local_dir: &Path, remote_path: &str) -> PathBuf {
+    let mut local_path = local_dir.to_path_buf();
+    for component in Path::new(remote_path).components() {
+        if let Component::Normal(part) = component {
+            local_path.push(part);
+        }
+    }
+    local_path
+}
+
+fn list_with(
+    source: &impl LogSource,
+    target: &RemoteTarget,
+) -> Result<Vec<RemoteLogFile>, EnokiError> {
+    let mut files = Vec::new();
+    for dir in &target.log_directories {
+        let entries = match source.readdir(Path::new(dir)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::debug!("Skipping remote log directory {}: {}", dir, err);
+                continue;
+            }
+        };
+        for (path, stat) in entries {
+            if !stat.is_file() || !target.is_log(&path) {
+                continue;
+            }
+            files.push(RemoteLogFile {
+                name: path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                path: path.to_string_lossy().to_string(),
+                size: stat.size.unwrap_or(0),
+                modified: stat.mtime.unwrap_or(0),
+            });
+        }
+    }
+    files.sort_by(|a, b| b.modified.cmp(&a.modified));
+    Ok(files)
+}
+
+/// Downloads logs from the target into `Enoki/Datalogs/<robot>`, under their remote paths.
+///
+/// `selected` are remote paths to pull, if none every log not pulled since the last sync is pulled.
+/// With `delete_remote` the remote copy is removed once the local file is verified to match in size.
+pub fn pull_logs(
+    target: &RemoteTarget,
+    robot: &str,
+    selected: Option<Vec<String>>,
+    delete_remote: bool,
+) -> Result<PullResult, EnokiError> {
+    let sftp = target.connect()?;
+    let local_dir = robot_log_directory(robot)?;
+    pull_with(&sftp, target, &local_dir, selected, delete_remote)
+}
+
+pub(crate) fn pull_with(
+    source: &impl LogSource,
+    target: &RemoteTarget,
+    local_dir: &Path,
+    selected: Option<Vec<String>>,
+    delete_remote: bool,
+) -> Result<PullResult, EnokiError> {
+    let mut state = SyncState::load(local_dir);
+
+    let to_pull = list_with(source, target)?
+        .into_iter()
+        .filter(|file| match &selected {
+            Some(selected) => selected.contains(&file.path),
+            None => !state.is_synced(file),
+        })
+        .collect::<Vec<_>>();
+
+    let mut result = PullResult::default();
+    for remote in to_pull {
+        tracing::info!("Pulling {} from {}", remote.path, target.host);
+        let local_path = match pull_file(source, &remote, local_dir) {
+            Ok(local_path) => local_path,
+            Err(err) => {
+                tracing::warn!("Failed to pull {}: {}", remote.path, err);
+                result.failed.push(FailedPull {
+                    remote,
+                    error: err.to_string(),
+                });
+                continue;
+            }
+        };
+        state.downloaded.insert(remote.path.clone(), remote.size);
+        state.save(local_dir)?;
+
+        let deleted_remote = if delete_remote {
+            match source.unlink(Path::new(&remote.path)) {
+                Ok(()) => true,
+                Err(err) => {
+                    tracing::warn!("Failed to delete remote log {}: {}", remote.path, err);
+                    false
+                }
+            }
+        } else {
+            false
+        };
+
+        result.pulled.push(PulledLog {
+            remote,
+            local_path,
+            deleted_remote,
+        });
+    }
+    Ok(result)
+}
+
+fn pull_file(
+    source: &impl LogSource,
+    remote: &RemoteLogFile,
+    local_dir: &Path,
+) -> Result<PathBuf, EnokiError> {
+    let local_path = local_log_path(local_dir, &remote.path);
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut remote_file = source.open(Path::new(&remote.path))?;
+    // download next to the destination first so a dropped connection never leaves a truncated log
+    let mut partial_name = local_path.file_name().unwrap_or_default().to_os_string();
+    partial_name.push(".partial");
+    let partial_path = local_path.with_file_name(partial_name);
+    {
+        let mut local_file = BufWriter::new(fs::File::create(&partial_path)?);
+        io::copy(&mut remote_file, &mut local_file)?;
+    }
+
+    // e.g. the log the robot is still writing to
+    let size = fs::metadata(&partial_path)?.len();
+    if size != remote.size {
+        fs::remove_file(&partial_path)?;
+        return Err(EnokiError::DatalogIo(format!(
+            "{} was {} bytes when listed but {} when pulled",
+            remote.path, remote.size, size
+        )));
+    }
+    fs::rename(&partial_path, &local_path)?;
+    Ok(local_path)
+}
+
+#[tauri::command]
+pub fn roborio_log_target(team: u16) -> Result<RemoteTarget, EnokiError> {
+    RemoteTarget::roborio(team)
+}
+
+#[tauri::command]
+pub fn coprocessor_log_target(host: String, username: String, password: String) -> RemoteTarget {
+    RemoteTarget::coprocessor(host, username, password)
+}
+
+#[tauri::command]
+pub async fn list_robot_logs(target: RemoteTarget) -> Result<Vec<RemoteLogFile>, EnokiError> {
+    tokio::task::spawn_blocking(move || list_remote_logs(&target))
+        .await
+        .map_err(|err| EnokiError::DatalogIo(err.to_string()))?
+}
+
+#[tauri::command]
+pub async fn pull_robot_logs(
+    target: RemoteTarget,
+    robot: String,
+    selected: Option<Vec<String>>,
+    delete_remote: bool,
+) -> Result<PullResult, EnokiError> {
+    tokio::task::spawn_blocking(move || pull_logs(&target, &robot, selected, delete_remote))
+        .await
+        .map_err(|err| EnokiError::DatalogIo(err.to_string()))?
+}
@@ -1,3 +1,4 @@
 pub mod pinging;
 pub mod team_number;
-pub mod rio_sys_api_diagnostics;
\ No newline at end of file
+pub mod rio_sys_api_diagnostics;
+pub mod log_retrieval;
@@ -20,18 +20,14 @@ impl TeamNumbers {
     }
 }
 
-/// Converts the given team number into a String containing the IP of the roboRIO
-/// Assumes the roboRIO will exist at 10.TE.AM.2
-pub(crate) fn ip_from_team_number(team: u16) -> Ipv4Addr {
-    let s = team.to_string();
-
-    let v4str = match s.len() {
-        1 | 2 => format!("10.0.{}.2", team),
-        3 => format!("10.{}.{}.2", &s[0..1], &s[1..3]),
-        4 => format!("10.{}.{}.2", &s[0..2], &s[2..4]),
-        5 => format!("10.{}.{}.2", &s[0..3], &s[3..5]),
-        _ => unreachable!(), // Team numbers shouldn't be >5 characters
-    };
+/// The highest team number that still fits into the TE.AM octets of an address
+pub const MAX_TEAM_NUMBER: u16 = 255 * 100 + 99;
 
-    v4str.parse().unwrap()
-}
\ No newline at end of file
+/// Converts the given team number into the IP of its roboRIO, none if it's too high to have one
+/// Assumes the roboRIO will exist at 10.TE.AM.2
+pub(crate) fn ip_from_team_number(team: u16) -> Option<Ipv4Addr> {
+    if team > MAX_TEAM_NUMBER {
+        return None;
+    }
+    Some(Ipv4Addr::new(10, (team / 100) as u8, (team % 100) as u8, 2))
+}
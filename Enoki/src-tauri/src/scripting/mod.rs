@@ -0,0 +1,464 @@
+//! Runs user python scripts from `Documents/Enoki/Scripts` in an embedded interpreter.
+//!
+//! Scripts can `import enoki_core` and define any of these hooks, all called without arguments:
+//! - `on_start()` once after the file is loaded
+//! - `on_tick()` every [TICK_PERIOD] until the script is stopped
+//! - `on_stop()` once when the script stops, also after an exception so it can clean up
+//! - `main()` a coroutine started as a task after `on_start`, cancelled when the script stops
+//!
+//! A script without `on_tick` or `main` is done after `on_start`, one with only `main` when it returns.
+//!
+//! Each script has its own asyncio loop. Hooks can be `async def`, and tasks they create run
+//! between ticks, so they can await what `enoki_core` offers, e.g. a subscription's next update.
+//!
+//! Every script runs on its own worker thread. A hook that runs longer than the script's timeout,
+//! or keeps running after the script was asked to stop, gets a `KeyboardInterrupt` raised in it.
+//! Exceptions are logged with their traceback and can restart the script, see [RestartPolicy].
+//!
+//! Plugins are scripts packaged with a manifest and run the same way, see [plugins].
+//! Scripts can register commands and data providers for the frontend to call, see [registry].
+//! The frontend's console runs python interactively next to the scripts, see [repl].
+
+pub mod plugins;
+pub mod python;
+pub mod registry;
+pub mod repl;
+pub mod tauri_cmds;
+mod worker;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::{prelude::*, types::PyList};
+use serde::{Deserialize, Serialize};
+use tauri::api::path::document_dir;
+use thiserror::Error;
+
+use crate::{
+    enoki_types::{now, EnokiTimeStamp},
+    error::EnokiError,
+    python_helpers::{enoki_core::enoki_core, logging::format_py_err},
+};
+
+use self::plugins::{Grant, Permission};
+use self::worker::{spawn_worker, ScriptControl};
+
+pub const SCRIPTS_DIRECTORY: &str = "Enoki/Scripts";
+/// How often `on_tick` is called, the same rate as a robot's main loop
+pub const TICK_PERIOD: Duration = Duration::from_millis(20);
+/// How long [stop_all_scripts] waits for scripts to run their `on_stop`
+const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The typed wrappers around `enoki_core`, importable as `enoki`
+static ENOKI_PY: &str = include_str!("../../scripting_base/enoki.py");
+
+static PYTHON: Lazy<()> = Lazy::new(|| {
+    pyo3::append_to_inittab!(enoki_core);
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        if let Err(err) = setup_interpreter(py) {
+            tracing::error!("Failed to set up python: {}", format_py_err(py, &err));
+        }
+    });
+});
+
+static SCRIPTS: Lazy<Mutex<HashMap<String, ScriptHandle>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("No scripts directory")]
+    NoScriptsDirectory,
+    #[error("Script {0} not found")]
+    NotFound(String),
+    #[error("Script {0} is already running")]
+    AlreadyRunning(String),
+    #[error("Script {0} is not running")]
+    NotRunning(String),
+    #[error("No plugins directory")]
+    NoPluginsDirectory,
+    #[error("Plugin {0} not found")]
+    PluginNotFound(String),
+    #[error("Invalid plugin in {}: {}", .0.display(), .1)]
+    InvalidPlugin(PathBuf, String),
+    #[error(
+        "Plugin {plugin} needs API version {version}, Enoki provides {}",
+        plugins::ENOKI_API_VERSION
+    )]
+    UnsupportedApiVersion { plugin: String, version: u32 },
+    #[error("Plugin {plugin} didn't declare the {permission} permission")]
+    PermissionDenied {
+        plugin: String,
+        permission: Permission,
+    },
+    #[error(
+        "Threads that weren't started by Enoki or with threading can't use the {0} permission"
+    )]
+    UngrantedThread(Permission),
+    #[error("Plugin {0} didn't stop in time")]
+    PluginStuck(String),
+    #[error("Failed to watch plugins: {0}")]
+    PluginWatch(String),
+    #[error("Only scripts can register commands and data providers")]
+    NotAScript,
+    #[error("{name} is already registered by script {script}")]
+    AlreadyRegistered { name: String, script: String },
+    #[error("The arguments of command {0} have to be described by an object schema")]
+    InvalidSchema(String),
+    #[error("Command {0} not found")]
+    CommandNotFound(String),
+    #[error("Data provider {0} not found")]
+    ProviderNotFound(String),
+    #[error("Invalid arguments for command {command}: {reason}")]
+    InvalidArguments { command: String, reason: String },
+    #[error("{name} raised an exception:\n{error}")]
+    CallFailed { name: String, error: String },
+    #[error("{0} didn't return in time")]
+    CallTimedOut(String),
+    #[error("REPL {0} not found")]
+    ReplNotFound(usize),
+    #[error("REPL {0} was stopped")]
+    ReplStopped(usize),
+    #[error("Failed to run the REPL: {0}")]
+    ReplFailed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state")]
+pub enum ScriptState {
+    Stopped,
+    Running,
+    /// Failed and waiting to be started again, `restarts` counts the restarts in a row
+    Restarting {
+        error: String,
+        restarts: u32,
+    },
+    /// Raised an exception or got stuck, `error` is the formatted traceback
+    Failed {
+        error: String,
+    },
+}
+
+impl ScriptState {
+    /// Running or about to run again
+    pub fn is_active(&self) -> bool {
+        matches!(self, ScriptState::Running | ScriptState::Restarting { .. })
+    }
+}
+
+/// What happens when a script raises an exception or times out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy")]
+pub enum RestartPolicy {
+    Never,
+    /// Restarted up to `max_restarts` times in a row, waiting `delay_ms` before the first restart
+    /// and twice as long before each one after it. A run that lasts a minute resets the count.
+    OnFailure {
+        max_restarts: u32,
+        delay_ms: u64,
+    },
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::OnFailure {
+            max_restarts: 3,
+            delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptOptions {
+    /// How long one hook, or the top level of the script, may run before it is interrupted
+    pub timeout_ms: u64,
+    pub restart: RestartPolicy,
+}
+
+impl Default for ScriptOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 2000,
+            restart: RestartPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptStatus {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub state: ScriptState,
+    /// When the script was last started
+    pub started: Option<EnokiTimeStamp>,
+    /// What it was last started with
+    pub options: Option<ScriptOptions>,
+}
+
+#[derive(Debug)]
+struct ScriptHandle {
+    path: PathBuf,
+    state: ScriptState,
+    started: EnokiTimeStamp,
+    options: ScriptOptions,
+    control: Arc<ScriptControl>,
+    /// What the script may do if it's a plugin, plain scripts may do anything
+    grant: Option<Arc<Grant>>,
+}
+
+impl ScriptHandle {
+    fn status(&self, name: &str) -> ScriptStatus {
+        ScriptStatus {
+            name: name.to_string(),
+            path: self.path.clone(),
+            state: self.state.clone(),
+            started: Some(self.started),
+            options: Some(self.options),
+        }
+    }
+}
+
+/// Starts the interpreter with `enoki_core` importable, only does anything the first time
+pub fn init_python() {
+    Lazy::force(&PYTHON);
+}
+
+fn setup_interpreter(py: Python) -> PyResult<()> {
+    plugins::inherit_thread_grants(py)?;
+    // lets scripts import helpers that sit next to them
+    if let Some(dir) = scripts_dir() {
+        py.import("sys")?
+            .getattr("path")?
+            .downcast::<PyList>()?
+            .insert(0, dir.to_string_lossy())?;
+    }
+    // the wrappers are optional, scripts can always use enoki_core directly
+    if let Err(err) = PyModule::from_code(py, ENOKI_PY, "enoki.py", "enoki") {
+        tracing::warn!(
+            "Failed to load the enoki module: {}",
+            format_py_err(py, &err)
+        );
+    }
+    Ok(())
+}
+
+/// `Documents/Enoki/Scripts`, created if it doesn't exist
+pub fn scripts_dir() -> Option<PathBuf> {
+    let dir = document_dir()?.join(SCRIPTS_DIRECTORY);
+    match fs::create_dir_all(&dir) {
+        Ok(()) => Some(dir),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to create scripts directory {}: {}",
+                dir.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+/// The `.py` files in the scripts directory by name,
+/// files starting with `_` are left out so they can be used as helpers scripts import
+pub fn discover_scripts() -> Result<BTreeMap<String, PathBuf>, EnokiError> {
+    let dir = scripts_dir().ok_or(ScriptError::NoScriptsDirectory)?;
+    let mut scripts = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("py") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            if !name.starts_with('_') {
+                scripts.insert(name.to_string(), path.clone());
+            }
+        }
+    }
+    Ok(scripts)
+}
+
+/// Starts a script from the scripts directory on its own worker
+pub fn start_script(name: &str, options: ScriptOptions) -> Result<(), EnokiError> {
+    let path = discover_scripts()?
+        .remove(name)
+        .ok_or_else(|| ScriptError::NotFound(name.to_string()))?;
+    spawn_script(name.to_string(), path, options)
+}
+
+pub(crate) fn spawn_script(
+    name: String,
+    path: PathBuf,
+    options: ScriptOptions,
+) -> Result<(), EnokiError> {
+    spawn_supervised(name, path, options, None)
+}
+
+/// Starts a worker for the script at `path`, a plugin's script gets its grant
+fn spawn_supervised(
+    name: String,
+    path: PathBuf,
+    options: ScriptOptions,
+    grant: Option<Arc<Grant>>,
+) -> Result<(), EnokiError> {
+    init_python();
+    // held until the handle is inserted so a script that ends instantly can still record its state
+    let mut scripts = SCRIPTS.lock();
+    if let Some(handle) = scripts.get(&name) {
+        if handle.state.is_active() {
+            return Err(ScriptError::AlreadyRunning(name).into());
+        }
+    }
+
+    let control = Arc::new(ScriptControl::default());
+    spawn_worker(
+        name.clone(),
+        path.clone(),
+        control.clone(),
+        options,
+        grant.clone(),
+    )?;
+    tracing::info!("Started script {} from {}", name, path.display());
+    scripts.insert(
+        name,
+        ScriptHandle {
+            path,
+            state: ScriptState::Running,
+            started: now(),
+            options,
+            control,
+            grant,
+        },
+    );
+    Ok(())
+}
+
+/// Asks a script to stop, it gets a moment to finish its current hook before it is interrupted
+/// and then runs `on_stop` on its worker
+pub fn stop_script(name: &str) -> Result<(), EnokiError> {
+    match SCRIPTS.lock().get(name) {
+        Some(handle) if handle.state.is_active() => {
+            handle.control.request_stop();
+            Ok(())
+        }
+        _ => Err(ScriptError::NotRunning(name.to_string()).into()),
+    }
+}
+
+/// Stops every script and waits a bit for their `on_stop` hooks,
+/// scripts that take longer are left to finish on their own
+pub fn stop_all_scripts() {
+    repl::stop_all_repls();
+    for handle in SCRIPTS.lock().values() {
+        handle.control.request_stop();
+    }
+    let start = Instant::now();
+    while SCRIPTS
+        .lock()
+        .values()
+        .any(|handle| handle.state.is_active())
+    {
+        if start.elapsed() > EXIT_TIMEOUT {
+            tracing::warn!("Scripts didn't stop in time");
+            return;
+        }
+        std::thread::sleep(TICK_PERIOD);
+    }
+}
+
+pub fn script_status(name: &str) -> Result<ScriptStatus, EnokiError> {
+    if let Some(handle) = SCRIPTS.lock().get(name) {
+        return Ok(handle.status(name));
+    }
+    match discover_scripts()?.remove(name) {
+        Some(path) => Ok(ScriptStatus {
+            name: name.to_string(),
+            path,
+            state: ScriptState::Stopped,
+            started: None,
+            options: None,
+        }),
+        None => Err(ScriptError::NotFound(name.to_string()).into()),
+    }
+}
+
+/// Runs `cleanup` once the calling script stops or restarts, e.g. to stop callbacks it registered.
+/// Returns false without running it if this wasn't called from a script.
+pub fn on_script_stop(cleanup: impl FnOnce() + Send + 'static) -> bool {
+    worker::on_script_stop(Box::new(cleanup))
+}
+
+/// The asyncio loop of the calling script, which then keeps running until the script is stopped
+/// so other threads can schedule calls into the script on it. None if this wasn't called from a script.
+pub fn serving_loop() -> Option<PyObject> {
+    let (_, control) = worker::current_script()?;
+    let event_loop = control.event_loop()?;
+    control.keep_serving();
+    Some(event_loop)
+}
+
+/// Every script in the scripts directory and every script that has run, by name
+pub fn script_statuses() -> Result<Vec<ScriptStatus>, EnokiError> {
+    let mut statuses = discover_scripts()?
+        .into_iter()
+        .map(|(name, path)| {
+            let status = ScriptStatus {
+                name: name.clone(),
+                path,
+                state: ScriptState::Stopped,
+                started: None,
+                options: None,
+            };
+            (name, status)
+        })
+        .collect::<BTreeMap<_, _>>();
+    // plugins are listed by plugins::plugin_statuses
+    for (name, handle) in SCRIPTS.lock().iter() {
+        if handle.grant.is_none() {
+            statuses.insert(name.clone(), handle.status(name));
+        }
+    }
+    Ok(statuses.into_values().collect())
+}
+
+pub fn scripting_plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R> {
+    tauri::plugin::Builder::new("scripting")
+        .setup(|_app_handle| {
+            tracing::info!("Setting up scripting plugin");
+            init_python();
+            plugins::start_plugins();
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            tauri_cmds::list_scripts,
+            tauri_cmds::start_script,
+            tauri_cmds::stop_script,
+            tauri_cmds::script_status,
+            tauri_cmds::scripts_directory,
+            tauri_cmds::list_plugins,
+            tauri_cmds::start_plugin,
+            tauri_cmds::stop_plugin,
+            tauri_cmds::reload_plugin,
+            tauri_cmds::plugins_directory,
+            tauri_cmds::list_script_registry,
+            tauri_cmds::call_script_command,
+            tauri_cmds::read_data_provider,
+            tauri_cmds::start_repl,
+            tauri_cmds::write_repl,
+            tauri_cmds::read_out_repl,
+            tauri_cmds::read_err_repl,
+            tauri_cmds::repl_status,
+            tauri_cmds::complete_repl,
+            tauri_cmds::interrupt_repl,
+            tauri_cmds::stop_repl
+        ])
+        .build()
+}
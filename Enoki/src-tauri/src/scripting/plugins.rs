@@ -0,0 +1,611 @@
+//! Plugins are folders in `Documents/Enoki/Plugins` with a `plugin.json` manifest:
+//!
+//! ```json
+//! {
+//!     "name": "shot-tracker",
+//!     "version": "1.2.0",
+//!     "entry": "main.py",
+//!     "api_version": 1,
+//!     "permissions": ["nt_publish", "filesystem"]
+//! }
+//! ```
+//!
+//! The entry point is run like any script, with the plugin's folder importable so it can be
+//! split into modules. Every valid plugin is started with the app and reloaded when its files change.
+//!
+//! Permissions only gate what `enoki_core` lets a plugin do, [Permission] lists which calls need what.
+//! They are checked for the plugin's worker, the callbacks it registers and the threads it starts
+//! with `threading`, executors included. Python threads started any other way aren't granted anything
+//! and can't use what needs a permission, so a plugin can't get around its permissions with one.
+//! They can't stop a plugin from using python's own modules like `os` or `socket`.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use notify::{RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::{
+    prelude::*,
+    types::{PyCFunction, PyDict, PyList, PyTuple},
+};
+use serde::{Deserialize, Serialize};
+use tauri::api::path::document_dir;
+
+use crate::{
+    enoki_types::EnokiTimeStamp,
+    error::{log_result, EnokiError},
+};
+
+use super::{
+    spawn_supervised, stop_script, ScriptError, ScriptOptions, ScriptState, EXIT_TIMEOUT, SCRIPTS,
+    TICK_PERIOD,
+};
+
+pub const PLUGINS_DIRECTORY: &str = "Enoki/Plugins";
+pub const MANIFEST_FILE: &str = "plugin.json";
+/// The version of `enoki_core` plugins can ask for, bumped when it changes in a way they'd notice
+pub const ENOKI_API_VERSION: u32 = 1;
+/// Plugins run as scripts named with this prefix so they can't clash with a script
+const PLUGIN_PREFIX: &str = "plugin:";
+/// How long files have to stay unchanged before a plugin is reloaded, saving often touches several
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The plugins that were started and the folders they were started from, by name
+static PLUGINS: Lazy<Mutex<BTreeMap<String, LoadedPlugin>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+static WATCHER: Lazy<()> = Lazy::new(|| {
+    if let Err(err) = watch_plugins() {
+        tracing::error!("{}", err);
+    }
+});
+
+/// Hands the grant of a thread starting a `threading.Thread` to the new thread
+const INHERIT_GRANTS_PY: &str = r#"
+import threading
+
+def install(capture, adopt):
+    start = threading.Thread.start
+    bootstrap = threading.Thread._bootstrap_inner
+
+    def start_with_grant(self):
+        self._enoki_grant = capture()
+        start(self)
+
+    def bootstrap_with_grant(self):
+        adopt(getattr(self, "_enoki_grant", None))
+        bootstrap(self)
+
+    threading.Thread.start = start_with_grant
+    threading.Thread._bootstrap_inner = bootstrap_with_grant
+"#;
+
+thread_local! {
+    /// Who this thread runs python for, none if nothing was granted to it
+    static GRANT: RefCell<Option<ThreadGrant>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// `NetworkTablePubbedTopic.set_value`
+    NtPublish,
+    /// `Datalog.open`, `Datalog.open_async`, `Datalog.create`, `Datalog.set_session_metadata`
+    /// and `Datalog.read_session_metadata`
+    Filesystem,
+    /// Running commands on the robot, for when the terminal gets python bindings
+    Terminal,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Permission::NtPublish => "nt_publish",
+            Permission::Filesystem => "filesystem",
+            Permission::Terminal => "terminal",
+        };
+        f.write_str(name)
+    }
+}
+
+fn default_entry() -> String {
+    "main.py".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    /// The script to run, relative to the plugin's folder
+    #[serde(default = "default_entry")]
+    pub entry: String,
+    /// The [ENOKI_API_VERSION] the plugin was written for, newer ones are refused
+    pub api_version: u32,
+    #[serde(default)]
+    pub permissions: BTreeSet<Permission>,
+}
+
+impl PluginManifest {
+    /// Parses and checks the manifest of the plugin in `dir`
+    pub fn parse(dir: &Path, json: &str) -> Result<Self, ScriptError> {
+        let invalid = |reason: String| ScriptError::InvalidPlugin(dir.to_path_buf(), reason);
+        let manifest = serde_json::from_str::<PluginManifest>(json)
+            .map_err(|err| invalid(format!("{} {}", MANIFEST_FILE, err)))?;
+        if manifest.name.trim().is_empty() {
+            return Err(invalid("the name is empty".to_string()));
+        }
+        // the entry has to stay inside the plugin's folder
+        let entry = Path::new(&manifest.entry);
+        if !entry
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(invalid(format!(
+                "entry {} is outside the plugin",
+                manifest.entry
+            )));
+        }
+        if manifest.api_version > ENOKI_API_VERSION {
+            return Err(ScriptError::UnsupportedApiVersion {
+                plugin: manifest.name,
+                version: manifest.api_version,
+            });
+        }
+        Ok(manifest)
+    }
+
+    pub fn read(dir: &Path) -> Result<Self, ScriptError> {
+        let json = fs::read_to_string(dir.join(MANIFEST_FILE)).map_err(|err| {
+            ScriptError::InvalidPlugin(dir.to_path_buf(), format!("{} {}", MANIFEST_FILE, err))
+        })?;
+        let manifest = Self::parse(dir, &json)?;
+        if !dir.join(&manifest.entry).is_file() {
+            return Err(ScriptError::InvalidPlugin(
+                dir.to_path_buf(),
+                format!("entry {} not found", manifest.entry),
+            ));
+        }
+        Ok(manifest)
+    }
+}
+
+/// The permissions a plugin was started with, held by every thread that runs python for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub plugin: String,
+    pub permissions: BTreeSet<Permission>,
+}
+
+/// Who the python on a thread runs for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThreadGrant {
+    /// The app, a plain script or the console, they may do everything
+    App,
+    /// A plugin, it may do what it declared
+    Plugin(Arc<Grant>),
+}
+
+/// The grant a `threading.Thread` was started with, only made by the thread starting it
+#[pyclass]
+struct InheritedGrant(Option<ThreadGrant>);
+
+/// The grant of the calling thread, none if nothing was granted to it
+pub fn current_grant() -> Option<ThreadGrant> {
+    GRANT.with(|grant| grant.borrow().clone())
+}
+
+/// Makes the calling thread run for the app or a plugin, e.g. a thread calling a plugin's callbacks
+pub fn set_thread_grant(grant: Option<ThreadGrant>) {
+    GRANT.with(|current| *current.borrow_mut() = grant);
+}
+
+/// Fails if the calling thread runs for a plugin that didn't declare `permission`
+/// or if nothing was granted to it
+pub fn check_permission(permission: Permission) -> Result<(), ScriptError> {
+    match current_grant() {
+        Some(ThreadGrant::App) => Ok(()),
+        Some(ThreadGrant::Plugin(grant)) if grant.permissions.contains(&permission) => Ok(()),
+        Some(ThreadGrant::Plugin(grant)) => Err(ScriptError::PermissionDenied {
+            plugin: grant.plugin.clone(),
+            permission,
+        }),
+        None => Err(ScriptError::UngrantedThread(permission)),
+    }
+}
+
+/// Makes every `threading.Thread` run for whoever started it
+pub(super) fn inherit_thread_grants(py: Python) -> PyResult<()> {
+    let capture = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        |args: &PyTuple, _kwargs: Option<&PyDict>| {
+            Py::new(args.py(), InheritedGrant(current_grant()))
+        },
+    )?;
+    let adopt = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+            // anything but what the starting thread captured grants nothing
+            let inherited = args.get_item(0)?.extract::<PyRef<InheritedGrant>>();
+            set_thread_grant(inherited.ok().and_then(|inherited| inherited.0.clone()));
+            Ok(())
+        },
+    )?;
+    PyModule::from_code(py, INHERIT_GRANTS_PY, "enoki_grants.py", "enoki_grants")?
+        .call_method1("install", (capture, adopt))
+        .map(drop)
+}
+
+/// [check_permission] for the python bindings, raises a PermissionError
+pub fn require_permission(permission: Permission) -> PyResult<()> {
+    check_permission(permission)
+        .map_err(|err| PyErr::new::<pyo3::exceptions::PyPermissionError, _>(err.to_string()))
+}
+
+#[derive(Debug)]
+struct LoadedPlugin {
+    dir: PathBuf,
+    /// Stopped from the app, it isn't reloaded until it's started again
+    stopped: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStatus {
+    /// From the manifest, or the folder name if the manifest couldn't be read
+    pub name: String,
+    pub dir: PathBuf,
+    pub manifest: Option<PluginManifest>,
+    /// `Failed` with the reason if the plugin can't be loaded
+    #[serde(flatten)]
+    pub state: ScriptState,
+    /// When the plugin was last started
+    pub started: Option<EnokiTimeStamp>,
+}
+
+fn plugin_key(name: &str) -> String {
+    format!("{}{}", PLUGIN_PREFIX, name)
+}
+
+/// `Documents/Enoki/Plugins`, created if it doesn't exist
+pub fn plugins_dir() -> Option<PathBuf> {
+    let dir = document_dir()?.join(PLUGINS_DIRECTORY);
+    if let Err(err) = fs::create_dir_all(&dir) {
+        tracing::warn!(
+            "Failed to create plugins directory {}: {}",
+            dir.display(),
+            err
+        );
+        return None;
+    }
+    // file events come with resolved paths, e.g. through /private on macos
+    Some(fs::canonicalize(&dir).unwrap_or(dir))
+}
+
+/// The folders in the plugins directory, hidden ones and ones starting with `_` are left out
+fn plugin_dirs() -> Result<Vec<PathBuf>, EnokiError> {
+    let dir = plugins_dir().ok_or(ScriptError::NoPluginsDirectory)?;
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str());
+        let visible = matches!(name, Some(name) if !name.starts_with(['.', '_']));
+        if path.is_dir() && visible {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Where the plugin named `name` is, the folder it was started from if it was
+fn find_plugin(name: &str) -> Result<PathBuf, EnokiError> {
+    if let Some(plugin) = PLUGINS.lock().get(name) {
+        return Ok(plugin.dir.clone());
+    }
+    for dir in plugin_dirs()? {
+        if PluginManifest::read(&dir)
+            .ok()
+            .map(|manifest| manifest.name)
+            == Some(name.to_string())
+        {
+            return Ok(dir);
+        }
+    }
+    Err(ScriptError::PluginNotFound(name.to_string()).into())
+}
+
+/// Starts every valid plugin and reloads them from then on when their files change
+pub fn start_plugins() {
+    let dirs = match plugin_dirs() {
+        Ok(dirs) => dirs,
+        Err(err) => {
+            tracing::error!("Failed to find plugins: {}", err);
+            return;
+        }
+    };
+    for dir in dirs {
+        let _ = log_result(load_plugin(&dir));
+    }
+    Lazy::force(&WATCHER);
+}
+
+/// Starts the plugin in `dir` with its own modules importable, returns its name
+fn load_plugin(dir: &Path) -> Result<String, EnokiError> {
+    let manifest = PluginManifest::read(dir)?;
+    let mut plugins = PLUGINS.lock();
+    if let Some(other) = plugins.get(&manifest.name) {
+        if other.dir != dir {
+            return Err(ScriptError::InvalidPlugin(
+                dir.to_path_buf(),
+                format!(
+                    "{} is already the name of {}",
+                    manifest.name,
+                    other.dir.display()
+                ),
+            )
+            .into());
+        }
+    }
+
+    super::init_python();
+    Python::with_gil(|py| -> PyResult<()> {
+        let path = py.import("sys")?.getattr("path")?.downcast::<PyList>()?;
+        let dir = dir.to_string_lossy();
+        if !path.contains(dir.as_ref())? {
+            path.insert(0, dir)?;
+        }
+        Ok(())
+    })
+    .map_err(|err| ScriptError::InvalidPlugin(dir.to_path_buf(), err.to_string()))?;
+
+    let grant = Grant {
+        plugin: manifest.name.clone(),
+        permissions: manifest.permissions.clone(),
+    };
+    spawn_supervised(
+        plugin_key(&manifest.name),
+        dir.join(&manifest.entry),
+        ScriptOptions::default(),
+        Some(Arc::new(grant)),
+    )?;
+    tracing::info!(
+        "Started plugin {} {} with permissions {:?}",
+        manifest.name,
+        manifest.version,
+        manifest.permissions
+    );
+    plugins.insert(
+        manifest.name.clone(),
+        LoadedPlugin {
+            dir: dir.to_path_buf(),
+            stopped: false,
+        },
+    );
+    Ok(manifest.name)
+}
+
+/// Stops a plugin, waits for it to finish and forgets its modules so the next start loads them fresh
+fn unload_plugin(name: &str) -> Result<(), EnokiError> {
+    let key = plugin_key(name);
+    if let Some(handle) = SCRIPTS.lock().get(&key) {
+        handle.control.request_stop();
+    }
+    let start = Instant::now();
+    while matches!(SCRIPTS.lock().get(&key), Some(handle) if handle.state.is_active()) {
+        if start.elapsed() > EXIT_TIMEOUT {
+            return Err(ScriptError::PluginStuck(name.to_string()).into());
+        }
+        std::thread::sleep(TICK_PERIOD);
+    }
+
+    let plugin = match PLUGINS.lock().remove(name) {
+        Some(plugin) => plugin,
+        None => return Ok(()),
+    };
+    Python::with_gil(|py| {
+        if let Err(err) = forget_modules(py, &plugin.dir) {
+            tracing::warn!("Failed to unload modules of plugin {}: {}", name, err);
+        }
+    });
+    Ok(())
+}
+
+/// Removes the modules loaded from `dir` and `dir` itself from the import path
+fn forget_modules(py: Python, dir: &Path) -> PyResult<()> {
+    let sys = py.import("sys")?;
+    let modules = sys.getattr("modules")?;
+    let loaded = modules.call_method0("items")?.iter()?;
+    let mut forget = Vec::new();
+    for item in loaded {
+        let (name, module) = item?.extract::<(String, &PyAny)>()?;
+        let file = module
+            .getattr("__file__")
+            .and_then(|file| file.extract::<String>());
+        if let Ok(file) = file {
+            if Path::new(&file).starts_with(dir) {
+                forget.push(name);
+            }
+        }
+    }
+    for name in forget {
+        modules.del_item(name)?;
+    }
+    let path = sys.getattr("path")?.downcast::<PyList>()?;
+    let dir = dir.to_string_lossy();
+    while path.contains(dir.as_ref())? {
+        path.call_method1("remove", (dir.as_ref(),))?;
+    }
+    Ok(())
+}
+
+/// Starts a plugin that isn't running with its current files
+pub fn start_plugin(name: &str) -> Result<(), EnokiError> {
+    let dir = find_plugin(name)?;
+    let key = plugin_key(name);
+    if matches!(SCRIPTS.lock().get(&key), Some(handle) if handle.state.is_active()) {
+        return Err(ScriptError::AlreadyRunning(key).into());
+    }
+    unload_plugin(name)?;
+    load_plugin(&dir).map(drop)
+}
+
+/// Stops a plugin until it's started again, changes to its files don't restart it
+pub fn stop_plugin(name: &str) -> Result<(), EnokiError> {
+    if let Some(plugin) = PLUGINS.lock().get_mut(name) {
+        plugin.stopped = true;
+    }
+    stop_script(&plugin_key(name))
+}
+
+/// Stops a plugin if it's running and starts it again with its current files
+pub fn reload_plugin(name: &str) -> Result<(), EnokiError> {
+    let dir = find_plugin(name)?;
+    unload_plugin(name)?;
+    load_plugin(&dir).map(drop)
+}
+
+fn plugin_status(dir: PathBuf) -> PluginStatus {
+    let manifest = match PluginManifest::read(&dir) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            return PluginStatus {
+                name: dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                dir,
+                manifest: None,
+                state: ScriptState::Failed {
+                    error: err.to_string(),
+                },
+                started: None,
+            }
+        }
+    };
+    let (state, started) = match SCRIPTS.lock().get(&plugin_key(&manifest.name)) {
+        Some(handle) => (handle.state.clone(), Some(handle.started)),
+        None => (ScriptState::Stopped, None),
+    };
+    PluginStatus {
+        name: manifest.name.clone(),
+        dir,
+        manifest: Some(manifest),
+        state,
+        started,
+    }
+}
+
+/// Every folder in the plugins directory, including ones that aren't valid plugins
+pub fn plugin_statuses() -> Result<Vec<PluginStatus>, EnokiError> {
+    Ok(plugin_dirs()?.into_iter().map(plugin_status).collect())
+}
+
+/// The plugin folder `path` is in, none for files outside of plugins or python's caches
+fn changed_plugin_dir(plugins_dir: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(plugins_dir).ok()?;
+    let mut components = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy());
+    let plugin = components.next()?;
+    if plugin.starts_with(['.', '_']) {
+        return None;
+    }
+    // written by python itself when the plugin is imported, it would reload forever otherwise
+    if components.any(|component| component == "__pycache__") {
+        return None;
+    }
+    Some(plugins_dir.join(plugin.as_ref()))
+}
+
+/// Reloads the plugin in a folder after its files changed, starts it if it's new
+/// and stops it if the folder is gone
+pub(crate) fn reload_dir(dir: &Path) {
+    let loaded = PLUGINS
+        .lock()
+        .iter()
+        .filter(|(_, plugin)| plugin.dir == dir)
+        .map(|(name, plugin)| (name.clone(), plugin.stopped))
+        .collect::<Vec<_>>();
+    if dir.exists() && loaded.iter().any(|(_, stopped)| *stopped) {
+        return;
+    }
+    for (name, _) in &loaded {
+        if let Err(err) = unload_plugin(name) {
+            tracing::error!("Failed to reload plugin {}: {}", name, err);
+            return;
+        }
+    }
+    if dir.join(MANIFEST_FILE).is_file() {
+        if let Ok(name) = log_result(load_plugin(dir)) {
+            tracing::info!("Reloaded plugin {} after its files changed", name);
+        }
+    }
+}
+
+/// Watches with `notify` itself, `tauri-plugin-fs-watch` wraps the same version of it but only
+/// emits to the webview, and plugins have to reload without a window around to forward the events
+fn watch_plugins() -> Result<(), EnokiError> {
+    let plugins_dir = plugins_dir().ok_or(ScriptError::NoPluginsDirectory)?;
+    let (event_sender, event_receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = event_sender.send(res);
+    })
+    .map_err(|err| ScriptError::PluginWatch(err.to_string()))?;
+    watcher
+        .watch(&plugins_dir, RecursiveMode::Recursive)
+        .map_err(|err| ScriptError::PluginWatch(err.to_string()))?;
+
+    std::thread::Builder::new()
+        .name("PluginWatcher".to_string())
+        .spawn(move || {
+            // the watcher stops when dropped so it has to live on this thread
+            let _watcher = watcher;
+            watch_loop(&plugins_dir, event_receiver, reload_dir);
+            tracing::warn!("Stopped watching plugins");
+        })?;
+    Ok(())
+}
+
+/// Calls `reload` once for every plugin folder that changed, after its files settled down,
+/// until `fs_events` disconnects
+pub(crate) fn watch_loop(
+    plugins_dir: &Path,
+    fs_events: mpsc::Receiver<notify::Result<notify::Event>>,
+    mut reload: impl FnMut(&Path),
+) {
+    let mut changed = BTreeSet::new();
+    loop {
+        let event = if changed.is_empty() {
+            fs_events
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+        } else {
+            fs_events.recv_timeout(RELOAD_DEBOUNCE)
+        };
+        match event {
+            Ok(Ok(event)) if !event.kind.is_access() => {
+                for path in &event.paths {
+                    changed.extend(changed_plugin_dir(plugins_dir, path));
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => tracing::warn!("Plugin watch error: {}", err),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                for dir in std::mem::take(&mut changed) {
+                    reload(&dir);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
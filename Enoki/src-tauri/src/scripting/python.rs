@@ -0,0 +1,52 @@
+use pyo3::prelude::*;
+
+use crate::networktable::ffi::python::callable;
+
+use super::registry::{self, to_json};
+
+fn registry_err(err: super::ScriptError) -> PyErr {
+    match err {
+        super::ScriptError::NotAScript => {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(err.to_string())
+        }
+        err => PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string()),
+    }
+}
+
+/// Lets the frontend call `callback(**args)`, `schema` is a JSON schema for `args`
+#[pyfunction]
+#[pyo3(signature = (name, callback, schema = None, description = None))]
+pub fn register_command(
+    py: Python,
+    name: String,
+    callback: &PyAny,
+    schema: Option<&PyAny>,
+    description: Option<String>,
+) -> PyResult<()> {
+    let callback = callable(callback)?;
+    let schema = match schema {
+        Some(schema) => to_json(py, schema)?,
+        None => serde_json::json!({ "type": "object" }),
+    };
+    registry::register_command(name, description.unwrap_or_default(), schema, callback)
+        .map_err(registry_err)
+}
+
+/// Lets the frontend read what `callback()` returns, its fields are put under the provider's prefix
+#[pyfunction]
+#[pyo3(signature = (name, callback, description = None))]
+pub fn register_provider(
+    name: String,
+    callback: &PyAny,
+    description: Option<String>,
+) -> PyResult<()> {
+    let callback = callable(callback)?;
+    registry::register_provider(name, description.unwrap_or_default(), callback)
+        .map_err(registry_err)
+}
+
+/// Removes a command or provider the calling script registered, false if it had none by that name
+#[pyfunction]
+pub fn unregister(name: String) -> bool {
+    registry::unregister(&name)
+}
@@ -0,0 +1,446 @@
+//! Commands and data providers scripts register for the frontend, e.g. for a dashboard button.
+//!
+//! Both are called on the worker of the script that registered them, between its ticks,
+//! and are removed when the script stops. A script that registered any keeps running until it's stopped.
+//!
+//! A command is called with the keyword arguments the frontend sends, checked against the JSON schema
+//! it was registered with, and returns anything `json.dumps` takes. A provider is called without arguments
+//! and returns an `EnokiObject` or a dict of values, its fields are put under `/Scripts/<script>/<provider>`.
+//! Either can be `async def`, it then runs as a task on the script's loop.
+
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::enoki_types::{now, EnokiField, EnokiKey, EnokiObject, TimestampedEnokiValue};
+use crate::error::EnokiError;
+use crate::python_helpers::logging::{format_py_err, tracing_log_exception};
+use crate::python_helpers::py_enoki_types::PyEnokiObject;
+
+use super::worker::{current_script, ScriptControl};
+use super::ScriptError;
+
+/// The key provider fields are put under
+pub const PROVIDER_ROOT: &str = "Scripts";
+/// How long the frontend waits for a command or provider to return
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+static COMMANDS: Lazy<Mutex<BTreeMap<String, Registered<CommandInfo>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+static PROVIDERS: Lazy<Mutex<BTreeMap<String, Registered<ProviderInfo>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandInfo {
+    pub name: String,
+    pub script: String,
+    pub description: String,
+    /// Describes the keyword arguments, `{"type": "object"}` if the script didn't give one
+    pub schema: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub script: String,
+    pub description: String,
+    /// Every field the provider returns is under this key
+    pub prefix: EnokiKey,
+}
+
+/// Everything scripts registered, sorted by name
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScriptRegistry {
+    pub commands: Vec<CommandInfo>,
+    pub providers: Vec<ProviderInfo>,
+}
+
+struct Registered<I> {
+    info: I,
+    callback: PyObject,
+    control: Arc<ScriptControl>,
+}
+
+/// Adds an entry for the calling script, replacing one it registered before under the same name
+fn register<I: Send + 'static>(
+    registry: &'static Mutex<BTreeMap<String, Registered<I>>>,
+    name: String,
+    script_of: fn(&I) -> &str,
+    info: impl FnOnce(String) -> I,
+    callback: PyObject,
+) -> Result<(), ScriptError> {
+    let (script, control) = current_script().ok_or(ScriptError::NotAScript)?;
+    let mut entries = registry.lock();
+    if let Some(entry) = entries.get(&name) {
+        if !Arc::ptr_eq(&entry.control, &control) {
+            return Err(ScriptError::AlreadyRegistered {
+                name,
+                script: script_of(&entry.info).to_string(),
+            });
+        }
+    }
+    let removed = (name.clone(), control.clone());
+    super::on_script_stop(move || {
+        let mut entries = registry.lock();
+        if matches!(entries.get(&removed.0), Some(entry) if Arc::ptr_eq(&entry.control, &removed.1))
+        {
+            entries.remove(&removed.0);
+        }
+    });
+    control.keep_serving();
+    tracing::info!("Script {} registered {}", script, name);
+    entries.insert(
+        name,
+        Registered {
+            info: info(script),
+            callback,
+            control,
+        },
+    );
+    Ok(())
+}
+
+pub(super) fn register_command(
+    name: String,
+    description: String,
+    schema: Value,
+    callback: PyObject,
+) -> Result<(), ScriptError> {
+    // the arguments are passed as keywords so they have to be an object
+    let takes_object = match schema.get("type") {
+        Some(type_name) => type_name == "object",
+        None => schema.is_object(),
+    };
+    if !takes_object {
+        return Err(ScriptError::InvalidSchema(name));
+    }
+    register(
+        &COMMANDS,
+        name.clone(),
+        |info| &info.script,
+        |script| CommandInfo {
+            name,
+            script,
+            description,
+            schema,
+        },
+        callback,
+    )
+}
+
+pub(super) fn register_provider(
+    name: String,
+    description: String,
+    callback: PyObject,
+) -> Result<(), ScriptError> {
+    register(
+        &PROVIDERS,
+        name.clone(),
+        |info| &info.script,
+        |script| ProviderInfo {
+            prefix: EnokiKey::root()
+                .child(PROVIDER_ROOT)
+                .child(script.as_str())
+                .child(name.as_str()),
+            name,
+            script,
+            description,
+        },
+        callback,
+    )
+}
+
+/// Removes a command or provider the calling script registered, false if it had none by that name
+pub(super) fn unregister(name: &str) -> bool {
+    let control = match current_script() {
+        Some((_, control)) => control,
+        None => return false,
+    };
+    let commands = remove_owned(&COMMANDS, name, &control);
+    let providers = remove_owned(&PROVIDERS, name, &control);
+    commands || providers
+}
+
+fn remove_owned<I>(
+    registry: &Mutex<BTreeMap<String, Registered<I>>>,
+    name: &str,
+    control: &Arc<ScriptControl>,
+) -> bool {
+    let mut entries = registry.lock();
+    match entries.get(name) {
+        Some(entry) if Arc::ptr_eq(&entry.control, control) => entries.remove(name).is_some(),
+        _ => false,
+    }
+}
+
+pub fn script_registry() -> ScriptRegistry {
+    ScriptRegistry {
+        commands: COMMANDS
+            .lock()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect(),
+        providers: PROVIDERS
+            .lock()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect(),
+    }
+}
+
+/// Calls a command with `args` as its keyword arguments and returns what it returned
+pub fn call_command(name: &str, args: Option<Value>) -> Result<Value, EnokiError> {
+    let args = args.unwrap_or_else(|| Value::Object(Default::default()));
+    let (info, callback, control) = match COMMANDS.lock().get(name) {
+        Some(entry) => (
+            entry.info.clone(),
+            entry.callback.clone(),
+            entry.control.clone(),
+        ),
+        None => return Err(ScriptError::CommandNotFound(name.to_string()).into()),
+    };
+    validate(&info.schema, &args, "arguments").map_err(|reason| ScriptError::InvalidArguments {
+        command: name.to_string(),
+        reason,
+    })?;
+    Ok(call_on_worker(
+        name,
+        &info.script,
+        &control,
+        callback,
+        Some(args),
+        to_json,
+    )?)
+}
+
+/// Calls a provider and returns its fields under its prefix
+pub fn read_provider(name: &str) -> Result<EnokiObject, EnokiError> {
+    let (info, callback, control) = match PROVIDERS.lock().get(name) {
+        Some(entry) => (
+            entry.info.clone(),
+            entry.callback.clone(),
+            entry.control.clone(),
+        ),
+        None => return Err(ScriptError::ProviderNotFound(name.to_string()).into()),
+    };
+    let prefix = info.prefix;
+    Ok(call_on_worker(
+        name,
+        &info.script,
+        &control,
+        callback,
+        None,
+        move |_py, value| provided_object(&prefix, value),
+    )?)
+}
+
+type Convert<T> = Box<dyn Fn(Python, &PyAny) -> PyResult<T> + Send + Sync>;
+
+/// Sends what a call returned back to the thread waiting for it
+struct Reply<T> {
+    name: String,
+    sender: Mutex<Option<mpsc::Sender<Result<T, String>>>>,
+    convert: Convert<T>,
+}
+
+impl<T> Reply<T> {
+    fn send(&self, py: Python, result: PyResult<&PyAny>) {
+        let result = result
+            .and_then(|value| (self.convert)(py, value))
+            .map_err(|err| {
+                if let Err(log_err) = tracing_log_exception(py, &err) {
+                    tracing::error!("{} failed: {}", self.name, format_py_err(py, &log_err));
+                }
+                format_py_err(py, &err)
+            });
+        if let Some(sender) = self.sender.lock().take() {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+/// Schedules the callback on the script's loop and waits for it, an async callback runs as a task
+fn call_on_worker<T: Send + 'static>(
+    name: &str,
+    script: &str,
+    control: &ScriptControl,
+    callback: PyObject,
+    args: Option<Value>,
+    convert: impl Fn(Python, &PyAny) -> PyResult<T> + Send + Sync + 'static,
+) -> Result<T, ScriptError> {
+    let (sender, receiver) = mpsc::channel();
+    let reply = Arc::new(Reply {
+        name: name.to_string(),
+        sender: Mutex::new(Some(sender)),
+        convert: Box::new(convert),
+    });
+    let not_running = || ScriptError::NotRunning(script.to_string());
+    let event_loop = control.event_loop().ok_or_else(not_running)?;
+    Python::with_gil(|py| {
+        let run_loop = event_loop.clone_ref(py);
+        let run = PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |call_args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+                let py = call_args.py();
+                let result = match &args {
+                    Some(args) => from_json(py, args)
+                        .and_then(|kwargs| Ok(kwargs.downcast::<PyDict>()?))
+                        .and_then(|kwargs| callback.call(py, (), Some(kwargs))),
+                    None => callback.call0(py),
+                };
+                let result = match result {
+                    Ok(result) => result.into_ref(py),
+                    Err(err) => {
+                        reply.send(py, Err(err));
+                        return Ok(());
+                    }
+                };
+                let asyncio = py.import("asyncio")?;
+                if !asyncio.call_method1("iscoroutine", (result,))?.is_true()? {
+                    reply.send(py, Ok(result));
+                    return Ok(());
+                }
+                let task = run_loop.call_method1(py, "create_task", (result,))?;
+                let reply = reply.clone();
+                let done = PyCFunction::new_closure(
+                    py,
+                    None,
+                    None,
+                    move |done_args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+                        let py = done_args.py();
+                        let task = done_args.get_item(0)?;
+                        reply.send(py, task.call_method0("result"));
+                        Ok(())
+                    },
+                )?;
+                task.call_method1(py, "add_done_callback", (done,))?;
+                Ok(())
+            },
+        )?;
+        event_loop.call_method1(py, "call_soon_threadsafe", (run,))
+    })
+    // the loop was closed in the meantime
+    .map_err(|_| not_running())?;
+
+    match receiver.recv_timeout(CALL_TIMEOUT) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) => Err(ScriptError::CallFailed {
+            name: name.to_string(),
+            error,
+        }),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(ScriptError::CallTimedOut(name.to_string())),
+        // dropped without being called, the script stopped first
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(not_running()),
+    }
+}
+
+/// What a provider returned with its keys under `prefix`, a dict's values are timestamped now
+fn provided_object(prefix: &EnokiKey, value: &PyAny) -> PyResult<EnokiObject> {
+    if let Ok(values) = value.downcast::<PyDict>() {
+        let timestamp = now();
+        let mut object = EnokiObject::new(timestamp);
+        for (key, value) in values {
+            let key = prefix.join(&EnokiKey::from(key.extract::<String>()?));
+            let value = TimestampedEnokiValue::new(timestamp, value.extract()?);
+            object.add_field(EnokiField::new(key, value));
+        }
+        return Ok(object);
+    }
+    let provided = EnokiObject::from(value.extract::<PyRef<PyEnokiObject>>()?.clone());
+    let mut object = EnokiObject::new(provided.get_timestamp());
+    for field in provided.get_fields() {
+        let key = match field.get_key().starts_with(prefix) {
+            true => field.get_key().clone(),
+            false => prefix.join(field.get_key()),
+        };
+        let rekeyed =
+            EnokiField::new(key, field.get_value_owned()).with_unit(field.get_unit().cloned());
+        match provided.get_history(field.get_key()) {
+            Some(history) => object.add_field_with_history(rekeyed, history.clone()),
+            None => object.add_field(rekeyed),
+        }
+    }
+    Ok(object)
+}
+
+pub(super) fn to_json(py: Python, value: &PyAny) -> PyResult<Value> {
+    let text: String = py
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&text)
+        .map_err(|err| PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string()))
+}
+
+fn from_json<'py>(py: Python<'py>, value: &Value) -> PyResult<&'py PyAny> {
+    py.import("json")?
+        .call_method1("loads", (value.to_string(),))
+}
+
+/// Checks `value` against the parts of a JSON schema that describe arguments:
+/// `type`, `enum`, `required`, `properties`, `additionalProperties: false` and `items`
+pub fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let types = match schema.get("type") {
+        Some(Value::String(type_name)) => vec![type_name.as_str()],
+        Some(Value::Array(type_names)) => type_names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|type_name| has_type(value, type_name)) {
+        return Err(format!("{} should be {}", path, types.join(" or ")));
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            return Err(format!(
+                "{} should be one of {}",
+                path,
+                Value::Array(options.clone())
+            ));
+        }
+    }
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("{} is missing {}", path, key));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+        for (key, value) in object {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate(property, value, &format!("{}.{}", path, key))?,
+                None if closed => return Err(format!("{} doesn't take {}", path, key)),
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(item_schema, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, type_name: &str) -> bool {
+    match type_name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
@@ -0,0 +1,367 @@
+//! Interactive python sessions for the frontend's console.
+//!
+//! Every session has its own namespace with `enoki_core`, `enoki` and the running network table
+//! clients bound as `clients` and `client`, and runs what it's sent on its own thread.
+//! Input is pushed a line at a time like at a python prompt, so a block ends with an empty line,
+//! and top level `await` runs on the session's own asyncio loop.
+//!
+//! What a session prints is kept until the frontend reads it and is logged like script output,
+//! stdout at info and stderr at warn. Other threads keep printing where they did before.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    os::raw::c_long,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex};
+use pyo3::{exceptions::PySystemExit, ffi, prelude::*, types::PyDict};
+use serde::Serialize;
+use tracing::Level;
+
+use crate::networktable::ffi::{network_table_clients, python::PyNetworkTableClientId};
+use crate::python_helpers::logging::{format_py_err, log_at};
+
+use super::plugins::{set_thread_grant, ThreadGrant};
+use super::{init_python, ScriptError};
+
+pub type ReplId = usize;
+
+/// The console class sessions run, importable as `enoki_repl`
+static ENOKI_REPL_PY: &str = include_str!("../../scripting_base/enoki_repl.py");
+
+static SESSIONS: Lazy<Mutex<HashMap<ReplId, Arc<ReplSession>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ReplStatus {
+    /// False once the session was stopped or called `exit()`
+    pub running: bool,
+    /// Running input or has input waiting to run
+    pub busy: bool,
+    /// The last line started a block that isn't finished, the prompt should be `...`
+    pub more: bool,
+}
+
+/// What a session printed that wasn't read yet
+#[derive(Default)]
+struct ReplOutput {
+    stdout: Mutex<String>,
+    stderr: Mutex<String>,
+}
+
+struct ReplSession {
+    id: ReplId,
+    console: PyObject,
+    output: Arc<ReplOutput>,
+    input: Mutex<VecDeque<String>>,
+    input_signal: Condvar,
+    /// Set from taking a line until it was run, and until the session is set up
+    busy: AtomicBool,
+    /// Set while the console runs a line, only changes with the gil held
+    running: AtomicBool,
+    more: AtomicBool,
+    stopped: AtomicBool,
+    /// `threading.get_ident()` of the session's thread
+    py_thread: AtomicU64,
+}
+
+impl ReplSession {
+    fn name(&self) -> String {
+        format!("Repl-{}", self.id)
+    }
+
+    fn status(&self) -> ReplStatus {
+        // busy is set with the input locked, so a line is never missed between the two
+        let input = self.input.lock();
+        ReplStatus {
+            running: !self.stopped.load(Ordering::Relaxed),
+            busy: self.busy.load(Ordering::Relaxed) || !input.is_empty(),
+            more: self.more.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Raises `KeyboardInterrupt` in the input that's running, if any,
+    /// the gil keeps it from finishing meanwhile
+    fn interrupt(&self, _py: Python) -> bool {
+        if !self.running.load(Ordering::Relaxed) {
+            return false;
+        }
+        let thread = self.py_thread.load(Ordering::Relaxed) as c_long;
+        unsafe {
+            ffi::PyThreadState_SetAsyncExc(thread, ffi::PyExc_KeyboardInterrupt);
+        }
+        true
+    }
+
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let _input = self.input.lock();
+        self.input_signal.notify_all();
+    }
+
+    /// Waits for the next line, none once the session is stopped
+    fn next_line(&self) -> Option<String> {
+        let mut input = self.input.lock();
+        loop {
+            if self.stopped.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(line) = input.pop_front() {
+                self.busy.store(true, Ordering::Relaxed);
+                return Some(line);
+            }
+            self.input_signal.wait(&mut input);
+        }
+    }
+}
+
+/// Stands in for stdout or stderr on a session's thread
+#[pyclass]
+struct ReplWriter {
+    output: Arc<ReplOutput>,
+    is_err: bool,
+    file: String,
+    thread: String,
+    /// What was written since the last newline, logged once the line is finished
+    line: Mutex<String>,
+}
+
+impl ReplWriter {
+    fn new(session: &ReplSession, is_err: bool) -> Self {
+        Self {
+            output: session.output.clone(),
+            is_err,
+            file: format!("<repl-{}>", session.id),
+            thread: format!("py:{}", session.name()),
+            line: Mutex::new(String::new()),
+        }
+    }
+}
+
+#[pymethods]
+impl ReplWriter {
+    fn write(&self, text: &str) -> usize {
+        let (buffer, level) = match self.is_err {
+            false => (&self.output.stdout, Level::INFO),
+            true => (&self.output.stderr, Level::WARN),
+        };
+        buffer.lock().push_str(text);
+
+        let mut line = self.line.lock();
+        line.push_str(text);
+        while let Some(end) = line.find('\n') {
+            let finished: String = line.drain(..=end).collect();
+            log_at(
+                level,
+                finished.trim_end().to_string(),
+                "?".to_string(),
+                self.file.clone(),
+                self.thread.clone(),
+            );
+        }
+        text.chars().count()
+    }
+
+    fn flush(&self) {}
+}
+
+fn console_class(py: Python<'_>) -> PyResult<&PyAny> {
+    let modules = py.import("sys")?.getattr("modules")?;
+    let module = match modules.get_item("enoki_repl") {
+        Ok(module) => module,
+        Err(_) => PyModule::from_code(py, ENOKI_REPL_PY, "enoki_repl.py", "enoki_repl")?,
+    };
+    module.getattr("Console")
+}
+
+fn new_console(py: Python, id: ReplId) -> PyResult<PyObject> {
+    let clients = PyDict::new(py);
+    for client in network_table_clients() {
+        let identity = client.identity.clone();
+        clients.set_item(identity, PyNetworkTableClientId::from(client).into_py(py))?;
+    }
+    Ok(console_class(py)?
+        .call1((clients, format!("<repl-{}>", id)))?
+        .into())
+}
+
+fn session(id: ReplId) -> Result<Arc<ReplSession>, ScriptError> {
+    SESSIONS
+        .lock()
+        .get(&id)
+        .cloned()
+        .ok_or(ScriptError::ReplNotFound(id))
+}
+
+/// Starts a session with a fresh namespace
+pub fn start_repl() -> Result<ReplId, ScriptError> {
+    init_python();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let console = Python::with_gil(|py| {
+        new_console(py, id).map_err(|err| ScriptError::ReplFailed(format_py_err(py, &err)))
+    })?;
+    let session = Arc::new(ReplSession {
+        id,
+        console,
+        output: Arc::new(ReplOutput::default()),
+        input: Mutex::new(VecDeque::new()),
+        input_signal: Condvar::new(),
+        busy: AtomicBool::new(true),
+        running: AtomicBool::new(false),
+        more: AtomicBool::new(false),
+        stopped: AtomicBool::new(false),
+        py_thread: AtomicU64::new(0),
+    });
+    let thread_session = session.clone();
+    std::thread::Builder::new()
+        .name(session.name())
+        .spawn(move || run_session(thread_session))
+        .map_err(|err| ScriptError::ReplFailed(err.to_string()))?;
+    SESSIONS.lock().insert(id, session);
+    tracing::info!("Started python REPL {}", id);
+    Ok(id)
+}
+
+fn run_session(session: Arc<ReplSession>) {
+    // the console may do whatever a plain script may
+    set_thread_grant(Some(ThreadGrant::App));
+    // the thread keeps one thread state for the whole session, the console's
+    // event loop and where it prints to are kept in it
+    Python::with_gil(|py| {
+        if let Err(err) = attach(py, &session) {
+            let err = format_py_err(py, &err);
+            tracing::error!("Failed to start python REPL {}: {}", session.id, err);
+            session.output.stderr.lock().push_str(&err);
+            session.stop();
+        }
+        session.busy.store(false, Ordering::Relaxed);
+
+        while let Some(line) = py.allow_threads(|| session.next_line()) {
+            // only changes with the gil held so an interrupt can't go off in the next line
+            session.running.store(true, Ordering::Relaxed);
+            let pushed = session
+                .console
+                .call_method1(py, "push", (line,))
+                .and_then(|more| more.is_true(py));
+            session.running.store(false, Ordering::Relaxed);
+            match pushed {
+                Ok(more) => session.more.store(more, Ordering::Relaxed),
+                Err(err) if err.is_instance_of::<PySystemExit>(py) => session.stop(),
+                // e.g. an interrupt that went off outside the code that was run
+                Err(err) => {
+                    session
+                        .output
+                        .stderr
+                        .lock()
+                        .push_str(&format!("{}\n", format_py_err(py, &err)));
+                    let _ = session.console.call_method0(py, "resetbuffer");
+                    session.more.store(false, Ordering::Relaxed);
+                }
+            }
+            session.busy.store(false, Ordering::Relaxed);
+        }
+
+        if let Err(err) = session.console.call_method0(py, "close") {
+            tracing::warn!(
+                "Failed to close python REPL {}: {}",
+                session.id,
+                format_py_err(py, &err)
+            );
+        }
+    });
+    tracing::info!("Python REPL {} stopped", session.id);
+}
+
+fn attach(py: Python, session: &ReplSession) -> PyResult<()> {
+    let ident = py.import("threading")?.call_method0("get_ident")?;
+    session.py_thread.store(ident.extract()?, Ordering::Relaxed);
+    let out = Py::new(py, ReplWriter::new(session, false))?;
+    let err = Py::new(py, ReplWriter::new(session, true))?;
+    session.console.call_method1(py, "attach", (out, err))?;
+    Ok(())
+}
+
+/// Queues input for the session, every line is pushed to the console on its own
+pub fn write_repl(id: ReplId, input: &str) -> Result<(), ScriptError> {
+    let session = session(id)?;
+    if session.stopped.load(Ordering::Relaxed) {
+        return Err(ScriptError::ReplStopped(id));
+    }
+    let mut queued = session.input.lock();
+    queued.extend(input.lines().map(str::to_string));
+    if input.is_empty() {
+        queued.push_back(String::new());
+    }
+    session.input_signal.notify_all();
+    Ok(())
+}
+
+/// Takes what the session printed to stdout since the last read
+pub fn read_out_repl(id: ReplId) -> Result<String, ScriptError> {
+    Ok(std::mem::take(&mut *session(id)?.output.stdout.lock()))
+}
+
+/// Takes what the session printed to stderr since the last read, tracebacks included
+pub fn read_err_repl(id: ReplId) -> Result<String, ScriptError> {
+    Ok(std::mem::take(&mut *session(id)?.output.stderr.lock()))
+}
+
+pub fn repl_status(id: ReplId) -> Result<ReplStatus, ScriptError> {
+    Ok(session(id)?.status())
+}
+
+/// `line` completed with every name in the session's namespace its last word could be
+pub fn complete_repl(id: ReplId, line: &str) -> Result<Vec<String>, ScriptError> {
+    let session = session(id)?;
+    Python::with_gil(|py| {
+        session
+            .console
+            .call_method1(py, "complete", (line,))
+            .and_then(|completions| completions.extract(py))
+            .map_err(|err| ScriptError::ReplFailed(format_py_err(py, &err)))
+    })
+}
+
+/// Raises `KeyboardInterrupt` in what the session is running and drops queued input,
+/// an unfinished block is discarded like at a python prompt
+pub fn interrupt_repl(id: ReplId) -> Result<(), ScriptError> {
+    let session = session(id)?;
+    session.input.lock().clear();
+    Python::with_gil(|py| {
+        if !session.interrupt(py) && session.more.swap(false, Ordering::Relaxed) {
+            let _ = session.console.call_method0(py, "resetbuffer");
+            session.output.stderr.lock().push_str("KeyboardInterrupt\n");
+        }
+    });
+    Ok(())
+}
+
+/// Interrupts what the session is running, stops it and forgets it
+pub fn stop_repl(id: ReplId) -> Result<(), ScriptError> {
+    let session = SESSIONS
+        .lock()
+        .remove(&id)
+        .ok_or(ScriptError::ReplNotFound(id))?;
+    session.stop();
+    Python::with_gil(|py| session.interrupt(py));
+    Ok(())
+}
+
+/// Stops every session, used on exit
+pub fn stop_all_repls() {
+    let sessions: Vec<_> = SESSIONS
+        .lock()
+        .drain()
+        .map(|(_, session)| session)
+        .collect();
+    for session in sessions {
+        session.stop();
+        Python::with_gil(|py| session.interrupt(py));
+    }
+}
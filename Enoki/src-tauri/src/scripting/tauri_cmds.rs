@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::error::{log_result, EnokiError};
+use crate::frontend_helpers::encoding::{EncodedObject, IpcEncoding};
+
+use super::plugins::{self, PluginStatus};
+use super::registry::{self, ScriptRegistry};
+use super::repl::{self, ReplId, ReplStatus};
+use super::{scripts_dir, ScriptError, ScriptOptions, ScriptStatus};
+
+#[tauri::command]
+pub fn list_scripts() -> Result<Vec<ScriptStatus>, EnokiError> {
+    log_result(super::script_statuses())
+}
+
+/// Starts a script with the default timeout and restart policy unless options are given
+#[tauri::command]
+pub fn start_script(name: String, options: Option<ScriptOptions>) -> Result<(), EnokiError> {
+    log_result(super::start_script(&name, options.unwrap_or_default()))
+}
+
+/// Returns once the script was asked to stop, it may still be running its `on_stop` hook
+#[tauri::command]
+pub fn stop_script(name: String) -> Result<(), EnokiError> {
+    log_result(super::stop_script(&name))
+}
+
+#[tauri::command]
+pub fn script_status(name: String) -> Result<ScriptStatus, EnokiError> {
+    super::script_status(&name)
+}
+
+/// Where scripts are loaded from so the frontend can open it
+#[tauri::command]
+pub fn scripts_directory() -> Result<PathBuf, EnokiError> {
+    Ok(scripts_dir().ok_or(ScriptError::NoScriptsDirectory)?)
+}
+
+#[tauri::command]
+pub fn list_plugins() -> Result<Vec<PluginStatus>, EnokiError> {
+    log_result(plugins::plugin_statuses())
+}
+
+/// Starts a plugin that was stopped or failed, with its current files
+#[tauri::command]
+pub fn start_plugin(name: String) -> Result<(), EnokiError> {
+    log_result(plugins::start_plugin(&name))
+}
+
+/// Stops a plugin until it's started again, changing its files won't restart it
+#[tauri::command]
+pub fn stop_plugin(name: String) -> Result<(), EnokiError> {
+    log_result(plugins::stop_plugin(&name))
+}
+
+/// Stops the plugin if it's running and starts it again with its current files
+#[tauri::command]
+pub fn reload_plugin(name: String) -> Result<(), EnokiError> {
+    log_result(plugins::reload_plugin(&name))
+}
+
+/// Where plugins are loaded from so the frontend can open it
+#[tauri::command]
+pub fn plugins_directory() -> Result<PathBuf, EnokiError> {
+    Ok(plugins::plugins_dir().ok_or(ScriptError::NoPluginsDirectory)?)
+}
+
+/// The commands and data providers running scripts registered
+#[tauri::command]
+pub fn list_script_registry() -> ScriptRegistry {
+    registry::script_registry()
+}
+
+/// Runs a command on its script's worker, `args` are checked against the command's schema
+#[tauri::command]
+pub async fn call_script_command(name: String, args: Option<Value>) -> Result<Value, EnokiError> {
+    let called = name.clone();
+    tokio::task::spawn_blocking(move || log_result(registry::call_command(&called, args)))
+        .await
+        .map_err(|err| ScriptError::CallFailed {
+            name,
+            error: err.to_string(),
+        })?
+}
+
+/// Calls a data provider on its script's worker
+#[tauri::command]
+pub async fn read_data_provider(
+    name: String,
+    encoding: Option<IpcEncoding>,
+) -> Result<EncodedObject, EnokiError> {
+    let called = name.clone();
+    let object = tokio::task::spawn_blocking(move || log_result(registry::read_provider(&called)))
+        .await
+        .map_err(|err| ScriptError::CallFailed {
+            name,
+            error: err.to_string(),
+        })??;
+    log_result(EncodedObject::encode(object, encoding))
+}
+
+/// Starts a python REPL session with `enoki_core`, `enoki` and the running clients bound
+#[tauri::command]
+pub fn start_repl() -> Result<ReplId, EnokiError> {
+    Ok(log_result(repl::start_repl())?)
+}
+
+/// Queues input for the session, a block of code ends with an empty line
+#[tauri::command]
+pub fn write_repl(id: ReplId, input: String) -> Result<(), EnokiError> {
+    Ok(repl::write_repl(id, &input)?)
+}
+
+#[tauri::command]
+pub fn read_out_repl(id: ReplId) -> Result<String, EnokiError> {
+    Ok(repl::read_out_repl(id)?)
+}
+
+#[tauri::command]
+pub fn read_err_repl(id: ReplId) -> Result<String, EnokiError> {
+    Ok(repl::read_err_repl(id)?)
+}
+
+#[tauri::command]
+pub fn repl_status(id: ReplId) -> Result<ReplStatus, EnokiError> {
+    Ok(repl::repl_status(id)?)
+}
+
+/// The input line completed with every name its last word could be
+#[tauri::command]
+pub fn complete_repl(id: ReplId, line: String) -> Result<Vec<String>, EnokiError> {
+    Ok(repl::complete_repl(id, &line)?)
+}
+
+/// Raises `KeyboardInterrupt` in what the session is running, like ctrl+c at a python prompt
+#[tauri::command]
+pub fn interrupt_repl(id: ReplId) -> Result<(), EnokiError> {
+    Ok(repl::interrupt_repl(id)?)
+}
+
+#[tauri::command]
+pub fn stop_repl(id: ReplId) -> Result<(), EnokiError> {
+    Ok(log_result(repl::stop_repl(id))?)
+}
@@ -0,0 +1,561 @@
+use std::{
+    cell::RefCell,
+    fmt, fs,
+    os::raw::c_long,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex};
+use pyo3::{
+    ffi,
+    prelude::*,
+    types::{IntoPyDict, PyTuple},
+};
+
+use crate::python_helpers::logging::{format_py_err, tracing_log_exception};
+
+use super::plugins::{set_thread_grant, Grant, ThreadGrant};
+use super::{RestartPolicy, ScriptOptions, ScriptState, SCRIPTS, TICK_PERIOD};
+
+/// How often the watchdog looks for hooks that ran too long
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(10);
+/// How long a hook may keep running after the script was asked to stop
+const STOP_GRACE: Duration = Duration::from_millis(500);
+/// A run that lasted this long resets the restart count
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+
+static WATCHDOG: Lazy<()> = Lazy::new(|| {
+    std::thread::Builder::new()
+        .name("ScriptWatchdog".to_string())
+        .spawn(watchdog_loop)
+        .expect("Failed to start the script watchdog");
+});
+
+thread_local! {
+    /// The name and control of the script whose worker this thread is
+    static CURRENT_SCRIPT: RefCell<Option<(String, Arc<ScriptControl>)>> = const { RefCell::new(None) };
+}
+
+/// Undoes something a script set up that would otherwise outlive it
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Cleanups(Mutex<Vec<Cleanup>>);
+
+impl fmt::Debug for Cleanups {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cleanups({})", self.0.lock().len())
+    }
+}
+
+/// Shared by a script's worker thread, the watchdog and whoever stops the script
+#[derive(Debug, Default)]
+pub(super) struct ScriptControl {
+    /// When the script was asked to stop
+    stop: Mutex<Option<Instant>>,
+    stop_signal: Condvar,
+    /// `threading.get_ident()` of the worker
+    py_thread: AtomicU64,
+    /// The hook the worker is in and when it was called
+    busy: Mutex<Option<(&'static str, Instant)>>,
+    /// A KeyboardInterrupt was injected into the current hook
+    interrupted: AtomicBool,
+    /// The hook the watchdog interrupted for taking too long, for the error it fails with
+    interrupted_hook: Mutex<Option<&'static str>>,
+    /// The worker was given up on, nothing it does is recorded anymore
+    abandoned: AtomicBool,
+    /// Run when the current run of the script ends
+    cleanups: Cleanups,
+    /// The asyncio loop of the current run, other threads schedule calls into the script on it
+    event_loop: Mutex<Option<PyObject>>,
+    /// The script registered something to call, so it keeps running its loop until it's stopped
+    serving: AtomicBool,
+}
+
+impl ScriptControl {
+    pub(super) fn request_stop(&self) {
+        let mut stop = self.stop.lock();
+        stop.get_or_insert_with(Instant::now);
+        self.stop_signal.notify_all();
+    }
+
+    pub(super) fn stop_requested(&self) -> bool {
+        self.stop.lock().is_some()
+    }
+
+    /// Sleeps for `duration` unless a stop is requested first, returns whether one was
+    fn wait_for_stop(&self, duration: Duration) -> bool {
+        let mut stop = self.stop.lock();
+        if stop.is_none() {
+            self.stop_signal.wait_for(&mut stop, duration);
+        }
+        stop.is_some()
+    }
+
+    /// When the watchdog should step in for the hook being run
+    fn deadline(&self, timeout: Duration) -> Option<(&'static str, Instant)> {
+        let (hook, started) = (*self.busy.lock())?;
+        let mut deadline = started + timeout;
+        if let Some(stop) = *self.stop.lock() {
+            if hook != "on_stop" {
+                deadline = deadline.min(stop.max(started) + STOP_GRACE);
+            }
+        }
+        Some((hook, deadline))
+    }
+
+    /// The loop of the current run, none once it's closed
+    pub(super) fn event_loop(&self) -> Option<PyObject> {
+        self.event_loop.lock().clone()
+    }
+
+    /// Keeps the script and its loop running after `on_start`, even without `on_tick` or `main`
+    pub(super) fn keep_serving(&self) {
+        self.serving.store(true, Ordering::Relaxed);
+    }
+
+    fn serving(&self) -> bool {
+        self.serving.load(Ordering::Relaxed)
+    }
+
+    fn run_cleanups(&self) {
+        let cleanups = std::mem::take(&mut *self.cleanups.0.lock());
+        for cleanup in cleanups {
+            cleanup();
+        }
+    }
+}
+
+/// Registers `cleanup` with the script whose worker is calling, false if this isn't a script's worker
+pub(super) fn on_script_stop(cleanup: Cleanup) -> bool {
+    CURRENT_SCRIPT.with(|current| match current.borrow().as_ref() {
+        Some((_, control)) => {
+            control.cleanups.0.lock().push(cleanup);
+            true
+        }
+        None => false,
+    })
+}
+
+/// The name and control of the script whose worker is calling
+pub(super) fn current_script() -> Option<(String, Arc<ScriptControl>)> {
+    CURRENT_SCRIPT.with(|current| current.borrow().clone())
+}
+
+/// The hooks a script defined
+struct ScriptHooks {
+    on_start: Option<PyObject>,
+    on_tick: Option<PyObject>,
+    on_stop: Option<PyObject>,
+    main: Option<PyObject>,
+}
+
+/// Runs a script until it stops, restarting it when its policy says to.
+/// Returns the state it ended in, none if it was abandoned by the watchdog.
+pub(super) fn run_worker(
+    name: &str,
+    path: &Path,
+    control: &ScriptControl,
+    options: ScriptOptions,
+) -> Option<ScriptState> {
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        let state = run_script(name, path, control);
+        if control.abandoned.load(Ordering::Relaxed) {
+            return None;
+        }
+        let error = match state {
+            ScriptState::Failed { error } if !control.stop_requested() => error,
+            state => return Some(state),
+        };
+
+        if started.elapsed() >= HEALTHY_RUN {
+            restarts = 0;
+        }
+        let delay = match options.restart {
+            RestartPolicy::OnFailure {
+                max_restarts,
+                delay_ms,
+            } if restarts < max_restarts => restart_delay(delay_ms, restarts),
+            _ => return Some(ScriptState::Failed { error }),
+        };
+        restarts += 1;
+        tracing::warn!(
+            "Restarting script {} in {:?}, restart {}",
+            name,
+            delay,
+            restarts
+        );
+        record(name, control, ScriptState::Restarting { error, restarts });
+        if control.wait_for_stop(delay) {
+            return Some(ScriptState::Stopped);
+        }
+        record(name, control, ScriptState::Running);
+    }
+}
+
+/// Doubles with every restart in a row
+fn restart_delay(delay_ms: u64, restarts: u32) -> Duration {
+    Duration::from_millis(delay_ms.saturating_mul(1 << restarts.min(16))).min(MAX_RESTART_DELAY)
+}
+
+/// Updates the state of a script unless its worker was abandoned
+pub(super) fn record(name: &str, control: &ScriptControl, state: ScriptState) {
+    if let Some(handle) = SCRIPTS.lock().get_mut(name) {
+        if !control.abandoned.load(Ordering::Relaxed) {
+            handle.state = state;
+        }
+    }
+}
+
+fn run_script(name: &str, path: &Path, control: &ScriptControl) -> ScriptState {
+    // namespaced so a script named like a module it imports doesn't replace it
+    let module_name = format!("enoki_scripts.{}", name);
+    control.interrupted_hook.lock().take();
+    let event_loop = match Python::with_gil(new_event_loop) {
+        Ok(event_loop) => event_loop,
+        Err(err) => return failed(name, control, err),
+    };
+    control.serving.store(false, Ordering::Relaxed);
+    *control.event_loop.lock() = Some(event_loop.clone());
+    let hooks = match load(path, &module_name, control) {
+        Ok(hooks) => hooks,
+        Err(err) => {
+            close_loop(&event_loop, control);
+            return failed(name, control, err);
+        }
+    };
+
+    let mut result = call(&event_loop, &hooks.on_start, "on_start", control);
+    if result.is_ok() && (hooks.on_tick.is_some() || hooks.main.is_some() || control.serving()) {
+        result = tick_loop(&hooks, &event_loop, control);
+    }
+    // tasks are cancelled before on_stop so they don't run into what it tears down
+    result = result.and(cancel_tasks(&event_loop, control));
+    // an interrupt from a stop request isn't a failure
+    if result.is_err() && control.stop_requested() {
+        result = Ok(());
+    }
+    let result = result.and(call(&event_loop, &hooks.on_stop, "on_stop", control));
+    close_loop(&event_loop, control);
+    control.run_cleanups();
+
+    // the next start loads the file fresh instead of reusing this module
+    Python::with_gil(|py| {
+        let _ = py
+            .import("sys")
+            .and_then(|sys| sys.getattr("modules")?.del_item(&module_name));
+    });
+
+    match result {
+        Ok(()) => {
+            tracing::info!("Script {} stopped", name);
+            ScriptState::Stopped
+        }
+        Err(err) => failed(name, control, err),
+    }
+}
+
+/// Runs the script's top level and picks up its hooks
+fn load(path: &Path, module_name: &str, control: &ScriptControl) -> PyResult<ScriptHooks> {
+    let code = fs::read_to_string(path)?;
+    let file_name = path.to_string_lossy();
+    with_timeout(control, "<module>", |py| {
+        let ident = py.import("threading")?.call_method0("get_ident")?;
+        control.py_thread.store(ident.extract()?, Ordering::Relaxed);
+
+        let module = PyModule::from_code(py, &code, &file_name, module_name)?;
+        let hook = |name: &str| -> PyResult<Option<PyObject>> {
+            match module.getattr(name) {
+                Ok(hook) if hook.is_callable() => Ok(Some(hook.into())),
+                Ok(_) => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                    "{} is not callable",
+                    name
+                ))),
+                Err(_) => Ok(None),
+            }
+        };
+        Ok(ScriptHooks {
+            on_start: hook("on_start")?,
+            on_tick: hook("on_tick")?,
+            on_stop: hook("on_stop")?,
+            main: hook("main")?,
+        })
+    })
+}
+
+/// The loop async hooks run on, set as the worker's current loop
+fn new_event_loop(py: Python) -> PyResult<PyObject> {
+    let asyncio = py.import("asyncio")?;
+    let event_loop = asyncio.call_method0("new_event_loop")?;
+    asyncio.call_method1("set_event_loop", (event_loop,))?;
+    Ok(event_loop.into())
+}
+
+/// Calls a hook, an async one runs on the script's loop until it's done
+fn call(
+    event_loop: &PyObject,
+    hook: &Option<PyObject>,
+    name: &'static str,
+    control: &ScriptControl,
+) -> PyResult<()> {
+    match hook {
+        Some(hook) => with_timeout(control, name, |py| {
+            let result = hook.call0(py)?;
+            if py
+                .import("asyncio")?
+                .call_method1("iscoroutine", (&result,))?
+                .is_true()?
+            {
+                event_loop.call_method1(py, "run_until_complete", (result,))?;
+            }
+            Ok(())
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Runs `f` with the gil while the watchdog makes sure it doesn't take too long
+fn with_timeout<T>(
+    control: &ScriptControl,
+    hook: &'static str,
+    f: impl FnOnce(Python) -> PyResult<T>,
+) -> PyResult<T> {
+    Python::with_gil(|py| {
+        *control.busy.lock() = Some((hook, Instant::now()));
+        let result = f(py);
+        *control.busy.lock() = None;
+        // an interrupt that arrived as the hook returned would go off in whatever runs next
+        if control.interrupted.swap(false, Ordering::Relaxed) {
+            // it went off too late to fail the hook
+            if result.is_ok() {
+                let mut interrupted_hook = control.interrupted_hook.lock();
+                if *interrupted_hook == Some(hook) {
+                    *interrupted_hook = None;
+                }
+            }
+            let thread = control.py_thread.load(Ordering::Relaxed) as c_long;
+            unsafe {
+                ffi::PyThreadState_SetAsyncExc(thread, std::ptr::null_mut());
+            }
+        }
+        result
+    })
+}
+
+fn tick_loop(hooks: &ScriptHooks, event_loop: &PyObject, control: &ScriptControl) -> PyResult<()> {
+    let mut main = match &hooks.main {
+        Some(main) => Some(with_timeout(control, "main", |py| {
+            event_loop.call_method1(py, "create_task", (main.call0(py)?,))
+        })?),
+        None => None,
+    };
+    let mut next_tick = Instant::now();
+    while !control.stop_requested() {
+        if let Some(task) = &main {
+            if Python::with_gil(|py| task.call_method0(py, "done")?.is_true(py))? {
+                // raises what main raised
+                Python::with_gil(|py| task.call_method0(py, "result").map(drop))?;
+                if hooks.on_tick.is_none() && !control.serving() {
+                    return Ok(());
+                }
+                main = None;
+            }
+        }
+        call(event_loop, &hooks.on_tick, "on_tick", control)?;
+        next_tick += TICK_PERIOD;
+        let current = Instant::now();
+        if next_tick > current {
+            run_loop(event_loop, next_tick - current, control)?;
+        } else {
+            // a slow tick doesn't cause a burst of ticks to catch up
+            next_tick = current;
+        }
+    }
+    Ok(())
+}
+
+/// Lets the script's tasks and calls into it run for `duration`, or sleeps if it has neither
+fn run_loop(event_loop: &PyObject, duration: Duration, control: &ScriptControl) -> PyResult<()> {
+    if !control.serving() && Python::with_gil(|py| pending_tasks(py, event_loop))?.is_empty() {
+        control.wait_for_stop(duration);
+        return Ok(());
+    }
+    with_timeout(control, "<event loop>", |py| {
+        let sleep = py
+            .import("asyncio")?
+            .call_method1("sleep", (duration.as_secs_f64(),))?;
+        event_loop
+            .call_method1(py, "run_until_complete", (sleep,))
+            .map(drop)
+    })
+}
+
+fn pending_tasks(py: Python, event_loop: &PyObject) -> PyResult<Vec<PyObject>> {
+    py.import("asyncio")?
+        .call_method1("all_tasks", (event_loop,))?
+        .iter()?
+        .map(|task| task.map(Into::into))
+        .collect()
+}
+
+/// Cancels the script's tasks and lets them finish, logging what they raised other than the cancel
+fn cancel_tasks(event_loop: &PyObject, control: &ScriptControl) -> PyResult<()> {
+    let tasks = Python::with_gil(|py| pending_tasks(py, event_loop))?;
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    with_timeout(control, "<event loop>", |py| {
+        let asyncio = py.import("asyncio")?;
+        for task in &tasks {
+            task.call_method0(py, "cancel")?;
+        }
+        let kwargs = [("return_exceptions", true)].into_py_dict(py);
+        let gathered = asyncio
+            .getattr("gather")?
+            .call(PyTuple::new(py, &tasks), Some(kwargs))?;
+        let results = event_loop.call_method1(py, "run_until_complete", (gathered,))?;
+        let cancelled = asyncio.getattr("CancelledError")?;
+        for result in results.as_ref(py).iter()? {
+            let result = result?;
+            if result.is_instance_of::<pyo3::exceptions::PyBaseException>()
+                && !result.is_instance(cancelled)?
+            {
+                tracing_log_exception(py, &PyErr::from_value(result))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Finishes the script's async generators and closes its loop
+fn close_loop(event_loop: &PyObject, control: &ScriptControl) {
+    control.event_loop.lock().take();
+    let closed = with_timeout(control, "<event loop>", |py| {
+        let shutdown = event_loop.call_method0(py, "shutdown_asyncgens")?;
+        event_loop.call_method1(py, "run_until_complete", (shutdown,))?;
+        event_loop.call_method0(py, "close")?;
+        py.import("asyncio")?
+            .call_method1("set_event_loop", (py.None(),))
+            .map(drop)
+    });
+    if let Err(err) = closed {
+        Python::with_gil(|py| {
+            tracing::warn!(
+                "Failed to close a script's event loop: {}",
+                format_py_err(py, &err)
+            )
+        });
+    }
+}
+
+fn failed(name: &str, control: &ScriptControl, err: PyErr) -> ScriptState {
+    let error = Python::with_gil(|py| {
+        if let Err(log_err) = tracing_log_exception(py, &err) {
+            tracing::error!("Script {} failed: {}", name, format_py_err(py, &log_err));
+        }
+        format_py_err(py, &err)
+    });
+    // the traceback of an interrupted hook doesn't say why it was interrupted
+    let error = match control.interrupted_hook.lock().take() {
+        Some(hook) => format!("{} took too long and was interrupted\n{}", hook, error),
+        None => error,
+    };
+    ScriptState::Failed { error }
+}
+
+fn watchdog_loop() {
+    loop {
+        std::thread::sleep(WATCHDOG_PERIOD);
+        let now = Instant::now();
+        let mut overdue = Vec::new();
+        let mut abandoned = Vec::new();
+        for (name, handle) in SCRIPTS.lock().iter_mut() {
+            let timeout = Duration::from_millis(handle.options.timeout_ms);
+            let (hook, deadline) = match handle.control.deadline(timeout) {
+                Some(deadline) => deadline,
+                None => continue,
+            };
+            if now < deadline {
+                continue;
+            }
+            if !handle.control.interrupted.load(Ordering::Relaxed) {
+                overdue.push((name.clone(), hook, handle.control.clone()));
+            } else if now >= deadline + timeout && !handle.control.abandoned.load(Ordering::Relaxed)
+            {
+                // an interrupt only goes off once the script runs python again,
+                // one stuck in a blocking call can't be stopped so its thread is left behind
+                tracing::error!("Script {} is stuck in {}, giving up on it", name, hook);
+                handle.control.abandoned.store(true, Ordering::Relaxed);
+                handle.control.request_stop();
+                handle.state = ScriptState::Failed {
+                    error: format!("{} is stuck and didn't respond to being interrupted", hook),
+                };
+                abandoned.push(handle.control.clone());
+            }
+        }
+        // the worker won't get to its cleanups, they run here instead
+        for control in abandoned {
+            control.run_cleanups();
+        }
+        if overdue.is_empty() {
+            continue;
+        }
+
+        // the script lock isn't held here as the worker may need it to give up the gil
+        Python::with_gil(|_py| {
+            for (name, hook, control) in overdue {
+                // checked again with the gil held, the hook may have returned in the meantime
+                match *control.busy.lock() {
+                    Some((busy, _)) if busy == hook => {}
+                    _ => continue,
+                }
+                if control.interrupted.swap(true, Ordering::Relaxed) {
+                    continue;
+                }
+                if !control.stop_requested() {
+                    *control.interrupted_hook.lock() = Some(hook);
+                }
+                tracing::warn!("Interrupting {} of script {}", hook, name);
+                let thread = control.py_thread.load(Ordering::Relaxed) as c_long;
+                unsafe {
+                    ffi::PyThreadState_SetAsyncExc(thread, ffi::PyExc_KeyboardInterrupt);
+                }
+            }
+        });
+    }
+}
+
+/// Starts the watchdog if it isn't running yet
+pub(super) fn start_watchdog() {
+    Lazy::force(&WATCHDOG);
+}
+
+pub(super) fn spawn_worker(
+    name: String,
+    path: PathBuf,
+    control: Arc<ScriptControl>,
+    options: ScriptOptions,
+    grant: Option<Arc<Grant>>,
+) -> std::io::Result<()> {
+    start_watchdog();
+    std::thread::Builder::new()
+        .name(format!("Script-{}", name))
+        .spawn(move || {
+            CURRENT_SCRIPT
+                .with(|current| *current.borrow_mut() = Some((name.clone(), control.clone())));
+            set_thread_grant(Some(grant.map_or(ThreadGrant::App, ThreadGrant::Plugin)));
+            if let Some(state) = run_worker(&name, &path, &control, options) {
+                record(&name, &control, state);
+            }
+        })
+        .map(drop)
+}
@@ -1,6 +1,2072 @@
+use std::io::Write;
 
+use crate::datalog::follow::WpilogFollower;
+use crate::datalog::summary::summarize_history;
+use crate::enoki_types::{EnokiValue, EnokiValueError, TimestampedEnokiValue};
 
 
 #[test]
 fn test_test() {
-}
\ No newline at end of file
+}
+
+fn wpilog_record(entry: u8, timestamp: u8, payload: &[u8]) -> Vec<u8> {
+    // 1 byte entry id, 1 byte payload size, 1 byte timestamp
+    let mut record = vec![0u8, entry, payload.len() as u8, timestamp];
+    record.extend_from_slice(payload);
+    record
+}
+
+#[test]
+fn test_follow_partial_records() {
+    let path = std::env::temp_dir().join(format!("enoki-follow-{}.wpilog", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+
+    let mut start = vec![0u8];
+    start.extend_from_slice(&1u32.to_le_bytes());
+    for s in ["/x", "double", ""] {
+        start.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        start.extend_from_slice(s.as_bytes());
+    }
+    let mut bytes = b"WPILOG\x00\x01\x00\x00\x00\x00".to_vec();
+    bytes.extend(wpilog_record(0, 1, &start));
+    let value = wpilog_record(1, 2, &2.5f64.to_le_bytes());
+
+    let mut follower = WpilogFollower::open(&path).unwrap();
+
+    file.write_all(&bytes).unwrap();
+    file.write_all(&value[..5]).unwrap();
+    file.flush().unwrap();
+    assert!(follower.poll().unwrap().is_empty());
+    // read even though nothing could be decoded from it, the writer is still busy
+    assert_eq!(follower.offset(), (bytes.len() + 5) as u64);
+
+    file.write_all(&value[5..]).unwrap();
+    let mut finish = vec![1u8];
+    finish.extend_from_slice(&1u32.to_le_bytes());
+    file.write_all(&wpilog_record(0, 3, &finish)).unwrap();
+    file.flush().unwrap();
+
+    let fields = follower.poll().unwrap();
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].get_value().value, EnokiValue::Double(2.5));
+    assert_eq!(fields[0].get_timestamp(), 2);
+    assert!(follower.all_entries_finished());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_summary_rates_and_gaps() {
+    let history: Vec<TimestampedEnokiValue> = [0, 20_000, 30_000, 250_000]
+        .iter()
+        .enumerate()
+        .map(|(i, t)| TimestampedEnokiValue::new(*t, EnokiValue::Double(i as f64)))
+        .collect();
+    let summary = summarize_history("/x".to_string(), &history.into(), 100_000);
+
+    assert_eq!(summary.samples, 4);
+    assert_eq!(summary.value_type, "Double");
+    assert_eq!(summary.max_rate, 100.0);
+    assert!((summary.average_rate - 12.0).abs() < 1e-9);
+    assert_eq!(summary.gaps.len(), 1);
+    assert_eq!((summary.gaps[0].start, summary.gaps[0].end), (30_000, 250_000));
+    let numeric = summary.numeric.unwrap();
+    assert_eq!((numeric.min, numeric.max, numeric.mean), (0.0, 3.0, 1.5));
+}
+
+#[test]
+fn test_rmpv_conversion() {
+    use network_tables::v4::Type;
+
+    let quoted = rmpv::Value::String("say \"hi\"".into());
+    assert_eq!(
+        EnokiValue::try_from(quoted),
+        Ok(EnokiValue::String("say \"hi\"".to_string()))
+    );
+
+    assert_eq!(
+        EnokiValue::from_rmpv(rmpv::Value::Array(vec![]), Some(&Type::StringArray)),
+        Ok(EnokiValue::StringArray(vec![]))
+    );
+    assert_eq!(
+        EnokiValue::try_from(rmpv::Value::Array(vec![])),
+        Err(EnokiValueError::AmbiguousEmptyArray)
+    );
+
+    let mixed = rmpv::Value::Array(vec![rmpv::Value::from(1), rmpv::Value::F64(2.5)]);
+    assert_eq!(
+        EnokiValue::try_from(mixed.clone()),
+        Ok(EnokiValue::DoubleArray(vec![1.0, 2.5]))
+    );
+    assert!(EnokiValue::from_rmpv(mixed, Some(&Type::IntArray)).is_err());
+
+    let heterogeneous = rmpv::Value::Array(vec![rmpv::Value::from(1), rmpv::Value::from("a")]);
+    assert!(matches!(
+        EnokiValue::try_from(heterogeneous),
+        Err(EnokiValueError::HeterogeneousArray(_))
+    ));
+    assert!(EnokiValue::try_from(rmpv::Value::Nil).is_err());
+    assert!(EnokiValue::try_from(rmpv::Value::Map(vec![])).is_err());
+
+    assert_eq!(
+        EnokiValue::from_rmpv(rmpv::Value::from(3), Some(&Type::Double)),
+        Ok(EnokiValue::Double(3.0))
+    );
+}
+
+#[test]
+fn test_key_paths() {
+    use crate::enoki_types::EnokiKey;
+
+    let key = EnokiKey::from("SmartDashboard//x/");
+    assert_eq!(key.segments(), &["SmartDashboard".to_string(), "x".to_string()]);
+    assert_eq!(String::from(&key), "/SmartDashboard/x");
+    assert_eq!(String::from(EnokiKey::root()), "/");
+
+    let escaped = EnokiKey::root().child("a:b/c");
+    assert_eq!(String::from(&escaped), "/a:b\\/c");
+    assert_eq!(EnokiKey::from(String::from(&escaped)), escaped);
+
+    // topic names are never escaped
+    let topic = EnokiKey::from_topic_name("/Vision/a*b\\c");
+    assert_eq!(topic.name(), Some("a*b\\c"));
+    assert_eq!(String::from(&topic), "/Vision/a\\*b\\\\c");
+    assert_eq!(topic.topic_name(), "/Vision/a*b\\c");
+    assert_eq!(EnokiKey::from("/a\\*b").topic_name(), "/a*b");
+    assert_eq!(EnokiKey::from_topic_name("FMSInfo/IsRed").topic_name(), "/FMSInfo/IsRed");
+
+    let left = EnokiKey::from("/Drive/Left");
+    let right = EnokiKey::from("/Drive/Right/velocity");
+    assert_eq!(right.relative_to(&left), "../Right/velocity");
+    assert_eq!(left.resolve("../Right/velocity"), right);
+    assert_eq!(
+        right.strip_prefix(&EnokiKey::from("/Drive")),
+        Some(EnokiKey::from("Right/velocity"))
+    );
+
+    assert!(right.matches("/Drive/*/velocity"));
+    assert!(right.matches("/**/velocity"));
+    assert!(right.matches("/Dr?ve/R*t/**"));
+    assert!(!right.matches("/*/velocity"));
+    assert!(EnokiKey::from("/temp").matches("/**/temp"));
+}
+
+#[test]
+fn test_object_query() {
+    use crate::enoki_types::{EnokiField, EnokiObject, EnokiQuery, EnokiValueKind};
+
+    let mut obj = EnokiObject::new(300);
+    for (key, value) in [
+        ("/Drive/Left/temp", EnokiValue::Double(40.0)),
+        ("/Drive/Right/temp", EnokiValue::Double(42.0)),
+        ("/Vision/targets", EnokiValue::IntArray(vec![1, 2])),
+        ("/Vision/name", EnokiValue::String("limelight".to_string())),
+    ] {
+        obj.add_field(EnokiField::new(key.into(), TimestampedEnokiValue::new(100, value)));
+    }
+    let history = [100, 200, 300]
+        .iter()
+        .map(|t| TimestampedEnokiValue::new(*t, EnokiValue::Int(*t as i64)))
+        .collect::<Vec<_>>();
+    obj.set_history(&"/Vision/latency".into(), history);
+
+    assert_eq!(obj.select("/Drive/*/temp").len(), 2);
+    assert_eq!(obj.select("/Vision/**").len(), 3);
+    assert_eq!(obj.numeric_fields().len(), 4);
+    assert_eq!(obj.array_fields().len(), 1);
+
+    let recent = obj.changed_since(200);
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent.get_history(&"/Vision/latency".into()).unwrap().len(), 2);
+
+    let query = EnokiQuery {
+        regex: Some("^/Vision/".to_string()),
+        kinds: vec![EnokiValueKind::Single],
+        ..Default::default()
+    };
+    assert_eq!(query.apply(&obj).unwrap().len(), 2);
+    let invalid = EnokiQuery {
+        regex: Some("(".to_string()),
+        ..Default::default()
+    };
+    assert!(invalid.apply(&obj).is_err());
+}
+
+#[test]
+fn test_columnar_history() {
+    use crate::enoki_types::{EnokiField, EnokiHistory, EnokiObject};
+
+    let mut history: EnokiHistory = [30, 10, 20]
+        .iter()
+        .map(|t| TimestampedEnokiValue::new(*t, EnokiValue::Double(*t as f64)))
+        .collect();
+    assert_eq!(history.timestamps(), &[10, 20, 30]);
+    assert_eq!(history.value_at(25).unwrap().value, EnokiValue::Double(20.0));
+    assert!(history.value_at(5).is_none());
+
+    let view = history.range(Some(15), Some(30));
+    assert_eq!(view.timestamps(), &[20, 30]);
+    assert_eq!(view.to_history().first().unwrap().timestamp, 20);
+    assert!(history.range(Some(40), None).is_empty());
+
+    history.push(TimestampedEnokiValue::new(40, EnokiValue::Int(4)));
+    assert_eq!(history.last().unwrap().value, EnokiValue::Int(4));
+    assert_eq!(history.get(0).unwrap().value, EnokiValue::Double(10.0));
+
+    let json = serde_json::to_value(&history).unwrap();
+    let expected = serde_json::to_value(history.to_vec()).unwrap();
+    assert_eq!(json, expected);
+
+    let mut obj = EnokiObject::new(0);
+    let field = EnokiField::new(
+        "/x".into(),
+        TimestampedEnokiValue::new(1, EnokiValue::Boolean(true)),
+    );
+    obj.add_field(field.clone());
+    let (_, no_history) = obj.get_field_with_history(&"/x".into()).unwrap();
+    assert!(no_history.is_none());
+    obj.record_field(field);
+    assert_eq!(obj.get_history(&"/x".into()).unwrap().len(), 1);
+    obj.set_history(&"/empty".into(), Vec::new());
+    assert!(!obj.has_field(&"/empty".into()));
+}
+
+#[test]
+fn test_msgpack_encoding() {
+    use crate::enoki_types::{EnokiField, EnokiObject};
+    use crate::frontend_helpers::encoding::encode_msgpack;
+
+    let mut obj = EnokiObject::new(7);
+    obj.add_field(EnokiField::new(
+        "/name".into(),
+        TimestampedEnokiValue::new(1, EnokiValue::String("a".to_string())),
+    ));
+    obj.set_history(
+        &"/x".into(),
+        vec![
+            TimestampedEnokiValue::new(1, EnokiValue::Double(0.5)),
+            TimestampedEnokiValue::new(2, EnokiValue::Double(1.5)),
+        ],
+    );
+
+    let bytes = encode_msgpack(&obj).unwrap();
+    let value = rmpv::decode::read_value(&mut bytes.as_slice()).unwrap();
+    let get = |value: &rmpv::Value, key: &str| {
+        value
+            .as_map()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v.clone())
+            .unwrap()
+    };
+
+    assert_eq!(get(&value, "timestamp").as_u64(), Some(7));
+    let history = get(&value, "history");
+    let history = history.as_array().unwrap();
+    assert!(history[0].is_nil());
+    let mut values = 0.5f64.to_le_bytes().to_vec();
+    values.extend_from_slice(&1.5f64.to_le_bytes());
+    assert_eq!(get(&history[1], "values").as_slice(), Some(values.as_slice()));
+    assert_eq!(get(&history[1], "type").as_str(), Some("Double"));
+}
+
+#[test]
+fn test_resample_and_align() {
+    use crate::enoki_types::EnokiHistory;
+    use crate::resample::{
+        align, resample, sample_at, time_grid, Interpolation, ResampleError, MAX_GRID_POINTS,
+    };
+
+    let history: EnokiHistory = vec![
+        TimestampedEnokiValue::new(10, EnokiValue::Double(0.0)),
+        TimestampedEnokiValue::new(20, EnokiValue::Double(10.0)),
+    ]
+    .into();
+    let grid = time_grid(5, 25, 5).unwrap();
+    assert_eq!(grid, vec![5, 10, 15, 20, 25]);
+    assert_eq!(
+        time_grid(0, MAX_GRID_POINTS - 1, 1).unwrap().len() as u64,
+        MAX_GRID_POINTS
+    );
+    assert!(matches!(
+        time_grid(0, u64::MAX, 1),
+        Err(ResampleError::GridTooLarge { points, .. }) if points > MAX_GRID_POINTS
+    ));
+
+    let held = resample(&history, &grid, Interpolation::ZeroOrderHold);
+    assert_eq!(held.timestamps(), &[10, 15, 20, 25]);
+    assert_eq!(held.get(1).unwrap().value, EnokiValue::Double(0.0));
+
+    let linear = resample(&history, &grid, Interpolation::Linear);
+    assert_eq!(linear.get(1).unwrap().value, EnokiValue::Double(5.0));
+    assert_eq!(
+        sample_at(&history, 5, Interpolation::Nearest),
+        Some(EnokiValue::Double(0.0))
+    );
+    assert_eq!(
+        sample_at(&history, 16, Interpolation::Nearest),
+        Some(EnokiValue::Double(10.0))
+    );
+
+    let other: EnokiHistory = vec![
+        TimestampedEnokiValue::new(12, EnokiValue::Int(1)),
+        TimestampedEnokiValue::new(40, EnokiValue::Int(2)),
+    ]
+    .into();
+    let pairs = align(&history, &other, 3);
+    assert_eq!(pairs.len(), 1);
+    assert_eq!((pairs[0].left.timestamp, pairs[0].right.timestamp), (10, 12));
+}
+
+#[test]
+fn test_units() {
+    use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+    use crate::units::{Unit, UnitError, UnitSystem};
+
+    assert!((Unit::Meter.convert(1.0, &Unit::Inch).unwrap() - 39.370_078_74).abs() < 1e-6);
+    assert!((Unit::Radian.convert(std::f64::consts::PI, &Unit::Degree).unwrap() - 180.0).abs() < 1e-9);
+    assert!((Unit::from("rps").convert(2.0, &Unit::RotationPerMinute).unwrap() - 120.0).abs() < 1e-9);
+    assert!((Unit::Celsius.convert(100.0, &Unit::Fahrenheit).unwrap() - 212.0).abs() < 1e-9);
+    assert!(matches!(
+        Unit::Meter.convert(1.0, &Unit::Degree),
+        Err(UnitError::Incompatible { .. })
+    ));
+    assert_eq!(Unit::from("furlongs"), Unit::Other("furlongs".to_string()));
+    assert_eq!(
+        Unit::from_metadata("{\"unit\": \"deg\"}"),
+        Some(Unit::Degree)
+    );
+
+    let mut obj = EnokiObject::new(0);
+    obj.set_history(
+        &EnokiKey::from("/arm/angle"),
+        vec![
+            TimestampedEnokiValue::new(1, EnokiValue::Double(0.0)),
+            TimestampedEnokiValue::new(2, EnokiValue::Double(std::f64::consts::PI)),
+        ],
+    );
+    assert!(obj.set_unit(&EnokiKey::from("/arm/angle"), Some(Unit::Radian)));
+    obj.add_field(EnokiField::new(
+        EnokiKey::from("/arm/angle"),
+        TimestampedEnokiValue::new(3, EnokiValue::Double(std::f64::consts::PI)),
+    ));
+    // replacing a field without a unit keeps the one it had
+    assert_eq!(obj.get_unit(&EnokiKey::from("/arm/angle")), Some(&Unit::Radian));
+
+    let converted = obj.convert_units(UnitSystem::Metric);
+    let key = EnokiKey::from("/arm/angle");
+    assert_eq!(converted.get_unit(&key), Some(&Unit::Degree));
+    let history = converted.get_history(&key).unwrap();
+    assert!(matches!(history.last().unwrap().value, EnokiValue::Double(v) if (v - 180.0).abs() < 1e-9));
+
+    let json = serde_json::to_value(converted.get_field(&key).unwrap()).unwrap();
+    assert_eq!(json["unit"], "deg");
+}
+
+#[test]
+fn test_derived_fields() {
+    use crate::derived::expr::{MAX_AVERAGE_WINDOW, MAX_DEPTH};
+    use crate::derived::{DerivedError, DerivedField, LiveDerived};
+    use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+
+    let skew = DerivedField::new(
+        EnokiKey::from("/Drive/skew"),
+        "abs({/Drive/Left/speed} - {/Drive/Right/speed}) > 0.5 || {/Vision/pose}[1] >= 2",
+    )
+    .unwrap();
+    assert_eq!(skew.inputs().len(), 3);
+    assert!(matches!(
+        DerivedField::new(EnokiKey::from("/x"), "{/a} +"),
+        Err(DerivedError::Parse { .. })
+    ));
+    assert!(matches!(
+        DerivedField::new(EnokiKey::from("/x"), "sqrt({/a})"),
+        Err(DerivedError::UnknownFunction(_))
+    ));
+    assert!(matches!(
+        DerivedField::new(EnokiKey::from("/x"), "{/x} * 2"),
+        Err(DerivedError::SelfReference(_))
+    ));
+
+    // what a user types can't run the app out of memory or stack
+    let parse_error = |source: &str| {
+        matches!(
+            DerivedField::new(EnokiKey::from("/x"), source),
+            Err(DerivedError::Parse { .. })
+        )
+    };
+    assert!(parse_error("average({/a}, 1000000000000)"));
+    let widest = format!("average({{/a}}, {})", MAX_AVERAGE_WINDOW);
+    assert!(DerivedField::new(EnokiKey::from("/x"), &widest).is_ok());
+    let deep = 100_000;
+    assert!(parse_error(&format!(
+        "{}{{/a}}{}",
+        "(".repeat(deep),
+        ")".repeat(deep)
+    )));
+    assert!(parse_error(&format!("{}{{/a}}", "-".repeat(deep))));
+    assert!(parse_error(&format!("{{/a}}{}", "[0]".repeat(deep))));
+    assert!(parse_error(&vec!["{/a}"; deep].join(" + ")));
+    let nested = format!(
+        "{}{{/a}}{}",
+        "(".repeat(MAX_DEPTH / 2),
+        ")".repeat(MAX_DEPTH / 2)
+    );
+    assert!(DerivedField::new(EnokiKey::from("/x"), &nested).is_ok());
+
+    let mut obj = EnokiObject::new(0);
+    let second = 1_000_000;
+    obj.set_history(
+        &EnokiKey::from("/Drive/position"),
+        (0..4)
+            .map(|t| TimestampedEnokiValue::new(t * second, EnokiValue::Double((t * t) as f64)))
+            .collect::<Vec<_>>(),
+    );
+    obj.set_history(
+        &EnokiKey::from("/Drive/offset"),
+        vec![TimestampedEnokiValue::new(second / 2, EnokiValue::Int(10))],
+    );
+
+    let velocity = DerivedField::new(EnokiKey::from("/Drive/velocity"), "derivative({/Drive/position})").unwrap();
+    let history = velocity.evaluate_history(&obj);
+    assert_eq!(history.timestamps(), &[second, 2 * second, 3 * second]);
+    assert_eq!(history.last().unwrap().value, EnokiValue::Double(5.0));
+
+    // the offset is held at its latest value, samples before it has one are skipped
+    let shifted = DerivedField::new(EnokiKey::from("/shifted"), "{/Drive/position} + {/Drive/offset}").unwrap();
+    let history = shifted.evaluate_history(&obj);
+    assert_eq!(history.first().unwrap().timestamp, second / 2);
+    assert_eq!(history.last().unwrap().value, EnokiValue::Double(19.0));
+
+    let average = DerivedField::new(EnokiKey::from("/avg"), "average({/Drive/position}, 2)").unwrap();
+    assert_eq!(
+        average.evaluate_history(&obj).last().unwrap().value,
+        EnokiValue::Double(6.5)
+    );
+
+    crate::derived::define_derived_field(velocity);
+    let mut live = LiveDerived::default();
+    let mut table = EnokiObject::new(0);
+    for t in 0..3 {
+        table.add_field(EnokiField::new(
+            EnokiKey::from("/Drive/position"),
+            TimestampedEnokiValue::new(t * second, EnokiValue::Double(2.0 * t as f64)),
+        ));
+        live.apply(&mut table);
+    }
+    let field = table.get_field(&EnokiKey::from("/Drive/velocity")).unwrap();
+    assert_eq!(field.get_value().value, EnokiValue::Double(2.0));
+    assert_eq!(field.get_timestamp(), 2 * second);
+    crate::derived::remove_derived_field(&EnokiKey::from("/Drive/velocity"));
+}
+
+#[test]
+fn test_round_trips() {
+    use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+    use crate::frontend_helpers::encoding::{decode_msgpack, encode_msgpack};
+    use crate::units::Unit;
+    use wpilog::log::DataLogValue;
+
+    // floats are f32s on the wire so these are picked to fit in one
+    let values = vec![
+        EnokiValue::ByteArray(vec![0, 1, 255]),
+        EnokiValue::Protobuf(vec![8, 150, 1]),
+        EnokiValue::Float(0.5),
+        EnokiValue::FloatArray(vec![1.25, -2.0]),
+        EnokiValue::Double(0.1),
+        EnokiValue::DoubleArray(vec![1.0, 2.5]),
+        EnokiValue::Int(-7),
+        EnokiValue::IntArray(vec![1, 2, 3]),
+        EnokiValue::String("enoki".to_string()),
+        EnokiValue::StringArray(vec!["a".to_string(), "".to_string()]),
+        EnokiValue::Boolean(true),
+        EnokiValue::BooleanArray(vec![true, false]),
+        EnokiValue::DoubleArray(vec![]),
+        EnokiValue::ByteArray(vec![]),
+    ];
+
+    let mut obj = EnokiObject::new(42);
+    for (index, value) in values.iter().enumerate() {
+        let sample = TimestampedEnokiValue::new(index as u64 + 1, value.clone());
+        let json = serde_json::to_string(&sample).unwrap();
+        assert_eq!(serde_json::from_str::<TimestampedEnokiValue>(&json).unwrap(), sample, "{}", json);
+
+        match DataLogValue::try_from(value.clone()) {
+            Ok(datalog) => assert_eq!(&EnokiValue::from(datalog), value),
+            Err(_) => assert!(matches!(value, EnokiValue::Protobuf(_))),
+        }
+
+        let key = EnokiKey::from(format!("/values/{}", index));
+        obj.set_history(&key, vec![sample.clone()]);
+    }
+    obj.set_unit(&EnokiKey::from("/values/4"), Some(Unit::Meter));
+    let mut mixed = obj.get_history(&EnokiKey::from("/values/6")).unwrap().clone();
+    mixed.push(TimestampedEnokiValue::new(100, EnokiValue::String("switched".to_string())));
+    obj.set_history(&EnokiKey::from("/values/6"), mixed);
+    obj.add_field(EnokiField::new(
+        EnokiKey::from("/no_history"),
+        TimestampedEnokiValue::new(5, EnokiValue::Int(1)),
+    ));
+
+    let assert_same = |decoded: &EnokiObject| {
+        assert_eq!(decoded.get_timestamp(), obj.get_timestamp());
+        assert_eq!(decoded.get_fields(), obj.get_fields());
+        for field in obj.get_fields() {
+            assert_eq!(
+                decoded.get_history(field.get_key()),
+                obj.get_history(field.get_key()),
+                "{}",
+                field.get_key()
+            );
+        }
+    };
+    assert_same(&serde_json::from_str(&serde_json::to_string(&obj).unwrap()).unwrap());
+    assert_same(&decode_msgpack(&encode_msgpack(&obj).unwrap()).unwrap());
+
+    // without a type tag numbers widen to doubles and bytes stay bytes
+    let untagged: EnokiValue = serde_json::from_str("[1, 2.5]").unwrap();
+    assert_eq!(untagged, EnokiValue::DoubleArray(vec![1.0, 2.5]));
+    assert!(serde_json::from_str::<TimestampedEnokiValue>(
+        r#"{"type": "ByteArray", "value": [1, 256], "timestamp": 0}"#
+    )
+    .is_err());
+    let reordered: TimestampedEnokiValue =
+        serde_json::from_str(r#"{"timestamp": 3, "value": 2, "type": "Float"}"#).unwrap();
+    assert_eq!(reordered, TimestampedEnokiValue::new(3, EnokiValue::Float(2.0)));
+}
+
+#[test]
+fn test_shared_snapshots() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::sync::Arc;
+
+    use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+    use crate::networktable::table::LiveTable;
+
+    let hash = |key: &EnokiKey| {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    };
+    // keys spelled differently intern to the same path
+    let key = EnokiKey::from("/Drive/speed");
+    let same = EnokiKey::from("Drive//speed/");
+    assert_eq!(key, same);
+    assert_eq!(hash(&key), hash(&same));
+    assert_eq!(EnokiKey::from("/Drive").child("speed"), key);
+    assert!(EnokiKey::from("/Drive/a") < EnokiKey::from("/Drive/b"));
+    assert_ne!(hash(&key), hash(&EnokiKey::from("/Drive/sped")));
+
+    let mut keyed = HashMap::new();
+    keyed.insert(key.clone(), 1);
+    drop(key);
+    assert_eq!(keyed.get(&same), Some(&1));
+
+    // a held snapshot keeps its objects while the table is written to
+    let mut table = LiveTable::default();
+    table.subscribe("/a", 0);
+    table.subscribe("/b", 0);
+    let field = |key: &str, timestamp: u64, value: i64| {
+        EnokiField::new(
+            EnokiKey::from(key),
+            TimestampedEnokiValue::new(timestamp, EnokiValue::Int(value)),
+        )
+    };
+    let mut received = EnokiObject::new(1);
+    for index in 0..1000 {
+        received.add_field(field(&format!("/a/{}", index), 1, index));
+    }
+    assert!(table.update("/a", &received));
+    assert!(!table.update("/c", &received));
+    let snapshot = table.snapshot();
+
+    let mut received = EnokiObject::new(2);
+    received.add_field(field("/a/7", 2, -7));
+    table.update("/a", &received);
+    let latest = table.snapshot();
+    assert_eq!(
+        snapshot["/a"]
+            .get_field(&EnokiKey::from("/a/7"))
+            .unwrap()
+            .get_value()
+            .value,
+        EnokiValue::Int(7)
+    );
+    assert_eq!(
+        latest["/a"]
+            .get_field(&EnokiKey::from("/a/7"))
+            .unwrap()
+            .get_value()
+            .value,
+        EnokiValue::Int(-7)
+    );
+    assert_eq!(latest["/a"].get_timestamp(), 2);
+    assert!(!Arc::ptr_eq(&snapshot["/a"], &latest["/a"]));
+    assert!(Arc::ptr_eq(&snapshot["/b"], &latest["/b"]));
+    // only the field that was written is new, the others are shared with the held snapshot
+    let shared = |key: &str| {
+        let key = EnokiKey::from(key);
+        let (before, _) = snapshot["/a"].get_field_with_history(&key).unwrap();
+        let (after, _) = latest["/a"].get_field_with_history(&key).unwrap();
+        std::ptr::eq(before, after)
+    };
+    assert!(!shared("/a/7"));
+    assert!((0..1000)
+        .filter(|&index| index != 7)
+        .all(|index| shared(&format!("/a/{}", index))));
+
+    // histories are shared the same way
+    let mut recorded = EnokiObject::new(0);
+    for index in 0..3 {
+        recorded.record_field(field("/x", index, index as i64));
+        recorded.record_field(field("/y", index, index as i64));
+    }
+    let held = recorded.clone();
+    recorded.record_field(field("/x", 3, 3));
+    let history =
+        |obj: &EnokiObject, key: &str| obj.get_history(&EnokiKey::from(key)).unwrap() as *const _;
+    assert_eq!(held.get_history(&EnokiKey::from("/x")).unwrap().len(), 3);
+    assert_eq!(
+        recorded.get_history(&EnokiKey::from("/x")).unwrap().len(),
+        4
+    );
+    assert_ne!(history(&held, "/x"), history(&recorded, "/x"));
+    assert_eq!(history(&held, "/y"), history(&recorded, "/y"));
+}
+
+/// A fresh folder for the scripts of a test, removed with everything in it when dropped
+struct ScriptDir(std::path::PathBuf);
+
+impl ScriptDir {
+    fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("enoki-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        ScriptDir(dir)
+    }
+
+    /// Writes `name.py` and returns its path with the one of `name.txt`,
+    /// `source` can call `write(text)` to append a word to it
+    fn script(&self, name: &str, source: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let path = self.0.join(format!("{}.py", name));
+        let out = self.0.join(format!("{}.txt", name));
+        std::fs::write(
+            &path,
+            format!(
+                "def write(text):\n    open({:?}, 'a').write(text + ' ')\n{}",
+                out.to_string_lossy(),
+                source
+            ),
+        )
+        .unwrap();
+        (path, out)
+    }
+}
+
+impl Drop for ScriptDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Scripts that fail for good and get 200ms per hook
+const NEVER_RESTART: crate::scripting::ScriptOptions = crate::scripting::ScriptOptions {
+    timeout_ms: 200,
+    restart: crate::scripting::RestartPolicy::Never,
+};
+
+/// The words a script wrote with `write`
+fn runs(out: &std::path::Path) -> Vec<String> {
+    std::fs::read_to_string(out)
+        .unwrap()
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+/// Waits for a script to stop and returns the state it stopped in
+fn wait_until_stopped(name: &str) -> crate::scripting::ScriptState {
+    use std::time::{Duration, Instant};
+
+    use crate::scripting::script_status;
+
+    let start = Instant::now();
+    while script_status(name).unwrap().state.is_active() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{} never stopped",
+            name
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    script_status(name).unwrap().state
+}
+
+#[test]
+fn test_script_hooks() {
+    use std::time::Duration;
+
+    use crate::scripting::{spawn_script, stop_script, RestartPolicy, ScriptOptions, ScriptState};
+
+    let dir = ScriptDir::new("scripts");
+    // on_stop writes how often the script started and ticked, it runs after a failure too
+    let script = |name: &str, tick: &str| {
+        dir.script(
+            name,
+            &format!(
+                "import enoki_core\n\
+                 from enoki_core import NetworkTable, EnokiObject\n\
+                 import enoki_core.Derived\n\
+                 ticks = 0\n\
+                 def on_start():\n    assert enoki_core.now() > 0\n    write('start')\n\
+                 def on_tick():\n    global ticks\n    ticks += 1\n    {}\n\
+                 def on_stop():\n    write(str(ticks))\n",
+                tick
+            ),
+        )
+    };
+
+    let (path, out) = script("ticking", "pass");
+    spawn_script("ticking".to_string(), path.clone(), NEVER_RESTART).unwrap();
+    assert!(spawn_script("ticking".to_string(), path, NEVER_RESTART).is_err());
+    std::thread::sleep(Duration::from_millis(100));
+    stop_script("ticking").unwrap();
+    assert_eq!(wait_until_stopped("ticking"), ScriptState::Stopped);
+    assert!(runs(&out)[1].parse::<u32>().unwrap() >= 2);
+    assert!(stop_script("ticking").is_err());
+
+    let (path, out) = script("failing", "raise ValueError('bad tick')");
+    spawn_script("failing".to_string(), path, NEVER_RESTART).unwrap();
+    match wait_until_stopped("failing") {
+        ScriptState::Failed { error } => {
+            assert!(error.contains("ValueError: bad tick"), "{}", error);
+            assert!(error.contains("on_tick"), "{}", error);
+        }
+        state => panic!("{:?}", state),
+    }
+    assert_eq!(runs(&out), ["start", "1"]);
+
+    // a tick that never returns is interrupted, and the script restarted once
+    let (path, out) = script("looping", "while True: pass");
+    let restart_once = ScriptOptions {
+        timeout_ms: 200,
+        restart: RestartPolicy::OnFailure {
+            max_restarts: 1,
+            delay_ms: 10,
+        },
+    };
+    spawn_script("looping".to_string(), path, restart_once).unwrap();
+    match wait_until_stopped("looping") {
+        ScriptState::Failed { error } => {
+            assert!(error.contains("on_tick took too long"), "{}", error);
+            assert!(error.contains("KeyboardInterrupt"), "{}", error);
+        }
+        state => panic!("{:?}", state),
+    }
+    assert_eq!(runs(&out), ["start", "1", "start", "1"]);
+
+    // stopping a script stuck in a tick interrupts it and still runs on_stop
+    let (path, out) = script("stuck", "while True: pass");
+    let patient = ScriptOptions {
+        timeout_ms: 60_000,
+        ..NEVER_RESTART
+    };
+    spawn_script("stuck".to_string(), path, patient).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    stop_script("stuck").unwrap();
+    assert_eq!(wait_until_stopped("stuck"), ScriptState::Stopped);
+    assert_eq!(runs(&out), ["start", "1"]);
+}
+
+#[test]
+fn test_network_table_listeners() {
+    use crate::enoki_types::{EnokiField, EnokiObject};
+    use crate::networktable::listeners::{NetworkTableListeners, MAX_PENDING_VALUES};
+
+    let speed = |timestamp: u64| {
+        let value = TimestampedEnokiValue::new(timestamp, EnokiValue::Double(timestamp as f64));
+        EnokiObject::from_field(EnokiField::new("/Drive/speed".into(), value))
+    };
+    let mut listeners = NetworkTableListeners::default();
+    listeners.push_announce("/Drive/speed", "double".to_string());
+    let drive = listeners.listen("/Drive".to_string());
+    let arm = listeners.listen("/Arm".to_string());
+
+    // topics announced before listening are in the first batch
+    let batch = drive.next_batch().unwrap();
+    assert_eq!(batch.announced, [("/Drive/speed".to_string(), "double".to_string())]);
+    assert!(batch.values.is_none());
+
+    // values that arrive between batches are taken together, each one in the field's history
+    listeners.push_announce("/Drivetrain/speed", "double".to_string());
+    for timestamp in 1..=3 {
+        listeners.push_values("/Drive", &speed(timestamp));
+    }
+    let batch = drive.next_batch().unwrap();
+    assert!(batch.announced.is_empty());
+    assert_eq!(batch.dropped, 0);
+    let values = batch.values.unwrap();
+    assert_eq!(values.get_timestamp(), 3);
+    assert_eq!(
+        values.get_field(&"/Drive/speed".into()).unwrap().get_value().value,
+        EnokiValue::Double(3.0)
+    );
+    assert_eq!(values.get_history(&"/Drive/speed".into()).unwrap().len(), 3);
+
+    // a listener that falls behind skips values instead of holding up the client
+    let total = MAX_PENDING_VALUES as u64 + 10;
+    for timestamp in 1..=total {
+        listeners.push_values("/Drive", &speed(timestamp));
+    }
+    let batch = drive.next_batch().unwrap();
+    assert_eq!(batch.dropped, MAX_PENDING_VALUES);
+    let history = batch.values.unwrap().get_history(&"/Drive/speed".into()).unwrap().clone();
+    assert_eq!(history.len(), 10);
+    assert_eq!(history.last().unwrap().timestamp, total);
+
+    // a waiting listener wakes up for new values and stops once closed
+    let waiting = std::thread::spawn({
+        let drive = drive.clone();
+        move || (drive.next_batch(), drive.next_batch())
+    });
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    listeners.push_values("/Drive", &speed(total + 1));
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    listeners.close_all();
+    let (batch, closed) = waiting.join().unwrap();
+    assert_eq!(batch.unwrap().values.unwrap().get_timestamp(), total + 1);
+    assert!(closed.is_none());
+    assert!(arm.next_batch().is_none());
+}
+
+#[test]
+fn test_datalog_entry_infos() {
+    use crate::datalog::follow::{entry_infos, entry_units, EntryInfo};
+    use crate::units::Unit;
+
+    let control = |kind: u8, entry: u32, strings: &[&str]| {
+        let mut payload = vec![kind];
+        payload.extend_from_slice(&entry.to_le_bytes());
+        for s in strings {
+            payload.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            payload.extend_from_slice(s.as_bytes());
+        }
+        wpilog_record(0, 1, &payload)
+    };
+    let mut bytes = b"WPILOG\x00\x01\x00\x00\x00\x00".to_vec();
+    bytes.extend(control(0, 1, &["/Arm/angle", "double", r#"{"unit":"deg"}"#]));
+    bytes.extend(control(0, 2, &["/Arm/mode", "int64", ""]));
+    bytes.extend(wpilog_record(1, 2, &1.5f64.to_le_bytes()));
+    bytes.extend(control(2, 1, &[r#"{"unit":"rad"}"#]));
+    bytes.extend(control(1, 2, &[]));
+    // started again under the same name, it keeps its place but takes the new type
+    bytes.extend(control(0, 3, &["/Arm/mode", "string", "{}"]));
+
+    let path = std::env::temp_dir().join(format!("enoki-entries-{}.wpilog", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    let entry = |name: &str, entry_type: &str, metadata: &str| EntryInfo {
+        name: name.to_string(),
+        entry_type: entry_type.to_string(),
+        metadata: metadata.to_string(),
+    };
+    assert_eq!(
+        entry_infos(&path).unwrap(),
+        [
+            entry("/Arm/angle", "double", r#"{"unit":"rad"}"#),
+            entry("/Arm/mode", "string", "{}"),
+        ]
+    );
+    let units = entry_units(&path).unwrap();
+    assert_eq!(units.len(), 1);
+    assert_eq!(units["/Arm/angle"], Unit::from("rad"));
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_datalog_writer_round_trip() {
+    use pyo3::{
+        exceptions::{PyKeyError, PyRuntimeError},
+        prelude::*,
+        types::IntoPyDict,
+    };
+
+    use crate::datalog::python::{create_datalog, open_datalog, PySessionMetadata};
+    use crate::datalog::session::SessionMetadata;
+    use crate::scripting::plugins::{set_thread_grant, ThreadGrant};
+    use crate::units::Unit;
+
+    let path = std::env::temp_dir().join(format!("enoki-writer-{}.wpilog", std::process::id()));
+    let path = path.to_string_lossy().to_string();
+    let session = SessionMetadata {
+        event: "2023mnmi".to_string(),
+        notes: "written by a script".to_string(),
+        ..Default::default()
+    };
+
+    // the test runs python for the app, which may use the filesystem
+    set_thread_grant(Some(ThreadGrant::App));
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let writer = create_datalog(path.clone(), Some(session.clone().into())).unwrap();
+        let writer = Py::new(py, writer).unwrap();
+        let writer = writer.as_ref(py);
+        writer
+            .call_method1("start_entry", ("/Arm/angle", "double", r#"{"unit":"deg"}"#))
+            .unwrap();
+        for timestamp in 1..=5u64 {
+            writer
+                .call_method1(
+                    "append",
+                    ("/Arm/angle", timestamp as f64 / 2.0, timestamp * 1000),
+                )
+                .unwrap();
+        }
+        // started by its first value, with that value's type
+        writer
+            .call_method1("append", ("/Arm/mode", "stowed", 2000))
+            .unwrap();
+        writer
+            .call_method1("append", ("/Arm/mode", "scoring", 4000))
+            .unwrap();
+        writer.call_method1("finish_entry", ("/Arm/mode",)).unwrap();
+        // characters that mean something in a key are escaped when it's read
+        writer
+            .call_method1("append", (r"/Vision/best*\tag?", 7.0, 3000))
+            .unwrap();
+        writer.call_method0("close").unwrap();
+        let closed = writer
+            .call_method1("append", ("/Arm/angle", 1.0))
+            .unwrap_err();
+        assert!(closed.is_instance_of::<PyRuntimeError>(py));
+
+        let reader = Py::new(py, open_datalog(path.clone()).unwrap()).unwrap();
+        let reader = reader.as_ref(py);
+        let names: Vec<String> = reader
+            .call_method0("entry_names")
+            .unwrap()
+            .extract()
+            .unwrap();
+        assert_eq!(names, ["/Arm/angle", "/Arm/mode", r"/Vision/best*\tag?"]);
+        let entries: Vec<&PyAny> = reader.call_method0("entries").unwrap().extract().unwrap();
+        let attr = |entry: &PyAny, name: &str| entry.getattr(name).unwrap().to_string();
+        assert_eq!(attr(entries[0], "type"), "double");
+        assert_eq!(attr(entries[0], "unit"), Unit::from("deg").to_string());
+        assert_eq!(attr(entries[0], "count"), "5");
+        assert_eq!(attr(entries[0], "first"), "1000");
+        assert_eq!(attr(entries[0], "last"), "5000");
+        assert_eq!(attr(entries[1], "type"), "string");
+        assert_eq!(attr(entries[1], "count"), "2");
+
+        let history = |args: (&str, Option<u64>, Option<u64>)| {
+            reader
+                .call_method1("history", args)
+                .and_then(|history| history.extract::<Vec<TimestampedEnokiValue>>())
+        };
+        assert_eq!(history(("/Arm/angle", None, None)).unwrap().len(), 5);
+        assert_eq!(
+            history(("/Arm/angle", Some(2000), Some(4000))).unwrap(),
+            [
+                TimestampedEnokiValue::new(2000, EnokiValue::Double(1.0)),
+                TimestampedEnokiValue::new(3000, EnokiValue::Double(1.5)),
+                TimestampedEnokiValue::new(4000, EnokiValue::Double(2.0)),
+            ]
+        );
+        let after = reader
+            .call_method(
+                "history",
+                ("/Arm/mode",),
+                Some([("start", 3000)].into_py_dict(py)),
+            )
+            .unwrap()
+            .extract::<Vec<TimestampedEnokiValue>>()
+            .unwrap();
+        assert_eq!(
+            after,
+            [TimestampedEnokiValue::new(
+                4000,
+                EnokiValue::String("scoring".to_string())
+            )]
+        );
+        assert_eq!(attr(entries[2], "count"), "1");
+        assert_eq!(
+            history((r"/Vision/best*\tag?", None, None)).unwrap(),
+            [TimestampedEnokiValue::new(3000, EnokiValue::Double(7.0))]
+        );
+        let missing = history(("/Arm/missing", None, None)).unwrap_err();
+        assert!(missing.is_instance_of::<PyKeyError>(py));
+
+        let read: Option<PySessionMetadata> = reader
+            .call_method0("session_metadata")
+            .unwrap()
+            .extract()
+            .unwrap();
+        assert_eq!(SessionMetadata::try_from(read.unwrap()).unwrap(), session);
+    });
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_session_metadata() {
+    use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+    use crate::datalog::python::PySessionMetadata;
+    use crate::datalog::session::{
+        read_header_session, Alliance, MatchType, SessionMetadata, MAX_HEADER_METADATA,
+    };
+
+    assert_eq!("Qual".parse(), Ok(MatchType::Qualification));
+    assert_eq!("playoff".parse(), Ok(MatchType::Elimination));
+    assert_eq!("BLUE".parse(), Ok(Alliance::Blue));
+    assert!("green".parse::<Alliance>().is_err());
+
+    let session = SessionMetadata {
+        event: "2023mnmi".to_string(),
+        match_type: Some(MatchType::Qualification),
+        match_number: Some(42),
+        alliance: Some(Alliance::Red),
+        notes: "brownout in auto".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        SessionMetadata::from_json(&session.to_json()),
+        Some(session.clone())
+    );
+    // fields missing from older logs are left at their defaults
+    assert_eq!(
+        SessionMetadata::from_json(r#"{"event": "2023mnmi"}"#)
+            .unwrap()
+            .match_number,
+        None
+    );
+
+    let path = std::env::temp_dir().join(format!("enoki-session-{}.wpilog", std::process::id()));
+    let with_header = |magic: &[u8], extra_len: u32, extra: &[u8]| {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&[0, 1]);
+        bytes.extend_from_slice(&extra_len.to_le_bytes());
+        bytes.extend_from_slice(extra);
+        std::fs::write(&path, bytes).unwrap();
+        read_header_session(&path)
+    };
+    let json = session.to_json();
+    let read = with_header(b"WPILOG", json.len() as u32, json.as_bytes()).unwrap();
+    assert_eq!(read, Some(session.clone()));
+    // a header that isn't json isn't a session
+    assert_eq!(with_header(b"WPILOG", 3, b"abc").unwrap(), None);
+    // the length isn't trusted before the magic is checked or when it's absurd
+    assert!(with_header(b"NOTLOG", u32::MAX, b"").is_err());
+    assert!(with_header(b"WPILOG", u32::MAX, b"").is_err());
+    assert!(with_header(b"WPILOG", MAX_HEADER_METADATA as u32 + 1, b"").is_err());
+    assert!(with_header(b"WPILOG", 10, b"{}").is_err());
+    std::fs::remove_file(&path).ok();
+
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let py_session = Py::new(py, PySessionMetadata::from(session.clone())).unwrap();
+        let py_session = py_session.as_ref(py);
+        assert_eq!(
+            py_session
+                .getattr("match_type")
+                .unwrap()
+                .extract::<String>()
+                .unwrap(),
+            "Qualification"
+        );
+        assert_eq!(
+            py_session
+                .getattr("alliance")
+                .unwrap()
+                .extract::<String>()
+                .unwrap(),
+            "Red"
+        );
+        let back: PySessionMetadata = py_session.extract().unwrap();
+        assert_eq!(SessionMetadata::try_from(back).unwrap(), session);
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("event", "2023mnmi").unwrap();
+        kwargs.set_item("match_type", "q").unwrap();
+        kwargs.set_item("alliance", "blue").unwrap();
+        let created = py
+            .get_type::<PySessionMetadata>()
+            .call((), Some(kwargs))
+            .unwrap();
+        let created = SessionMetadata::try_from(created.extract::<PySessionMetadata>().unwrap());
+        let created = created.unwrap();
+        assert_eq!(created.match_type, Some(MatchType::Qualification));
+        assert_eq!(created.alliance, Some(Alliance::Blue));
+        assert_eq!(created.robot, "");
+
+        kwargs.set_item("alliance", "green").unwrap();
+        let invalid = py
+            .get_type::<PySessionMetadata>()
+            .call((), Some(kwargs))
+            .unwrap();
+        let err =
+            SessionMetadata::try_from(invalid.extract::<PySessionMetadata>().unwrap()).unwrap_err();
+        assert!(err.is_instance_of::<PyValueError>(py));
+    });
+}
+
+#[test]
+fn test_pull_logs() {
+    use std::io::{self, Read};
+    use std::path::{Path, PathBuf};
+
+    use ssh2::FileStat;
+
+    use crate::robot_interface::log_retrieval::{pull_with, LogSource, RemoteTarget};
+
+    /// A target's file system under a local directory, reads of `growing` get longer than listed
+    struct LocalSource {
+        root: PathBuf,
+        growing: &'static str,
+    }
+
+    impl LogSource for LocalSource {
+        fn readdir(&self, dir: &Path) -> io::Result<Vec<(PathBuf, FileStat)>> {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(self.root.join(dir.strip_prefix("/").unwrap()))? {
+                let entry = entry?;
+                let stat = FileStat {
+                    size: Some(entry.metadata()?.len()),
+                    uid: None,
+                    gid: None,
+                    perm: Some(0o100644),
+                    atime: None,
+                    mtime: Some(0),
+                };
+                entries.push((dir.join(entry.file_name()), stat));
+            }
+            Ok(entries)
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+            let file = std::fs::File::open(self.root.join(path.strip_prefix("/").unwrap()))?;
+            if path.ends_with(self.growing) {
+                return Ok(Box::new(file.chain(&b"more"[..])));
+            }
+            Ok(Box::new(file))
+        }
+
+        fn unlink(&self, path: &Path) -> io::Result<()> {
+            std::fs::remove_file(self.root.join(path.strip_prefix("/").unwrap()))
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("enoki-pull-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let remote = dir.join("remote");
+    let local = dir.join("local");
+    for (path, contents) in [
+        ("u/logs/FRC_1.wpilog", "usb"),
+        ("home/lvuser/logs/FRC_1.wpilog", "home"),
+        ("home/lvuser/logs/FRC_2.wpilog", "open"),
+        ("home/lvuser/logs/notes.txt", "not a log"),
+    ] {
+        std::fs::create_dir_all(remote.join(path).parent().unwrap()).unwrap();
+        std::fs::write(remote.join(path), contents).unwrap();
+    }
+    std::fs::create_dir_all(&local).unwrap();
+    let source = LocalSource {
+        root: remote.clone(),
+        growing: "FRC_2.wpilog",
+    };
+    let target = RemoteTarget::roborio(4149).unwrap();
+
+    // the log still being written fails on its own, the others are pulled under their remote paths
+    let result = pull_with(&source, &target, &local, None, false).unwrap();
+    let mut pulled: Vec<_> = result
+        .pulled
+        .iter()
+        .map(|pulled| {
+            pulled
+                .local_path
+                .strip_prefix(&local)
+                .unwrap()
+                .to_path_buf()
+        })
+        .collect();
+    pulled.sort();
+    assert_eq!(
+        pulled,
+        [
+            Path::new("home/lvuser/logs/FRC_1.wpilog"),
+            Path::new("u/logs/FRC_1.wpilog")
+        ]
+    );
+    let read = |path: &str| std::fs::read_to_string(local.join(path)).unwrap();
+    assert_eq!(read("u/logs/FRC_1.wpilog"), "usb");
+    assert_eq!(read("home/lvuser/logs/FRC_1.wpilog"), "home");
+    assert_eq!(result.failed.len(), 1);
+    assert_eq!(
+        result.failed[0].remote.path,
+        "/home/lvuser/logs/FRC_2.wpilog"
+    );
+    assert!(!local.join("home/lvuser/logs/FRC_2.wpilog").exists());
+    assert!(!local.join("home/lvuser/logs/FRC_2.wpilog.partial").exists());
+
+    // pulled logs are remembered, deleting only removes what was pulled
+    let source = LocalSource {
+        root: remote.clone(),
+        growing: "none",
+    };
+    let result = pull_with(&source, &target, &local, None, true).unwrap();
+    assert!(result.failed.is_empty());
+    assert_eq!(result.pulled.len(), 1);
+    assert!(result.pulled[0].deleted_remote);
+    assert_eq!(read("home/lvuser/logs/FRC_2.wpilog"), "open");
+    assert!(!remote.join("home/lvuser/logs/FRC_2.wpilog").exists());
+    assert!(remote.join("u/logs/FRC_1.wpilog").exists());
+
+    // selected logs are pulled again
+    let selected = Some(vec!["/u/logs/FRC_1.wpilog".to_string()]);
+    let result = pull_with(&source, &target, &local, selected, false).unwrap();
+    assert_eq!(result.pulled.len(), 1);
+    assert!(pull_with(&source, &target, &local, None, false)
+        .unwrap()
+        .pulled
+        .is_empty());
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_roborio_addresses() {
+    use crate::robot_interface::log_retrieval::RemoteTarget;
+    use crate::robot_interface::team_number::MAX_TEAM_NUMBER;
+
+    let host = |team: u16| RemoteTarget::roborio(team).unwrap().host;
+    assert_eq!(host(1), "10.0.1.2");
+    assert_eq!(host(100), "10.1.0.2");
+    assert_eq!(host(604), "10.6.4.2");
+    assert_eq!(host(1005), "10.10.5.2");
+    assert_eq!(host(4149), "10.41.49.2");
+    assert_eq!(host(MAX_TEAM_NUMBER), "10.255.99.2");
+    assert!(RemoteTarget::roborio(MAX_TEAM_NUMBER + 1).is_err());
+    assert!(RemoteTarget::roborio(u16::MAX).is_err());
+}
+
+#[test]
+fn test_plugin_manifests() {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use pyo3::prelude::*;
+
+    use crate::scripting::plugins::{
+        check_permission, set_thread_grant, Grant, Permission, PluginManifest, ThreadGrant,
+        ENOKI_API_VERSION,
+    };
+    use crate::scripting::ScriptError;
+
+    let dir = Path::new("plugins/tracker");
+    let manifest = PluginManifest::parse(
+        dir,
+        r#"{"name": "tracker", "version": "1.0.0", "api_version": 1, "permissions": ["nt_publish"]}"#,
+    )
+    .unwrap();
+    assert_eq!(manifest.entry, "main.py");
+    assert_eq!(
+        manifest.permissions.into_iter().collect::<Vec<_>>(),
+        [Permission::NtPublish]
+    );
+
+    let invalid = |json: &str| PluginManifest::parse(dir, json).unwrap_err();
+    assert!(matches!(
+        invalid(
+            r#"{"name": "tracker", "version": "1", "api_version": 1, "permissions": ["root"]}"#
+        ),
+        ScriptError::InvalidPlugin(..)
+    ));
+    assert!(matches!(
+        invalid(r#"{"name": " ", "version": "1", "api_version": 1}"#),
+        ScriptError::InvalidPlugin(..)
+    ));
+    assert!(matches!(
+        invalid(
+            r#"{"name": "tracker", "version": "1", "api_version": 1, "entry": "../escape.py"}"#
+        ),
+        ScriptError::InvalidPlugin(..)
+    ));
+    let newer = format!(
+        r#"{{"name": "tracker", "version": "1", "api_version": {}}}"#,
+        ENOKI_API_VERSION + 1
+    );
+    assert!(matches!(
+        invalid(&newer),
+        ScriptError::UnsupportedApiVersion { version, .. } if version == ENOKI_API_VERSION + 1
+    ));
+
+    // threads nothing was granted to may do nothing, ones running for the app anything
+    assert!(matches!(
+        check_permission(Permission::Filesystem),
+        Err(ScriptError::UngrantedThread(Permission::Filesystem))
+    ));
+    set_thread_grant(Some(ThreadGrant::App));
+    assert!(check_permission(Permission::Filesystem).is_ok());
+    let tracker = ThreadGrant::Plugin(Arc::new(Grant {
+        plugin: "tracker".to_string(),
+        permissions: [Permission::NtPublish].into_iter().collect(),
+    }));
+    set_thread_grant(Some(tracker.clone()));
+    assert!(check_permission(Permission::NtPublish).is_ok());
+    match check_permission(Permission::Filesystem) {
+        Err(err) => assert_eq!(
+            err.to_string(),
+            "Plugin tracker didn't declare the filesystem permission"
+        ),
+        Ok(()) => panic!("tracker may not use the filesystem"),
+    }
+
+    // threads started from python run for whoever started them, a plugin can't shed its grant
+    crate::scripting::init_python();
+    let code = r#"
+import _thread, asyncio, threading
+from enoki_core import Datalog
+
+def attempt():
+    try:
+        Datalog.open("/nonexistent.wpilog")
+    except Exception as err:
+        return type(err).__name__
+
+def in_thread():
+    result = []
+    thread = threading.Thread(target=lambda: result.append(attempt()))
+    thread.start()
+    thread.join()
+    return result[0]
+
+def in_executor():
+    async def run():
+        return await asyncio.get_running_loop().run_in_executor(None, attempt)
+    return asyncio.run(run())
+
+def in_raw_thread():
+    result = []
+    done = threading.Event()
+    _thread.start_new_thread(lambda: (result.append(attempt()), done.set()), ())
+    done.wait()
+    return result[0]
+"#;
+    let attempts = || {
+        Python::with_gil(|py| {
+            let module = PyModule::from_code(py, code, "grants.py", "grants").unwrap();
+            ["attempt", "in_thread", "in_executor", "in_raw_thread"].map(|attempt| {
+                module
+                    .call_method0(attempt)
+                    .and_then(|result| result.extract::<String>())
+                    .unwrap()
+            })
+        })
+    };
+    assert_eq!(attempts(), ["PermissionError"; 4]);
+    // the session is recorded in the running datalog so setting it is writing to a file too
+    Python::with_gil(|py| {
+        let datalog = PyModule::import(py, "enoki_core")
+            .unwrap()
+            .getattr("Datalog")
+            .unwrap();
+        let session = datalog.call_method0("get_session_metadata").unwrap();
+        let err = datalog
+            .call_method1("set_session_metadata", (session,))
+            .unwrap_err();
+        assert!(err.is_instance_of::<pyo3::exceptions::PyPermissionError>(py));
+    });
+    set_thread_grant(Some(ThreadGrant::App));
+    // the file is missing but they may look for it, except for a thread threading didn't start
+    assert_eq!(
+        attempts(),
+        ["RuntimeError", "RuntimeError", "RuntimeError", "PermissionError"]
+    );
+    set_thread_grant(None);
+}
+
+#[test]
+fn test_plugin_hot_reload() {
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    use notify::event::{AccessKind, CreateKind, Event, EventKind, ModifyKind};
+    use parking_lot::Mutex;
+
+    use crate::scripting::plugins::{reload_dir, stop_plugin, watch_loop};
+    use crate::scripting::{script_status, ScriptState};
+
+    let plugins = std::env::temp_dir().join(format!("enoki-plugins-{}", std::process::id()));
+    let modify = |path: &str| {
+        Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(plugins.join(path)))
+    };
+
+    // a burst of saves reloads each folder once, after it stayed quiet for the debounce
+    let (fs_events, receiver) = mpsc::channel();
+    let reloaded = Arc::new(Mutex::new(Vec::new()));
+    let watcher = {
+        let plugins = plugins.clone();
+        let reloaded = reloaded.clone();
+        std::thread::spawn(move || {
+            watch_loop(&plugins, receiver, |dir| {
+                reloaded.lock().push(dir.to_path_buf())
+            })
+        })
+    };
+    for _ in 0..8 {
+        fs_events.send(modify("tracker/main.py")).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    fs_events
+        .send(Ok(
+            Event::new(EventKind::Create(CreateKind::File)).add_path(plugins.join("other/util.py"))
+        ))
+        .unwrap();
+    assert!(reloaded.lock().is_empty());
+    // caches python writes, hidden and shared folders, reads and files elsewhere don't reload
+    fs_events
+        .send(modify("tracker/__pycache__/main.cpython-311.pyc"))
+        .unwrap();
+    fs_events.send(modify(".git/index")).unwrap();
+    fs_events.send(modify("_shared/helpers.py")).unwrap();
+    fs_events
+        .send(Ok(
+            Event::new(EventKind::Access(AccessKind::Any)).add_path(plugins.join("idle/main.py"))
+        ))
+        .unwrap();
+    fs_events
+        .send(Ok(Event::new(EventKind::Modify(ModifyKind::Any))
+            .add_path(std::env::temp_dir().join("main.py"))))
+        .unwrap();
+    let start = Instant::now();
+    while reloaded.lock().len() < 2 {
+        assert!(start.elapsed() < Duration::from_secs(5), "never reloaded");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    fs_events
+        .send(modify("tracker/__pycache__/util.cpython-311.pyc"))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(600));
+    drop(fs_events);
+    watcher.join().unwrap();
+    assert_eq!(
+        *reloaded.lock(),
+        [plugins.join("other"), plugins.join("tracker")]
+    );
+
+    // reloading a folder starts, restarts and stops the plugin in it
+    let dir = plugins.join("hot-reload");
+    let out = plugins.join("hot-reload.txt");
+    let write_plugin = |version: &str| {
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("plugin.json"),
+            r#"{"name": "hot-reload", "version": "1.0.0", "api_version": 1}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.py"),
+            format!(
+                "def on_start():\n    open({out:?}, 'a').write('{version} ')\n\
+                 def on_tick():\n    pass\n\
+                 def on_stop():\n    open({out:?}, 'a').write('stop ')\n",
+                out = out.to_string_lossy()
+            ),
+        )
+        .unwrap();
+    };
+    let wait_for = |expected: &[&str]| {
+        let start = Instant::now();
+        loop {
+            let runs = std::fs::read_to_string(&out).unwrap_or_default();
+            if runs.split_whitespace().eq(expected.iter().copied()) {
+                return;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", runs);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    };
+    let state = || script_status("plugin:hot-reload").unwrap().state;
+
+    write_plugin("v1");
+    reload_dir(&dir);
+    wait_for(&["v1"]);
+    write_plugin("v2");
+    reload_dir(&dir);
+    wait_for(&["v1", "stop", "v2"]);
+
+    // one stopped from the app stays stopped until its folder is gone
+    stop_plugin("hot-reload").unwrap();
+    wait_for(&["v1", "stop", "v2", "stop"]);
+    write_plugin("v3");
+    reload_dir(&dir);
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(state(), ScriptState::Stopped);
+    std::fs::remove_dir_all(&dir).unwrap();
+    reload_dir(&dir);
+    write_plugin("v4");
+    reload_dir(&dir);
+    wait_for(&["v1", "stop", "v2", "stop", "v4"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    reload_dir(&dir);
+    wait_for(&["v1", "stop", "v2", "stop", "v4", "stop"]);
+    std::fs::remove_dir_all(&plugins).unwrap();
+}
+
+#[test]
+fn test_numpy_interop() {
+    use pyo3::{exceptions::PyImportError, prelude::*};
+
+    use crate::enoki_types::{EnokiField, EnokiKey, EnokiObject};
+    use crate::python_helpers::numpy::use_numpy;
+    use crate::python_helpers::py_enoki_types::PyEnokiObject;
+
+    let key = EnokiKey::from("/Drive/speed");
+    let mut object = EnokiObject::new(0);
+    object.set_history(
+        &key,
+        vec![
+            TimestampedEnokiValue::new(10, EnokiValue::Double(1.5)),
+            TimestampedEnokiValue::new(20, EnokiValue::Double(-2.0)),
+            TimestampedEnokiValue::new(20, EnokiValue::Double(-2.5)),
+        ],
+    );
+    let mode = TimestampedEnokiValue::new(20, EnokiValue::String("auto".to_string()));
+    object.add_field(EnokiField::new("/Drive/mode".into(), mode));
+
+    // histories survive the trip through python untouched
+    let round_trip = EnokiObject::from(PyEnokiObject::from(object.clone()));
+    assert_eq!(round_trip.get_history(&key), object.get_history(&key));
+
+    crate::scripting::init_python();
+    Python::with_gil(|py| {
+        let object = Py::new(py, PyEnokiObject::from(object)).unwrap();
+        let array = EnokiValue::DoubleArray(vec![0.5, 1.0]);
+        let type_name = |value: &EnokiValue| {
+            Python::with_gil(|py| {
+                value
+                    .clone()
+                    .into_py(py)
+                    .as_ref(py)
+                    .get_type()
+                    .name()
+                    .unwrap()
+                    .to_string()
+            })
+        };
+        let import_error = |result: PyResult<PyObject>| {
+            assert!(result.unwrap_err().is_instance_of::<PyImportError>(py));
+        };
+
+        let numpy = match py.import("numpy") {
+            Ok(numpy) => numpy,
+            // numpy is optional, without it whatever needs it says so and values stay lists
+            Err(_) => {
+                assert!(use_numpy(py, true)
+                    .unwrap_err()
+                    .is_instance_of::<PyImportError>(py));
+                assert_eq!(type_name(&array), "list");
+                import_error(object.call_method1(py, "history_arrays", ("/Drive/speed",)));
+                import_error(object.call_method0(py, "to_pandas"));
+                return;
+            }
+        };
+        let arrays = object
+            .call_method1(py, "history_arrays", ("/Drive/speed",))
+            .unwrap();
+        let (timestamps, values) = arrays.extract::<(&PyAny, &PyAny)>(py).unwrap();
+        let timestamps = timestamps.call_method0("tolist").unwrap();
+        assert_eq!(timestamps.extract::<Vec<i64>>().unwrap(), [10, 20, 20]);
+        let dtype = values.getattr("dtype").unwrap().str().unwrap();
+        assert_eq!(dtype.to_str().unwrap(), "float64");
+
+        let ints = numpy.call_method1("arange", (3,)).unwrap();
+        assert_eq!(
+            ints.extract::<EnokiValue>().unwrap(),
+            EnokiValue::IntArray(vec![0, 1, 2])
+        );
+        let doubles = numpy.call_method1("linspace", (0.0, 1.0, 3)).unwrap();
+        assert_eq!(
+            doubles.extract::<EnokiValue>().unwrap(),
+            EnokiValue::DoubleArray(vec![0.0, 0.5, 1.0])
+        );
+        let grid = numpy.call_method1("zeros", ((2, 2),)).unwrap();
+        assert!(grid.extract::<EnokiValue>().is_err());
+
+        // only the thread that asked for ndarrays gets them, like a script's worker
+        assert_eq!(type_name(&array), "list");
+        use_numpy(py, true).unwrap();
+        assert_eq!(type_name(&array), "ndarray");
+        let elsewhere = py.allow_threads(|| {
+            let array = array.clone();
+            std::thread::spawn(move || type_name(&array))
+                .join()
+                .unwrap()
+        });
+        assert_eq!(elsewhere, "list");
+        use_numpy(py, false).unwrap();
+        assert_eq!(type_name(&array), "list");
+
+        if py.import("pandas").is_err() {
+            import_error(object.call_method0(py, "to_pandas"));
+            return;
+        }
+        let frame = object.call_method0(py, "to_pandas").unwrap();
+        let frame = frame.as_ref(py);
+        let column = |name: &str| {
+            frame
+                .get_item(name)
+                .unwrap()
+                .call_method0("tolist")
+                .unwrap()
+        };
+        let index = frame.getattr("index").unwrap();
+        assert_eq!(
+            index.getattr("name").unwrap().extract::<String>().unwrap(),
+            "timestamp"
+        );
+        assert_eq!(
+            index
+                .call_method0("tolist")
+                .unwrap()
+                .extract::<Vec<i64>>()
+                .unwrap(),
+            [10, 20]
+        );
+        // the later of two values at the same timestamp wins
+        assert_eq!(
+            column("/Drive/speed").extract::<Vec<f64>>().unwrap(),
+            [1.5, -2.5]
+        );
+        // a field without a history is a single row at its timestamp
+        let mode = column("/Drive/mode");
+        assert!(mode.get_item(0).unwrap().extract::<f64>().unwrap().is_nan());
+        assert_eq!(
+            mode.get_item(1).unwrap().extract::<String>().unwrap(),
+            "auto"
+        );
+    });
+}
+
+#[test]
+fn test_typed_values() {
+    use std::net::Ipv4Addr;
+
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use crate::networktable::ffi::python::PyNetworkTableClientId;
+    use crate::networktable::handler::NetworkTableClientId;
+    use crate::scripting::plugins::{set_thread_grant, ThreadGrant};
+
+    // publishing needs a permission, the test runs python for the app
+    set_thread_grant(Some(ThreadGrant::App));
+    crate::scripting::init_python();
+    Python::with_gil(|py| {
+        let client_id = NetworkTableClientId::new(Ipv4Addr::LOCALHOST, 5810, "test".to_string());
+        let locals = PyDict::new(py);
+        locals
+            .set_item("client_id", PyNetworkTableClientId::from(client_id).into_py(py))
+            .unwrap();
+        let code = r#"
+from typing import List
+import enoki_core
+from enoki_core import Double, Int, IntArray, Raw, Protobuf, TypedValue, NetworkTable
+from enoki import NetworkTable as Typed
+
+def raises(error, f, *args):
+    try:
+        f(*args)
+    except error:
+        return
+    raise AssertionError(f"{f} didn't raise {error.__name__}")
+
+assert Double(1).type == "Double" and Double(1).value == 1.0
+assert IntArray([]).type == "IntArray"
+assert Raw(b"\x01\x02").type == "ByteArray" and Raw([1, 2]).value == b"\x01\x02"
+assert Protobuf(b"").type == "Protobuf"
+assert Double(1) == Double(1.0)
+raises(TypeError, Int, 1.5)
+raises(TypeError, Int, True)
+raises(TypeError, Raw, [256])
+
+# bytes aren't taken for an int array anymore
+assert enoki_core.TimestampedEnokiValue(b"ab", 0).value == b"ab"
+assert enoki_core.TimestampedEnokiValue(Double(2), 0).value == 2.0
+
+topic = NetworkTable.NetworkTablePubbedTopic("/Arm/setpoint", "Double", client_id)
+topic.set_value(1)
+topic.set_value(Int(1))
+raises(TypeError, topic.set_value, "up")
+raises(TypeError, NetworkTable.NetworkTablePubbedTopic("/Arm/on", "Boolean", client_id).set_value, 1)
+raises(ValueError, NetworkTable.NetworkTablePubbedTopic, "/Arm/x", "double", client_id)
+
+setpoint = Typed.Topic[float]("/Arm/setpoint", client_id)
+setpoint.set_value(1)
+assert setpoint.topic_type() == "Double"
+raises(TypeError, setpoint.set_value, True)
+modes = Typed.Topic[List[int]]("/Arm/modes", client_id)
+modes.set_value([1, 2])
+assert modes.topic_type() == "IntArray"
+raises(TypeError, modes.set_value, [1.5])
+raises(TypeError, Typed.Topic[dict]("/Arm/config", client_id).set_value, {})
+"#;
+        if let Err(err) = py.run(code, None, Some(locals)) {
+            panic!("{}", crate::python_helpers::logging::format_py_err(py, &err));
+        }
+    });
+}
+
+#[test]
+fn test_async_scripts() {
+    use std::time::Duration;
+
+    use crate::scripting::{spawn_script, stop_script, ScriptState};
+
+    let dir = ScriptDir::new("async");
+    // every hook is async and writes what it got to, main runs until it's cancelled
+    let script = |name: &str, main: &str| {
+        dir.script(
+            name,
+            &format!(
+                "import asyncio\n\
+                 import enoki_core\n\
+                 async def on_start():\n    await asyncio.sleep(0.01)\n    write('start')\n\
+                 async def main():\n    try:\n        {}\n    except asyncio.CancelledError:\n        write('cancelled')\n        raise\n\
+                 async def on_stop():\n    await asyncio.sleep(0)\n    write('stop')\n",
+                main
+            ),
+        )
+    };
+
+    // reads run on their own thread and raise their errors where they're awaited, several at once
+    let (path, out) = script(
+        "reading",
+        "try:\n            await enoki_core.Datalog.open_async('/nonexistent.wpilog')\n        \
+         except RuntimeError:\n            write('missing')\n        \
+         reads = [enoki_core.Datalog.open_async('/nonexistent.wpilog') for _ in range(5)]\n        \
+         errors = await asyncio.gather(*reads, return_exceptions=True)\n        \
+         write(str(sum(isinstance(error, RuntimeError) for error in errors)))\n        \
+         while True:\n            await asyncio.sleep(0.01)",
+    );
+    spawn_script("reading".to_string(), path, NEVER_RESTART).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    stop_script("reading").unwrap();
+    assert_eq!(wait_until_stopped("reading"), ScriptState::Stopped);
+    assert_eq!(runs(&out), ["start", "missing", "5", "cancelled", "stop"]);
+
+    // a script with only main is done when main returns
+    let (path, out) = script(
+        "returning",
+        "await asyncio.sleep(0.05)\n        write('done')",
+    );
+    spawn_script("returning".to_string(), path, NEVER_RESTART).unwrap();
+    assert_eq!(wait_until_stopped("returning"), ScriptState::Stopped);
+    assert_eq!(runs(&out), ["start", "done", "stop"]);
+
+    let (path, out) = script(
+        "raising",
+        "await asyncio.sleep(0.01)\n        raise ValueError('bad main')",
+    );
+    spawn_script("raising".to_string(), path, NEVER_RESTART).unwrap();
+    match wait_until_stopped("raising") {
+        ScriptState::Failed { error } => {
+            assert!(error.contains("ValueError: bad main"), "{}", error)
+        }
+        state => panic!("{:?}", state),
+    }
+    assert_eq!(runs(&out), ["start", "stop"]);
+}
+
+#[test]
+fn test_script_registry() {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use crate::scripting::registry::{call_command, read_provider, script_registry};
+    use crate::scripting::{script_status, spawn_script, stop_script, ScriptState};
+
+    let dir = ScriptDir::new("registry");
+    // registers commands and a provider and does nothing else, it keeps running to serve them
+    let (path, _) = dir.script(
+        "serving",
+        "import asyncio\n\
+         import enoki_core\n\
+         from enoki_core import Scripting\n\
+         zeroed = []\n\
+         def zero(modules, offset=0.0):\n    zeroed.extend(modules)\n    return {'zeroed': len(zeroed), 'offset': offset}\n\
+         async def shot_map(distance):\n    await asyncio.sleep(0.01)\n    return [distance * 2]\n\
+         def broken():\n    raise ValueError('no encoders')\n\
+         def on_start():\n    \
+         Scripting.register_command('zero_encoders', zero, {'type': 'object', 'required': ['modules'], \
+         'properties': {'modules': {'type': 'array', 'items': {'type': 'integer'}}, 'offset': {'type': 'number'}}}, 'Zeroes the swerve encoders')\n    \
+         Scripting.register_command('shot_map', shot_map)\n    \
+         Scripting.register_command('broken', broken)\n    \
+         Scripting.register_provider('odometry', lambda: {'x': 1.5, 'pose/heading': 90.0})\n",
+    );
+    spawn_script("serving".to_string(), path, NEVER_RESTART).unwrap();
+    let start = Instant::now();
+    while script_registry().providers.is_empty() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "nothing was registered"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let registry = script_registry();
+    let names = registry
+        .commands
+        .iter()
+        .map(|command| command.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["broken", "shot_map", "zero_encoders"]);
+    assert_eq!(
+        registry.commands[2].description,
+        "Zeroes the swerve encoders"
+    );
+    assert_eq!(registry.commands[1].schema, json!({ "type": "object" }));
+    assert_eq!(
+        String::from(&registry.providers[0].prefix),
+        "/Scripts/serving/odometry"
+    );
+
+    assert_eq!(
+        call_command("zero_encoders", Some(json!({ "modules": [1, 2] }))).unwrap(),
+        json!({ "zeroed": 2, "offset": 0.0 })
+    );
+    let invalid = call_command("zero_encoders", Some(json!({ "modules": [1.5] }))).unwrap_err();
+    assert!(
+        invalid.to_string().contains("modules[0] should be integer"),
+        "{}",
+        invalid
+    );
+    assert!(call_command("zero_encoders", None).is_err());
+    assert_eq!(
+        call_command("shot_map", Some(json!({ "distance": 3 }))).unwrap(),
+        json!([6])
+    );
+    let failed = call_command("broken", None).unwrap_err();
+    assert!(
+        failed.to_string().contains("ValueError: no encoders"),
+        "{}",
+        failed
+    );
+    assert!(call_command("missing", None).is_err());
+
+    let odometry = read_provider("odometry").unwrap();
+    assert_eq!(
+        odometry
+            .get_field(&"/Scripts/serving/odometry/pose/heading".into())
+            .unwrap()
+            .get_value()
+            .value,
+        EnokiValue::Double(90.0)
+    );
+    assert_eq!(odometry.len(), 2);
+
+    // everything the script registered goes away with it
+    assert!(script_status("serving").unwrap().state.is_active());
+    stop_script("serving").unwrap();
+    assert_eq!(wait_until_stopped("serving"), ScriptState::Stopped);
+    assert!(script_registry().commands.is_empty());
+    assert!(read_provider("odometry").is_err());
+}
+
+#[test]
+fn test_repl_sessions() {
+    use std::time::{Duration, Instant};
+
+    use crate::scripting::repl::{
+        complete_repl, interrupt_repl, read_err_repl, read_out_repl, repl_status, start_repl,
+        stop_repl, write_repl,
+    };
+
+    fn settle(id: usize) {
+        let start = Instant::now();
+        while repl_status(id).unwrap().busy {
+            assert!(start.elapsed() < Duration::from_secs(5), "repl {} hung", id);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    let first = start_repl().unwrap();
+    let second = start_repl().unwrap();
+    settle(first);
+    settle(second);
+    assert!(read_out_repl(first).unwrap().contains("enoki_core"));
+    read_out_repl(second).unwrap();
+
+    // a block is only run once it's ended by an empty line
+    write_repl(first, "offset = 2\ndef shifted(x):\n    return x + offset").unwrap();
+    settle(first);
+    assert!(repl_status(first).unwrap().more);
+    write_repl(first, "\nprint(shifted(1))\nshifted(40)\n").unwrap();
+    settle(first);
+    assert!(!repl_status(first).unwrap().more);
+    assert_eq!(read_out_repl(first).unwrap(), "3\n42\n");
+    assert_eq!(read_out_repl(first).unwrap(), "");
+
+    // top level await runs on the session's loop
+    write_repl(first, "import asyncio\nawait asyncio.sleep(0.01, 'slept')").unwrap();
+    settle(first);
+    assert_eq!(read_out_repl(first).unwrap(), "'slept'\n");
+
+    // sessions don't share names
+    write_repl(second, "shifted(1)").unwrap();
+    settle(second);
+    assert!(read_err_repl(second).unwrap().contains("NameError"));
+    assert_eq!(read_out_repl(second).unwrap(), "");
+
+    let completions = complete_repl(first, "x = shif").unwrap();
+    assert_eq!(completions, ["x = shifted("]);
+    assert!(complete_repl(second, "enoki_core.Scr")
+        .unwrap()
+        .contains(&"enoki_core.Scripting".to_string()));
+
+    write_repl(second, "while True:\n    pass\n\n").unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(repl_status(second).unwrap().busy);
+    interrupt_repl(second).unwrap();
+    settle(second);
+    assert!(read_err_repl(second).unwrap().contains("KeyboardInterrupt"));
+    write_repl(second, "print('still here')").unwrap();
+    settle(second);
+    assert_eq!(read_out_repl(second).unwrap(), "still here\n");
+
+    write_repl(second, "exit()").unwrap();
+    settle(second);
+    assert!(!repl_status(second).unwrap().running);
+    assert!(write_repl(second, "1").is_err());
+
+    stop_repl(first).unwrap();
+    stop_repl(second).unwrap();
+    assert!(repl_status(first).is_err());
+}
+
+#[test]
+fn test_retention_policy() {
+    use std::collections::HashSet;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    use crate::retention::{enforce_policy, new_log_path, RetentionPolicy};
+
+    let docs = std::env::temp_dir().join(format!("enoki-retention-{}", std::process::id()));
+    let dir = docs.join("Logs");
+    std::fs::remove_dir_all(&docs).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // logs are named after when they were created, that's the age retention goes by
+    let log = |days_ago: i64, size: usize| -> PathBuf {
+        let created = chrono::Local::now() - chrono::Duration::days(days_ago);
+        let path = dir.join(created.format("%Y-%m-%d_%H-%M-%S.wpilog").to_string());
+        std::fs::write(&path, vec![b'x'; size]).unwrap();
+        path
+    };
+    let policy = |max_total_bytes, max_age_days, keep_last, compress| RetentionPolicy {
+        max_total_bytes,
+        max_age_days,
+        keep_last,
+        compress,
+    };
+    let favorite_key =
+        |path: &Path| format!("Logs/{}", path.file_name().unwrap().to_string_lossy());
+    let remaining = || {
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let newest = log(0, 100);
+    let recent = log(2, 100);
+    log(40, 100);
+    let favorite = log(50, 100);
+    let favorites: HashSet<_> = [favorite_key(&favorite)].into_iter().collect();
+    let enforce = |policy: RetentionPolicy, active: Option<&Path>| {
+        enforce_policy(&docs, "Logs", &policy, &favorites, active).unwrap()
+    };
+
+    // too old, but favorites are never touched
+    enforce(policy(None, Some(30), None, false), None);
+    assert_eq!(
+        remaining(),
+        [favorite.clone(), recent.clone(), newest.clone()]
+    );
+
+    // only the newest one is kept, the file being written doesn't count and isn't removed
+    let active = log(-1, 100);
+    enforce(policy(None, None, Some(1), false), Some(&active));
+    assert_eq!(
+        remaining(),
+        [favorite.clone(), newest.clone(), active.clone()]
+    );
+
+    // the newest files are kept even when they're too old
+    std::fs::remove_file(&active).unwrap();
+    let older = log(45, 100);
+    log(60, 100);
+    enforce(policy(None, Some(0), Some(2), false), None);
+    assert_eq!(remaining(), [favorite.clone(), older, newest.clone()]);
+
+    // oldest files go first to get under the size limit
+    log(1, 100);
+    enforce(policy(Some(250), None, None, false), None);
+    assert_eq!(remaining().len(), 2);
+    assert!(remaining().contains(&favorite));
+    assert!(remaining().contains(&newest));
+
+    // old files are compressed instead, once
+    std::fs::remove_file(&favorite).unwrap();
+    let old = log(40, 10_000);
+    let compressed = PathBuf::from(format!("{}.gz", old.display()));
+    let compressing = policy(None, Some(30), None, true);
+    enforce(compressing.clone(), None);
+    enforce(compressing, None);
+    assert_eq!(remaining(), [compressed.clone(), newest.clone()]);
+    let mut contents = Vec::new();
+    flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap())
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(contents, vec![b'x'; 10_000]);
+    assert!(std::fs::metadata(&compressed).unwrap().len() < 10_000);
+
+    // compressed files are still removed for size
+    enforce(policy(Some(100), Some(30), None, true), None);
+    assert_eq!(remaining(), [newest.clone()]);
+
+    // a log that can't be compressed is left as it is and doesn't hold up the others
+    let stuck = log(40, 100);
+    let blocked = PathBuf::from(format!("{}.gz", stuck.display()));
+    std::fs::create_dir(&blocked).unwrap();
+    let old = log(45, 100);
+    enforce(policy(None, Some(30), None, true), None);
+    let compressed = PathBuf::from(format!("{}.gz", old.display()));
+    assert_eq!(
+        remaining(),
+        [compressed, stuck.clone(), blocked.clone(), newest.clone()]
+    );
+    std::fs::remove_dir(&blocked).unwrap();
+    std::fs::remove_file(&stuck).unwrap();
+
+    // a log started in the same second as another doesn't replace it
+    let relative = format!("Enoki/Retention-{}", std::process::id());
+    let first = new_log_path(&relative, "wpilog").unwrap();
+    std::fs::create_dir_all(first.parent().unwrap()).unwrap();
+    std::fs::write(&first, "first").unwrap();
+    let second = new_log_path(&relative, "wpilog").unwrap();
+    let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+    if name(&second)[..19] == name(&first)[..19] {
+        assert_eq!(name(&second), format!("{}-1.wpilog", &name(&first)[..19]));
+    }
+    assert!(!second.exists());
+
+    std::fs::remove_dir_all(first.parent().unwrap()).ok();
+    std::fs::remove_dir_all(docs).ok();
+}
@@ -0,0 +1,302 @@
+use std::{convert::Infallible, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{enoki_types::EnokiValue, error::EnokiError};
+
+/// What a unit measures, only units of the same dimension convert into each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dimension {
+    Length,
+    Angle,
+    LinearVelocity,
+    AngularVelocity,
+    Time,
+    Mass,
+    Temperature,
+    Voltage,
+    Current,
+    Unknown,
+}
+
+/// A unit attached to a field, anything we don't recognize is kept as `Other` so it can still be shown
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unit {
+    Meter,
+    Centimeter,
+    Millimeter,
+    Inch,
+    Foot,
+    Radian,
+    Degree,
+    Rotation,
+    MeterPerSecond,
+    FootPerSecond,
+    InchPerSecond,
+    RadianPerSecond,
+    DegreePerSecond,
+    RotationPerSecond,
+    RotationPerMinute,
+    Second,
+    Millisecond,
+    Microsecond,
+    Kilogram,
+    Pound,
+    Celsius,
+    Fahrenheit,
+    Volt,
+    Amp,
+    Other(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum UnitError {
+    #[error("Cannot convert {from} to {to}")]
+    Incompatible { from: String, to: String },
+    #[error("Cannot convert a {0} value")]
+    NotNumeric(String),
+}
+
+impl Unit {
+    pub fn symbol(&self) -> &str {
+        match self {
+            Unit::Meter => "m",
+            Unit::Centimeter => "cm",
+            Unit::Millimeter => "mm",
+            Unit::Inch => "in",
+            Unit::Foot => "ft",
+            Unit::Radian => "rad",
+            Unit::Degree => "deg",
+            Unit::Rotation => "rot",
+            Unit::MeterPerSecond => "m/s",
+            Unit::FootPerSecond => "ft/s",
+            Unit::InchPerSecond => "in/s",
+            Unit::RadianPerSecond => "rad/s",
+            Unit::DegreePerSecond => "deg/s",
+            Unit::RotationPerSecond => "rps",
+            Unit::RotationPerMinute => "rpm",
+            Unit::Second => "s",
+            Unit::Millisecond => "ms",
+            Unit::Microsecond => "us",
+            Unit::Kilogram => "kg",
+            Unit::Pound => "lb",
+            Unit::Celsius => "C",
+            Unit::Fahrenheit => "F",
+            Unit::Volt => "V",
+            Unit::Amp => "A",
+            Unit::Other(symbol) => symbol,
+        }
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Meter | Unit::Centimeter | Unit::Millimeter | Unit::Inch | Unit::Foot => {
+                Dimension::Length
+            }
+            Unit::Radian | Unit::Degree | Unit::Rotation => Dimension::Angle,
+            Unit::MeterPerSecond | Unit::FootPerSecond | Unit::InchPerSecond => {
+                Dimension::LinearVelocity
+            }
+            Unit::RadianPerSecond
+            | Unit::DegreePerSecond
+            | Unit::RotationPerSecond
+            | Unit::RotationPerMinute => Dimension::AngularVelocity,
+            Unit::Second | Unit::Millisecond | Unit::Microsecond => Dimension::Time,
+            Unit::Kilogram | Unit::Pound => Dimension::Mass,
+            Unit::Celsius | Unit::Fahrenheit => Dimension::Temperature,
+            Unit::Volt => Dimension::Voltage,
+            Unit::Amp => Dimension::Current,
+            Unit::Other(_) => Dimension::Unknown,
+        }
+    }
+
+    /// `(offset, scale)` so that `base = (value + offset) * scale`,
+    /// the base units are SI with celsius for temperature
+    fn to_base(&self) -> (f64, f64) {
+        use std::f64::consts::PI;
+        let scale = match self {
+            Unit::Meter | Unit::Radian | Unit::MeterPerSecond | Unit::RadianPerSecond => 1.0,
+            Unit::Centimeter => 0.01,
+            Unit::Millimeter => 0.001,
+            Unit::Inch | Unit::InchPerSecond => 0.0254,
+            Unit::Foot | Unit::FootPerSecond => 0.3048,
+            Unit::Degree | Unit::DegreePerSecond => PI / 180.0,
+            Unit::Rotation | Unit::RotationPerSecond => 2.0 * PI,
+            Unit::RotationPerMinute => 2.0 * PI / 60.0,
+            Unit::Second | Unit::Kilogram | Unit::Celsius | Unit::Volt | Unit::Amp => 1.0,
+            Unit::Millisecond => 1e-3,
+            Unit::Microsecond => 1e-6,
+            Unit::Pound => 0.453_592_37,
+            Unit::Fahrenheit => return (-32.0, 5.0 / 9.0),
+            Unit::Other(_) => 1.0,
+        };
+        (0.0, scale)
+    }
+
+    pub fn can_convert_to(&self, to: &Unit) -> bool {
+        self == to || (self.dimension() == to.dimension() && self.dimension() != Dimension::Unknown)
+    }
+
+    pub fn convert(&self, value: f64, to: &Unit) -> Result<f64, UnitError> {
+        if self == to {
+            return Ok(value);
+        }
+        if !self.can_convert_to(to) {
+            return Err(UnitError::Incompatible {
+                from: self.to_string(),
+                to: to.to_string(),
+            });
+        }
+        let (from_offset, from_scale) = self.to_base();
+        let (to_offset, to_scale) = to.to_base();
+        Ok((value + from_offset) * from_scale / to_scale - to_offset)
+    }
+
+    /// Converts numeric values and arrays, integers become doubles unless the unit is unchanged
+    pub fn convert_value(&self, value: &EnokiValue, to: &Unit) -> Result<EnokiValue, UnitError> {
+        if self == to {
+            return Ok(value.clone());
+        }
+        let convert = |v: f64| self.convert(v, to);
+        let convert_all =
+            |values: Vec<f64>| values.into_iter().map(convert).collect::<Result<Vec<_>, _>>();
+        match value {
+            EnokiValue::Double(v) => Ok(EnokiValue::Double(convert(*v)?)),
+            EnokiValue::Float(v) => Ok(EnokiValue::Float(convert(*v)?)),
+            EnokiValue::Int(v) => Ok(EnokiValue::Double(convert(*v as f64)?)),
+            EnokiValue::DoubleArray(v) => Ok(EnokiValue::DoubleArray(convert_all(v.clone())?)),
+            EnokiValue::FloatArray(v) => Ok(EnokiValue::FloatArray(convert_all(v.clone())?)),
+            EnokiValue::IntArray(v) => Ok(EnokiValue::DoubleArray(convert_all(
+                v.iter().map(|v| *v as f64).collect(),
+            )?)),
+            other => Err(UnitError::NotNumeric(other.get_type())),
+        }
+    }
+
+    /// Reads the `unit` key of NT topic properties or json wpilog entry metadata
+    pub fn from_properties(properties: &serde_json::Value) -> Option<Unit> {
+        properties
+            .get("unit")
+            .or_else(|| properties.get("units"))
+            .and_then(|unit| unit.as_str())
+            .filter(|unit| !unit.trim().is_empty())
+            .map(Unit::from)
+    }
+
+    pub fn from_metadata(metadata: &str) -> Option<Unit> {
+        serde_json::from_str::<serde_json::Value>(metadata)
+            .ok()
+            .as_ref()
+            .and_then(Unit::from_properties)
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Unit::from(s))
+    }
+}
+
+impl From<&str> for Unit {
+    fn from(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "m" | "meter" | "meters" | "metre" | "metres" => Unit::Meter,
+            "cm" | "centimeter" | "centimeters" => Unit::Centimeter,
+            "mm" | "millimeter" | "millimeters" => Unit::Millimeter,
+            "in" | "inch" | "inches" | "\"" => Unit::Inch,
+            "ft" | "foot" | "feet" | "'" => Unit::Foot,
+            "rad" | "radian" | "radians" => Unit::Radian,
+            "deg" | "degree" | "degrees" | "°" => Unit::Degree,
+            "rot" | "rotation" | "rotations" | "rev" | "revolution" | "revolutions" => {
+                Unit::Rotation
+            }
+            "m/s" | "mps" | "meters per second" => Unit::MeterPerSecond,
+            "ft/s" | "fps" | "feet per second" => Unit::FootPerSecond,
+            "in/s" | "ips" | "inches per second" => Unit::InchPerSecond,
+            "rad/s" | "radians per second" => Unit::RadianPerSecond,
+            "deg/s" | "°/s" | "degrees per second" => Unit::DegreePerSecond,
+            "rps" | "rot/s" | "rev/s" | "rotations per second" => Unit::RotationPerSecond,
+            "rpm" | "rot/min" | "rev/min" | "rotations per minute" => Unit::RotationPerMinute,
+            "s" | "sec" | "second" | "seconds" => Unit::Second,
+            "ms" | "millisecond" | "milliseconds" => Unit::Millisecond,
+            "us" | "µs" | "microsecond" | "microseconds" => Unit::Microsecond,
+            "kg" | "kilogram" | "kilograms" => Unit::Kilogram,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Pound,
+            "c" | "°c" | "celsius" => Unit::Celsius,
+            "f" | "°f" | "fahrenheit" => Unit::Fahrenheit,
+            "v" | "volt" | "volts" => Unit::Volt,
+            "a" | "amp" | "amps" | "ampere" | "amperes" => Unit::Amp,
+            _ => Unit::Other(s.trim().to_string()),
+        }
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+impl Serialize for Unit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.symbol())
+    }
+}
+
+impl<'a> Deserialize<'a> for Unit {
+    fn deserialize<D: serde::Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Unit::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+/// The units a display prefers, dimensions a system has no preference for are left as they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnitSystem {
+    /// Meters, radians and radians per second, what WPILib math expects
+    Si,
+    /// Meters with degrees and rpm which read better on a dashboard
+    Metric,
+    /// Inches, feet per second, degrees, rpm, pounds and fahrenheit
+    Imperial,
+}
+
+impl FromStr for UnitSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "si" => Ok(UnitSystem::Si),
+            "metric" => Ok(UnitSystem::Metric),
+            "imperial" | "us" => Ok(UnitSystem::Imperial),
+            _ => Err(format!("Unknown unit system {}", s)),
+        }
+    }
+}
+
+impl UnitSystem {
+    pub fn preferred(&self, unit: &Unit) -> Unit {
+        match (self, unit.dimension()) {
+            (UnitSystem::Imperial, Dimension::Length) => Unit::Inch,
+            (_, Dimension::Length) => Unit::Meter,
+            (UnitSystem::Si, Dimension::Angle) => Unit::Radian,
+            (_, Dimension::Angle) => Unit::Degree,
+            (UnitSystem::Imperial, Dimension::LinearVelocity) => Unit::FootPerSecond,
+            (_, Dimension::LinearVelocity) => Unit::MeterPerSecond,
+            (UnitSystem::Si, Dimension::AngularVelocity) => Unit::RadianPerSecond,
+            (_, Dimension::AngularVelocity) => Unit::RotationPerMinute,
+            (UnitSystem::Imperial, Dimension::Mass) => Unit::Pound,
+            (_, Dimension::Mass) => Unit::Kilogram,
+            (UnitSystem::Imperial, Dimension::Temperature) => Unit::Fahrenheit,
+            (_, Dimension::Temperature) => Unit::Celsius,
+            _ => unit.clone(),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn convert_unit(value: f64, from: String, to: String) -> Result<f64, EnokiError> {
+    Ok(Unit::from(from.as_str()).convert(value, &Unit::from(to.as_str()))?)
+}